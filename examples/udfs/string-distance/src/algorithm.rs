@@ -0,0 +1,162 @@
+//! Scoring algorithms selected with the `algorithm` parameter.
+
+#[cfg(feature = "jaro")]
+use crate::jaro::{jaro_similarity, jaro_winkler_similarity, windowed_similarity};
+use crate::levenshtein_similarity;
+#[cfg(feature = "ngram")]
+use crate::ngram::GramIdf;
+#[cfg(feature = "phonetic")]
+use crate::phonetic::phonetic_key_similarity;
+#[cfg(feature = "substring")]
+use crate::substring::lcstr_similarity;
+#[cfg(all(feature = "phonetic", feature = "ngram"))]
+use crate::phonetic::phonetic_similarity;
+#[cfg(feature = "token")]
+use crate::token::{token_set_ratio, Idf, Tokenizer};
+#[cfg(feature = "ngram")]
+use crate::{target_dice_similarity, target_missing_grams};
+
+/// A scoring algorithm and its settings
+///
+/// Every algorithm but Levenshtein and exact matching belongs to a Cargo
+/// feature, and a build without it has no variant for it.
+#[derive(Clone, Debug)]
+pub enum Algorithm {
+    /// Edit distance, matched against `max_distance` (default)
+    Levenshtein,
+    #[cfg(feature = "ngram")]
+    /// Character n-gram Dice coefficient, optionally IDF-weighted, matched
+    /// against `min_similarity`
+    Ngram { n: usize, pad: bool, idf: Option<GramIdf> },
+    #[cfg(feature = "jaro")]
+    /// Jaro similarity, matched against `min_similarity`
+    Jaro,
+    #[cfg(feature = "jaro")]
+    /// Jaro-Winkler similarity, matched against `min_similarity`
+    JaroWinkler,
+    #[cfg(feature = "token")]
+    /// Token set ratio, optionally IDF-weighted and lowered for tokens that
+    /// mix scripts, matched against `min_similarity`
+    TokenSetRatio { tokenizer: Tokenizer, idf: Option<Idf>, mixed_script_penalty: f64 },
+    /// Plain equality of the normalized strings, without any DP
+    ExactNormalized,
+    #[cfg(all(feature = "phonetic", feature = "ngram"))]
+    /// Blend of Double Metaphone agreement and padded n-gram Dice, weighted
+    /// `phonetic` to `ngram`, matched against `min_similarity`
+    NameMatch { n: usize, phonetic: f64, ngram: f64 },
+    #[cfg(feature = "ngram")]
+    /// Number of the target's trigrams missing from the field, matched
+    /// against `max_missing`
+    TrigramCoverage { pad: bool, max_missing: usize },
+    #[cfg(feature = "phonetic")]
+    /// Edit distance between Double Metaphone keys, matched against
+    /// `max_distance`, with a Levenshtein bound deciding fields exactly at
+    /// the limit
+    PhoneticKey { tiebreak: Option<usize> },
+    #[cfg(feature = "jaro")]
+    /// Unmatched chars and transpositions among chars matched at most
+    /// `window` positions apart, matched against `max_distance`
+    Windowed { window: usize },
+    #[cfg(feature = "substring")]
+    /// Longest common substring, matched against `min_common_len` chars
+    Lcstr { min_common_len: usize },
+}
+
+/// The parameter a match is decided against
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    /// `max_distance`, for distance-based algorithms
+    MaxDistance,
+    /// `min_similarity`, for similarity-based algorithms
+    MinSimilarity,
+}
+
+impl Limit {
+    pub fn name(self) -> &'static str {
+        match self {
+            Limit::MaxDistance => "max_distance",
+            Limit::MinSimilarity => "min_similarity",
+        }
+    }
+
+    /// The limit of the other kind of algorithm
+    pub fn other(self) -> Limit {
+        match self {
+            Limit::MaxDistance => Limit::MinSimilarity,
+            Limit::MinSimilarity => Limit::MaxDistance,
+        }
+    }
+}
+
+impl Algorithm {
+    /// The limit a match is decided against, `None` for exact matching and
+    /// for algorithms with a limit of their own
+    pub fn limit(&self) -> Option<Limit> {
+        match self {
+            Algorithm::Levenshtein => Some(Limit::MaxDistance),
+            #[cfg(feature = "phonetic")]
+            Algorithm::PhoneticKey { .. } => Some(Limit::MaxDistance),
+            #[cfg(feature = "jaro")]
+            Algorithm::Windowed { .. } => Some(Limit::MaxDistance),
+            Algorithm::ExactNormalized => None,
+            #[cfg(feature = "ngram")]
+            Algorithm::TrigramCoverage { .. } => None,
+            #[cfg(feature = "substring")]
+            Algorithm::Lcstr { .. } => None,
+            #[cfg(feature = "ngram")]
+            Algorithm::Ngram { .. } => Some(Limit::MinSimilarity),
+            #[cfg(feature = "jaro")]
+            Algorithm::Jaro | Algorithm::JaroWinkler => Some(Limit::MinSimilarity),
+            #[cfg(feature = "token")]
+            Algorithm::TokenSetRatio { .. } => Some(Limit::MinSimilarity),
+            #[cfg(all(feature = "phonetic", feature = "ngram"))]
+            Algorithm::NameMatch { .. } => Some(Limit::MinSimilarity),
+        }
+    }
+
+    /// Normalized similarity of two strings in [0, 1]
+    pub fn similarity(&self, s1: &str, s2: &str) -> f64 {
+        match self {
+            Algorithm::Levenshtein => levenshtein_similarity(s1, s2),
+            #[cfg(feature = "ngram")]
+            Algorithm::Ngram { n, pad, idf } => {
+                target_dice_similarity(s1, s2, *n, *pad, idf.as_ref())
+            }
+            #[cfg(feature = "jaro")]
+            Algorithm::Jaro => jaro_similarity(s1, s2),
+            #[cfg(feature = "jaro")]
+            Algorithm::JaroWinkler => jaro_winkler_similarity(s1, s2),
+            #[cfg(feature = "token")]
+            Algorithm::TokenSetRatio { tokenizer, idf, mixed_script_penalty } => {
+                token_set_ratio(s1, s2, tokenizer, idf.as_ref(), *mixed_script_penalty)
+            }
+            #[cfg(all(feature = "phonetic", feature = "ngram"))]
+            Algorithm::NameMatch { n, phonetic, ngram } => {
+                let sound = phonetic_similarity(s1, s2);
+                let spelling = target_dice_similarity(s1, s2, *n, true, None);
+                (phonetic * sound + ngram * spelling) / (phonetic + ngram)
+            }
+            #[cfg(feature = "ngram")]
+            Algorithm::TrigramCoverage { pad, .. } => {
+                // The share of the target's trigrams the field covers
+                match target_missing_grams(s1, s2, 3, *pad) {
+                    (_, 0) => 1.0,
+                    (missing, total) => 1.0 - missing as f64 / total as f64,
+                }
+            }
+            #[cfg(feature = "phonetic")]
+            Algorithm::PhoneticKey { .. } => phonetic_key_similarity(s1, s2),
+            #[cfg(feature = "jaro")]
+            Algorithm::Windowed { window } => windowed_similarity(s1, s2, *window),
+            #[cfg(feature = "substring")]
+            Algorithm::Lcstr { .. } => lcstr_similarity(s1, s2),
+            Algorithm::ExactNormalized => {
+                if s1 == s2 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Arrays of strings, as `get_field_array` and `get_param_array` write
+//! them.
+//!
+//! The encoding is little-endian, like the parameters blob: a `u32` count
+//! of elements, then for each one a `u32` length and that many bytes of
+//! UTF-8.
+
+/// The elements of an encoded array
+///
+/// Returns `None` when `bytes` is truncated, has bytes past its last
+/// element, or holds an element that isn't UTF-8.
+pub fn parse(bytes: &[u8]) -> Option<Vec<&str>> {
+    let mut rest = bytes;
+    // The count isn't trusted for an allocation, each element is read first
+    let count = take_u32(&mut rest)?;
+    let mut elements = Vec::new();
+    for _ in 0..count {
+        let len = take_u32(&mut rest)? as usize;
+        if len > rest.len() {
+            return None;
+        }
+        let (element, tail) = rest.split_at(len);
+        elements.push(core::str::from_utf8(element).ok()?);
+        rest = tail;
+    }
+
+    if !rest.is_empty() {
+        return None;
+    }
+    Some(elements)
+}
+
+/// Read a `u32` off the front of `rest`
+fn take_u32(rest: &mut &[u8]) -> Option<u32> {
+    let (bytes, tail) = rest.split_first_chunk::<4>()?;
+    *rest = tail;
+    Some(u32::from_le_bytes(*bytes))
+}
+
+/// `elements` encoded as `parse` reads them
+#[cfg(test)]
+pub fn encode(elements: &[&str]) -> Vec<u8> {
+    let mut bytes = (elements.len() as u32).to_le_bytes().to_vec();
+    for element in elements {
+        bytes.extend((element.len() as u32).to_le_bytes());
+        bytes.extend(element.as_bytes());
+    }
+    bytes
+}
@@ -0,0 +1,77 @@
+//! All parameters in one binary blob, as `get_params_blob` writes them.
+//!
+//! The blob is little-endian throughout: a `u32` count of parameters,
+//! then for each one a `u32` name length, the UTF-8 name, a one-byte type
+//! tag and the value:
+//!
+//! | Tag | Type | Value |
+//! |-----|------|-------|
+//! | 0 | string | `u32` length, UTF-8 bytes |
+//! | 1 | integer | `i64` |
+//! | 2 | float | `f64` |
+//! | 3 | boolean | one byte, 0 or 1 |
+
+use crate::json::Value;
+
+/// The parameters in `blob`, as members of an object like a `config`
+///
+/// Integers become numbers, as a `config` would give them. Returns `None`
+/// when the blob is truncated, has bytes past its last parameter, an
+/// unknown tag, a boolean other than 0 or 1, or text that isn't UTF-8.
+pub fn parse(blob: &[u8]) -> Option<Value> {
+    let mut reader = Reader { rest: blob };
+    let count = reader.u32()?;
+
+    let mut members = Vec::new();
+    for _ in 0..count {
+        let name = reader.text()?;
+        let value = match reader.take(1)?[0] {
+            0 => Value::String(reader.text()?),
+            1 => Value::Number(i64::from_le_bytes(reader.array()?) as f64),
+            2 => Value::Number(f64::from_le_bytes(reader.array()?)),
+            3 => match reader.take(1)?[0] {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        members.push((name, value));
+    }
+
+    if !reader.rest.is_empty() {
+        return None;
+    }
+    Some(Value::Object(members))
+}
+
+/// The unread part of a blob
+struct Reader<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.rest.len() {
+            return None;
+        }
+        let (taken, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Some(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.array()?))
+    }
+
+    /// A `u32` length and that many bytes of UTF-8
+    fn text(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        core::str::from_utf8(bytes).ok().map(String::from)
+    }
+}
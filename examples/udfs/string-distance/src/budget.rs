@@ -0,0 +1,61 @@
+//! The `cell_budget` one `filter` call may spend on comparisons.
+//!
+//! Each comparison of a field value with a target is charged up front, one
+//! cell per pair of chars: what a full Levenshtein DP between them computes,
+//! and more than the banded or cut-short DPs do. A comparison the rest of
+//! the budget can't cover isn't made, nor is any after it.
+
+/// Cells left to spend, `None` without a budget
+static mut LEFT: Option<u64> = None;
+/// Whether a comparison was refused since the last `reset`
+static mut EXHAUSTED: bool = false;
+
+/// No budget and nothing refused, as at the start of a call
+pub fn reset() {
+    unsafe {
+        LEFT = None;
+        EXHAUSTED = false;
+    }
+}
+
+/// Allow `cells` more cells from now on
+pub fn limit(cells: u64) {
+    unsafe { LEFT = Some(cells) }
+}
+
+/// Drop the budget, keeping whether it ran out, once the call is done
+pub fn lift() {
+    unsafe { LEFT = None }
+}
+
+/// Charge a comparison of `value` with `target`
+///
+/// Returns `false`, and refuses every later comparison too, when the cells
+/// left don't cover it. An empty operand still costs one cell per char of
+/// the other.
+pub fn charge(value: &str, target: &str) -> bool {
+    unsafe {
+        let left = match LEFT {
+            Some(left) => left,
+            None => return true,
+        };
+        let cells = (value.chars().count().max(1) as u64)
+            .saturating_mul(target.chars().count().max(1) as u64);
+        if EXHAUSTED || cells > left {
+            EXHAUSTED = true;
+            return false;
+        }
+        LEFT = Some(left - cells);
+        true
+    }
+}
+
+/// Whether a comparison was refused, so the call stopped short
+pub fn exhausted() -> bool {
+    unsafe { EXHAUSTED }
+}
+
+/// Whether the call still running has run out, refusing what's left
+pub fn stopped() -> bool {
+    unsafe { LEFT.is_some() && EXHAUSTED }
+}
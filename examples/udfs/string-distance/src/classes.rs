@@ -0,0 +1,171 @@
+//! Edit budgets per character class, for `max_digit_edits` and
+//! `max_letter_edits`.
+
+/// Limits on the edits that involve digits and letters
+///
+/// An insertion or deletion involves one char, a substitution two; an edit
+/// counts against the budget of each class involved, so replacing the
+/// letter "O" by the digit "0" is both a digit and a letter edit. Edits of
+/// other chars (spaces, punctuation) only count towards the total.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ClassBudgets {
+    /// The `max_digit_edits`, unlimited when `None`
+    pub digits: Option<usize>,
+    /// The `max_letter_edits`, unlimited when `None`
+    pub letters: Option<usize>,
+}
+
+/// Marks a state no alignment reaches within the limits
+const UNREACHABLE: usize = usize::MAX;
+
+impl ClassBudgets {
+    pub fn is_set(&self) -> bool {
+        self.digits.is_some() || self.letters.is_some()
+    }
+
+    /// Whether some alignment of `field` with `target` stays within the
+    /// budgets and within `max_edits` edits in total
+    ///
+    /// The minimum-distance alignment isn't enough: of several alignments
+    /// at the same distance one may spend a digit edit where another spends
+    /// a letter edit, and a longer one may fit the budgets when no shortest
+    /// one does. So each DP cell keeps, for every pair of (digit, letter)
+    /// edit counts within the budgets, the fewest total edits of an
+    /// alignment of the prefixes that reaches it. That is O(len × len ×
+    /// budgets²), fine for the short codes budgets are meant for.
+    pub fn allow(&self, field: &str, target: &str, max_edits: usize) -> bool {
+        if !self.is_set() {
+            return true;
+        }
+
+        let shape = Shape::new(self, max_edits);
+        let target: Vec<char> = target.chars().collect();
+        let cells = target.len() + 1;
+
+        // Row 0: the first j target chars inserted
+        let mut prev = vec![UNREACHABLE; cells * shape.states];
+        prev[0] = 0;
+        for (j, &t) in target.iter().enumerate() {
+            let (done, next) = prev.split_at_mut((j + 1) * shape.states);
+            shape.relax(next, &done[j * shape.states..], Edit::of(t), max_edits);
+        }
+        let mut curr = vec![UNREACHABLE; cells * shape.states];
+
+        for f in field.chars() {
+            curr.fill(UNREACHABLE);
+            let (first, _) = curr.split_at_mut(shape.states);
+            shape.relax(first, &prev[..shape.states], Edit::of(f), max_edits); // Deletion
+
+            for (j, &t) in target.iter().enumerate() {
+                let (done, next) = curr.split_at_mut((j + 1) * shape.states);
+                let cell = &mut next[..shape.states];
+                let diagonal = &prev[j * shape.states..(j + 1) * shape.states];
+                let above = &prev[(j + 1) * shape.states..(j + 2) * shape.states];
+
+                let substitution = if f == t { Edit::NONE } else { Edit::of(f).and(Edit::of(t)) };
+                shape.relax(cell, diagonal, substitution, max_edits); // Substitution
+                shape.relax(cell, &done[j * shape.states..], Edit::of(t), max_edits); // Insertion
+                shape.relax(cell, above, Edit::of(f), max_edits); // Deletion
+            }
+
+            // No state left: no alignment can get back within the limits
+            if curr.iter().all(|&total| total == UNREACHABLE) {
+                return false;
+            }
+            core::mem::swap(&mut prev, &mut curr);
+        }
+
+        prev[target.len() * shape.states..].iter().any(|&total| total != UNREACHABLE)
+    }
+
+    /// Whether substituting `field` into `target` position by position
+    /// stays within the budgets (for `require_equal_length`)
+    pub fn allow_substitutions(&self, field: &str, target: &str) -> bool {
+        let (mut digits, mut letters) = (0, 0);
+        for (f, t) in field.chars().zip(target.chars()).filter(|(f, t)| f != t) {
+            let edit = Edit::of(f).and(Edit::of(t));
+            digits += edit.digit as usize;
+            letters += edit.letter as usize;
+        }
+        self.digits.is_none_or(|max| digits <= max) && self.letters.is_none_or(|max| letters <= max)
+    }
+}
+
+/// The classes one edit involves (nothing for a match)
+#[derive(Clone, Copy)]
+struct Edit {
+    cost: usize,
+    digit: bool,
+    letter: bool,
+}
+
+impl Edit {
+    const NONE: Edit = Edit {
+        cost: 0,
+        digit: false,
+        letter: false,
+    };
+
+    /// Inserting or deleting `c`
+    fn of(c: char) -> Edit {
+        Edit {
+            cost: 1,
+            digit: c.is_numeric(),
+            letter: c.is_alphabetic(),
+        }
+    }
+
+    /// A substitution involving both chars
+    fn and(self, other: Edit) -> Edit {
+        Edit {
+            cost: 1,
+            digit: self.digit || other.digit,
+            letter: self.letter || other.letter,
+        }
+    }
+}
+
+/// Layout of the (digit edits, letter edits) states of one DP cell
+///
+/// An unlimited class isn't tracked and has a single state.
+struct Shape {
+    digits: Option<usize>,
+    letters: Option<usize>,
+    states: usize,
+}
+
+impl Shape {
+    fn new(budgets: &ClassBudgets, max_edits: usize) -> Self {
+        // Neither budget can be spent beyond the edits allowed in total
+        let digits = budgets.digits.map(|max| max.min(max_edits));
+        let letters = budgets.letters.map(|max| max.min(max_edits));
+        let states = (digits.map_or(0, |max| max) + 1) * (letters.map_or(0, |max| max) + 1);
+        Shape { digits, letters, states }
+    }
+
+    /// Extend every reachable state of `from` by `edit` into `to`, keeping
+    /// the fewest total edits per state
+    fn relax(&self, to: &mut [usize], from: &[usize], edit: Edit, max_edits: usize) {
+        let width = self.letters.map_or(1, |max| max + 1);
+        for (state, &total) in from[..self.states].iter().enumerate() {
+            if total == UNREACHABLE || total + edit.cost > max_edits {
+                continue;
+            }
+            let (mut digits, mut letters) = (state / width, state % width);
+            if edit.digit && self.digits.is_some() {
+                digits += 1;
+            }
+            if edit.letter && self.letters.is_some() {
+                letters += 1;
+            }
+            if self.digits.is_some_and(|max| digits > max)
+                || self.letters.is_some_and(|max| letters > max)
+            {
+                continue;
+            }
+
+            let next = &mut to[digits * width + letters];
+            *next = (*next).min(total + edit.cost);
+        }
+    }
+}
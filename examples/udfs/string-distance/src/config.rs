@@ -0,0 +1,119 @@
+//! The `config` parameter: every setting in one JSON object.
+
+#[cfg(feature = "params_blob")]
+use crate::blob;
+use crate::json::{self, Value};
+
+/// The parsed `config` of the current query
+///
+/// A query that sets `config` carries its settings as members of one JSON
+/// object, e.g. `{"target": "iPhone", "max_distance": 1}`, instead of as
+/// individual parameters. It is the same for every document of a query, so
+/// it is parsed once and kept until the text changes. The parameters of a
+/// `get_params_blob` blob are kept the same way, as a config of their own.
+pub struct Config {
+    text: String,
+    #[cfg(feature = "params_blob")]
+    blob: Vec<u8>,
+    settings: Option<Value>,
+}
+
+/// Why a `config` was not accepted
+pub struct Malformed;
+
+impl Config {
+    pub const fn new() -> Self {
+        Config {
+            text: String::new(),
+            #[cfg(feature = "params_blob")]
+            blob: Vec::new(),
+            settings: None,
+        }
+    }
+
+    /// Switch to the config `text` (`None` if the query has none)
+    ///
+    /// Text that isn't a JSON object still counts as a config, one without
+    /// any settings, so the query matches nothing rather than silently
+    /// falling back to the individual parameters.
+    pub fn update(&mut self, text: Option<&str>) -> Result<(), Malformed> {
+        #[cfg(feature = "params_blob")]
+        self.blob.clear();
+        let text = match text {
+            Some(text) => text,
+            None => {
+                self.text.clear();
+                self.settings = None;
+                return Ok(());
+            }
+        };
+        if self.settings.is_some() && self.text == text {
+            return Ok(());
+        }
+
+        self.text.clear();
+        self.text.push_str(text);
+        self.parse(text)
+    }
+
+    /// Switch to the parameters in `blob`, as `get_params_blob` wrote it
+    ///
+    /// They stand in for the individual parameters as a config's members
+    /// would, and a `config` string among them is the config instead, as
+    /// it would be individually. Returns `None`, leaving no config, when
+    /// the blob can't be read.
+    #[cfg(feature = "params_blob")]
+    pub fn update_blob(&mut self, blob: &[u8]) -> Option<Result<(), Malformed>> {
+        if self.settings.is_some() && !self.blob.is_empty() && self.blob == blob {
+            return Some(Ok(()));
+        }
+
+        self.text.clear();
+        self.blob.clear();
+        let params = match blob::parse(blob) {
+            Some(params) => params,
+            None => {
+                self.settings = None;
+                return None;
+            }
+        };
+        self.blob.extend_from_slice(blob);
+        if let Some(Value::String(text)) = params.get("config") {
+            return Some(self.parse(text));
+        }
+        self.settings = Some(params);
+        Some(Ok(()))
+    }
+
+    /// Take the config `text` as the settings
+    ///
+    /// Text that isn't a JSON object gives a config without settings.
+    fn parse(&mut self, text: &str) -> Result<(), Malformed> {
+        match json::parse(text) {
+            Some(settings @ Value::Object(_)) => {
+                self.settings = Some(settings);
+                Ok(())
+            }
+            _ => {
+                self.settings = Some(Value::Object(Vec::new()));
+                Err(Malformed)
+            }
+        }
+    }
+
+    /// Look up the setting `name`
+    ///
+    /// Returns `None` when there is no config, so the caller reads the
+    /// individual parameter, and `Some(None)` when the config lacks `name`.
+    pub fn get(&self, name: &str) -> Option<Option<&Value>> {
+        self.settings.as_ref().map(|settings| settings.get(name))
+    }
+
+    /// The names of the settings, `None` when there is no config
+    pub fn names(&self) -> Option<Vec<&str>> {
+        match self.settings.as_ref()? {
+            Value::Object(members) => Some(members.iter().map(|(name, _)| name.as_str()).collect()),
+            _ => Some(Vec::new()),
+        }
+    }
+}
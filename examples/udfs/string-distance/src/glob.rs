@@ -0,0 +1,155 @@
+//! Glob patterns whose literal segments each match within their own budget
+//! (`"mode": "glob"`).
+
+/// A parsed `glob` pattern
+///
+/// `*` matches any run of chars, and the literals between the stars are
+/// matched fuzzily, each within its own number of edits: "iphone{1}*13{0}"
+/// is "iphone" within one edit, anything, then exactly "13". A `{n}` may
+/// stand anywhere in its literal, and `\` makes the next char literal.
+pub struct Glob {
+    segments: Vec<Segment>,
+    /// Whether the first segment may start anywhere (the pattern starts
+    /// with `*`)
+    open_start: bool,
+    /// Whether the last segment may end anywhere (the pattern ends with `*`)
+    open_end: bool,
+}
+
+struct Segment {
+    literal: Vec<char>,
+    budget: usize,
+}
+
+impl Glob {
+    /// Parse `pattern`, giving segments without a `{n}` `default_budget`
+    ///
+    /// Returns `None` for a `{` without its `}` or digits, a segment with
+    /// two budgets, a budget on an empty segment, or a trailing `\`.
+    pub fn parse(pattern: &str, default_budget: usize) -> Option<Glob> {
+        let mut segments = Vec::new();
+        let mut literal = Vec::new();
+        let mut budget = None;
+        let mut chars = pattern.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => literal.push(chars.next()?),
+                '{' => {
+                    let mut digits = String::new();
+                    loop {
+                        match chars.next()? {
+                            '}' => break,
+                            c if c.is_ascii_digit() => digits.push(c),
+                            _ => return None,
+                        }
+                    }
+                    if budget.is_some() {
+                        return None;
+                    }
+                    budget = Some(digits.parse().ok()?);
+                }
+                '*' => {
+                    segments.push(Segment::new(&mut literal, budget.take(), default_budget)?);
+                }
+                c => literal.push(c),
+            }
+        }
+        segments.push(Segment::new(&mut literal, budget, default_budget)?);
+
+        let open_start = segments.len() > 1 && segments[0].literal.is_empty();
+        let open_end = segments.len() > 1 && segments[segments.len() - 1].literal.is_empty();
+        // Empty segments, the sides of `*`s, match nothing but the empty run
+        segments.retain(|segment| !segment.literal.is_empty());
+        // A pattern of stars only matches anything
+        let open = segments.is_empty() && pattern.contains('*');
+        Some(Glob {
+            segments,
+            open_start: open_start || open,
+            open_end: open_end || open,
+        })
+    }
+
+    /// Whether `field` matches, each segment within its budget
+    pub fn matches(&self, field: &str) -> bool {
+        self.matches_with(field, |segment| segment.budget)
+    }
+
+    /// Whether `field` matches without any edit
+    pub fn matches_exactly(&self, field: &str) -> bool {
+        self.matches_with(field, |_| 0)
+    }
+
+    /// Match the segments left to right, each one ending as early as its
+    /// budget allows
+    ///
+    /// A `*` follows every segment but the last, so whatever the previous
+    /// segment left over, the next may start at any later position: the
+    /// earliest end leaves it the most room, and no other end needs to be
+    /// tried. Each segment is one DP over the rest of the field.
+    fn matches_with(&self, field: &str, budget: impl Fn(&Segment) -> usize) -> bool {
+        let field: Vec<char> = field.chars().collect();
+        let last = match self.segments.len() {
+            0 => return self.open_start || field.is_empty(),
+            len => len - 1,
+        };
+
+        let mut start = 0;
+        for (k, segment) in self.segments.iter().enumerate() {
+            let anywhere = k > 0 || self.open_start;
+            let ends = end_distances(&field[start..], &segment.literal, anywhere);
+            let budget = budget(segment);
+            if k == last && !self.open_end {
+                return ends[ends.len() - 1] <= budget;
+            }
+            match ends.iter().position(|&d| d <= budget) {
+                Some(end) => start += end,
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+impl Segment {
+    /// The segment of `literal` (taken, leaving it empty)
+    fn new(literal: &mut Vec<char>, budget: Option<usize>, default: usize) -> Option<Segment> {
+        if literal.is_empty() && budget.is_some() {
+            return None;
+        }
+        Some(Segment {
+            literal: core::mem::take(literal),
+            budget: budget.unwrap_or(default),
+        })
+    }
+}
+
+/// For each end position in `field`, the fewest edits turning a run ending
+/// there into `literal`
+///
+/// The run starts at 0, or with `anywhere` at any position (free leading
+/// chars, as in a substring search).
+fn end_distances(field: &[char], literal: &[char], anywhere: bool) -> Vec<usize> {
+    let mut column: Vec<usize> = (0..=literal.len()).collect();
+    let mut ends = Vec::with_capacity(field.len() + 1);
+    ends.push(column[literal.len()]);
+
+    for (i, &c) in field.iter().enumerate() {
+        let mut diagonal = column[0];
+        column[0] = if anywhere { 0 } else { i + 1 };
+        for (j, &l) in literal.iter().enumerate() {
+            let cost = if c == l { 0 } else { 1 };
+            let next = core::cmp::min(
+                core::cmp::min(
+                    column[j] + 1,     // Insertion
+                    column[j + 1] + 1, // Deletion
+                ),
+                diagonal + cost,       // Substitution
+            );
+            diagonal = column[j + 1];
+            column[j + 1] = next;
+        }
+        ends.push(column[literal.len()]);
+    }
+    ends
+}
@@ -0,0 +1,160 @@
+//! Extended grapheme clusters, for `filter_units`.
+//!
+//! The extended grapheme cluster rules of UAX #29 without their tables, as
+//! `words` applies the word-boundary rules: chars are classified by ranges
+//! of the properties concerned. CR LF stays together and other controls
+//! stand alone; combining marks, joiners, variation selectors and emoji
+//! skin tones stay with the char they follow; Hangul jamo join into
+//! syllables; regional indicators pair up into flags; and a zero-width
+//! joiner between two emoji makes them one ("👨‍👩‍👧").
+
+use crate::words::is_extend;
+
+/// Grapheme-break class of a char
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Cr,
+    Lf,
+    Control,
+    /// Combining marks, variation selectors, skin tones and tags
+    Extend,
+    /// Zero-width joiner
+    Zwj,
+    RegionalIndicator,
+    /// Emoji and other pictographs a joiner can join
+    Pictographic,
+    /// Hangul leading consonant, vowel, trailing consonant, and the two
+    /// kinds of precomposed syllable
+    L,
+    V,
+    T,
+    Lv,
+    Lvt,
+    Other,
+}
+
+fn class(c: char) -> Class {
+    match c {
+        '\r' => Class::Cr,
+        '\n' => Class::Lf,
+        '\u{200D}' => Class::Zwj,
+        '\u{00AD}' | '\u{200B}' | '\u{200E}' | '\u{200F}' | '\u{2028}'..='\u{202E}'
+        | '\u{2060}'..='\u{206F}' | '\u{FEFF}' => Class::Control,
+        c if c.is_control() => Class::Control,
+        c if is_extend(c) => Class::Extend,
+        '\u{1F3FB}'..='\u{1F3FF}'   // Skin tones
+        | '\u{E0020}'..='\u{E007F}' // Tags
+        | '\u{E0100}'..='\u{E01EF}' => Class::Extend,
+        '\u{1F1E6}'..='\u{1F1FF}' => Class::RegionalIndicator,
+        '\u{1100}'..='\u{115F}' | '\u{A960}'..='\u{A97F}' => Class::L,
+        '\u{1160}'..='\u{11A7}' | '\u{D7B0}'..='\u{D7C6}' => Class::V,
+        '\u{11A8}'..='\u{11FF}' | '\u{D7CB}'..='\u{D7FB}' => Class::T,
+        '\u{AC00}'..='\u{D7A3}' if (c as u32 - 0xAC00).is_multiple_of(28) => Class::Lv,
+        '\u{AC00}'..='\u{D7A3}' => Class::Lvt,
+        c if is_pictographic(c) => Class::Pictographic,
+        _ => Class::Other,
+    }
+}
+
+/// Emoji and the symbols that share their blocks
+fn is_pictographic(c: char) -> bool {
+    matches!(c,
+        '\u{00A9}' | '\u{00AE}' | '\u{203C}' | '\u{2049}' | '\u{2122}' | '\u{2139}'
+        | '\u{2194}'..='\u{2199}'
+        | '\u{21A9}' | '\u{21AA}'
+        | '\u{231A}'..='\u{23FF}'
+        | '\u{24C2}'
+        | '\u{25A0}'..='\u{27BF}'   // Geometric shapes, dingbats
+        | '\u{2934}' | '\u{2935}'
+        | '\u{2B00}'..='\u{2BFF}'
+        | '\u{3030}' | '\u{303D}' | '\u{3297}' | '\u{3299}'
+        | '\u{1F000}'..='\u{1F1E5}'
+        | '\u{1F200}'..='\u{1FAFF}' // Pictographs, emoticons, transport
+        | '\u{1FC00}'..='\u{1FFFD}'
+    )
+}
+
+/// Extended grapheme clusters of `s`, in order
+///
+/// Every char of `s` is in exactly one cluster, so the clusters concatenate
+/// back to `s`.
+pub fn graphemes(s: &str) -> Vec<&str> {
+    let mut clusters = Vec::new();
+    let mut start = 0;
+    let mut previous = None;
+    // Regional indicators in a row up to the previous char
+    let mut indicators = 0;
+    // Whether the previous chars are a pictograph and its extenders, and
+    // whether a joiner follows them
+    let mut in_emoji = false;
+    let mut emoji_joiner = false;
+
+    for (i, c) in s.char_indices() {
+        let class = class(c);
+        if let Some(previous) = previous {
+            if breaks(previous, class, indicators, emoji_joiner) {
+                clusters.push(&s[start..i]);
+                start = i;
+            }
+        }
+        emoji_joiner = class == Class::Zwj && in_emoji;
+        in_emoji = match class {
+            Class::Pictographic => true,
+            Class::Extend => in_emoji,
+            _ => false,
+        };
+        indicators = if class == Class::RegionalIndicator { indicators + 1 } else { 0 };
+        previous = Some(class);
+    }
+    if start < s.len() {
+        clusters.push(&s[start..]);
+    }
+    clusters
+}
+
+/// Whether there is a cluster boundary between a char of class `before` and
+/// one of class `after`
+fn breaks(before: Class, after: Class, indicators: usize, emoji_joiner: bool) -> bool {
+    use Class::*;
+
+    match (before, after) {
+        (Cr, Lf) => false,
+        (Cr | Lf | Control, _) | (_, Cr | Lf | Control) => true,
+        (L, L | V | Lv | Lvt) => false,
+        (Lv | V, V | T) => false,
+        (Lvt | T, T) => false,
+        (_, Extend | Zwj) => false,
+        (Zwj, Pictographic) => !emoji_joiner,
+        // Flags are pairs: an indicator after a complete pair starts a new one
+        (RegionalIndicator, RegionalIndicator) => indicators.is_multiple_of(2),
+        _ => true,
+    }
+}
+
+/// Levenshtein distance between `s1` and `s2` counting whole clusters
+///
+/// A cluster is replaced, inserted or deleted as one edit, however many
+/// chars it has.
+pub fn grapheme_distance(s1: &str, s2: &str) -> usize {
+    let a = graphemes(s1);
+    let b = graphemes(s2);
+
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let cost = if x == y { 0 } else { 1 };
+            let next = core::cmp::min(
+                core::cmp::min(
+                    row[j] + 1,     // Insertion
+                    row[j + 1] + 1, // Deletion
+                ),
+                diagonal + cost,    // Substitution
+            );
+            diagonal = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
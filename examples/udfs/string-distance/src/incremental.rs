@@ -0,0 +1,81 @@
+//! Levenshtein DP that reuses rows across consecutive sorted field values.
+
+/// DP rows kept from the previous call of `PrefixDp::distance`
+///
+/// Row `i` of the Levenshtein DP (field chars as rows, target chars as
+/// columns) depends only on the target and the first `i` field chars. Two
+/// field values sharing a prefix of `k` chars therefore share rows `0..=k`
+/// exactly, and only the rows for the differing suffix need computing.
+pub struct PrefixDp {
+    target: Vec<char>,
+    field: Vec<char>,
+    /// Rows `0..rows.len() / (target.len() + 1)`, flattened
+    rows: Vec<usize>,
+}
+
+impl PrefixDp {
+    pub const fn new() -> Self {
+        PrefixDp {
+            target: Vec::new(),
+            field: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Levenshtein distance of `field` to `target`, reusing cached rows
+    ///
+    /// Reuses at most `hint` rows, and never more than the prefix actually
+    /// shared with the previous field; the cache is reset when the target
+    /// changes. A wrong hint therefore only costs time. As with
+    /// `levenshtein_banded`, the result is exact when at most `bound` and
+    /// otherwise only known to exceed it.
+    pub fn distance(&mut self, field: &str, target: &str, hint: usize, bound: usize) -> usize {
+        if !self.target.iter().copied().eq(target.chars()) {
+            self.target = target.chars().collect();
+            self.field.clear();
+            self.rows.clear();
+        }
+        let width = self.target.len() + 1;
+        if self.rows.is_empty() {
+            self.rows.extend(0..width);
+        }
+
+        let field: Vec<char> = field.chars().collect();
+        let cached_rows = self.rows.len() / width - 1;
+        let shared = self
+            .field
+            .iter()
+            .zip(&field)
+            .take_while(|(a, b)| a == b)
+            .count()
+            .min(hint)
+            .min(cached_rows);
+        self.rows.truncate((shared + 1) * width);
+
+        for (i, &c) in field.iter().enumerate().skip(shared) {
+            let prev = i * width;
+            self.rows.push(i + 1);
+            for (j, &t) in self.target.iter().enumerate() {
+                let cost = if c == t { 0 } else { 1 };
+                let cell = core::cmp::min(
+                    core::cmp::min(
+                        self.rows[prev + width + j] + 1, // Insertion
+                        self.rows[prev + j + 1] + 1,     // Deletion
+                    ),
+                    self.rows[prev + j] + cost,          // Substitution
+                );
+                self.rows.push(cell);
+            }
+
+            // Same early exit as levenshtein_banded; the rows so far stay cached
+            let row_min = self.rows[prev + width..].iter().copied().min().unwrap_or(0);
+            if row_min > bound {
+                self.field = field;
+                return row_min;
+            }
+        }
+
+        self.field = field;
+        self.rows[self.rows.len() - 1]
+    }
+}
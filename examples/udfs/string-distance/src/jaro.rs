@@ -0,0 +1,105 @@
+//! Jaro and Jaro-Winkler similarity.
+
+/// Winkler's prefix scaling factor
+const PREFIX_SCALE: f64 = 0.1;
+
+/// Longest common prefix rewarded by Jaro-Winkler
+const MAX_PREFIX: usize = 4;
+
+/// Jaro similarity in [0, 1]
+///
+/// Characters match when equal and no further apart than half the longer
+/// string's length (minus one); the score combines the matched fraction of
+/// each string with the number of transpositions among the matches.
+pub fn jaro_similarity(s1: &str, s2: &str) -> f64 {
+    let a: Vec<char> = s1.chars().collect();
+    let b: Vec<char> = s2.chars().collect();
+
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let window = (core::cmp::max(a.len(), b.len()) / 2).saturating_sub(1);
+    let (matches, half_transpositions) = window_matches(&a, &b, window);
+    if matches == 0 {
+        return 0.0;
+    }
+
+    let m = matches as f64;
+    let t = (half_transpositions / 2) as f64;
+    (m / a.len() as f64 + m / b.len() as f64 + (m - t) / m) / 3.0
+}
+
+/// Edit distance over Jaro-style matches within a fixed `window`
+///
+/// Characters match as in `jaro_similarity`, but no further apart than
+/// `window` positions whatever the lengths. Each char of the longer string
+/// left unmatched is one edit, and each pair of matches out of order one
+/// more (an odd one out counting as a pair): "abcd" and "abdc" are 1 apart,
+/// and "abc" and "abd" too.
+pub fn windowed_distance(s1: &str, s2: &str, window: usize) -> usize {
+    let a: Vec<char> = s1.chars().collect();
+    let b: Vec<char> = s2.chars().collect();
+
+    let (matches, half_transpositions) = window_matches(&a, &b, window);
+    a.len().max(b.len()) - matches + half_transpositions.div_ceil(2)
+}
+
+/// Similarity from `windowed_distance`, `1 - distance / max_len` in [0, 1]
+pub fn windowed_similarity(s1: &str, s2: &str, window: usize) -> f64 {
+    let max_len = s1.chars().count().max(s2.chars().count());
+    if max_len == 0 {
+        return 1.0;
+    }
+    1.0 - windowed_distance(s1, s2, window) as f64 / max_len as f64
+}
+
+/// Number of chars of `a` and `b` matched within `window` of each other,
+/// and the number of matched positions whose chars differ in order
+///
+/// Each char of `a` takes the first unmatched equal char of `b` in the
+/// window. The second count is twice the transpositions.
+fn window_matches(a: &[char], b: &[char], window: usize) -> (usize, usize) {
+    let mut b_matched = vec![false; b.len()];
+    let mut a_matches: Vec<char> = Vec::with_capacity(a.len());
+
+    for (i, &c) in a.iter().enumerate() {
+        let lo = i.saturating_sub(window);
+        let hi = core::cmp::min(i.saturating_add(window).saturating_add(1), b.len());
+        for j in lo..hi {
+            if !b_matched[j] && b[j] == c {
+                b_matched[j] = true;
+                a_matches.push(c);
+                break;
+            }
+        }
+    }
+
+    // Matched chars of `b` in order, compared pairwise with those of `a`
+    let b_matches = b.iter().zip(&b_matched).filter(|&(_, &matched)| matched);
+    let half_transpositions = a_matches
+        .iter()
+        .zip(b_matches)
+        .filter(|&(x, (y, _))| x != y)
+        .count();
+    (a_matches.len(), half_transpositions)
+}
+
+/// Jaro-Winkler similarity in [0, 1]
+///
+/// Boosts the Jaro score for strings sharing a common prefix of up to
+/// `MAX_PREFIX` chars.
+pub fn jaro_winkler_similarity(s1: &str, s2: &str) -> f64 {
+    let jaro = jaro_similarity(s1, s2);
+    let prefix = s1
+        .chars()
+        .zip(s2.chars())
+        .take(MAX_PREFIX)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    jaro + prefix as f64 * PREFIX_SCALE * (1.0 - jaro)
+}
@@ -23,7 +23,94 @@
 //! This will match documents where `product_name` differs from "iPhone"
 //! by at most 2 character edits (insertions, deletions, or substitutions).
 
+// The module runs single-threaded inside its wasm instance, and its buffers
+// are statics so that they stay out of the instance's small stack.
+#![allow(static_mut_refs)]
+
 use core::slice;
+use std::borrow::Cow;
+
+mod algorithm;
+#[cfg(feature = "arrays")]
+mod array;
+#[cfg(feature = "params_blob")]
+mod blob;
+mod budget;
+mod classes;
+mod config;
+mod glob;
+mod graphemes;
+mod incremental;
+#[cfg(feature = "jaro")]
+mod jaro;
+mod json;
+mod memo;
+mod modes;
+mod multi;
+#[cfg(feature = "ngram")]
+mod ngram;
+mod normalize;
+#[cfg(feature = "arrays")]
+mod pairing;
+mod partial;
+#[cfg(feature = "phonetic")]
+mod phonetic;
+mod regex;
+mod repeat;
+mod sample;
+mod script;
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(feature = "stream")]
+mod stream;
+#[cfg(feature = "substring")]
+mod substring;
+mod token;
+mod translit;
+mod variants;
+mod weighted;
+mod words;
+#[cfg(test)]
+mod tests;
+
+use algorithm::{Algorithm, Limit};
+use classes::ClassBudgets;
+use config::{Config, Malformed};
+use glob::Glob;
+use graphemes::grapheme_distance;
+use incremental::PrefixDp;
+#[cfg(feature = "jaro")]
+use jaro::{jaro_winkler_similarity, windowed_distance};
+use memo::FieldMemo;
+use modes::{
+    date_distance, email_distance, name_order_distance, normalize_url, numeric_typo_distance,
+    pattern_distance, phone_distance, suffix_distance, token_prefix_distance,
+};
+use multi::SharedField;
+#[cfg(feature = "ngram")]
+use ngram::{gram_idf, GramIdf, TargetGrams};
+use normalize::{Normalization, Overflow, Pipeline, Step, TargetCache, UnknownChars};
+#[cfg(feature = "arrays")]
+use pairing::{pairing_cost, MAX_ELEMENTS};
+use partial::{occurrences, stepped_window, Stepping};
+#[cfg(feature = "phonetic")]
+use phonetic::phonetic_key_match;
+use regex::Regex;
+use repeat::extra_copies;
+use sample::sampled;
+use script::scripts_differ;
+#[cfg(feature = "stream")]
+use stream::StreamingLevenshtein;
+#[cfg(feature = "substring")]
+use substring::{lcstr_match, lcstr_similarity};
+use token::Tokenizer;
+#[cfg(all(feature = "token", feature = "arrays"))]
+use token::token_set_ratio_of;
+#[cfg(feature = "token")]
+use token::Idf;
+use translit::{Table, Transliteration};
+use variants::spelling_variants;
+use weighted::{substitution_distance, weighted_levenshtein, within_bound, Costs, SubCosts};
 
 // Host function imports
 extern "C" {
@@ -31,7 +118,7 @@ extern "C" {
     fn has_field(ctx_id: i64, field_ptr: *const u8, field_len: i32) -> i32;
 
     /// Get a string field value from the document
-    /// Returns the length of the value written, or -1 on error
+    /// Writes the value length, returns 1 on success and 0 if not found
     fn get_field_string(
         ctx_id: i64,
         field_ptr: *const u8,
@@ -40,9 +127,55 @@ extern "C" {
         value_len_ptr: *mut i32,
     ) -> i32;
 
+    /// Get the ID of the document
+    /// Writes the ID length, returns 1 on success and 0 if there is none
+    fn get_document_id(ctx_id: i64, id_ptr: *mut u8, id_len_ptr: *mut i32) -> i32;
+
+    /// Get up to `buf_len` bytes of a string field starting at byte `offset`
+    /// Writes the chunk length (0 at the end of the value), returns 0 on success
+    #[cfg(feature = "stream")]
+    fn get_field_chunk(
+        ctx_id: i64,
+        field_ptr: *const u8,
+        field_len: i32,
+        offset: i32,
+        buf_ptr: *mut u8,
+        buf_len: i32,
+        out_len_ptr: *mut i32,
+    ) -> i32;
+
+    /// Get a field holding an array of strings, encoded as in the `array`
+    /// module (codes and lengths as for `get_param_string`, with 2 when the
+    /// field isn't an array of strings)
+    #[cfg(feature = "arrays")]
+    fn get_field_array(
+        ctx_id: i64,
+        field_ptr: *const u8,
+        field_len: i32,
+        value_ptr: *mut u8,
+        value_len_ptr: *mut i32,
+    ) -> i32;
+
+    /// Get the share of documents in [0, 1] whose `field` holds this
+    /// document's value; returns 0 on success, 1 when it isn't known
+    #[cfg(feature = "doc_freq")]
+    fn get_field_doc_freq(
+        ctx_id: i64,
+        field_ptr: *const u8,
+        field_len: i32,
+        out_ptr: *mut f64,
+    ) -> i32;
+
     /// Get an integer parameter from the query
     fn get_param_i64(name_ptr: *const u8, name_len: i32, out_ptr: *mut i64) -> i32;
 
+    /// Get a float parameter from the query
+    #[cfg(feature = "f64_params")]
+    fn get_param_f64(name_ptr: *const u8, name_len: i32, out_ptr: *mut f64) -> i32;
+
+    /// Get a boolean parameter from the query (written as 0 or 1)
+    fn get_param_bool(name_ptr: *const u8, name_len: i32, out_ptr: *mut i32) -> i32;
+
     /// Get a string parameter from the query
     fn get_param_string(
         name_ptr: *const u8,
@@ -51,34 +184,330 @@ extern "C" {
         value_len_ptr: *mut i32,
     ) -> i32;
 
+    /// Get an array-of-strings parameter from the query, encoded as for
+    /// `get_field_array`
+    #[cfg(feature = "arrays")]
+    fn get_param_array(
+        name_ptr: *const u8,
+        name_len: i32,
+        value_ptr: *mut u8,
+        value_len_ptr: *mut i32,
+    ) -> i32;
+
+    /// Get the names of the query's parameters as a JSON array of strings
+    /// (codes and lengths as for `get_param_string`)
+    #[cfg(feature = "param_names")]
+    fn get_param_names(value_ptr: *mut u8, value_len_ptr: *mut i32) -> i32;
+
+    /// Get all of the query's parameters in one binary blob (format in the
+    /// `blob` module; codes and lengths as for `get_param_string`, with 1
+    /// when the host doesn't pass them this way)
+    #[cfg(feature = "params_blob")]
+    fn get_params_blob(out_ptr: *mut u8, out_len_ptr: *mut i32) -> i32;
+
+    /// Read a monotonic clock in nanoseconds (for profiling)
+    #[cfg(feature = "profile")]
+    fn get_time_ns() -> i64;
+
+    /// Report a counter of the call that just finished (for `stats`)
+    #[cfg(feature = "stats")]
+    fn emit_stat(key_ptr: *const u8, key_len: i32, value: i64);
+
     /// Log a message (for debugging)
     fn log(level: i32, msg_ptr: *const u8, msg_len: i32);
 }
 
+// Host imports this build links against (see `udf_required_imports`)
+const REQUIRED_IMPORTS: &[&str] = &[
+    "has_field",
+    "get_field_string",
+    "get_document_id",
+    #[cfg(feature = "stream")]
+    "get_field_chunk",
+    #[cfg(feature = "arrays")]
+    "get_field_array",
+    #[cfg(feature = "doc_freq")]
+    "get_field_doc_freq",
+    "get_param_i64",
+    #[cfg(feature = "f64_params")]
+    "get_param_f64",
+    "get_param_bool",
+    "get_param_string",
+    #[cfg(feature = "arrays")]
+    "get_param_array",
+    #[cfg(feature = "param_names")]
+    "get_param_names",
+    #[cfg(feature = "params_blob")]
+    "get_params_blob",
+    #[cfg(feature = "profile")]
+    "get_time_ns",
+    #[cfg(feature = "stats")]
+    "emit_stat",
+    "log",
+];
+
+// Every parameter some export reads, for `strict_params`
+const KNOWN_PARAMS: &[&str] = &[
+    "algorithm", "all_targets", "alternation_delimiter", "case_insensitive", "case_mismatch_cost",
+    "cell_budget", "collapse_whitespace", "components", "concat_fields", "concat_separator",
+    "config", "country_code", "doc_freq_weight", "empty_field_behavior", "empty_target_matches",
+    "field", "field_char_end", "field_char_start", "field_extract_pattern", "fields", "fold_table",
+    "fold_width", "idf", "ignore_accents", "length_penalty", "mask_digits", "match_policy",
+    "match_window", "max_array_elements", "max_digit_edits", "max_distance", "max_domain",
+    "max_letter_edits", "max_local", "max_missing", "max_target_len", "max_targets", "max_total",
+    "max_total_distance", "min_common_len", "min_margin", "min_match_prefix", "min_similarity",
+    "min_target_len_for_fuzzy", "mixed_script_penalty", "mode", "negate", "ngram_idf", "ngram_size",
+    "ngram_weight", "normalization_pipeline", "normalization_variants", "pad_ngrams", "pattern",
+    "penalize_repeats", "phonetic_tiebreak", "phonetic_weight", "position_decay",
+    "position_weighting", "profile", "redact_logs", "repeat_cost", "require_equal_length",
+    "require_prefix_len", "script_mismatch_penalty", "self_test_bool", "self_test_f64",
+    "self_test_i64", "self_test_string", "share_field_decoding", "short_length_penalty", "stats",
+    "stream_field", "strict_params", "strip_prefixes", "strip_suffixes", "sub_costs", "target",
+    "target_field", "target_fields", "target_sample_rate", "target_separator", "target_tokens",
+    "target_variants", "target_weights", "targets", "threshold", "threshold_kind", "token_pattern",
+    "tokenizer", "tokens_field", "transliterate", "unit", "unknown_char_placeholder",
+    "unknown_char_policy", "unmatched_penalty", "variant_cost", "vote_threshold", "window_overlap",
+    "window_refine", "window_step",
+];
+
 // Memory buffer for string operations
 static mut BUFFER: [u8; 1024] = [0; 1024];
 static mut TARGET_BUFFER: [u8; 256] = [0; 256];
+// Targets too long for TARGET_BUFFER, up to `max_target_len`
+static mut TARGET_HEAP: Vec<u8> = Vec::new();
+static mut MODE_BUFFER: [u8; 32] = [0; 32];
+static mut ALGORITHM_BUFFER: [u8; 32] = [0; 32];
+// Structured (JSON) parameters, parsed right after reading
+static mut PARAM_BUFFER: [u8; 1024] = [0; 1024];
+// Structured parameters too long for PARAM_BUFFER
+static mut PARAM_HEAP: Vec<u8> = Vec::new();
+// Arrays read with `get_field_array` or `get_param_array`
+#[cfg(feature = "arrays")]
+static mut ARRAY_BUFFER: Vec<u8> = Vec::new();
+// Comma-separated REQUIRED_IMPORTS, built by `udf_required_imports`
+static mut REQUIRED_IMPORTS_LIST: String = String::new();
+// DP rows kept between `filter_sorted` calls
+static mut SORTED_DP: PrefixDp = PrefixDp::new();
+// Normalized target kept between calls of the same query
+static mut NORMALIZED_TARGET: TargetCache = TargetCache::new();
+// Normalized target per `normalization_variants` entry, kept the same way
+static mut VARIANT_TARGETS: Vec<TargetCache> = Vec::new();
+// Hashed target n-grams kept between calls of the same query
+#[cfg(feature = "ngram")]
+static mut TARGET_GRAMS: TargetGrams = TargetGrams::new();
+// Field values and normalized forms kept between calls for one document
+static mut FIELD_MEMO: FieldMemo = FieldMemo::new();
+// Last `transliterate` map read
+static mut TRANSLITERATION: Table = Table::new();
+// Last `fold_table` read
+static mut FOLD_TABLE: Table = Table::new();
+// Whether the last `filter` match was at distance 0, for `filter_ex`
+static mut EXACT_MATCH: bool = false;
+// What decided the last comparison `filter` made, for `filter_reason`
+static mut MATCH_REASON: MatchReason = MatchReason::NotCompared;
+// The class the last `"vote"`-mode `filter` elected, for `filter_vote`
+static mut VOTE_CLASS: i32 = -1;
+// Whether a host import broke its contract during the current export call
+static mut HOST_FAULT: bool = false;
+// Whether a parameter was rejected during the current export call
+static mut PARAM_REJECTED: bool = false;
+// The `config` parameter's text as read, and its parsed settings
+static mut CONFIG_BUFFER: Vec<u8> = Vec::new();
+static mut CONFIG: Config = Config::new();
+// Whether the parameters changed since `load_config` last read them, `None`
+// for a host that never calls `params_changed`
+static mut PARAMS_CHANGED: Option<bool> = None;
+// Whether the query's `targets` share one decoding of the field, once
+// worked out for the query (see `shares_field_decoding`)
+static mut SHARED_DECODING: Option<bool> = None;
+// The `get_params_blob` blob as read
+#[cfg(feature = "params_blob")]
+static mut BLOB_BUFFER: Vec<u8> = Vec::new();
+
+// Log levels passed to the host for diagnostics
+const LOG_DEBUG: i32 = 0;
+const LOG_WARN: i32 = 2;
+const LOG_ERROR: i32 = 3;
+
+// Returned instead of 0/1 when the query can't be evaluated as given
+const DIAG_INVALID: i32 = -1;
+// Returned when an operand can't be decoded: a normalized operand outgrows
+// the buffer it was read into, or a host import breaks its contract
+const DIAG_OVERFLOW: i32 = -2;
+// Returned when an output buffer the host passed can't hold the result
+const DIAG_TOO_SMALL: i32 = -3;
+
+// Default cap on the `target` length in bytes (`max_target_len`)
+const DEFAULT_MAX_TARGET_LEN: usize = 4096;
+// Default cap on the number of `targets` (`max_targets`)
+const DEFAULT_MAX_TARGETS: usize = 10_000;
+// Default cap on the elements of an array operand (`max_array_elements`)
+#[cfg(feature = "arrays")]
+const DEFAULT_MAX_ARRAY_ELEMENTS: usize = 1_000;
+
+// Longest target (in chars) handled by the stack-only fast path
+const SHORT_TARGET_MAX: usize = 32;
 
 /// Calculate Levenshtein distance between two strings
 fn levenshtein_distance(s1: &str, s2: &str) -> usize {
+    // Every distance is within usize::MAX
+    levenshtein_within(s1, s2, usize::MAX).unwrap_or(usize::MAX)
+}
+
+/// Levenshtein distance if it is at most `max`
+///
+/// Returns `Some(distance)` when the distance is within `max` and `None`
+/// otherwise, doing as little work as possible to find out:
+/// - strings whose lengths differ by more than `max` are rejected outright,
+///   since each edit changes the length by at most one
+/// - the common prefix and suffix are stripped, as they never change the
+///   distance
+/// - the rest goes through a DP restricted to the band of cells within
+///   `max` of the diagonal, stopping once a whole row exceeds `max`
+pub fn levenshtein_within(s1: &str, s2: &str, max: usize) -> Option<usize> {
+    let len1 = s1.chars().count();
+    let len2 = s2.chars().count();
+    if len1.abs_diff(len2) > max {
+        #[cfg(feature = "stats")]
+        stats::early_exit();
+        return None;
+    }
+
+    // Shared affixes are byte-identical, so one byte length fits both
+    let prefix: usize = s1
+        .chars()
+        .zip(s2.chars())
+        .take_while(|(c1, c2)| c1 == c2)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    let (s1, s2) = (&s1[prefix..], &s2[prefix..]);
+    let suffix: usize = s1
+        .chars()
+        .rev()
+        .zip(s2.chars().rev())
+        .take_while(|(c1, c2)| c1 == c2)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    let s1 = &s1[..s1.len() - suffix];
+    let s2 = &s2[..s2.len() - suffix];
+
     let len1 = s1.chars().count();
     let len2 = s2.chars().count();
+    let distance = if len1 == 0 || len2 == 0 {
+        len1.max(len2)
+    } else if len2 <= SHORT_TARGET_MAX && len1 < u16::MAX as usize {
+        // Short targets fit in fixed stack rows; u16 cells hold any distance
+        // as long as the field is shorter than u16::MAX chars
+        levenshtein_short(s1, s2, max)
+    } else {
+        // Already `None` over `max`, never a sentinel passed off as a distance
+        return levenshtein_banded(s1, s2, len1, len2, max);
+    };
+
+    if distance <= max {
+        Some(distance)
+    } else {
+        None
+    }
+}
+
+/// Banded 2-row Levenshtein DP for `levenshtein_within`
+///
+/// Only cells within `band = min(bound, max(len1, len2))` of the diagonal
+/// are computed; the cells outside it are held at the sentinel `band + 1`.
+/// A cell `d` off the diagonal is at least `d` edits, so the sentinel never
+/// exceeds the true value of the cell it stands for: a computed cell is
+/// exact when its true value is at most `band`, and over `band` otherwise,
+/// never at or under it. (When `band < bound` no cell is outside the band.)
+/// The minimum of a DP row never decreases from one row to the next and
+/// the distance is at least that minimum, so as soon as a whole row exceeds
+/// `bound` the remaining rows are skipped. Returns the exact distance when
+/// it is at most `bound` and `None` otherwise, so a sentinel (or a value
+/// derived from one) is never mistaken for a distance. `len1`/`len2` are
+/// the char counts of `s1`/`s2`, which differ by at most `bound`.
+fn levenshtein_banded(
+    s1: &str,
+    s2: &str,
+    len1: usize,
+    len2: usize,
+    bound: usize,
+) -> Option<usize> {
+    let target: Vec<char> = s2.chars().collect();
+    let band = bound.min(len1.max(len2));
+    let outside = band + 1;
+
+    // Cells past a row's band are never written, so they stay `outside`
+    let mut prev_row: Vec<usize> = (0..=len2)
+        .map(|j| if j <= band { j } else { outside })
+        .collect();
+    let mut curr_row: Vec<usize> = vec![outside; len2 + 1];
+
+    for (i, c1) in s1.chars().enumerate() {
+        let row = i + 1;
+        let lo = row.saturating_sub(band).max(1);
+        let hi = (row + band).min(len2);
+
+        curr_row[lo - 1] = if row <= band { row } else { outside };
+        #[cfg(feature = "stats")]
+        stats::dp_cells(hi + 1 - lo);
+        for j in lo..=hi {
+            let cost = if c1 == target[j - 1] { 0 } else { 1 };
+
+            curr_row[j] = core::cmp::min(
+                core::cmp::min(
+                    curr_row[j - 1] + 1,  // Insertion
+                    prev_row[j] + 1,      // Deletion
+                ),
+                prev_row[j - 1] + cost,   // Substitution
+            );
+        }
+
+        // Row minimum above the bound: the result can't get back under it
+        let row_min = curr_row[lo - 1..=hi].iter().copied().min().unwrap_or(0);
+        if row_min > bound {
+            #[cfg(feature = "stats")]
+            stats::early_exit();
+            return None;
+        }
+
+        // Swap rows
+        core::mem::swap(&mut prev_row, &mut curr_row);
+    }
 
-    if len1 == 0 {
-        return len2;
+    let distance = prev_row[len2];
+    if distance <= bound {
+        Some(distance)
+    } else {
+        None
     }
-    if len2 == 0 {
-        return len1;
+}
+
+/// Levenshtein distance for targets of at most `SHORT_TARGET_MAX` chars
+///
+/// Same bounded 2-row DP as `levenshtein_banded`, over full rows, but the
+/// rows and the decoded target live in fixed stack arrays, so no allocation
+/// and no shared buffer is involved. Callers must ensure `s2` fits and `s1`
+/// has fewer than `u16::MAX` chars.
+fn levenshtein_short(s1: &str, s2: &str, bound: usize) -> usize {
+    let mut target = ['\0'; SHORT_TARGET_MAX];
+    let mut len2 = 0;
+    for c in s2.chars() {
+        target[len2] = c;
+        len2 += 1;
     }
 
-    // Use a 2-row approach to save memory
-    let mut prev_row: Vec<usize> = (0..=len2).collect();
-    let mut curr_row: Vec<usize> = vec![0; len2 + 1];
+    let mut prev_row = [0u16; SHORT_TARGET_MAX + 1];
+    let mut curr_row = [0u16; SHORT_TARGET_MAX + 1];
+    for (j, cell) in prev_row.iter_mut().enumerate().take(len2 + 1) {
+        *cell = j as u16;
+    }
 
     for (i, c1) in s1.chars().enumerate() {
-        curr_row[0] = i + 1;
+        curr_row[0] = (i + 1) as u16;
+        #[cfg(feature = "stats")]
+        stats::dp_cells(len2);
 
-        for (j, c2) in s2.chars().enumerate() {
+        for (j, &c2) in target[..len2].iter().enumerate() {
             let cost = if c1 == c2 { 0 } else { 1 };
 
             curr_row[j + 1] = core::cmp::min(
@@ -90,15 +519,285 @@ fn levenshtein_distance(s1: &str, s2: &str) -> usize {
             );
         }
 
-        // Swap rows
+        let row_min = curr_row[..=len2].iter().copied().min().unwrap_or(0) as usize;
+        if row_min > bound {
+            #[cfg(feature = "stats")]
+            stats::early_exit();
+            return row_min;
+        }
+
         core::mem::swap(&mut prev_row, &mut curr_row);
     }
 
-    prev_row[len2]
+    prev_row[len2] as usize
+}
+
+/// Check whether two strings share their first `k` chars
+///
+/// Strings shorter than `k` are compared as a whole, so "ab" shares any
+/// prefix length with "ab" but not with "abc".
+fn shares_prefix(s1: &str, s2: &str, k: usize) -> bool {
+    s1.chars().take(k).eq(s2.chars().take(k))
+}
+
+/// Length in chars of the longest common prefix of two strings
+fn common_prefix_len(s1: &str, s2: &str) -> usize {
+    s1.chars().zip(s2.chars()).take_while(|(c1, c2)| c1 == c2).count()
+}
+
+/// The chars `[start, end)` of `s`
+///
+/// Indices past the end are clamped to it, and `start >= end` gives "".
+fn char_range(s: &str, start: usize, end: usize) -> &str {
+    let byte_offset = |n: usize| s.char_indices().nth(n).map_or(s.len(), |(i, _)| i);
+    if start >= end {
+        return "";
+    }
+    &s[byte_offset(start)..byte_offset(end)]
+}
+
+/// N-gram Dice coefficient of a field and the query's target, whose n-grams
+/// are hashed once per query rather than once per document
+#[cfg(feature = "ngram")]
+fn target_dice_similarity(
+    field: &str,
+    target: &str,
+    n: usize,
+    pad: bool,
+    idf: Option<&GramIdf>,
+) -> f64 {
+    unsafe { TARGET_GRAMS.dice_similarity(field, target, n, pad, idf) }
+}
+
+/// The query target's n-grams missing from a field, and how many it has
+/// (see `TargetGrams::missing`)
+#[cfg(feature = "ngram")]
+fn target_missing_grams(field: &str, target: &str, n: usize, pad: bool) -> (usize, usize) {
+    unsafe { TARGET_GRAMS.missing(field, target, n, pad) }
+}
+
+/// Normalized Levenshtein similarity in [0, 1]
+///
+/// `1 - distance / max_len`, where identical strings (including two empty
+/// strings) score 1.0.
+fn levenshtein_similarity(s1: &str, s2: &str) -> f64 {
+    let max_len = core::cmp::max(s1.chars().count(), s2.chars().count());
+    if max_len == 0 {
+        return 1.0;
+    }
+
+    1.0 - levenshtein_distance(s1, s2) as f64 / max_len as f64
+}
+
+/// Levenshtein similarity discounted for short strings, in [0, 1]
+///
+/// `1 - distance / max_len * (1 + penalty / max_len)`, clamped at 0. The
+/// extra factor charges each edit more the shorter the strings are, so one
+/// edit in 3 chars (0.33 with the default `penalty` of 3) counts for far
+/// more than one in 30 (0.96), while identical strings still score 1.0.
+fn length_confidence(s1: &str, s2: &str, penalty: f64) -> f64 {
+    let max_len = core::cmp::max(s1.chars().count(), s2.chars().count());
+    if max_len == 0 {
+        return 1.0;
+    }
+
+    let max_len = max_len as f64;
+    let edits = levenshtein_distance(s1, s2) as f64 / max_len;
+    (1.0 - edits * (1.0 + penalty / max_len)).max(0.0)
+}
+
+/// `similarity` less `penalty` times the share of the longer string's chars
+/// the shorter one lacks, clamped at 0
+///
+/// `similarity - penalty * |len1 - len2| / max_len`: equal lengths keep
+/// their similarity, and a field twice the target's length loses half the
+/// penalty, however few edits apart the two are.
+fn length_penalized(similarity: f64, s1: &str, s2: &str, penalty: f64) -> f64 {
+    let (len1, len2) = (s1.chars().count(), s2.chars().count());
+    let max_len = core::cmp::max(len1, len2);
+    if max_len == 0 {
+        return similarity;
+    }
+
+    let gap = len1.abs_diff(len2) as f64 / max_len as f64;
+    (similarity - penalty * gap).max(0.0)
+}
+
+/// Read `length_penalty` (default 0), `None` unless finite and non-negative
+unsafe fn get_length_penalty() -> Option<f64> {
+    match get_f64_param("length_penalty").unwrap_or(0.0) {
+        penalty if penalty.is_finite() && penalty >= 0.0 => Some(penalty),
+        _ => None,
+    }
+}
+
+/// Helper to log a debug message through the host
+unsafe fn log_debug(msg: &str) {
+    log(LOG_DEBUG, msg.as_ptr(), msg.len() as i32);
+}
+
+/// Helper to log a warning through the host
+unsafe fn log_warn(msg: &str) {
+    log(LOG_WARN, msg.as_ptr(), msg.len() as i32);
+}
+
+/// Helper to log an error through the host
+unsafe fn log_error(msg: &str) {
+    log(LOG_ERROR, msg.as_ptr(), msg.len() as i32);
+}
+
+/// An operand quoted for a log message, or only its length with `redact_logs`
+///
+/// Field values and targets may be PII the host only hands over truncated
+/// or normalized; with `"redact_logs": true` no message may carry their
+/// contents, only lengths and distances. Every message that mentions an
+/// operand renders it through here.
+unsafe fn loggable(text: &str) -> String {
+    if get_bool_param("redact_logs") == Some(true) {
+        format!("({} bytes)", text.len())
+    } else {
+        format!("\"{}\"", text)
+    }
+}
+
+/// Run an export's `body`, returning `fault` instead of its result when a
+/// host import broke its contract meanwhile, or `invalid` when a parameter
+/// it read was rejected
+///
+/// Every export but `self_test` (which checks the imports itself) and
+/// `udf_required_imports` runs through this, so a misread length or code
+/// never turns into a result, and neither does a value no reader accepts.
+unsafe fn with_host_checks<T>(fault: T, invalid: T, body: impl FnOnce() -> T) -> T {
+    HOST_FAULT = false;
+    PARAM_REJECTED = false;
+    load_config();
+    let result = body();
+    if HOST_FAULT || PARAM_REJECTED {
+        EXACT_MATCH = false;
+        MATCH_REASON = MatchReason::NotCompared;
+        VOTE_CLASS = -1;
+        return if HOST_FAULT { fault } else { invalid };
+    }
+    result
+}
+
+/// Record that `import` broke its contract, logging the first violation of
+/// the call
+///
+/// The helpers read what it returned as unset, and `with_host_checks`
+/// turns the export's result into its error value.
+unsafe fn host_fault(import: &str, violation: &str) {
+    if !HOST_FAULT {
+        log_error(&format!("host import {} {}", import, violation));
+    }
+    HOST_FAULT = true;
+}
+
+/// Record that the query's parameter can't be used, logging the first
+/// rejection of the call
+///
+/// The reader returns it as unset, and `with_host_checks` turns the
+/// export's result into its invalid value.
+unsafe fn reject_param(message: &str) {
+    if !PARAM_REJECTED {
+        log_error(message);
+    }
+    PARAM_REJECTED = true;
+}
+
+/// What a string import wrote into a `capacity`-byte buffer
+enum Written {
+    /// The value, this many bytes long
+    Value(usize),
+    /// Nothing: the value needs a buffer of this many bytes
+    TooSmall(usize),
+    /// Nothing: not found, not a string, or a contract violation
+    Unset,
+}
+
+/// Check the `result` and `len` of a string import against its contract
+///
+/// 0 must come with a length that fits the buffer and 3 with one that
+/// doesn't; 1 (not found) and 2 (not a string) write nothing, and any
+/// other code is a violation.
+unsafe fn written(import: &str, result: i32, len: i32, capacity: usize) -> Written {
+    match (result, usize::try_from(len)) {
+        (0, Ok(len)) if len <= capacity => Written::Value(len),
+        (3, Ok(len)) if len > capacity => Written::TooSmall(len),
+        (1 | 2, _) => Written::Unset,
+        (0 | 3, _) => {
+            let violation =
+                format!("returned {} with length {} for {} bytes", result, len, capacity);
+            host_fault(import, &violation);
+            Written::Unset
+        }
+        _ => {
+            host_fault(import, &format!("returned unknown code {}", result));
+            Written::Unset
+        }
+    }
+}
+
+/// Check the `result` and `len` of `get_field_string` or
+/// `get_document_id` against their contract
+///
+/// Unlike the parameter imports, 1 means the value was written, with a
+/// length that must fit the buffer, and 0 that nothing was; any other code
+/// is a violation. Only a written value's length comes back, so a call that
+/// wrote nothing never reads what an earlier one left in the buffer.
+unsafe fn field_written(import: &str, result: i32, len: i32, capacity: usize) -> Option<usize> {
+    match (result, usize::try_from(len)) {
+        (1, Ok(len)) if len <= capacity => Some(len),
+        (0, _) => None,
+        (1, _) => {
+            let violation = format!("returned 1 with length {} for {} bytes", len, capacity);
+            host_fault(import, &violation);
+            None
+        }
+        _ => {
+            host_fault(import, &format!("returned unknown code {}", result));
+            None
+        }
+    }
+}
+
+/// Whether a scalar import's `result` says it wrote a value
+///
+/// 1 (not found), 2 (wrong type) and 3 (write error) didn't; any other
+/// nonzero code is a violation.
+unsafe fn scalar_written(import: &str, result: i32) -> bool {
+    match result {
+        0 => true,
+        1..=3 => false,
+        _ => {
+            host_fault(import, &format!("returned unknown code {}", result));
+            false
+        }
+    }
+}
+
+/// Whether `has_field` says the field exists
+unsafe fn field_exists(ctx_id: i64, field_name: &str) -> bool {
+    match has_field(ctx_id, field_name.as_ptr(), field_name.len() as i32) {
+        0 => false,
+        1 => true,
+        result => {
+            host_fault("has_field", &format!("returned {}, not 0 or 1", result));
+            false
+        }
+    }
 }
 
 /// Helper to get a string parameter
-unsafe fn get_string_param(name: &str, buffer: &mut [u8]) -> Option<&str> {
+unsafe fn get_string_param<'a>(name: &str, buffer: &'a mut [u8]) -> Option<&'a str> {
+    if let Some(setting) = CONFIG.get(name) {
+        return config_string(setting?, buffer);
+    }
+    // Nothing could be written, can't read it
+    if buffer.is_empty() {
+        return None;
+    }
+
     let mut len = buffer.len() as i32;
     let result = get_param_string(
         name.as_ptr(),
@@ -107,109 +806,4682 @@ unsafe fn get_string_param(name: &str, buffer: &mut [u8]) -> Option<&str> {
         &mut len,
     );
 
-    if result != 0 || len <= 0 {
-        return None;
+    // Too long for `buffer` reads as unset
+    match written("get_param_string", result, len, buffer.len()) {
+        Written::Value(len) if len > 0 => core::str::from_utf8(&buffer[..len]).ok(),
+        _ => None,
     }
+}
 
-    core::str::from_utf8(&buffer[..len as usize]).ok()
+/// Copy a `config` setting into `buffer`, as `get_string_param` reads it
+///
+/// Text too long for `buffer` reads as unset, as it would from the host
+/// (see `config_text` for the rest).
+fn config_string<'a>(setting: &json::Value, buffer: &'a mut [u8]) -> Option<&'a str> {
+    let text = config_text(setting)?;
+    let out = buffer.get_mut(..text.len())?;
+    out.copy_from_slice(text.as_bytes());
+    core::str::from_utf8(out).ok()
 }
 
-/// Helper to get an i64 parameter
-unsafe fn get_i64_param(name: &str) -> Option<i64> {
-    let mut value: i64 = 0;
-    let result = get_param_i64(
+/// A `config` setting as the text of a string parameter
+///
+/// Strings are taken as they are, and arrays and objects as their JSON
+/// text, which is how individual parameters pass them (e.g. `targets`).
+/// Other types and empty strings read as unset.
+fn config_text(setting: &json::Value) -> Option<Cow<'_, str>> {
+    let text = match setting {
+        json::Value::String(text) => Cow::Borrowed(text.as_str()),
+        json::Value::Array(_) | json::Value::Object(_) => Cow::Owned(setting.to_json()),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+/// Read a structured (JSON) parameter such as `targets`, in full or not at
+/// all
+///
+/// Text that doesn't fit `PARAM_BUFFER` is re-read into `PARAM_HEAP`, as
+/// `get_target_param` does for long targets: read as unset, a long
+/// `targets` list would quietly give way to `target`.
+unsafe fn get_json_param(name: &str) -> Option<&'static str> {
+    if let Some(setting) = CONFIG.get(name) {
+        let text = config_text(setting?)?;
+        let out = match PARAM_BUFFER.get_mut(..text.len()) {
+            Some(out) => out,
+            None => {
+                PARAM_HEAP.resize(text.len(), 0);
+                &mut PARAM_HEAP[..]
+            }
+        };
+        out.copy_from_slice(text.as_bytes());
+        return core::str::from_utf8(out).ok();
+    }
+
+    let mut len = PARAM_BUFFER.len() as i32;
+    let result = get_param_string(
         name.as_ptr(),
         name.len() as i32,
-        &mut value,
+        PARAM_BUFFER.as_mut_ptr(),
+        &mut len,
     );
+    let needed = match written("get_param_string", result, len, PARAM_BUFFER.len()) {
+        Written::Value(len) if len > 0 => return core::str::from_utf8(&PARAM_BUFFER[..len]).ok(),
+        Written::Value(_) | Written::Unset => return None,
+        Written::TooSmall(needed) => needed,
+    };
 
-    if result == 0 {
-        Some(value)
-    } else {
-        None
+    PARAM_HEAP.resize(needed, 0);
+    let mut len = needed as i32;
+    let result = get_param_string(
+        name.as_ptr(),
+        name.len() as i32,
+        PARAM_HEAP.as_mut_ptr(),
+        &mut len,
+    );
+    match written("get_param_string", result, len, PARAM_HEAP.len()) {
+        Written::Value(len) if len > 0 => core::str::from_utf8(&PARAM_HEAP[..len]).ok(),
+        _ => None,
     }
 }
 
-/// Helper to get a field value as string
-unsafe fn get_field(ctx_id: i64, field_name: &str, buffer: &mut [u8]) -> Option<&str> {
-    // First check if field exists
-    let has = has_field(ctx_id, field_name.as_ptr(), field_name.len() as i32);
-    if has == 0 {
-        return None;
+/// Read the `config` parameter, if any, into `CONFIG`
+///
+/// Every export calls this first (see `with_host_checks`). With a
+/// `config` set, the parameter
+/// helpers answer from its members and skip the host calls; without one
+/// they read the individual parameters as usual. A build with the
+/// "params_blob" feature reads every parameter in one `get_params_blob`
+/// call instead, and only falls back to this when the host doesn't
+/// provide them that way.
+///
+/// Once the host calls `params_changed`, the config is read again only
+/// after it does, so the rest of a query's calls make no host call for it,
+/// with a config or without one.
+unsafe fn load_config() {
+    if PARAMS_CHANGED == Some(false) {
+        return;
+    }
+    SHARED_DECODING = None;
+    read_config();
+    // A faulty read is retried on the next call
+    if !HOST_FAULT {
+        if let Some(changed) = PARAMS_CHANGED.as_mut() {
+            *changed = false;
+        }
     }
+}
 
-    // Get field value
-    let mut len = buffer.len() as i32;
-    let result = get_field_string(
-        ctx_id,
-        field_name.as_ptr(),
-        field_name.len() as i32,
-        buffer.as_mut_ptr(),
+/// `load_config` without the check for unchanged parameters
+unsafe fn read_config() {
+    #[cfg(feature = "params_blob")]
+    if load_params_blob() {
+        return;
+    }
+
+    let name = "config";
+    let mut len = CONFIG_BUFFER.len() as i32;
+    let mut result = get_param_string(
+        name.as_ptr(),
+        name.len() as i32,
+        CONFIG_BUFFER.as_mut_ptr(),
         &mut len,
     );
 
-    if result != 0 || len <= 0 {
-        return None;
+    // Buffer too small: `len` is the size the host needs
+    let mut written_config = written("get_param_string", result, len, CONFIG_BUFFER.len());
+    if let Written::TooSmall(needed) = written_config {
+        CONFIG_BUFFER.resize(needed, 0);
+        len = needed as i32;
+        result = get_param_string(
+            name.as_ptr(),
+            name.len() as i32,
+            CONFIG_BUFFER.as_mut_ptr(),
+            &mut len,
+        );
+        written_config = written("get_param_string", result, len, CONFIG_BUFFER.len());
     }
 
-    core::str::from_utf8(&buffer[..len as usize]).ok()
+    let text = match written_config {
+        Written::Value(len) if len > 0 => core::str::from_utf8(&CONFIG_BUFFER[..len]).ok(),
+        _ => None,
+    };
+    if let Err(Malformed) = CONFIG.update(text) {
+        log_error("config is not a JSON object, nothing can match");
+    }
 }
 
-/// Main filter function exported to WASM
+/// Read all parameters with `get_params_blob` into `CONFIG`
 ///
-/// Parameters (from query JSON):
-/// - `field`: Name of the field to check (e.g., "product_name")
-/// - `target`: Target string to compare against
-/// - `max_distance`: Maximum Levenshtein distance to allow
+/// The helpers then answer from the blob, as from a `config`. Returns
+/// false, for the usual reads, when the host has no blob (1); a blob
+/// that can't be read is a host fault.
+#[cfg(feature = "params_blob")]
+unsafe fn load_params_blob() -> bool {
+    let mut len = BLOB_BUFFER.len() as i32;
+    let mut result = get_params_blob(BLOB_BUFFER.as_mut_ptr(), &mut len);
+
+    // Buffer too small: `len` is the size the host needs
+    let mut blob = written("get_params_blob", result, len, BLOB_BUFFER.len());
+    if let Written::TooSmall(needed) = blob {
+        BLOB_BUFFER.resize(needed, 0);
+        len = needed as i32;
+        result = get_params_blob(BLOB_BUFFER.as_mut_ptr(), &mut len);
+        blob = written("get_params_blob", result, len, BLOB_BUFFER.len());
+    }
+
+    let len = match blob {
+        Written::Value(len) => len,
+        _ => return false,
+    };
+    match CONFIG.update_blob(&BLOB_BUFFER[..len]) {
+        Some(Ok(())) => true,
+        Some(Err(Malformed)) => {
+            log_error("config is not a JSON object, nothing can match");
+            true
+        }
+        None => {
+            // The fault fails the call
+            host_fault("get_params_blob", "wrote a malformed blob");
+            false
+        }
+    }
+}
+
+/// The first of the query's parameters that no export reads, for
+/// `strict_params`
 ///
-/// Returns:
-/// - 1 (i32) if the field value is within max_distance of target
-/// - 0 (i32) otherwise
-#[no_mangle]
-pub extern "C" fn filter(ctx_id: i64) -> i32 {
-    unsafe {
-        // Get parameters
-        let field_name = match get_string_param("field", &mut BUFFER[0..256]) {
-            Some(s) => s,
-            None => {
-                // Default field name if not specified
-                "name"
-            }
-        };
+/// Returns `Some(None)` when all are known and `None` when they can't be
+/// listed (see `param_names`).
+unsafe fn unknown_param() -> Option<Option<String>> {
+    // The pieces of a joined target are named by the query
+    let pieces = get_string_list("target_fields").flatten().unwrap_or_default();
+    let known = |name: &String| KNOWN_PARAMS.contains(&name.as_str()) || pieces.contains(name);
+    Some(param_names()?.into_iter().find(|name| !known(name)))
+}
 
-        let target = match get_string_param("target", &mut TARGET_BUFFER) {
-            Some(s) => s,
-            None => {
-                // No target specified, can't match
-                return 0;
-            }
-        };
+/// The names of the query's parameters, `None` when they can't be listed
+///
+/// With a `config`, its members are the parameters, and individual ones
+/// are ignored anyway; without one the host lists them with
+/// `get_param_names` (a build with the "param_names" feature). A list the
+/// host garbled is a host fault, and reads as empty.
+unsafe fn param_names() -> Option<Vec<String>> {
+    if let Some(names) = CONFIG.names() {
+        return Some(names.into_iter().map(String::from).collect());
+    }
 
-        let max_distance = get_i64_param("max_distance").unwrap_or(2) as usize;
+    #[cfg(feature = "param_names")]
+    {
+        let mut buffer = vec![0u8; 1024];
+        let mut len = buffer.len() as i32;
+        let mut result = get_param_names(buffer.as_mut_ptr(), &mut len);
+        let mut listed = written("get_param_names", result, len, buffer.len());
+        if let Written::TooSmall(needed) = listed {
+            buffer.resize(needed, 0);
+            len = needed as i32;
+            result = get_param_names(buffer.as_mut_ptr(), &mut len);
+            listed = written("get_param_names", result, len, buffer.len());
+        }
+        let len = match listed {
+            Written::Value(len) => len,
+            _ => return None,
+        };
 
-        // Get document field value
-        let value = match get_field(ctx_id, field_name, &mut BUFFER[256..]) {
-            Some(s) => s,
+        let list = core::str::from_utf8(&buffer[..len]).ok().and_then(json::parse);
+        let names = list.as_ref().and_then(|list| {
+            let items = list.as_array()?;
+            items.iter().map(|item| item.as_str().map(String::from)).collect()
+        });
+        match names {
+            Some(names) => Some(names),
             None => {
-                // Field doesn't exist or is not a string
-                return 0;
+                // The fault fails the call
+                host_fault("get_param_names", "wrote no JSON array of strings");
+                Some(Vec::new())
             }
-        };
+        }
+    }
 
-        // Calculate distance
-        let distance = levenshtein_distance(value, target);
+    #[cfg(not(feature = "param_names"))]
+    None
+}
 
-        // Return 1 if within threshold, 0 otherwise
-        if distance <= max_distance {
-            1
-        } else {
-            0
+/// Whether the query sets the parameter `name`, whatever its type
+///
+/// Asks `get_param_string` for nothing but the answer: 1 is unset, while
+/// a string (too long for no buffer, or empty) or another type is set.
+unsafe fn param_is_set(name: &str) -> bool {
+    if let Some(setting) = CONFIG.get(name) {
+        return setting.is_some();
+    }
+    let mut len = 0;
+    let mut buffer = [0u8; 0];
+    match get_param_string(name.as_ptr(), name.len() as i32, buffer.as_mut_ptr(), &mut len) {
+        1 => false,
+        0 | 2 | 3 => true,
+        result => {
+            host_fault("get_param_string", &format!("returned unknown code {}", result));
+            false
         }
     }
 }
 
-// No-op panic handler for smaller binary
-#[panic_handler]
-fn panic(_: &core::panic::PanicInfo) -> ! {
-    loop {}
+/// Helper to get an edit-distance limit
+///
+/// A negative one, which as a `usize` would wrap around and let everything
+/// match, is rejected (see `reject_param`).
+unsafe fn get_distance_param(name: &str) -> Option<usize> {
+    let value = get_i64_param(name)?;
+    if value < 0 {
+        reject_param(&format!("{} is negative", name));
+        return None;
+    }
+    Some(value as usize)
+}
+
+/// Helper to get an i64 parameter
+unsafe fn get_i64_param(name: &str) -> Option<i64> {
+    // Whole numbers only, like the host's i64 parameters
+    if let Some(setting) = CONFIG.get(name) {
+        return setting?.as_f64().filter(|v| v.fract() == 0.0).map(|v| v as i64);
+    }
+
+    let mut value: i64 = 0;
+    let result = get_param_i64(
+        name.as_ptr(),
+        name.len() as i32,
+        &mut value,
+    );
+
+    if scalar_written("get_param_i64", result) {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Helper to get an f64 parameter
+///
+/// A NaN or an infinity is rejected (see `reject_param`), whether the host
+/// passed it so or it was parsed from text like "NaN" or "1e999": NaN
+/// compares false either way, and `negate` would match on it.
+unsafe fn get_f64_param(name: &str) -> Option<f64> {
+    let value = read_f64_param(name)?;
+    if !value.is_finite() {
+        reject_param(&format!("{} is not a finite number", name));
+        return None;
+    }
+    Some(value)
+}
+
+/// Read an f64 parameter as the host or `config` has it
+///
+/// Falls back to parsing a string value (e.g. "0.8") for hosts that pass
+/// floats as text, which is all there is without the `f64_params` feature.
+unsafe fn read_f64_param(name: &str) -> Option<f64> {
+    if let Some(setting) = CONFIG.get(name) {
+        return match setting? {
+            json::Value::Number(value) => Some(*value),
+            json::Value::String(text) => text.trim().parse().ok(),
+            _ => None,
+        };
+    }
+
+    #[cfg(feature = "f64_params")]
+    {
+        let mut value: f64 = 0.0;
+        let result = get_param_f64(
+            name.as_ptr(),
+            name.len() as i32,
+            &mut value,
+        );
+
+        if scalar_written("get_param_f64", result) {
+            return Some(value);
+        }
+    }
+
+    let mut buffer = [0u8; 32];
+    get_string_param(name, &mut buffer)?.trim().parse().ok()
+}
+
+/// Helper to get a boolean parameter
+unsafe fn get_bool_param(name: &str) -> Option<bool> {
+    if let Some(setting) = CONFIG.get(name) {
+        return match setting? {
+            json::Value::Bool(value) => Some(*value),
+            _ => None,
+        };
+    }
+
+    let mut value: i32 = 0;
+    let result = get_param_bool(
+        name.as_ptr(),
+        name.len() as i32,
+        &mut value,
+    );
+
+    if !scalar_written("get_param_bool", result) {
+        return None;
+    }
+    match value {
+        0 | 1 => Some(value == 1),
+        _ => {
+            host_fault("get_param_bool", &format!("wrote {}, not 0 or 1", value));
+            None
+        }
+    }
+}
+
+/// Helper to get a field value as string
+///
+/// An existing field the host reports as 0 bytes long is the empty string,
+/// not a missing field, so that it gets `empty_field_behavior`.
+unsafe fn get_field<'a>(ctx_id: i64, field_name: &str, buffer: &'a mut [u8]) -> Option<&'a str> {
+    // First check if field exists
+    if !field_exists(ctx_id, field_name) {
+        return None;
+    }
+    // Nothing could be written, can't read it
+    if buffer.is_empty() {
+        return None;
+    }
+
+    // Get field value
+    let mut len = buffer.len() as i32;
+    let result = get_field_string(
+        ctx_id,
+        field_name.as_ptr(),
+        field_name.len() as i32,
+        buffer.as_mut_ptr(),
+        &mut len,
+    );
+
+    match field_written("get_field_string", result, len, buffer.len()) {
+        Some(len) => core::str::from_utf8(&buffer[..len]).ok(),
+        _ => None,
+    }
+}
+
+/// Helper to get the ID of the document, empty if the host has none
+unsafe fn document_id(ctx_id: i64, buffer: &mut [u8]) -> Option<&str> {
+    let mut len = buffer.len() as i32;
+    let result = get_document_id(ctx_id, buffer.as_mut_ptr(), &mut len);
+
+    let len = field_written("get_document_id", result, len, buffer.len())?;
+    core::str::from_utf8(&buffer[..len]).ok()
+}
+
+/// Helper to stream a string field value through `on_chunk`
+///
+/// The value is read with `get_field_chunk` into `buffer`, and each chunk
+/// handed to `on_chunk` ends on a char boundary (a split UTF-8 sequence is
+/// carried over to the next read). Stops early when `on_chunk` returns
+/// `false`. Returns `None` if the field is missing or not valid UTF-8.
+#[cfg(feature = "stream")]
+unsafe fn stream_field(
+    ctx_id: i64,
+    field_name: &str,
+    buffer: &mut [u8],
+    mut on_chunk: impl FnMut(&str) -> bool,
+) -> Option<()> {
+    if !field_exists(ctx_id, field_name) {
+        return None;
+    }
+
+    let mut offset = 0usize;
+    let mut carry = 0usize;
+    loop {
+        let space = buffer.len() - carry;
+        let mut len: i32 = 0;
+        let result = get_field_chunk(
+            ctx_id,
+            field_name.as_ptr(),
+            field_name.len() as i32,
+            offset as i32,
+            buffer[carry..].as_mut_ptr(),
+            space as i32,
+            &mut len,
+        );
+
+        // 1 is not found and 3 a write error; the length must fit the space
+        let len = match (result, usize::try_from(len)) {
+            (0, Ok(len)) if len <= space => len,
+            (1 | 3, _) => return None,
+            _ => {
+                let violation =
+                    format!("returned {} with length {} for {} bytes", result, len, space);
+                host_fault("get_field_chunk", &violation);
+                return None;
+            }
+        };
+        if len == 0 {
+            // End of value; leftover bytes are a truncated char
+            return if carry == 0 { Some(()) } else { None };
+        }
+        offset += len;
+
+        let filled = carry + len;
+        let valid = match core::str::from_utf8(&buffer[..filled]) {
+            Ok(_) => filled,
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return None,
+        };
+        let chunk = core::str::from_utf8(&buffer[..valid]).ok()?;
+        if !on_chunk(chunk) {
+            return Some(());
+        }
+
+        buffer.copy_within(valid..filled, 0);
+        carry = filled - valid;
+    }
+}
+
+/// Read an array of strings with `import`, normalizing each element
+///
+/// `call` runs the import with a buffer and its length, returning its
+/// code. An array that doesn't fit `ARRAY_BUFFER` is re-read at the length
+/// the import asked for, as `get_json_param` does. Returns `None` when not
+/// found, and `ListError::Malformed` when not an array of strings, when an
+/// element overflows normalization, or when the import wrote something that
+/// doesn't decode (a host fault). Arrays of more than `max_array_elements`
+/// elements are rejected before any is normalized.
+#[cfg(feature = "arrays")]
+unsafe fn read_array(
+    import: &str,
+    normalization: Normalization,
+    mut call: impl FnMut(&mut [u8], &mut i32) -> i32,
+) -> Option<Result<Vec<String>, ListError>> {
+    if ARRAY_BUFFER.is_empty() {
+        ARRAY_BUFFER.resize(1024, 0);
+    }
+    let mut len = ARRAY_BUFFER.len() as i32;
+    let mut result = call(&mut ARRAY_BUFFER, &mut len);
+    if let (3, Ok(needed)) = (result, usize::try_from(len)) {
+        if needed > ARRAY_BUFFER.len() {
+            ARRAY_BUFFER.resize(needed, 0);
+            len = needed as i32;
+            result = call(&mut ARRAY_BUFFER, &mut len);
+        }
+    }
+
+    // Set but not an array of strings
+    if result == 2 {
+        return Some(Err(ListError::Malformed));
+    }
+    let len = match written(import, result, len, ARRAY_BUFFER.len()) {
+        Written::Value(len) => len,
+        _ => return None,
+    };
+    let items = match array::parse(&ARRAY_BUFFER[..len]) {
+        Some(items) => items,
+        None => {
+            host_fault(import, "wrote an array that doesn't decode");
+            return Some(Err(ListError::Malformed));
+        }
+    };
+    Some(normalize_elements(&items, normalization))
+}
+
+/// Read the document's `field_name` as an array of strings (see
+/// `read_array`)
+#[cfg(feature = "arrays")]
+unsafe fn get_array_field(
+    ctx_id: i64,
+    field_name: &str,
+    normalization: Normalization,
+) -> Option<Result<Vec<String>, ListError>> {
+    read_array("get_field_array", normalization, |buffer, len| {
+        get_field_array(
+            ctx_id,
+            field_name.as_ptr(),
+            field_name.len() as i32,
+            buffer.as_mut_ptr(),
+            len,
+        )
+    })
+}
+
+/// Read the array-of-strings parameter `name` (see `read_array`)
+///
+/// A `config` setting is an array itself; text, which is how a single
+/// parameter passes one, is parsed as a JSON array as the host does.
+#[cfg(feature = "arrays")]
+unsafe fn get_array_param(
+    name: &str,
+    normalization: Normalization,
+) -> Option<Result<Vec<String>, ListError>> {
+    if let Some(setting) = CONFIG.get(name) {
+        let parsed;
+        let items = match setting? {
+            json::Value::String(text) => {
+                parsed = json::parse(text);
+                parsed.as_ref().and_then(json::Value::as_array)
+            }
+            setting => setting.as_array(),
+        };
+        let items: Option<Vec<&str>> =
+            items.and_then(|items| items.iter().map(json::Value::as_str).collect());
+        return Some(match items {
+            Some(items) => normalize_elements(&items, normalization),
+            None => Err(ListError::Malformed),
+        });
+    }
+
+    read_array("get_param_array", normalization, |buffer, len| {
+        get_param_array(name.as_ptr(), name.len() as i32, buffer.as_mut_ptr(), len)
+    })
+}
+
+/// Read the normalization options from the query
+///
+/// A `normalization_pipeline` enables the steps it lists, in its order,
+/// and no others; their switches are then ignored. An invalid pipeline is
+/// rejected (see `get_pipeline`), and the switches stand in for it until
+/// `with_host_checks` turns the result into the export's invalid value.
+unsafe fn get_normalization() -> Normalization {
+    let pipeline = match get_pipeline() {
+        Some(Some(pipeline)) => pipeline,
+        _ => {
+            return Normalization {
+                fold_table: get_fold_table(),
+                fold_width: get_bool_param("fold_width").unwrap_or(false),
+                case_insensitive: get_bool_param("case_insensitive").unwrap_or(false),
+                transliteration: get_transliteration(),
+                ignore_accents: get_bool_param("ignore_accents").unwrap_or(false),
+                mask_digits: get_bool_param("mask_digits").unwrap_or(false),
+                collapse_whitespace: get_bool_param("collapse_whitespace").unwrap_or(false),
+                unknown_chars: get_unknown_chars(),
+                pipeline: None,
+            };
+        }
+    };
+    pipeline_normalization(pipeline)
+}
+
+/// The normalization enabling the steps of `pipeline`, in its order
+///
+/// The settings of the steps (the `fold_table` and `transliterate` maps,
+/// the `unknown_char_policy`) are read from the query.
+unsafe fn pipeline_normalization(pipeline: Pipeline) -> Normalization {
+    Normalization {
+        fold_table: if pipeline.contains(Step::FoldTable) {
+            get_fold_table()
+        } else {
+            None
+        },
+        fold_width: pipeline.contains(Step::FoldWidth),
+        case_insensitive: pipeline.contains(Step::CaseInsensitive),
+        transliteration: if pipeline.contains(Step::Transliterate) {
+            get_transliteration()
+        } else {
+            None
+        },
+        ignore_accents: pipeline.contains(Step::IgnoreAccents),
+        mask_digits: pipeline.contains(Step::MaskDigits),
+        collapse_whitespace: pipeline.contains(Step::CollapseWhitespace),
+        unknown_chars: if pipeline.contains(Step::UnknownChars) {
+            get_unknown_chars()
+        } else {
+            UnknownChars::Keep
+        },
+        pipeline: Some(pipeline),
+    }
+}
+
+/// Read `normalization_pipeline`, the normalization steps in order
+///
+/// Steps are named by the parameters that enable them (`"case_insensitive"`,
+/// `"unknown_char_policy"`, ...). Returns `Some(None)` when unset, and
+/// rejects it (see `reject_param`) and returns `None` when it isn't a JSON
+/// array of strings, names an unknown step or lists one twice.
+unsafe fn get_pipeline() -> Option<Option<Pipeline>> {
+    let text = match get_json_param("normalization_pipeline") {
+        Some(text) => text,
+        None => return Some(None),
+    };
+    let names = json::parse(text).and_then(|value| {
+        value
+            .as_array()?
+            .iter()
+            .map(|name| name.as_str().map(String::from))
+            .collect::<Option<Vec<_>>>()
+    });
+    let names = match names {
+        Some(names) => names,
+        None => {
+            reject_param("normalization_pipeline is not a JSON array of strings");
+            return None;
+        }
+    };
+    Some(Some(parse_pipeline("normalization_pipeline", &names, reject_param)?))
+}
+
+/// The pipeline of the steps `names`, in order, reporting errors as
+/// `param`'s through `report`
+///
+/// Returns `None` for an unknown step or one listed twice.
+unsafe fn parse_pipeline(
+    param: &str,
+    names: &[String],
+    report: unsafe fn(&str),
+) -> Option<Pipeline> {
+    let mut steps = Vec::with_capacity(names.len());
+    for name in names {
+        match Step::from_name(name) {
+            Some(step) => steps.push(step),
+            None => {
+                report(&format!("unknown {} step \"{}\"", param, name));
+                return None;
+            }
+        }
+    }
+    let pipeline = Pipeline::new(&steps);
+    if pipeline.is_none() {
+        report(&format!("{} lists a step twice", param));
+    }
+    pipeline
+}
+
+/// Read `normalization_variants`, the normalizations the field is tried
+/// under
+///
+/// Each variant is a JSON array of steps like a `normalization_pipeline`,
+/// e.g. `[[], ["case_insensitive"], ["case_insensitive", "ignore_accents"]]`
+/// for raw, case-folded and accent-folded. Returns `Some(None)` when unset,
+/// and logs and returns `None` when it isn't a non-empty array of arrays of
+/// strings or a variant is an invalid pipeline.
+unsafe fn get_variants() -> Option<Option<Vec<Normalization>>> {
+    let text = match get_json_param("normalization_variants") {
+        Some(text) => text,
+        None => return Some(None),
+    };
+    let variants = json::parse(text).and_then(|value| {
+        value
+            .as_array()?
+            .iter()
+            .map(|variant| {
+                variant
+                    .as_array()?
+                    .iter()
+                    .map(|name| name.as_str().map(String::from))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .collect::<Option<Vec<_>>>()
+    });
+    let variants = match variants {
+        Some(variants) if !variants.is_empty() => variants,
+        _ => {
+            log_error("normalization_variants is not a non-empty JSON array of step arrays");
+            return None;
+        }
+    };
+
+    let mut normalizations = Vec::with_capacity(variants.len());
+    for names in &variants {
+        let pipeline = parse_pipeline("normalization_variants", names, log_error)?;
+        normalizations.push(pipeline_normalization(pipeline));
+    }
+    Some(Some(normalizations))
+}
+
+/// Read the `transliterate` map of source → replacement
+///
+/// `transliterate` is a JSON object of strings, e.g. `{"щ": "shch"}`, and is
+/// parsed only when it differs from the last map read (see `Table`). A
+/// malformed map, or one with an empty source, is logged and ignored.
+unsafe fn get_transliteration() -> Option<Transliteration> {
+    let text = get_json_param("transliterate")?;
+    if !TRANSLITERATION.holds(text) {
+        let pairs = match json::parse(text) {
+            Some(json::Value::Object(members)) => members
+                .into_iter()
+                .map(|(from, to)| Some((from, to.as_str()?.to_string())))
+                .collect::<Option<Vec<_>>>(),
+            _ => None,
+        };
+        match pairs {
+            Some(pairs) if pairs.iter().all(|(from, _)| !from.is_empty()) => {
+                TRANSLITERATION.load(text, pairs)
+            }
+            _ => {
+                log_warn("transliterate is not a JSON object of strings, ignoring it");
+                return None;
+            }
+        }
+    }
+    Some(TRANSLITERATION.current())
+}
+
+/// Read the `fold_table` of char → replacement
+///
+/// `fold_table` is a JSON object of strings keyed by single chars, e.g.
+/// `{"ß": "ss", "ı": "i", "ﬁ": "fi"}`, parsed only when it differs from the
+/// last table read, as `transliterate` is. A malformed table, or one with a
+/// key that isn't one char, is logged and ignored.
+unsafe fn get_fold_table() -> Option<Transliteration> {
+    let text = get_json_param("fold_table")?;
+    if !FOLD_TABLE.holds(text) {
+        let pairs = match json::parse(text) {
+            Some(json::Value::Object(members)) => members
+                .into_iter()
+                .map(|(from, to)| Some((from, to.as_str()?.to_string())))
+                .collect::<Option<Vec<_>>>(),
+            _ => None,
+        };
+        match pairs {
+            Some(pairs) if pairs.iter().all(|(from, _)| from.chars().count() == 1) => {
+                FOLD_TABLE.load(text, pairs)
+            }
+            _ => {
+                log_warn("fold_table is not a JSON object of strings keyed by chars, ignoring it");
+                return None;
+            }
+        }
+    }
+    Some(FOLD_TABLE.current())
+}
+
+/// Read `unknown_char_policy` and, for `"replace"`, its placeholder
+///
+/// `unknown_char_placeholder` must be a single char and defaults to U+FFFD,
+/// the Unicode replacement character. An unknown policy or an invalid
+/// placeholder is logged and treated as `"keep"`.
+unsafe fn get_unknown_chars() -> UnknownChars {
+    let mut buffer = [0u8; 32];
+    match get_string_param("unknown_char_policy", &mut buffer) {
+        None | Some("keep") => UnknownChars::Keep,
+        Some("drop") => UnknownChars::Drop,
+        Some("replace") => {
+            let mut placeholder = [0u8; 8];
+            let placeholder = get_string_param("unknown_char_placeholder", &mut placeholder);
+            let mut chars = placeholder.unwrap_or("\u{FFFD}").chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => UnknownChars::Replace(c),
+                _ => {
+                    log_warn("unknown_char_placeholder is not a single char, ignoring it");
+                    UnknownChars::Keep
+                }
+            }
+        }
+        Some(policy) => {
+            log_warn(&format!("unknown unknown_char_policy \"{}\", ignoring it", policy));
+            UnknownChars::Keep
+        }
+    }
+}
+
+/// Read the decay of `"position_weighting": "linear"`
+///
+/// Returns `Some(None)` without position weighting (unset or `"none"`),
+/// and `None` for an unknown weighting or a decay that isn't a finite,
+/// non-negative number.
+unsafe fn get_position_decay() -> Option<Option<f64>> {
+    let mut buffer = [0u8; 32];
+    match get_string_param("position_weighting", &mut buffer) {
+        None | Some("none") => Some(None),
+        Some("linear") => match get_f64_param("position_decay").unwrap_or(0.25) {
+            decay if decay.is_finite() && decay >= 0.0 => Some(Some(decay)),
+            _ => None,
+        },
+        Some(_) => None,
+    }
+}
+
+/// Read how `"partial"` mode steps through the field
+///
+/// `window_step` defaults to 1, the exhaustive search, and `window_overlap`
+/// to `max_distance`, room for that many inserted chars; negative values
+/// count as 0 (and a step of 0 as 1). `window_refine` defaults to true.
+unsafe fn get_stepping(max_distance: usize) -> Stepping {
+    Stepping {
+        step: get_i64_param("window_step").map_or(1, |step| step.max(1) as usize),
+        overlap: get_i64_param("window_overlap").map_or(max_distance, |v| v.max(0) as usize),
+        refine: get_bool_param("window_refine") != Some(false),
+    }
+}
+
+/// Read the tokenizer used by token-based algorithms
+///
+/// Returns `None` for an unknown tokenizer, or for `"regex"` without a
+/// valid `token_pattern` (see the `regex` module for the supported syntax
+/// and complexity bounds).
+unsafe fn get_tokenizer() -> Option<Tokenizer> {
+    let mut buffer = [0u8; 32];
+    match get_string_param("tokenizer", &mut buffer) {
+        None | Some("whitespace") => Some(Tokenizer::Whitespace),
+        Some("chars") => Some(Tokenizer::Chars),
+        Some("unicode_words") => Some(Tokenizer::UnicodeWords),
+        Some("regex") => {
+            let mut pattern = [0u8; 256];
+            let pattern = get_string_param("token_pattern", &mut pattern)?;
+            Regex::new(pattern).map(Tokenizer::Regex)
+        }
+        Some(_) => None,
+    }
+}
+
+/// Read the extra edit cost for operands in different scripts
+///
+/// `script_mismatch_penalty` is either a number of edits or `"nomatch"`,
+/// which is returned as `usize::MAX`. Defaults to 0 (no penalty).
+unsafe fn get_script_mismatch_penalty() -> usize {
+    let mut buffer = [0u8; 32];
+    if get_string_param("script_mismatch_penalty", &mut buffer) == Some("nomatch") {
+        return usize::MAX;
+    }
+    get_i64_param("script_mismatch_penalty").map_or(0, |v| v.max(0) as usize)
+}
+
+/// Read the `max_digit_edits` and `max_letter_edits` budgets
+///
+/// Negative budgets count as 0, allowing no edit of that class.
+unsafe fn get_class_budgets() -> ClassBudgets {
+    ClassBudgets {
+        digits: get_i64_param("max_digit_edits").map(|v| v.max(0) as usize),
+        letters: get_i64_param("max_letter_edits").map(|v| v.max(0) as usize),
+    }
+}
+
+/// Extra edits charged for repeated substrings in `value` (`penalize_repeats`)
+///
+/// Each extra copy of a repeated unit (see `extra_copies`) costs
+/// `repeat_cost` edits, default 1.
+unsafe fn get_repeat_penalty(value: &str) -> usize {
+    if get_bool_param("penalize_repeats") != Some(true) {
+        return 0;
+    }
+    let cost = get_i64_param("repeat_cost").map_or(1, |v| v.max(0) as usize);
+    extra_copies(value).saturating_mul(cost)
+}
+
+/// Read the optional `idf` token weights
+///
+/// `idf` is a JSON object mapping tokens to non-negative weights, e.g.
+/// `{"inc": 0.1, "acme": 3.2}`. Returns `Some(None)` when it is not set and
+/// `None` when it is malformed.
+#[cfg(feature = "token")]
+unsafe fn get_idf() -> Option<Option<Idf>> {
+    let text = match get_json_param("idf") {
+        Some(text) => text,
+        None => return Some(None),
+    };
+
+    let members = match json::parse(text)? {
+        json::Value::Object(members) => members,
+        _ => return None,
+    };
+
+    let mut idf = Idf::new();
+    for (token, weight) in members {
+        let weight = weight.as_f64().filter(|w| w.is_finite() && *w >= 0.0)?;
+        idf.insert(token, weight);
+    }
+    Some(Some(idf))
+}
+
+/// Read the score `token_set_ratio` loses for tokens mixing scripts
+///
+/// Defaults to 0 (off); `None` when it isn't a finite, non-negative number.
+#[cfg(feature = "token")]
+unsafe fn get_mixed_script_penalty() -> Option<f64> {
+    match get_f64_param("mixed_script_penalty").unwrap_or(0.0) {
+        penalty if penalty.is_finite() && penalty >= 0.0 => Some(penalty),
+        _ => None,
+    }
+}
+
+/// Read the optional `ngram_idf` n-gram weights for `n`-char n-grams
+///
+/// `ngram_idf` is a JSON object mapping n-grams to non-negative weights,
+/// e.g. `{"ing": 0.2, "xyl": 4.0}`. Returns `Some(None)` when it is not set
+/// and `None` when it is malformed or a key isn't `n` chars long.
+#[cfg(feature = "ngram")]
+unsafe fn get_ngram_idf(n: usize) -> Option<Option<GramIdf>> {
+    let text = match get_json_param("ngram_idf") {
+        Some(text) => text,
+        None => return Some(None),
+    };
+
+    let members = match json::parse(text)? {
+        json::Value::Object(members) => members,
+        _ => return None,
+    };
+
+    let mut weights = Vec::with_capacity(members.len());
+    for (gram, weight) in members {
+        let weight = weight.as_f64().filter(|w| w.is_finite() && *w >= 0.0)?;
+        weights.push((gram, weight));
+    }
+    Some(Some(gram_idf(weights, n)?))
+}
+
+/// Read the optional `sub_costs` substitution cost table
+///
+/// `sub_costs` is a JSON array of `{"from": t, "to": f, "cost": c}` entries,
+/// where `from` is a target char, `to` the field char it may be read as and
+/// `c` a non-negative cost, e.g. `[{"from": "O", "to": "0", "cost": 0.2}]`.
+/// Returns `Some(None)` when it is not set and `None` when it is malformed.
+unsafe fn get_sub_costs() -> Option<Option<SubCosts>> {
+    let text = match get_json_param("sub_costs") {
+        Some(text) => text,
+        None => return Some(None),
+    };
+
+    let single_char = |value: &json::Value| {
+        let mut chars = value.as_str()?.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+
+    let mut sub_costs = SubCosts::new();
+    for entry in json::parse(text)?.as_array()? {
+        let from = single_char(entry.get("from")?)?;
+        let to = single_char(entry.get("to")?)?;
+        let cost = entry.get("cost")?.as_f64().filter(|c| c.is_finite() && *c >= 0.0)?;
+        sub_costs.insert((from, to), cost);
+    }
+    Some(Some(sub_costs))
+}
+
+/// Look up an algorithm by name, reading its settings from the query
+///
+/// Returns `None` for an unknown algorithm name, including one this build
+/// leaves out (see `missing_feature`).
+unsafe fn algorithm_from_name(name: &str) -> Option<Algorithm> {
+    match name {
+        "levenshtein" => Some(Algorithm::Levenshtein),
+        #[cfg(feature = "ngram")]
+        "ngram" => {
+            let n = get_i64_param("ngram_size").filter(|&n| n >= 1).unwrap_or(3) as usize;
+            Some(Algorithm::Ngram {
+                n,
+                pad: get_bool_param("pad_ngrams").unwrap_or(false),
+                idf: get_ngram_idf(n)?,
+            })
+        }
+        #[cfg(feature = "jaro")]
+        "jaro" => Some(Algorithm::Jaro),
+        #[cfg(feature = "jaro")]
+        "jaro_winkler" => Some(Algorithm::JaroWinkler),
+        #[cfg(feature = "token")]
+        "token_set_ratio" => Some(Algorithm::TokenSetRatio {
+            tokenizer: get_tokenizer()?,
+            idf: get_idf()?,
+            mixed_script_penalty: get_mixed_script_penalty()?,
+        }),
+        "exact_normalized" => Some(Algorithm::ExactNormalized),
+        #[cfg(feature = "ngram")]
+        "trigram_coverage" => Some(Algorithm::TrigramCoverage {
+            pad: get_bool_param("pad_ngrams").unwrap_or(false),
+            max_missing: get_i64_param("max_missing").unwrap_or(0).max(0) as usize,
+        }),
+        #[cfg(feature = "phonetic")]
+        "phonetic_key" => Some(Algorithm::PhoneticKey {
+            tiebreak: match get_i64_param("phonetic_tiebreak") {
+                None => None,
+                Some(bound) => Some(usize::try_from(bound).ok()?),
+            },
+        }),
+        #[cfg(feature = "jaro")]
+        "windowed" => Some(Algorithm::Windowed {
+            window: match get_i64_param("match_window") {
+                None => 2,
+                Some(window) => usize::try_from(window).ok()?,
+            },
+        }),
+        #[cfg(feature = "substring")]
+        "lcstr" => Some(Algorithm::Lcstr {
+            min_common_len: match get_i64_param("min_common_len") {
+                None => 3,
+                Some(len) => usize::try_from(len).ok()?,
+            },
+        }),
+        #[cfg(all(feature = "phonetic", feature = "ngram"))]
+        "name_match" => {
+            let (phonetic, ngram) = get_name_match_weights()?;
+            Some(Algorithm::NameMatch {
+                n: get_i64_param("ngram_size").filter(|&n| n >= 1).unwrap_or(2) as usize,
+                phonetic,
+                ngram,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Read the `phonetic_weight` and `ngram_weight` of `name_match`
+///
+/// Both default to 0.5. Returns `None` unless both are finite and
+/// non-negative and at least one is positive.
+#[cfg(all(feature = "phonetic", feature = "ngram"))]
+unsafe fn get_name_match_weights() -> Option<(f64, f64)> {
+    let phonetic = get_f64_param("phonetic_weight").unwrap_or(0.5);
+    let ngram = get_f64_param("ngram_weight").unwrap_or(0.5);
+    let valid = |w: f64| w.is_finite() && w >= 0.0;
+    if !(valid(phonetic) && valid(ngram)) || phonetic + ngram <= 0.0 {
+        return None;
+    }
+    Some((phonetic, ngram))
+}
+
+/// The algorithms left out of a build without their feature, and the
+/// features each one needs
+const ALGORITHM_FEATURES: &[(&str, &[(&str, bool)])] = &[
+    ("ngram", &[("ngram", cfg!(feature = "ngram"))]),
+    ("trigram_coverage", &[("ngram", cfg!(feature = "ngram"))]),
+    ("jaro", &[("jaro", cfg!(feature = "jaro"))]),
+    ("jaro_winkler", &[("jaro", cfg!(feature = "jaro"))]),
+    ("windowed", &[("jaro", cfg!(feature = "jaro"))]),
+    ("token_set_ratio", &[("token", cfg!(feature = "token"))]),
+    ("phonetic_key", &[("phonetic", cfg!(feature = "phonetic"))]),
+    ("lcstr", &[("substring", cfg!(feature = "substring"))]),
+    (
+        "name_match",
+        &[("phonetic", cfg!(feature = "phonetic")), ("ngram", cfg!(feature = "ngram"))],
+    ),
+];
+
+/// The feature algorithm `name` needs that this build was compiled without,
+/// `None` for an algorithm compiled in or an unknown name
+fn missing_feature(name: &str) -> Option<&'static str> {
+    let (_, features) = ALGORITHM_FEATURES.iter().find(|(algorithm, _)| *algorithm == name)?;
+    features.iter().find(|(_, enabled)| !enabled).map(|(feature, _)| *feature)
+}
+
+/// Whether `operand` reads as a NaN or an infinity ("NaN", "inf",
+/// "-Infinity"), which the digit-only modes would otherwise compare as
+/// having no digits
+fn spells_non_finite(operand: &str) -> bool {
+    operand.trim().parse::<f64>().is_ok_and(|value| !value.is_finite())
+}
+
+/// Check the query's `algorithm` and `match_policy` against the algorithms
+/// compiled in, logging an error for one left out
+///
+/// Returns `false` when the query needs an algorithm this build lacks, so a
+/// query written for a full build fails loudly instead of matching nothing.
+unsafe fn algorithms_compiled_in() -> bool {
+    let mut buffer = [0u8; 32];
+    if let Some(name) = get_string_param("algorithm", &mut buffer) {
+        if let Some(feature) = missing_feature(name) {
+            log_error(&format!(
+                "algorithm \"{}\" is not compiled in, it needs the \"{}\" feature",
+                name, feature
+            ));
+            return false;
+        }
+    }
+
+    if !cfg!(feature = "jaro")
+        && get_string_param("match_policy", &mut buffer) == Some("distance_or_similarity")
+    {
+        log_error(
+            "match_policy \"distance_or_similarity\" is not compiled in, it needs the \"jaro\" \
+             feature",
+        );
+        return false;
+    }
+    true
+}
+
+/// Read the scoring algorithm from the query
+///
+/// Returns `None` for an unknown algorithm name.
+unsafe fn get_algorithm() -> Option<Algorithm> {
+    match get_string_param("algorithm", &mut ALGORITHM_BUFFER) {
+        None => Some(Algorithm::Levenshtein),
+        Some(name) => algorithm_from_name(name),
+    }
+}
+
+/// A query setting the limit its algorithm isn't matched against
+enum LimitConflict {
+    /// Both limits are set; the algorithm's own one decides
+    Both(Limit),
+    /// Only the other kind of algorithm's limit is set
+    Wrong(Limit),
+}
+
+/// Check the explicit `max_distance` and `min_similarity` against the limit
+/// `algorithm` is matched against (see `Algorithm::limit`)
+///
+/// `threshold` isn't considered: it is read as whichever limit applies.
+unsafe fn limit_conflict(algorithm: &Algorithm) -> Option<LimitConflict> {
+    let own = algorithm.limit()?;
+    let is_set = |limit: Limit| match limit {
+        Limit::MaxDistance => get_i64_param("max_distance").is_some(),
+        Limit::MinSimilarity => get_f64_param("min_similarity").is_some(),
+    };
+    match (is_set(own), is_set(own.other())) {
+        (_, false) => None,
+        (true, true) => Some(LimitConflict::Both(own)),
+        (false, true) => Some(LimitConflict::Wrong(own)),
+    }
+}
+
+/// How a match is decided by the `threshold` parameter
+#[derive(Clone, Copy)]
+enum Threshold {
+    /// Edit distance at most this (`max_distance`)
+    Distance(usize),
+    /// Similarity at least this (`min_similarity`)
+    Similarity(f64),
+}
+
+impl Threshold {
+    fn distance(self) -> Option<usize> {
+        match self {
+            Threshold::Distance(d) => Some(d),
+            Threshold::Similarity(_) => None,
+        }
+    }
+
+    fn similarity(self) -> Option<f64> {
+        match self {
+            Threshold::Similarity(s) => Some(s),
+            Threshold::Distance(_) => None,
+        }
+    }
+}
+
+/// Read the `threshold` parameter and decide what it means
+///
+/// `threshold_kind` may be `"distance"` or `"similarity"`; otherwise the
+/// kind is detected from the value: an integer >= 1 is a distance and a
+/// number strictly between 0 and 1 is a similarity. Anything else (0, 1.5,
+/// negative) is ambiguous and needs `threshold_kind`. Returns `Some(None)`
+/// when `threshold` is not set and `None` when it is invalid.
+unsafe fn get_threshold() -> Option<Option<Threshold>> {
+    let value = match get_f64_param("threshold") {
+        Some(value) => value,
+        None => return Some(None),
+    };
+
+    let is_distance = value >= 0.0 && value.fract() == 0.0;
+    let is_similarity = (0.0..=1.0).contains(&value);
+
+    let mut kind = [0u8; 32];
+    let (threshold, how) = match get_string_param("threshold_kind", &mut kind) {
+        Some("distance") if is_distance => (Threshold::Distance(value as usize), "threshold_kind"),
+        Some("similarity") if is_similarity => (Threshold::Similarity(value), "threshold_kind"),
+        None if is_distance && value >= 1.0 => (Threshold::Distance(value as usize), "auto"),
+        None if value > 0.0 && value < 1.0 => (Threshold::Similarity(value), "auto"),
+        _ => return None,
+    };
+
+    let name = match threshold {
+        Threshold::Distance(_) => "max_distance",
+        Threshold::Similarity(_) => "min_similarity",
+    };
+    log_debug(&format!("threshold {} used as {} ({})", value, name, how));
+
+    Some(Some(threshold))
+}
+
+/// Read the edit budget for the distance-only entry points
+///
+/// `max_distance`, else a distance `threshold`, else 2. Returns `None` when
+/// `threshold` is invalid.
+unsafe fn get_max_distance() -> Option<usize> {
+    let threshold = get_threshold()?;
+    let max_distance = get_distance_param("max_distance")
+        .or(threshold.and_then(Threshold::distance))
+        .unwrap_or(2);
+    Some(max_distance)
+}
+
+/// Read the weighted `components` list from the query
+///
+/// `components` is a JSON array of `{"algorithm": name, "weight": w}`
+/// objects (`weight` defaults to 1.0). Returns `None` if it is missing or
+/// malformed, names an unknown algorithm, has a negative weight, or the
+/// weights sum to zero.
+unsafe fn get_components() -> Option<Vec<(Algorithm, f64)>> {
+    let text = get_json_param("components")?;
+    let entries = json::parse(text)?;
+
+    let mut components = Vec::new();
+    for entry in entries.as_array()? {
+        let algorithm = algorithm_from_name(entry.get("algorithm")?.as_str()?)?;
+        let weight = match entry.get("weight") {
+            Some(weight) => weight.as_f64()?,
+            None => 1.0,
+        };
+        if weight.is_nan() || weight < 0.0 {
+            return None;
+        }
+        components.push((algorithm, weight));
+    }
+
+    let total_weight: f64 = components.iter().map(|&(_, weight)| weight).sum();
+    if total_weight <= 0.0 || !total_weight.is_finite() {
+        return None;
+    }
+    Some(components)
+}
+
+/// Read and join the fields listed in `concat_fields`
+///
+/// `concat_fields` is a JSON array of field names, e.g.
+/// `["first_name", "last_name"]`. Their values are joined with
+/// `concat_separator` (default a single space); missing fields count as
+/// empty. Returns `Some(None)` when it is not set and `None` when it is not
+/// an array of strings.
+unsafe fn get_concat_fields(ctx_id: i64) -> Option<Option<String>> {
+    let text = match get_json_param("concat_fields") {
+        Some(text) => text,
+        None => return Some(None),
+    };
+    let names = json::parse(text)?;
+
+    let mut separator = [0u8; 32];
+    let separator = get_string_param("concat_separator", &mut separator).unwrap_or(" ");
+
+    let mut joined = String::new();
+    for (i, name) in names.as_array()?.iter().enumerate() {
+        if i > 0 {
+            joined.push_str(separator);
+        }
+        if let Some(value) = get_field(ctx_id, name.as_str()?, &mut BUFFER[256..]) {
+            joined.push_str(value);
+        }
+    }
+    Some(Some(joined))
+}
+
+/// The `target` parameter is longer than `max_target_len` (already logged)
+struct TargetTooLong;
+
+/// Read the target: the document's `target_field` when set, else `target`
+unsafe fn get_target(ctx_id: i64) -> Result<Option<&'static str>, TargetTooLong> {
+    let mut name = [0u8; 256];
+    match get_string_param("target_field", &mut name) {
+        // An empty target field is no target, like an empty `target`
+        Some(target_field) => {
+            Ok(get_field(ctx_id, target_field, &mut TARGET_BUFFER).filter(|t| !t.is_empty()))
+        }
+        None => get_target_param(),
+    }
+}
+
+/// Read `max_target_len`, the longest `target` accepted in bytes
+unsafe fn get_max_target_len() -> usize {
+    match get_i64_param("max_target_len") {
+        Some(max_len) => max_len.max(0) as usize,
+        None => DEFAULT_MAX_TARGET_LEN,
+    }
+}
+
+/// Reject targets longer than `max_target_len`, logging the error
+unsafe fn check_target_len(len: usize) -> Result<(), TargetTooLong> {
+    let max_len = get_max_target_len();
+    if len > max_len {
+        log_error(&format!("target is {} bytes, over max_target_len {}", len, max_len));
+        return Err(TargetTooLong);
+    }
+    Ok(())
+}
+
+/// Read the `target` parameter, in full or not at all
+///
+/// Targets that don't fit `TARGET_BUFFER` are re-read into `TARGET_HEAP`,
+/// as long as they are within `max_target_len` bytes (default
+/// `DEFAULT_MAX_TARGET_LEN`); longer ones log an error instead of being
+/// compared truncated.
+unsafe fn get_target_param() -> Result<Option<&'static str>, TargetTooLong> {
+    // A target passed in pieces is joined instead
+    if let Some(joined) = get_joined_target() {
+        return joined;
+    }
+
+    let name = "target";
+    if let Some(setting) = CONFIG.get(name) {
+        // Not set, not a string, or empty
+        let target = match setting.and_then(json::Value::as_str) {
+            Some(target) if !target.is_empty() => target,
+            _ => return Ok(None),
+        };
+        check_target_len(target.len())?;
+        return Ok(Some(target));
+    }
+
+    let mut len = TARGET_BUFFER.len() as i32;
+    let result = get_param_string(
+        name.as_ptr(),
+        name.len() as i32,
+        TARGET_BUFFER.as_mut_ptr(),
+        &mut len,
+    );
+
+    // Not found, not a string, or empty
+    let needed = match written("get_param_string", result, len, TARGET_BUFFER.len()) {
+        Written::Value(0) | Written::Unset => return Ok(None),
+        Written::Value(len) => {
+            check_target_len(len)?;
+            return Ok(core::str::from_utf8(&TARGET_BUFFER[..len]).ok());
+        }
+        Written::TooSmall(needed) => needed,
+    };
+    check_target_len(needed)?;
+
+    // Buffer too small: `needed` is the size the host needs
+    TARGET_HEAP.resize(needed, 0);
+    let mut len = needed as i32;
+    let result = get_param_string(
+        name.as_ptr(),
+        name.len() as i32,
+        TARGET_HEAP.as_mut_ptr(),
+        &mut len,
+    );
+    match written("get_param_string", result, len, TARGET_HEAP.len()) {
+        Written::Value(len) if len > 0 => Ok(core::str::from_utf8(&TARGET_HEAP[..len]).ok()),
+        _ => Ok(None),
+    }
+}
+
+/// Read and join the parameters listed in `target_fields` into `TARGET_HEAP`
+///
+/// `target_fields` is a JSON array of parameter names, e.g.
+/// `["target_brand", "target_model"]`. Their values are joined with
+/// `target_separator` (default a single space); missing ones count as
+/// empty. Returns `None` when it is not set, so `target` is read, and no
+/// target when it is not an array of strings or the join is empty.
+unsafe fn get_joined_target() -> Option<Result<Option<&'static str>, TargetTooLong>> {
+    let names = match get_string_list("target_fields") {
+        Some(Some(names)) => names,
+        Some(None) => return None,
+        // Malformed target_fields, no target
+        None => return Some(Ok(None)),
+    };
+
+    let mut separator = [0u8; 32];
+    let separator = get_string_param("target_separator", &mut separator).unwrap_or(" ");
+
+    let mut joined = String::new();
+    for (i, name) in names.iter().enumerate() {
+        if i > 0 {
+            joined.push_str(separator);
+        }
+        if let Some(piece) = get_json_param(name) {
+            joined.push_str(piece);
+        }
+    }
+    if let Err(too_long) = check_target_len(joined.len()) {
+        return Some(Err(too_long));
+    }
+    if joined.is_empty() {
+        return Some(Ok(None));
+    }
+
+    TARGET_HEAP.clear();
+    TARGET_HEAP.extend_from_slice(joined.as_bytes());
+    Some(Ok(core::str::from_utf8(&TARGET_HEAP).ok()))
+}
+
+/// The target under `normalization`, normalized once per query
+///
+/// The normalized target may be as long as the longest target accepted
+/// (`max_target_len`, and at least `TARGET_BUFFER`); a longer one is logged
+/// as an error and `None` returned.
+unsafe fn normalized_target(target: &str, normalization: Normalization) -> Option<&'static str> {
+    let limit = get_max_target_len().max(TARGET_BUFFER.len());
+    match NORMALIZED_TARGET.get(target, normalization, limit) {
+        Ok(normalized) => Some(normalized),
+        Err(Overflow { len }) => {
+            log_error(&format!("normalized target is {} bytes, over {}", len, limit));
+            None
+        }
+    }
+}
+
+/// The field value under `normalization`
+///
+/// The normalized value must still fit the field buffer (or be no longer
+/// than the value, for a joined `concat_fields`); a longer one is logged as
+/// an error and `None` returned. Forms of the document's field values are
+/// remembered for its later calls (see `FieldMemo`).
+unsafe fn normalize_value<'a>(
+    value: &'a str,
+    normalization: Normalization,
+) -> Option<Cow<'a, str>> {
+    if let Some(form) = FIELD_MEMO.form(value, normalization) {
+        return Some(Cow::Owned(form.to_string()));
+    }
+
+    let limit = (BUFFER.len() - 256).max(value.len());
+    match normalization.apply_within(value, limit) {
+        Ok(normalized) => {
+            if let Cow::Owned(form) = &normalized {
+                FIELD_MEMO.remember(value, normalization, form);
+            }
+            Some(normalized)
+        }
+        Err(Overflow { len }) => {
+            log_error(&format!("normalized field is {} bytes, over {}", len, limit));
+            None
+        }
+    }
+}
+
+/// Normalize both operands (see `normalize_value` and `normalized_target`)
+unsafe fn normalize_operands<'a>(
+    value: &'a str,
+    target: &str,
+    normalization: Normalization,
+) -> Option<(Cow<'a, str>, &'static str)> {
+    let value = normalize_value(value, normalization)?;
+    let target = normalized_target(target, normalization)?;
+    Some((value, target))
+}
+
+/// Read the document field value and the target named by the query
+///
+/// Returns `(value, target)`, or `None` if there is no target, the field
+/// is missing (see `get_value`) or `cell_budget` can't cover comparing
+/// them. `target_override` (one of `targets`) replaces the query's
+/// `target`.
+unsafe fn get_operands<'a>(
+    ctx_id: i64,
+    field_override: Option<&str>,
+    target_override: Option<&'a str>,
+) -> Result<Option<(Cow<'static, str>, &'a str)>, TargetTooLong> {
+    // No target specified (or its target_field is missing), can't match
+    let target = match target_override {
+        Some(target) => target,
+        None => match get_target(ctx_id)? {
+            Some(target) => target,
+            None => return Ok(None),
+        },
+    };
+    Ok(get_value(ctx_id, field_override)
+        .filter(|value| budget::charge(value, target))
+        .map(|value| (value, target)))
+}
+
+/// Read the document field value to compare
+///
+/// The value is `field` (overridden by `field_override`, one of `fields`),
+/// or the joined `concat_fields` when set, cut to the chars
+/// `[field_char_start, field_char_end)` when either is set, then narrowed
+/// to the `field_extract_pattern` capture when that is set, and finally
+/// stripped of a `strip_prefixes`/`strip_suffixes` boilerplate. Returns
+/// `None` if the field is missing, `concat_fields`, the pattern or either
+/// list is malformed, the pattern doesn't match, or `cell_budget` ran out.
+unsafe fn get_value(ctx_id: i64, field_override: Option<&str>) -> Option<Cow<'static, str>> {
+    // Past the budget no comparison is made, so the field needn't be read
+    if budget::stopped() {
+        return None;
+    }
+    let value = match get_concat_fields(ctx_id)? {
+        Some(joined) => Cow::Owned(joined),
+        None => {
+            // Default field name if not specified
+            let field_name = match field_override {
+                Some(field_name) => field_name,
+                None => get_string_param("field", &mut BUFFER[0..256]).unwrap_or("name"),
+            };
+
+            // Field doesn't exist or is not a string; read once per document
+            let mut id = [0u8; 256];
+            let document = document_id(ctx_id, &mut id);
+            let fetch = || get_field(ctx_id, field_name, &mut BUFFER[256..]);
+            Cow::Owned(FIELD_MEMO.value(document, field_name, fetch)?)
+        }
+    };
+
+    let start = get_i64_param("field_char_start");
+    let end = get_i64_param("field_char_end");
+    let value = if start.is_none() && end.is_none() {
+        value
+    } else {
+        // Out-of-range indices are clamped to the value
+        let start = start.map_or(0, |start| start.max(0) as usize);
+        let end = end.map_or(usize::MAX, |end| end.max(0) as usize);
+        match value {
+            Cow::Borrowed(value) => Cow::Borrowed(char_range(value, start, end)),
+            Cow::Owned(value) => Cow::Owned(char_range(&value, start, end).to_string()),
+        }
+    };
+
+    let value = match get_extract_pattern()? {
+        Some(pattern) => Cow::Owned(pattern.extract(&value)?.to_string()),
+        None => value,
+    };
+    strip_affixes(value)
+}
+
+/// `value` without the longest of `strip_prefixes` it starts with and the
+/// longest of `strip_suffixes` it then ends with
+///
+/// Affixes compare ignoring case with `case_insensitive`, and the whitespace
+/// left at a cut goes with the affix. Returns `None`, logging an error,
+/// when either list isn't a JSON array of strings.
+unsafe fn strip_affixes(value: Cow<'static, str>) -> Option<Cow<'static, str>> {
+    let mut lists = [None, None];
+    for (list, name) in lists.iter_mut().zip(["strip_prefixes", "strip_suffixes"]) {
+        *list = match get_string_list(name) {
+            Some(affixes) => affixes,
+            None => {
+                log_error(&format!("{} is not a JSON array of strings, nothing can match", name));
+                return None;
+            }
+        };
+    }
+    let [prefixes, suffixes] = lists;
+    if prefixes.is_none() && suffixes.is_none() {
+        return Some(value);
+    }
+
+    let fold_case = get_bool_param("case_insensitive").unwrap_or(false);
+    let mut rest = &value[..];
+    if let Some(prefixes) = prefixes {
+        let len = prefixes
+            .iter()
+            .filter_map(|prefix| affix_len(rest.chars(), prefix.chars(), fold_case))
+            .max();
+        if let Some(len) = len {
+            rest = rest[len..].trim_start();
+        }
+    }
+    if let Some(suffixes) = suffixes {
+        let len = suffixes
+            .iter()
+            .filter_map(|suffix| affix_len(rest.chars().rev(), suffix.chars().rev(), fold_case))
+            .max();
+        if let Some(len) = len {
+            rest = rest[..rest.len() - len].trim_end();
+        }
+    }
+    if rest.len() == value.len() {
+        return Some(value);
+    }
+    Some(Cow::Owned(rest.to_string()))
+}
+
+/// Bytes of `value` taken up by `affix` when `value` starts with it, comparing
+/// chars ignoring case with `fold_case`
+///
+/// The chars come from the front, or both reversed for a suffix.
+fn affix_len(
+    mut value: impl Iterator<Item = char>,
+    affix: impl Iterator<Item = char>,
+    fold_case: bool,
+) -> Option<usize> {
+    let mut len = 0;
+    for a in affix {
+        let c = value.next()?;
+        let same = if fold_case { c.to_lowercase().eq(a.to_lowercase()) } else { c == a };
+        if !same {
+            return None;
+        }
+        len += c.len_utf8();
+    }
+    Some(len)
+}
+
+/// Read `field_extract_pattern`, the regex whose first capture (or whole
+/// match) stands in for the field value
+///
+/// Returns `Some(None)` when unset and `None`, logging an error, when the
+/// pattern is malformed or over the complexity bounds of the `regex`
+/// module.
+unsafe fn get_extract_pattern() -> Option<Option<Regex>> {
+    let mut buffer = [0u8; 256];
+    let pattern = match get_string_param("field_extract_pattern", &mut buffer) {
+        Some(pattern) => pattern,
+        None => return Some(None),
+    };
+    match Regex::new(pattern) {
+        Some(regex) => Some(Some(regex)),
+        None => {
+            log_error("field_extract_pattern is not a supported regex, nothing can match");
+            None
+        }
+    }
+}
+
+/// Read the `targets` parameter: a JSON array of target strings
+///
+/// Without `targets`, a `target` split by `alternation_delimiter` stands in
+/// for it (see `get_alternatives`). Returns `Some(None)` when neither is
+/// set and `None` when `targets` is malformed.
+unsafe fn get_targets() -> Result<Option<Vec<String>>, ListError> {
+    let text = match get_json_param("targets") {
+        Some(text) => text,
+        None => {
+            let alternatives = get_alternatives();
+            if let Some(alternatives) = &alternatives {
+                check_list_len("targets", alternatives.len(), get_max_targets())?;
+            }
+            return Ok(alternatives);
+        }
+    };
+
+    let targets = json::parse(text).ok_or(ListError::Malformed)?;
+    let targets = targets.as_array().ok_or(ListError::Malformed)?;
+    check_list_len("targets", targets.len(), get_max_targets())?;
+    let targets = targets
+        .iter()
+        .map(|target| target.as_str().map(String::from))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(ListError::Malformed)?;
+    Ok(Some(targets))
+}
+
+/// Why a list (`targets` or an array operand) couldn't be read
+enum ListError {
+    /// Not a JSON array of strings
+    Malformed,
+    /// More elements than `max_targets` or `max_array_elements` allow
+    /// (already logged)
+    TooLong,
+}
+
+/// Read `max_targets`, the most `targets` compared
+unsafe fn get_max_targets() -> usize {
+    match get_i64_param("max_targets") {
+        Some(max) => max.max(0) as usize,
+        None => DEFAULT_MAX_TARGETS,
+    }
+}
+
+/// Read `max_array_elements`, the most elements an array operand may have
+#[cfg(feature = "arrays")]
+unsafe fn get_max_array_elements() -> usize {
+    match get_i64_param("max_array_elements") {
+        Some(max) => max.max(0) as usize,
+        None => DEFAULT_MAX_ARRAY_ELEMENTS,
+    }
+}
+
+/// Reject a `what` list of `len` elements over `max`, logging the error
+///
+/// Lists are checked before their elements are copied or compared, so an
+/// oversized one costs no more than its parse.
+unsafe fn check_list_len(what: &str, len: usize, max: usize) -> Result<(), ListError> {
+    if len > max {
+        log_error(&format!("{} has {} elements, over the cap of {}", what, len, max));
+        return Err(ListError::TooLong);
+    }
+    Ok(())
+}
+
+/// Split `target` on `alternation_delimiter` into alternative targets
+///
+/// `"color|colour"` with the delimiter `"|"` gives both spellings, matched
+/// like a `targets` list, and a target without the delimiter is a list of
+/// itself; empty alternatives are dropped. Returns `None` without a
+/// (non-empty) delimiter, with a `target_field`, and when `target` can't be
+/// read, so the target is read as usual (and a too long one still gives
+/// -1).
+unsafe fn get_alternatives() -> Option<Vec<String>> {
+    let mut buffer = [0u8; 32];
+    let delimiter = get_string_param("alternation_delimiter", &mut buffer)?;
+    let mut name = [0u8; 256];
+    if delimiter.is_empty() || get_string_param("target_field", &mut name).is_some() {
+        return None;
+    }
+    let target = get_target_param().ok()??;
+
+    let alternatives = target.split(delimiter).filter(|alternative| !alternative.is_empty());
+    Some(alternatives.map(String::from).collect())
+}
+
+/// The `filter` result for an empty or blank field, per `empty_field_behavior`
+///
+/// Returns `None` for `"skip"`, when the field is compared like any other.
+unsafe fn empty_field_result() -> Option<i32> {
+    let mut behavior = [0u8; 32];
+    match get_string_param("empty_field_behavior", &mut behavior) {
+        None | Some("nomatch") => Some(0),
+        Some("match") => Some(1),
+        Some("skip") => None,
+        // Unknown behavior, can't match
+        Some(_) => Some(0),
+    }
+}
+
+/// `filter` for field values too large for the field buffer (`stream_field`)
+///
+/// The field is read in chunks and fed to an online Levenshtein DP, which
+/// stops reading as soon as the distance must exceed `max_distance`. Only
+/// the plain distance check and `empty_field_behavior` apply;
+/// normalizations run per chunk.
+#[cfg(feature = "stream")]
+unsafe fn filter_streamed(
+    ctx_id: i64,
+    field_override: Option<&str>,
+    target_override: Option<&str>,
+) -> i32 {
+    let (name_buffer, chunk_buffer) = BUFFER.split_at_mut(256);
+    let field_name = match field_override {
+        Some(field_name) => field_name,
+        None => get_string_param("field", name_buffer).unwrap_or("name"),
+    };
+
+    // No target specified, can't match
+    let target = match target_override {
+        Some(target) => target,
+        None => match get_target(ctx_id) {
+            Ok(Some(target)) => target,
+            Ok(None) => return 0,
+            Err(TargetTooLong) => return DIAG_INVALID,
+        },
+    };
+
+    let max_distance = match get_max_distance() {
+        Some(_) if !fuzzy_allowed(target) => 0,
+        Some(max_distance) => max_distance,
+        None => return 0,
+    };
+    let max_distance = match doc_freq_limit(ctx_id, Some(field_name), max_distance) {
+        Some(max_distance) => max_distance,
+        None => return 0,
+    };
+
+    let normalization = get_normalization();
+    let target = match normalized_target(target, normalization) {
+        Some(target) => target,
+        None => return DIAG_OVERFLOW,
+    };
+    let mut dp = StreamingLevenshtein::new(target, max_distance);
+
+    // Whether every chunk read was blank, and all of them were read
+    let (mut blank, mut complete) = (true, true);
+    let streamed = stream_field(ctx_id, field_name, chunk_buffer, |chunk| {
+        let chunk = normalization.apply(chunk);
+        blank &= chunk.trim().is_empty();
+        complete = chunk.chars().all(|c| dp.push(c));
+        complete
+    });
+    if streamed.is_none() {
+        return 0;
+    }
+    #[cfg(feature = "stats")]
+    {
+        stats::field_examined();
+        if !complete {
+            stats::early_exit();
+        }
+    }
+    // Empty fields get a fixed result, as in `filter_field`
+    if blank && complete {
+        if let Some(result) = empty_field_result() {
+            return result;
+        }
+    }
+
+    EXACT_MATCH = dp.distance() == 0;
+    (dp.distance() <= max_distance) as i32
+}
+
+/// Main filter function exported to WASM
+///
+/// Parameters (from query JSON, or the members of a `config` object):
+/// - `field`: Name of the field to check (e.g., "product_name")
+/// - `target`: Target string to compare against
+/// - `max_distance`: Maximum Levenshtein distance to allow
+///
+/// and the rest of the README's Parameters table, which selects other
+/// algorithms, modes, normalizations and limits.
+///
+/// Returns:
+/// - 1 (i32) if the field value matches the target
+/// - 0 (i32) otherwise
+/// - -1 (i32) if the query is invalid
+/// - -2 (i32) if an operand or list is over its limit, or a host import
+///   broke its contract
+///
+/// `negate` swaps 1 and 0 but never changes the negative codes; the README's
+/// Error Codes section lists their causes.
+#[no_mangle]
+pub extern "C" fn filter(ctx_id: i64) -> i32 {
+    unsafe {
+        with_host_checks(DIAG_OVERFLOW, DIAG_INVALID, || {
+            if get_bool_param("stats") != Some(true) {
+                return filter_profiled(ctx_id);
+            }
+
+            #[cfg(feature = "stats")]
+            {
+                // Work done before this call isn't this call's
+                stats::take();
+                let result = filter_profiled(ctx_id);
+                for (key, value) in stats::take().named() {
+                    emit_stat(key.as_ptr(), key.len() as i32, value.min(i64::MAX as u64) as i64);
+                }
+                result
+            }
+
+            #[cfg(not(feature = "stats"))]
+            {
+                log_warn("stats is ignored in a build without the \"stats\" feature");
+                filter_profiled(ctx_id)
+            }
+        })
+    }
+}
+
+/// `filter_match`, timed with `profile`
+unsafe fn filter_profiled(ctx_id: i64) -> i32 {
+    if get_bool_param("profile") != Some(true) {
+        return filter_within_budget(ctx_id);
+    }
+
+    #[cfg(feature = "profile")]
+    {
+        let start = get_time_ns();
+        let result = filter_within_budget(ctx_id);
+        let elapsed = get_time_ns() - start;
+        log_debug(&format!("filter took {} ns (result {})", elapsed, result));
+        result
+    }
+
+    #[cfg(not(feature = "profile"))]
+    {
+        log_warn("profile is ignored in a build without the \"profile\" feature");
+        filter_within_budget(ctx_id)
+    }
+}
+
+/// `filter_match` within `cell_budget`, when set
+///
+/// The budget is only spent by this call's comparisons: other exports
+/// compare without one.
+unsafe fn filter_within_budget(ctx_id: i64) -> i32 {
+    budget::reset();
+    match get_i64_param("cell_budget") {
+        None => {}
+        Some(cells) if cells >= 0 => budget::limit(cells as u64),
+        Some(_) => {
+            log_error("cell_budget is negative");
+            return DIAG_INVALID;
+        }
+    }
+    let result = filter_match(ctx_id);
+    budget::lift();
+    if budget::exhausted() {
+        log_debug(&format!("cell_budget exhausted, result {} from the comparisons made", result));
+    }
+    result
+}
+
+/// `filter` that also reports whether the match was exact
+///
+/// Takes the same parameters and returns the same result as `filter`, and
+/// writes 1 to `exact_ptr` when the document matched at distance 0 (the
+/// normalized field equals the target, or contains it in `"partial"`
+/// mode), so exact hits can be boosted over fuzzy ones without a second
+/// query. Otherwise, including when there is no match, it writes 0. With
+/// several `fields` or `targets`, the pair that decided the match counts,
+/// and every target must be exact under `all_targets`.
+///
+/// # Safety
+///
+/// `exact_ptr` must be valid for writing an `i32`.
+#[no_mangle]
+pub unsafe extern "C" fn filter_ex(ctx_id: i64, exact_ptr: *mut i32) -> i32 {
+    let result = filter(ctx_id);
+    *exact_ptr = EXACT_MATCH as i32;
+    result
+}
+
+/// What decided the last comparison `filter` made, as `filter_reason`
+/// reports it
+///
+/// A prefilter rejecting a field that the comparison itself would have
+/// let through is a bug, and the reasons are there to catch it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MatchReason {
+    /// No comparison was made: a missing field, an invalid parameter, an
+    /// empty field or target, or a code path that doesn't record reasons
+    NotCompared = 0,
+    /// The field is byte-identical to the target (the fast path)
+    ByteEqual = 1,
+    /// The lengths differ by more than `max_distance`
+    LengthPrefilterRejected = 2,
+    /// The field fails `require_prefix_len` or `min_match_prefix`
+    PrefixRejected = 3,
+    /// The lengths differ under `require_equal_length`
+    EqualLengthRejected = 4,
+    /// The mode or algorithm compared the field with the target
+    Computed = 5,
+}
+
+/// `filter` that also reports what decided the match
+///
+/// Takes the same parameters and returns the same result as `filter`, and
+/// writes a `MatchReason` code to `reason_ptr`: 1 for the byte-equality
+/// fast path, 2, 3 or 4 for a rejection by the length, prefix or
+/// equal-length prefilter, 5 when the field was compared in full, and 0
+/// when there was no comparison. With several `fields` or `targets`, it is
+/// the reason of the last pair compared, the one that matched if any.
+/// `negate` doesn't change it.
+///
+/// # Safety
+///
+/// `reason_ptr` must be valid for writing an `i32`.
+#[no_mangle]
+pub unsafe extern "C" fn filter_reason(ctx_id: i64, reason_ptr: *mut i32) -> i32 {
+    let result = filter(ctx_id);
+    *reason_ptr = MATCH_REASON as i32;
+    result
+}
+
+/// `filter` that also reports the class a `"vote"`-mode match elected
+///
+/// Takes the same parameters and returns the same result as `filter`, and
+/// writes the winning class id to `class_ptr`, or -1 when no class got more
+/// than `vote_threshold`, outside `"vote"` mode, and on a diagnostic code.
+/// `negate` doesn't change it.
+///
+/// # Safety
+///
+/// `class_ptr` must be valid for writing an `i32`.
+#[no_mangle]
+pub unsafe extern "C" fn filter_vote(ctx_id: i64, class_ptr: *mut i32) -> i32 {
+    let result = filter(ctx_id);
+    *class_ptr = VOTE_CLASS;
+    result
+}
+
+/// `filter` that also reports whether `cell_budget` cut it short
+///
+/// Takes the same parameters and returns the same result as `filter`, and
+/// writes 1 to `exhausted_ptr` when the budget refused a comparison, so the
+/// result only stands for the comparisons made before it ran out: a match
+/// found is a match, but a miss may be a match never looked at. Otherwise,
+/// including without a budget, it writes 0.
+///
+/// # Safety
+///
+/// `exhausted_ptr` must be valid for writing an `i32`.
+#[no_mangle]
+pub unsafe extern "C" fn filter_budgeted(ctx_id: i64, exhausted_ptr: *mut i32) -> i32 {
+    let result = filter(ctx_id);
+    *exhausted_ptr = budget::exhausted() as i32;
+    result
+}
+
+/// Whether `target` is long enough for fuzzy matching
+///
+/// A target shorter than `min_target_len_for_fuzzy` chars (unset by
+/// default) is one or two edits from far too many fields, so it is only
+/// matched exactly: `max_distance` 0, `min_similarity` 1.0. The override is
+/// logged at debug level.
+unsafe fn fuzzy_allowed(target: &str) -> bool {
+    let min_len = match get_i64_param("min_target_len_for_fuzzy") {
+        Some(min_len) if min_len > 0 => min_len as usize,
+        _ => return true,
+    };
+    let len = target.chars().count();
+    if len >= min_len {
+        return true;
+    }
+    log_debug(&format!("target of {} chars under min_target_len_for_fuzzy, exact only", len));
+    false
+}
+
+/// `max_distance` scaled down for a field value common in the corpus
+///
+/// With `doc_freq_weight` w and the share f of documents holding the value
+/// (from `get_field_doc_freq`), the limit is `max_distance * (1 - w * f)`,
+/// rounded, so a value found everywhere needs a closer match than a rare
+/// one. Without a weight, when the host doesn't know the frequency, and for
+/// a `concat_fields` value, which has no frequency of its own, the limit is
+/// unchanged. Returns `None`, logging an error, for a weight outside
+/// [0, 1].
+unsafe fn doc_freq_limit(
+    ctx_id: i64,
+    field_override: Option<&str>,
+    max_distance: usize,
+) -> Option<usize> {
+    let weight = match get_f64_param("doc_freq_weight") {
+        Some(weight) if (0.0..=1.0).contains(&weight) => weight,
+        Some(_) => {
+            log_error("doc_freq_weight is outside [0, 1], nothing can match");
+            return None;
+        }
+        None => return Some(max_distance),
+    };
+    if get_json_param("concat_fields").is_some() {
+        return Some(max_distance);
+    }
+
+    #[cfg(feature = "doc_freq")]
+    {
+        let mut name = [0u8; 256];
+        let field_name = match field_override {
+            Some(field_name) => field_name,
+            None => get_string_param("field", &mut name).unwrap_or("name"),
+        };
+        let mut freq = 0.0;
+        let result =
+            get_field_doc_freq(ctx_id, field_name.as_ptr(), field_name.len() as i32, &mut freq);
+        if !scalar_written("get_field_doc_freq", result) {
+            return Some(max_distance);
+        }
+        if !(0.0..=1.0).contains(&freq) {
+            host_fault("get_field_doc_freq", &format!("wrote frequency {}", freq));
+            return Some(max_distance);
+        }
+
+        let limit = (max_distance as f64 * (1.0 - weight * freq)).round() as usize;
+        if limit < max_distance {
+            log_debug(&format!("value at doc freq {} allows {} edits", freq, limit));
+        }
+        Some(limit)
+    }
+
+    #[cfg(not(feature = "doc_freq"))]
+    {
+        let _ = (ctx_id, field_override, weight);
+        log_warn("doc_freq_weight is ignored in a build without the \"doc_freq\" feature");
+        Some(max_distance)
+    }
+}
+
+/// Read the `fields` parameter: a JSON array of field names
+///
+/// Returns `Some(None)` when unset and `None` when malformed.
+unsafe fn get_fields() -> Option<Option<Vec<String>>> {
+    get_string_list("fields")
+}
+
+/// Read the parameter `name`, a JSON array of strings
+///
+/// Returns `Some(None)` when unset and `None` when malformed.
+unsafe fn get_string_list(name: &str) -> Option<Option<Vec<String>>> {
+    let text = match get_json_param(name) {
+        Some(text) => text,
+        None => return Some(None),
+    };
+
+    let items = json::parse(text)?;
+    let items = items
+        .as_array()?
+        .iter()
+        .map(|item| item.as_str().map(String::from))
+        .collect::<Option<Vec<_>>>()?;
+    Some(Some(items))
+}
+
+/// The match decision behind `filter`: `fields` OR'd, `targets` OR'd (or
+/// AND'd with `all_targets`), then `negate`
+unsafe fn filter_match(ctx_id: i64) -> i32 {
+    EXACT_MATCH = false;
+    MATCH_REASON = MatchReason::NotCompared;
+    VOTE_CLASS = -1;
+
+
+    // A misspelled parameter would silently fall back to its default
+    if get_bool_param("strict_params") == Some(true) {
+        match unknown_param() {
+            Some(None) => {}
+            Some(Some(name)) => {
+                log_error(&format!("unknown parameter {}", name));
+                return DIAG_INVALID;
+            }
+            None => log_warn(
+                "strict_params can't check parameters without a config or the \"param_names\" \
+                 feature",
+            ),
+        }
+    }
+
+    if !algorithms_compiled_in() {
+        return DIAG_INVALID;
+    }
+
+    // Malformed fields or targets list, can't match (even when negated)
+    let names = match get_fields() {
+        Some(names) => names,
+        None => return 0,
+    };
+    let targets = match get_targets() {
+        Ok(targets) => targets,
+        Err(ListError::Malformed) => return 0,
+        Err(ListError::TooLong) => return DIAG_OVERFLOW,
+    };
+
+    // Rejected as it is read, but a query may read no normalization
+    if get_pipeline().is_none() {
+        return DIAG_INVALID;
+    }
+    // Invalid normalization_variants, can't match (even when negated)
+    if get_variants().is_none() {
+        return 0;
+    }
+
+    // A huge list is only compared in part, a sample kept per document;
+    // every target votes in "vote" mode
+    let mut mode = [0u8; 32];
+    let vote = get_string_param("mode", &mut mode) == Some("vote");
+    let targets = match (targets, get_target_sample_rate()) {
+        (_, None) => {
+            // Invalid target_sample_rate, can't match
+            return 0;
+        }
+        (Some(targets), Some(Some(_))) if vote => {
+            log_warn("target_sample_rate is ignored in \"vote\" mode");
+            Some(targets)
+        }
+        (Some(targets), Some(Some(rate))) => {
+            Some(sample_targets(ctx_id, names.as_deref(), targets, rate))
+        }
+        (targets, Some(_)) => targets,
+    };
+
+    let result = match targets {
+        // The targets vote for their classes instead of matching alone
+        targets if vote => vote_match(ctx_id, names.as_deref(), targets.as_deref()),
+        // One edit budget for every field, instead of a decision per field
+        targets if get_i64_param("max_total_distance").is_some() => {
+            filter_total_distance(ctx_id, names.as_deref(), targets.as_deref())
+        }
+        None => filter_fields(ctx_id, names.as_deref(), None),
+        // An empty list has no target to match
+        Some(targets) if targets.is_empty() => 0,
+        // Every target must match; the first miss or diagnostic code stops
+        Some(targets) if get_bool_param("all_targets") == Some(true) => {
+            let mut exact = true;
+            let result = targets
+                .iter()
+                .map(|target| {
+                    let result = filter_fields(ctx_id, names.as_deref(), Some(target));
+                    exact &= EXACT_MATCH;
+                    result
+                })
+                .find(|&result| result != 1)
+                .unwrap_or(1);
+            EXACT_MATCH = exact;
+            result
+        }
+        // Only the closest target counts, and only if it stands out
+        Some(targets) if get_f64_param("min_margin").is_some() => {
+            filter_unambiguous(ctx_id, names.as_deref(), &targets)
+        }
+        // First match wins; a diagnostic code stops the search too
+        Some(targets) => match filter_shared_targets(ctx_id, names.as_deref(), &targets) {
+            Some(result) => result,
+            None => filter_any_target(ctx_id, names.as_deref(), &targets),
+        },
+    };
+
+    // A miss, a diagnostic code or a negated result is never exact
+    let negate = get_bool_param("negate") == Some(true);
+    EXACT_MATCH &= result == 1 && !negate;
+
+    if negate && result >= 0 {
+        1 - result
+    } else {
+        result
+    }
+}
+
+/// `"mode": "vote"`: the `targets` vote for their classes
+///
+/// Each target is compared with the field (or `fields`) as without a mode,
+/// and a matching one adds its `target_weights` weight to its class. The
+/// class with the most weight wins when that is more than `vote_threshold`
+/// (0 by default), a tie going to the class that matched first, and is kept
+/// for `filter_vote`. A diagnostic code from any comparison stops the vote.
+unsafe fn vote_match(ctx_id: i64, names: Option<&[String]>, targets: Option<&[String]>) -> i32 {
+    let targets = match targets {
+        Some(targets) => targets,
+        None => {
+            log_error("\"vote\" mode needs a targets list");
+            return DIAG_INVALID;
+        }
+    };
+    let votes = match get_target_weights() {
+        Some(Some(votes)) if votes.len() == targets.len() => votes,
+        Some(Some(votes)) => {
+            log_error(&format!(
+                "target_weights has {} entries for {} targets",
+                votes.len(),
+                targets.len()
+            ));
+            return DIAG_INVALID;
+        }
+        Some(None) => {
+            log_error("\"vote\" mode needs target_weights");
+            return DIAG_INVALID;
+        }
+        None => {
+            log_error("target_weights is not an array of {\"class\": n, \"weight\": w} entries");
+            return DIAG_INVALID;
+        }
+    };
+    let threshold = match get_f64_param("vote_threshold") {
+        None => 0.0,
+        Some(threshold) if threshold.is_finite() => threshold,
+        Some(_) => {
+            // Invalid vote_threshold, can't match
+            return 0;
+        }
+    };
+
+    // Weight and exactness per class, in the order the classes first matched
+    let mut tally: Vec<(i32, f64, bool)> = Vec::new();
+    for (target, &(class, weight)) in targets.iter().zip(&votes) {
+        match filter_fields(ctx_id, names, Some(target)) {
+            1 => {}
+            0 => continue,
+            code => return code,
+        }
+        match tally.iter_mut().find(|(voted, _, _)| *voted == class) {
+            Some((_, total, exact)) => {
+                *total += weight;
+                *exact |= EXACT_MATCH;
+            }
+            None => tally.push((class, weight, EXACT_MATCH)),
+        }
+    }
+
+    let mut winner: Option<(i32, f64, bool)> = None;
+    for &(class, total, exact) in &tally {
+        if total > threshold && winner.is_none_or(|(_, best, _)| total > best) {
+            winner = Some((class, total, exact));
+        }
+    }
+    match winner {
+        Some((class, _, exact)) => {
+            VOTE_CLASS = class;
+            EXACT_MATCH = exact;
+            1
+        }
+        None => {
+            EXACT_MATCH = false;
+            0
+        }
+    }
+}
+
+/// Read `target_weights`, each target's class and vote in `"vote"` mode
+///
+/// `target_weights` is a JSON array with one `{"class": n, "weight": w}`
+/// entry per target, in the order of `targets`, where `n` is a class id from
+/// 0 to 2^31 - 1 and `w` a non-negative weight. Returns `Some(None)` when it
+/// is not set and `None` when it is malformed.
+unsafe fn get_target_weights() -> Option<Option<Vec<(i32, f64)>>> {
+    let text = match get_json_param("target_weights") {
+        Some(text) => text,
+        None => return Some(None),
+    };
+
+    let entries = json::parse(text)?;
+    let votes = entries
+        .as_array()?
+        .iter()
+        .map(|entry| {
+            let class = entry
+                .get("class")?
+                .as_f64()
+                .filter(|class| class.fract() == 0.0 && (0.0..=i32::MAX as f64).contains(class))?;
+            let weight = entry.get("weight")?.as_f64().filter(|w| w.is_finite() && *w >= 0.0)?;
+            Some((class as i32, weight))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Some(votes))
+}
+
+/// Read `target_sample_rate`, the share of `targets` compared per document
+///
+/// Returns `Some(None)` when unset and `None`, logging an error, when
+/// outside (0, 1].
+unsafe fn get_target_sample_rate() -> Option<Option<f64>> {
+    match get_f64_param("target_sample_rate") {
+        None => Some(None),
+        Some(rate) if rate > 0.0 && rate <= 1.0 => Some(Some(rate)),
+        Some(_) => {
+            log_error("target_sample_rate is outside (0, 1], nothing can match");
+            None
+        }
+    }
+}
+
+/// The `targets` this document is compared with at `rate` (see `sampled`)
+///
+/// The document is named by its value of the first of `fields`, or of
+/// `field` (`concat_fields`), as read for comparing, so the same document
+/// always gets the same sample, in every query. Without that value there
+/// is nothing to name it by, and every target is kept.
+unsafe fn sample_targets(
+    ctx_id: i64,
+    names: Option<&[String]>,
+    mut targets: Vec<String>,
+    rate: f64,
+) -> Vec<String> {
+    let first = names.and_then(|names| names.first()).map(String::as_str);
+    let key = match get_value(ctx_id, first) {
+        Some(key) => key,
+        None => return targets,
+    };
+
+    let total = targets.len();
+    targets.retain(|target| sampled(&key, target, rate));
+    log_debug(&format!("comparing {} of {} targets", targets.len(), total));
+    targets
+}
+
+/// `max_total_distance`: the fields share one edit budget
+///
+/// Every field (each of `fields`, or just `field`) is compared with its
+/// closest target, or with the one target, by the Levenshtein distance
+/// after normalization. Each must be within `max_distance` when that is
+/// set, and the document matches when their distances add up to at most
+/// `max_total_distance`: fields that each pass can still fail together. A
+/// missing field can't match, and modes, algorithms, costs and penalties
+/// don't apply.
+unsafe fn filter_total_distance(
+    ctx_id: i64,
+    names: Option<&[String]>,
+    targets: Option<&[String]>,
+) -> i32 {
+    let budget = match get_distance_param("max_total_distance") {
+        Some(budget) => budget,
+        None => return 0,
+    };
+    // Unset, only the budget limits each field
+    let max_distance = get_distance_param("max_distance").unwrap_or(budget);
+
+    let fields: Vec<Option<&str>> = match names {
+        Some(names) => names.iter().map(|name| Some(name.as_str())).collect(),
+        None => vec![None],
+    };
+    let targets: Vec<Option<&str>> = match targets {
+        Some(targets) => targets.iter().map(|target| Some(target.as_str())).collect(),
+        None => vec![None],
+    };
+    if targets.is_empty() {
+        return 0;
+    }
+
+    let normalization = get_normalization();
+    let mut total = 0;
+    for field in fields {
+        // Within the field's own limit and what is left of the budget
+        let mut bound = max_distance.min(budget - total);
+        let mut best = None;
+        for &target in &targets {
+            let (value, target) = match get_operands(ctx_id, field, target) {
+                Ok(Some(operands)) => operands,
+                // Missing field or no target, can't match
+                Ok(None) => return 0,
+                Err(TargetTooLong) => return DIAG_INVALID,
+            };
+            let (value, target) = match normalize_operands(&value, target, normalization) {
+                Some(operands) => operands,
+                None => return DIAG_OVERFLOW,
+            };
+            if let Some(distance) = levenshtein_within(&value, target, bound) {
+                best = Some(distance);
+                // Only a closer target can improve on it
+                match distance.checked_sub(1) {
+                    Some(closer) => bound = closer,
+                    None => break,
+                }
+            }
+        }
+        match best {
+            Some(distance) => total += distance,
+            None => return 0,
+        }
+    }
+
+    EXACT_MATCH = total == 0;
+    1
+}
+
+/// `targets` with `min_margin`: the closest target decides, unless the
+/// runner-up is nearly as close
+///
+/// Targets are ranked by their `algorithm` similarity to the field (the
+/// best over `fields`). When the best is less than `min_margin` ahead of
+/// the second best the field is ambiguous and doesn't match, however well
+/// it matches the best; otherwise the best target is matched as usual. A
+/// single target has no runner-up.
+unsafe fn filter_unambiguous(ctx_id: i64, names: Option<&[String]>, targets: &[String]) -> i32 {
+    let min_margin = match get_f64_param("min_margin") {
+        Some(margin) if margin.is_finite() && margin >= 0.0 => margin,
+        _ => {
+            // Invalid margin, can't match
+            return 0;
+        }
+    };
+    let algorithm = match get_algorithm() {
+        Some(algorithm) => algorithm,
+        None => {
+            // Unknown algorithm, can't match
+            return 0;
+        }
+    };
+
+    let mut best: Option<(f64, &str)> = None;
+    let mut second = f64::NEG_INFINITY;
+    for target in targets {
+        let similarity = match target_similarity(ctx_id, names, target, &algorithm) {
+            Ok(Some(similarity)) => similarity,
+            // Every field is missing: no target can match
+            Ok(None) => return 0,
+            Err(code) => return code,
+        };
+        match best {
+            Some((top, _)) if similarity <= top => second = second.max(similarity),
+            _ => {
+                second = best.map_or(second, |(top, _)| top);
+                best = Some((similarity, target));
+            }
+        }
+    }
+
+    let (top, target) = match best {
+        Some(best) => best,
+        None => return 0,
+    };
+    if top - second < min_margin {
+        log_debug(&format!("ambiguous match, runner-up within {} of the best", min_margin));
+        return 0;
+    }
+    filter_fields(ctx_id, names, Some(target))
+}
+
+/// Best similarity of `target` to the field, or to any of `names`
+///
+/// `Ok(None)` when every field is missing; `Err` holds the diagnostic code
+/// to return (see `filter`).
+unsafe fn target_similarity(
+    ctx_id: i64,
+    names: Option<&[String]>,
+    target: &str,
+    algorithm: &Algorithm,
+) -> Result<Option<f64>, i32> {
+    let fields: Vec<Option<&str>> = match names {
+        Some(names) => names.iter().map(|name| Some(name.as_str())).collect(),
+        None => vec![None],
+    };
+
+    let normalization = get_normalization();
+    let mut best: Option<f64> = None;
+    for field in fields {
+        let (value, target) = match get_operands(ctx_id, field, Some(target)) {
+            Ok(Some(operands)) => operands,
+            Ok(None) => continue,
+            Err(TargetTooLong) => return Err(DIAG_INVALID),
+        };
+        let (value, target) = match normalize_operands(&value, target, normalization) {
+            Some(operands) => operands,
+            None => return Err(DIAG_OVERFLOW),
+        };
+        let similarity = algorithm.similarity(&value, target);
+        best = Some(best.map_or(similarity, |best| best.max(similarity)));
+    }
+    Ok(best)
+}
+
+/// `targets` OR'd for a plain Levenshtein query, reading the field once
+///
+/// Each target would otherwise read, normalize and decode the field again.
+/// Here that happens once, and every target runs its own banded DP against
+/// the shared decoding (see `SharedField`) until one matches. Returns `None`
+/// when the query needs more than the plain distance check (see
+/// `shares_field_decoding`) or the field is empty, so the targets are
+/// matched one by one through `filter_field` instead.
+unsafe fn filter_shared_targets(
+    ctx_id: i64,
+    names: Option<&[String]>,
+    targets: &[String],
+) -> Option<i32> {
+    if names.is_some() || !shares_field_decoding() {
+        return None;
+    }
+    let max_distance = get_max_distance()?;
+
+    // Field doesn't exist or is not a string: no target can match
+    let value = match get_value(ctx_id, None) {
+        Some(value) => value,
+        None => return Some(0),
+    };
+    let normalization = get_normalization();
+    let value = match normalize_value(&value, normalization) {
+        Some(value) => value,
+        None => return Some(DIAG_OVERFLOW),
+    };
+    // Empty fields follow `empty_field_behavior`
+    if value.trim().is_empty() {
+        return None;
+    }
+
+    let mut field = SharedField::new(&value);
+    let mut chars = Vec::new();
+    for original in targets {
+        let target = match normalized_target(original, normalization) {
+            Some(target) => target,
+            None => return Some(DIAG_OVERFLOW),
+        };
+
+        // A target emptied by normalization would match nearly everything short
+        if target.is_empty() {
+            log_warn(&format!("target {} is empty after normalization", loggable(original)));
+            if get_bool_param("empty_target_matches") == Some(true) {
+                return Some(1);
+            }
+            continue;
+        }
+
+        chars.clear();
+        chars.extend(target.chars());
+        if let Some(distance) = field.within(&chars, max_distance) {
+            EXACT_MATCH = distance == 0;
+            MATCH_REASON = MatchReason::Computed;
+            return Some(1);
+        }
+        MATCH_REASON = if length_rejected(&value, target, max_distance) {
+            MatchReason::LengthPrefilterRejected
+        } else {
+            MatchReason::Computed
+        };
+    }
+    Some(0)
+}
+
+/// The parameters a query may set and still match its `targets` through
+/// `filter_shared_targets`, which honors them all as `filter_field` does
+///
+/// Anything else (`fields`, a mode, another kind of limit, costs,
+/// penalties, prefix checks, ...) needs the comparison target by target.
+/// The ones deciding the way through `filter_match` before it
+/// (`all_targets`, `min_margin`, ...) are listed as they never get there.
+const SHARED_DECODING_PARAMS: &[&str] = &[
+    "algorithm", "all_targets", "alternation_delimiter", "case_insensitive",
+    "collapse_whitespace", "concat_fields", "concat_separator", "config", "empty_field_behavior",
+    "empty_target_matches", "field", "field_char_end", "field_char_start",
+    "field_extract_pattern", "fold_table", "fold_width", "ignore_accents", "mask_digits",
+    "max_array_elements", "max_distance", "max_target_len", "max_targets", "max_total_distance",
+    "min_margin", "negate", "normalization_pipeline", "profile", "redact_logs",
+    "share_field_decoding", "strict_params", "strip_prefixes", "strip_suffixes", "target",
+    "target_field", "target_fields", "target_sample_rate", "target_separator", "targets",
+    "threshold", "threshold_kind", "transliterate", "unknown_char_placeholder",
+    "unknown_char_policy",
+];
+
+/// Whether the query's `targets` can share one decoding of the field (see
+/// `filter_shared_targets`), worked out once per query
+///
+/// It can when the query sets no parameter outside
+/// `SHARED_DECODING_PARAMS`, compares with Levenshtein against a distance
+/// and doesn't turn sharing off with `share_field_decoding: false`. The
+/// parameters set are listed when `param_names` can; otherwise each known
+/// one outside the list is asked for, which only pays off when the answer
+/// is kept for the query, so without `params_changed` the targets aren't
+/// shared.
+unsafe fn shares_field_decoding() -> bool {
+    if let Some(shared) = SHARED_DECODING {
+        return shared;
+    }
+    let only_shared_params = match param_names() {
+        Some(names) => names.iter().all(|name| SHARED_DECODING_PARAMS.contains(&name.as_str())),
+        None if PARAMS_CHANGED.is_some() => KNOWN_PARAMS
+            .iter()
+            .filter(|name| !SHARED_DECODING_PARAMS.contains(name))
+            .all(|name| !param_is_set(name)),
+        None => return false,
+    };
+    let shared = only_shared_params
+        && get_bool_param("share_field_decoding") != Some(false)
+        && matches!(get_algorithm(), Some(Algorithm::Levenshtein))
+        && matches!(get_threshold(), Some(None | Some(Threshold::Distance(_))));
+    // Kept until `params_changed`, for a host that calls it
+    if PARAMS_CHANGED.is_some() && !HOST_FAULT {
+        SHARED_DECODING = Some(shared);
+    }
+    shared
+}
+
+/// The match decision for one target: `fields` OR'd, or just `field`
+///
+/// Each field is read only when the ones before it didn't match, so a match
+/// saves the host calls reading the rest.
+unsafe fn filter_fields(ctx_id: i64, names: Option<&[String]>, target: Option<&str>) -> i32 {
+    match names {
+        None => filter_variants(ctx_id, None, target),
+        // First match wins; a diagnostic code stops the search too
+        Some(names) => names
+            .iter()
+            .map(|name| filter_variants(ctx_id, Some(name), target))
+            .find(|&result| result != 0)
+            .unwrap_or(0),
+    }
+}
+
+/// Whether any field (each of `fields`, or just `field`) matches any of
+/// `targets`; the first match or diagnostic code ends the search
+///
+/// The fields are the outer loop: each is read once for all the targets,
+/// and the fields after the first matching one are never read at all, as
+/// reading a field is the host call that costs.
+unsafe fn filter_any_target(ctx_id: i64, names: Option<&[String]>, targets: &[String]) -> i32 {
+    let field_matches = |name: Option<&str>| {
+        targets
+            .iter()
+            .map(|target| filter_variants(ctx_id, name, Some(target)))
+            .find(|&result| result != 0)
+    };
+    match names {
+        None => field_matches(None),
+        Some(names) => names.iter().find_map(|name| field_matches(Some(name))),
+    }
+    .unwrap_or(0)
+}
+
+/// `filter_field`, or with `target_variants` the first match among the
+/// target's spelling variants (see `variants::spelling_variants`)
+///
+/// Matching any variant is matching the closest one, so the variants are
+/// tried in order and the first match or diagnostic code ends the search.
+/// Each target of a list is one target with its own variants, so
+/// `all_targets` still asks for every target.
+unsafe fn filter_variants(
+    ctx_id: i64,
+    field_override: Option<&str>,
+    target_override: Option<&str>,
+) -> i32 {
+    if get_bool_param("target_variants") != Some(true) {
+        return filter_field(ctx_id, field_override, target_override);
+    }
+    let variants = match target_override {
+        Some(target) => spelling_variants(target),
+        None => match get_target(ctx_id) {
+            Ok(Some(target)) => spelling_variants(target),
+            // No target, as `filter_field` reports it
+            Ok(None) => return filter_field(ctx_id, field_override, None),
+            Err(TargetTooLong) => return DIAG_INVALID,
+        },
+    };
+
+    variants
+        .iter()
+        .map(|variant| filter_field(ctx_id, field_override, Some(variant)))
+        .find(|&result| result != 0)
+        .unwrap_or(0)
+}
+
+/// Read `mode`, how each field is compared with a target
+///
+/// `"vote"` only tallies the targets (see `vote_match`), each compared as
+/// without a mode, so it reads as unset.
+unsafe fn get_mode() -> Option<&'static str> {
+    get_string_param("mode", &mut MODE_BUFFER).filter(|&mode| mode != "vote")
+}
+
+/// The match decision for a single field, `field_override` or `field`,
+/// against `target_override` or `target`
+unsafe fn filter_field(
+    ctx_id: i64,
+    field_override: Option<&str>,
+    target_override: Option<&str>,
+) -> i32 {
+    #[cfg(feature = "token")]
+    if let Some(result) = filter_tokens(ctx_id, field_override, target_override) {
+        return result;
+    }
+
+    if get_bool_param("stream_field") == Some(true) {
+        // Streamed values aren't charged, but the budget still stops them
+        if budget::stopped() {
+            return 0;
+        }
+        #[cfg(feature = "stream")]
+        return filter_streamed(ctx_id, field_override, target_override);
+
+        // Without streaming the value can't be read in full
+        #[cfg(not(feature = "stream"))]
+        {
+            log_error("stream_field needs a build with the \"stream\" feature");
+            return DIAG_INVALID;
+        }
+    }
+
+    // The pattern stands in for the target, which needn't be set
+    if get_mode() == Some("regex") {
+        return regex_match(ctx_id, field_override);
+    }
+
+    // Tag sets are arrays, read as such and normalized element by element
+    if get_mode() == Some("set_pairing") {
+        return set_pairing_match(ctx_id, field_override, target_override);
+    }
+
+    let (value, target) = match get_operands(ctx_id, field_override, target_override) {
+        Ok(Some(operands)) => operands,
+        Ok(None) => return 0,
+        Err(TargetTooLong) => return DIAG_INVALID,
+    };
+    #[cfg(feature = "stats")]
+    stats::field_examined();
+
+    // Get parameters; explicit max_distance/min_similarity win over threshold
+    let threshold = match get_threshold() {
+        Some(threshold) => threshold,
+        None => {
+            // Invalid or ambiguous threshold, can't match
+            return 0;
+        }
+    };
+    let distance_limit = get_distance_param("max_distance")
+        .or(threshold.and_then(Threshold::distance));
+    let max_distance = distance_limit.unwrap_or(2);
+    let mode = get_mode();
+
+    // Short targets match exactly or not at all
+    let exact_only = !fuzzy_allowed(target);
+    let max_distance = if exact_only { 0 } else { max_distance };
+
+    // Byte-identical operands are at distance 0, which some algorithms accept
+    // whatever the limit. Normalizing can't make equal bytes differ, so only
+    // the target's form (normalized once per query) is looked at, and the
+    // field is neither normalized nor decoded.
+    let similarity_threshold =
+        distance_limit.is_none() && matches!(threshold, Some(Threshold::Similarity(_)));
+    if value.as_bytes() == target.as_bytes() {
+        // A target emptied by normalization gets `empty_target_matches` below
+        let normalized = normalized_target(target, get_normalization()).filter(|t| !t.is_empty());
+        if normalized.is_some_and(|form| identical_matches(form, mode, similarity_threshold)) {
+            EXACT_MATCH = true;
+            MATCH_REASON = MatchReason::ByteEqual;
+            return 1;
+        }
+    }
+
+    // Common values need a closer match
+    let max_distance = match doc_freq_limit(ctx_id, field_override, max_distance) {
+        Some(max_distance) => max_distance,
+        None => {
+            // Invalid doc_freq_weight, can't match
+            return 0;
+        }
+    };
+    MATCH_REASON = MatchReason::Computed;
+
+    // Lines are split before `collapse_whitespace` can join them
+    if mode == Some("per_line") {
+        return per_line_match(&value, target, max_distance);
+    }
+
+    // The target is a pattern, not text to normalize
+    if mode == Some("pattern") {
+        return pattern_match(&value, target, max_distance);
+    }
+    if mode == Some("glob") {
+        return glob_match(&value, target, max_distance);
+    }
+
+    // The closest of several normalizations decides
+    match get_variants() {
+        Some(None) => {}
+        Some(Some(_)) if mode.is_some() => {
+            log_warn("normalization_variants is ignored with a mode");
+        }
+        Some(Some(variants)) => return variants_match(&value, target, &variants, max_distance),
+        None => {
+            // Invalid normalization_variants, can't match
+            return 0;
+        }
+    }
+
+    // Apply normalizations to both operands
+    let mut normalization = get_normalization();
+    if let Some(mode @ ("phone" | "numeric_typo")) = mode {
+        for (operand, name) in [(&value[..], "field"), (target, "target")] {
+            if spells_non_finite(operand) {
+                log_error(&format!("{} {} is not a finite number", name, loggable(operand)));
+                return DIAG_INVALID;
+            }
+        }
+        // Text normalizations are meaningless on digits
+        let digits = normalization.for_digits();
+        if digits != normalization {
+            log_warn(&format!("text normalizations ignored in \"{}\" mode", mode));
+            normalization = digits;
+        }
+    }
+    let original = target;
+    let (value, target) = match normalize_operands(&value, target, normalization) {
+        Some(operands) => operands,
+        None => return DIAG_OVERFLOW,
+    };
+    EXACT_MATCH = value == target;
+
+    // A target emptied by normalization would match nearly everything short
+    if target.is_empty() {
+        log_warn(&format!("target {} is empty after normalization", loggable(original)));
+        MATCH_REASON = MatchReason::NotCompared;
+        return get_bool_param("empty_target_matches").unwrap_or(false) as i32;
+    }
+
+    // Empty fields get a fixed result, like missing ones
+    if value.trim().is_empty() {
+        if let Some(result) = empty_field_result() {
+            MATCH_REASON = MatchReason::NotCompared;
+            return result;
+        }
+    }
+
+    // Blocking key: cheap prefix check before any scoring
+    if let Some(k) = get_i64_param("require_prefix_len").filter(|&k| k > 0) {
+        if !shares_prefix(&value, target, k as usize) {
+            MATCH_REASON = MatchReason::PrefixRejected;
+            return 0;
+        }
+    }
+    // A match that disagrees early is likely spurious, however few the edits
+    if let Some(k) = get_i64_param("min_match_prefix").filter(|&k| k > 0) {
+        if common_prefix_len(&value, target) < k as usize {
+            MATCH_REASON = MatchReason::PrefixRejected;
+            return 0;
+        }
+    }
+
+    // Fixed-format codes: edits may not change the length
+    let equal_length = get_bool_param("require_equal_length").unwrap_or(false);
+    if equal_length && value.chars().count() != target.chars().count() {
+        MATCH_REASON = MatchReason::EqualLengthRejected;
+        return 0;
+    }
+
+    // Cross-script operands (e.g. Latin vs Cyrillic look-alikes) cost extra
+    let script_penalty = match get_script_mismatch_penalty() {
+        0 => 0,
+        penalty if scripts_differ(&value, target) => penalty,
+        _ => 0,
+    };
+    if script_penalty == usize::MAX {
+        return 0;
+    }
+
+    match mode {
+        None => {}
+        Some("email") => {
+            let max_local = get_distance_param("max_local").unwrap_or(max_distance);
+            let max_domain = get_distance_param("max_domain").unwrap_or(max_distance);
+            let distance = email_distance(&value, target, max_local, max_domain);
+            // Exact when the canonical forms agree, whatever the raw text
+            EXACT_MATCH = distance == Some(0);
+            return distance.is_some() as i32;
+        }
+        Some("phone") => {
+            let country_code = get_i64_param("country_code").map(|code| code.to_string());
+            let distance = phone_distance(&value, target, country_code.as_deref(), max_distance);
+            EXACT_MATCH = distance == Some(0);
+            return distance.is_some() as i32;
+        }
+        Some("numeric_typo") => {
+            let distance = numeric_typo_distance(&value, target, max_distance);
+            EXACT_MATCH = distance == Some(0);
+            return distance.is_some() as i32;
+        }
+        Some("url") => {
+            let distance = levenshtein_within(
+                &normalize_url(&value),
+                &normalize_url(target),
+                max_distance,
+            );
+            EXACT_MATCH = distance == Some(0);
+            return distance.is_some() as i32;
+        }
+        Some("date") => {
+            let max_days = distance_limit.unwrap_or(0);
+            let days = date_distance(&value, target, max_days);
+            EXACT_MATCH = days == Some(0);
+            return days.is_some() as i32;
+        }
+        Some("partial") => {
+            let stepping = get_stepping(max_distance);
+            let (distance, _, _) = stepped_window(&value, target, &stepping, max_distance);
+            EXACT_MATCH = distance == 0;
+            return (distance <= max_distance) as i32;
+        }
+        Some("token_prefix") => {
+            let tokenizer = match get_tokenizer() {
+                Some(tokenizer) => tokenizer,
+                None => {
+                    // Unknown tokenizer, can't match
+                    return 0;
+                }
+            };
+            let distance = token_prefix_distance(&value, target, &tokenizer, max_distance);
+            EXACT_MATCH = distance == 0;
+            return (distance <= max_distance) as i32;
+        }
+        Some("suffix") => {
+            let distance = suffix_distance(&value, target, max_distance);
+            EXACT_MATCH = distance == 0;
+            return (distance <= max_distance) as i32;
+        }
+        Some("name_order") => {
+            let distance = name_order_distance(&value, target, max_distance);
+            EXACT_MATCH = distance == Some(0);
+            return distance.is_some() as i32;
+        }
+        Some(_) => {
+            // Unknown mode, can't match
+            return 0;
+        }
+    }
+
+    let mut policy = [0u8; 32];
+    match get_string_param("match_policy", &mut policy) {
+        None | Some("algorithm") => {}
+        #[cfg(feature = "jaro")]
+        Some("distance_or_similarity") => {
+            let min_similarity = if exact_only {
+                1.0
+            } else {
+                get_f64_param("min_similarity")
+                    .or(threshold.and_then(Threshold::similarity))
+                    .unwrap_or(0.8)
+            };
+            return distance_or_similarity_match(&value, target, max_distance, min_similarity)
+                as i32;
+        }
+        Some("consensus") => {
+            let min_similarity = if exact_only {
+                1.0
+            } else {
+                get_f64_param("min_similarity")
+                    .or(threshold.and_then(Threshold::similarity))
+                    .unwrap_or(0.8)
+            };
+            return consensus_match(&value, target, max_distance, min_similarity);
+        }
+        Some(_) => {
+            // Unknown policy, can't match
+            return 0;
+        }
+    }
+
+    let algorithm = match get_algorithm() {
+        Some(algorithm) => algorithm,
+        None => {
+            // Unknown algorithm, can't match
+            return 0;
+        }
+    };
+
+    match limit_conflict(&algorithm) {
+        None => {}
+        Some(LimitConflict::Both(own)) => {
+            let (own, other) = (own.name(), own.other().name());
+            log_warn(&format!("{} ignored, this algorithm is matched against {}", other, own));
+        }
+        Some(LimitConflict::Wrong(own)) => {
+            let (own, other) = (own.name(), own.other().name());
+            log_error(&format!("{} doesn't apply to this algorithm, set {}", other, own));
+            return DIAG_INVALID;
+        }
+    }
+
+    if matches!(algorithm, Algorithm::ExactNormalized) {
+        return (value == target) as i32;
+    }
+
+    #[cfg(feature = "ngram")]
+    if let Algorithm::TrigramCoverage { pad, max_missing } = algorithm {
+        let (missing, _) = target_missing_grams(&value, target, 3, pad);
+        return (missing <= max_missing) as i32;
+    }
+
+    // The shared run is checked first, so no field gets past a
+    // `min_common_len` it falls short of, not even one equal to an
+    // exact-only target
+    #[cfg(feature = "substring")]
+    if let Algorithm::Lcstr { min_common_len } = algorithm {
+        if !lcstr_match(&value, target, min_common_len) {
+            return 0;
+        }
+        return (!exact_only || value == target) as i32;
+    }
+
+    #[cfg(feature = "phonetic")]
+    if let Algorithm::PhoneticKey { tiebreak } = algorithm {
+        return phonetic_key_match(&value, target, max_distance, tiebreak) as i32;
+    }
+
+    #[cfg(feature = "jaro")]
+    if let Algorithm::Windowed { window } = algorithm {
+        let distance = windowed_distance(&value, target, window);
+        EXACT_MATCH = distance == 0;
+        return (distance <= max_distance) as i32;
+    }
+
+    let min_similarity = if exact_only {
+        1.0
+    } else {
+        get_f64_param("min_similarity")
+            .or(threshold.and_then(Threshold::similarity))
+            .unwrap_or(0.8)
+    };
+
+    if !matches!(algorithm, Algorithm::Levenshtein) {
+        return (algorithm.similarity(&value, target) >= min_similarity) as i32;
+    }
+
+    // A similarity threshold compares normalized Levenshtein similarity
+    if let (None, Some(Threshold::Similarity(_))) = (distance_limit, threshold) {
+        return (levenshtein_similarity(&value, target) >= min_similarity) as i32;
+    }
+
+    // The script and repeat penalties eat into the edit budget
+    let penalty = script_penalty.saturating_add(get_repeat_penalty(&value));
+    let max_distance = match max_distance.checked_sub(penalty) {
+        Some(max_distance) => max_distance,
+        None => return 0,
+    };
+
+    let sub_costs = match get_sub_costs() {
+        Some(sub_costs) => sub_costs,
+        None => {
+            // Malformed cost table, can't match
+            return 0;
+        }
+    };
+
+    let case_mismatch = match get_f64_param("case_mismatch_cost") {
+        Some(cost) if !(cost.is_finite() && cost >= 0.0) => {
+            // Invalid cost, can't match
+            return 0;
+        }
+        case_mismatch => case_mismatch,
+    };
+
+    let variant = match get_f64_param("variant_cost") {
+        Some(cost) if !(cost.is_finite() && cost >= 0.0) => {
+            // Invalid cost, can't match
+            return 0;
+        }
+        variant => variant,
+    };
+
+    let position_decay = match get_position_decay() {
+        Some(position_decay) => position_decay,
+        None => {
+            // Unknown weighting or invalid decay, can't match
+            return 0;
+        }
+    };
+    let costs = Costs {
+        table: sub_costs,
+        case_mismatch,
+        variant,
+        position_decay,
+    };
+    let budgets = get_class_budgets();
+
+    // Equal lengths were checked above; only substitutions remain
+    if equal_length {
+        let bound = max_distance as f64;
+        let distance = substitution_distance(&value, target, &costs, bound);
+        return (within_bound(distance, bound) && budgets.allow_substitutions(&value, target))
+            as i32;
+    }
+
+    // Substitution costs from the query switch to the weighted DP; the
+    // class budgets count unit edits, whatever they cost
+    if costs.is_weighted() {
+        let bound = max_distance as f64;
+        let distance = weighted_levenshtein(&value, target, &costs, bound);
+        return (within_bound(distance, bound) && budgets.allow(&value, target, usize::MAX))
+            as i32;
+    }
+
+    // Return 1 if within threshold, 0 otherwise
+    let within = levenshtein_within(&value, target, max_distance).is_some();
+    if !within && length_rejected(&value, target, max_distance) {
+        MATCH_REASON = MatchReason::LengthPrefilterRejected;
+    }
+    (within && budgets.allow(&value, target, max_distance)) as i32
+}
+
+/// Whether `levenshtein_within` turns `s1` and `s2` down on their lengths
+/// alone, before any DP
+fn length_rejected(s1: &str, s2: &str, max: usize) -> bool {
+    s1.chars().count().abs_diff(s2.chars().count()) > max
+}
+
+/// The `normalization_variants` decision: the Levenshtein distance under
+/// each variant, the smallest within `max_distance` matching
+///
+/// Each variant's normalized target is kept in `VARIANT_TARGETS` and the
+/// field's forms in `FIELD_MEMO`, and a variant that normalizes both
+/// operands as an earlier one did is not compared again. Later variants are
+/// bounded by the best distance so far, and the search stops at 0. A
+/// variant emptying the target is skipped. Only the distance applies, as in
+/// `filter_sorted`: no algorithm, cost or penalty.
+unsafe fn variants_match(
+    value: &str,
+    target: &str,
+    variants: &[Normalization],
+    max_distance: usize,
+) -> i32 {
+    if VARIANT_TARGETS.len() < variants.len() {
+        VARIANT_TARGETS.resize_with(variants.len(), TargetCache::new);
+    }
+    let limit = get_max_target_len().max(TARGET_BUFFER.len());
+
+    let mut best: Option<usize> = None;
+    let mut compared: Vec<(Cow<str>, &str)> = Vec::with_capacity(variants.len());
+    for (&normalization, cache) in variants.iter().zip(VARIANT_TARGETS.iter_mut()) {
+        let value = match normalize_value(value, normalization) {
+            Some(value) => value,
+            None => return DIAG_OVERFLOW,
+        };
+        let target = match cache.get(target, normalization, limit) {
+            Ok(target) => target,
+            Err(Overflow { len }) => {
+                log_error(&format!("normalized target is {} bytes, over {}", len, limit));
+                return DIAG_OVERFLOW;
+            }
+        };
+        if target.is_empty() || compared.iter().any(|(v, t)| *v == value && *t == target) {
+            continue;
+        }
+
+        let bound = best.map_or(max_distance, |best| best - 1);
+        if let Some(distance) = levenshtein_within(&value, target, bound) {
+            best = Some(distance);
+            if distance == 0 {
+                break;
+            }
+        }
+        compared.push((value, target));
+    }
+
+    EXACT_MATCH = best == Some(0);
+    best.is_some() as i32
+}
+
+/// Whether a field value byte-identical to the target is sure to match
+///
+/// Equal strings are at distance 0, which Levenshtein compared against
+/// `max_distance`, the `"distance_or_similarity"` policy and the
+/// `"partial"`, `"suffix"` and `"name_order"` modes always accept, as does
+/// `exact_normalized`; such a value skips scoring. Similarity algorithms
+/// still score it, since a `min_similarity` above 1.0 or a tokenizer that
+/// finds no tokens rejects even equal strings, and so does Levenshtein
+/// compared by `similarity_threshold`. The other modes parse their
+/// operands (an unparseable date doesn't even match itself), blank values
+/// get `empty_field_behavior` and `penalize_repeats` may charge the value
+/// more edits than allowed, so those take the full path too. So do invalid
+/// settings, which match nothing, a limit conflict (see `limit_conflict`)
+/// and `sub_costs`, whose table would have to be parsed to tell. A value
+/// shorter than `min_match_prefix` doesn't match even itself. `value` is
+/// the normalized form of both.
+unsafe fn identical_matches(value: &str, mode: Option<&str>, similarity_threshold: bool) -> bool {
+    let mut policy = [0u8; 32];
+    let accepts_identity = match (mode, get_string_param("match_policy", &mut policy)) {
+        (Some("partial" | "suffix" | "name_order"), _) => true,
+        (Some(_), _) => false,
+        (None, Some("distance_or_similarity")) => true,
+        (None, None | Some("algorithm")) => match get_algorithm() {
+            Some(algorithm) if limit_conflict(&algorithm).is_some() => false,
+            Some(Algorithm::Levenshtein) => !similarity_threshold,
+            Some(Algorithm::ExactNormalized) => true,
+            _ => false,
+        },
+        (None, Some(_)) => false,
+    };
+    accepts_identity
+        && !value.trim().is_empty()
+        && get_bool_param("penalize_repeats") != Some(true)
+        && get_json_param("sub_costs").is_none()
+        && get_f64_param("case_mismatch_cost").is_none_or(|cost| cost.is_finite() && cost >= 0.0)
+        && get_f64_param("variant_cost").is_none_or(|cost| cost.is_finite() && cost >= 0.0)
+        && get_position_decay().is_some()
+        && get_f64_param("doc_freq_weight").is_none_or(|weight| (0.0..=1.0).contains(&weight))
+        && get_variants().is_some()
+        && get_i64_param("min_match_prefix").is_none_or(|k| value.chars().count() as i64 >= k)
+}
+
+/// The `"distance_or_similarity"` match policy: within `max_distance`
+/// edits, or else at least `min_similarity` Jaro-Winkler similarity
+///
+/// The banded DP usually settles it cheaply, so the similarity is only
+/// computed for fields it rejects. An `algorithm` set as well has no say
+/// and is ignored with a warning.
+#[cfg(feature = "jaro")]
+unsafe fn distance_or_similarity_match(
+    value: &str,
+    target: &str,
+    max_distance: usize,
+    min_similarity: f64,
+) -> bool {
+    let mut buffer = [0u8; 32];
+    if get_string_param("algorithm", &mut buffer).is_some() {
+        log_warn("algorithm ignored with match_policy \"distance_or_similarity\"");
+    }
+    levenshtein_within(value, target, max_distance).is_some()
+        || jaro_winkler_similarity(value, target) >= min_similarity
+}
+
+/// The `"consensus"` match policy: every one of `components` passes its own
+/// limit
+///
+/// The components are checked in their listed order and the first to fail
+/// decides, so the cheap ones belong first. A component without a limit of
+/// its own is held to the query's `max_distance` or `min_similarity`. An
+/// `algorithm` set as well has no say and is ignored with a warning, and
+/// invalid components are an error (see `get_consensus`).
+unsafe fn consensus_match(
+    value: &str,
+    target: &str,
+    max_distance: usize,
+    min_similarity: f64,
+) -> i32 {
+    let mut buffer = [0u8; 32];
+    if get_string_param("algorithm", &mut buffer).is_some() {
+        log_warn("algorithm ignored with match_policy \"consensus\"");
+    }
+    let components = match get_consensus(max_distance, min_similarity) {
+        Some(components) => components,
+        None => return DIAG_INVALID,
+    };
+    components
+        .iter()
+        .all(|(algorithm, limit)| component_passes(algorithm, *limit, value, target)) as i32
+}
+
+/// Read the `components` of the `"consensus"` policy, each an algorithm
+/// and the limit it must pass
+///
+/// `components` is a JSON array of `{"algorithm": name}` objects, each
+/// with the limit its algorithm is matched against (`max_distance` or
+/// `min_similarity`, see `Algorithm::limit`) or else the query's. The limit
+/// is returned as a number of edits or a similarity, whichever applies.
+/// Logs and returns `None` when the list is missing, empty or malformed,
+/// names an unknown algorithm or one not compiled in, or gives a component
+/// a limit it isn't matched against.
+unsafe fn get_consensus(max_distance: usize, min_similarity: f64) -> Option<Vec<(Algorithm, f64)>> {
+    let entries = get_json_param("components").and_then(json::parse);
+    let entries = match entries.as_ref().and_then(json::Value::as_array) {
+        Some(entries) if !entries.is_empty() => entries,
+        _ => {
+            log_error("match_policy \"consensus\" needs a non-empty components array");
+            return None;
+        }
+    };
+
+    let mut components = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let name = match entry.get("algorithm").and_then(json::Value::as_str) {
+            Some(name) => name,
+            None => {
+                log_error("a consensus component has no algorithm");
+                return None;
+            }
+        };
+        if let Some(feature) = missing_feature(name) {
+            log_error(&format!(
+                "algorithm \"{}\" is not compiled in, it needs the \"{}\" feature",
+                name, feature
+            ));
+            return None;
+        }
+        let algorithm = match algorithm_from_name(name) {
+            Some(algorithm) => algorithm,
+            None => {
+                log_error(&format!("unknown or invalid consensus algorithm \"{}\"", name));
+                return None;
+            }
+        };
+
+        let own = algorithm.limit();
+        let stray = [Limit::MaxDistance, Limit::MinSimilarity]
+            .into_iter()
+            .find(|&limit| Some(limit) != own && entry.get(limit.name()).is_some());
+        if let Some(stray) = stray {
+            let stray = stray.name();
+            log_error(&format!("{} doesn't apply to consensus algorithm \"{}\"", stray, name));
+            return None;
+        }
+        let limit = match own {
+            Some(Limit::MaxDistance) => match entry.get("max_distance") {
+                None => Some(max_distance as f64),
+                Some(limit) => limit.as_f64().filter(|d| *d >= 0.0 && d.fract() == 0.0),
+            },
+            Some(Limit::MinSimilarity) => match entry.get("min_similarity") {
+                None => Some(min_similarity),
+                Some(limit) => limit.as_f64().filter(|s| s.is_finite()),
+            },
+            None => Some(0.0),
+        };
+        match limit {
+            Some(limit) => components.push((algorithm, limit)),
+            None => {
+                log_error(&format!("invalid limit for consensus algorithm \"{}\"", name));
+                return None;
+            }
+        }
+    }
+    Some(components)
+}
+
+/// Whether `algorithm` alone accepts the operands within `limit`, a number
+/// of edits or a similarity as `Algorithm::limit` says
+fn component_passes(algorithm: &Algorithm, limit: f64, value: &str, target: &str) -> bool {
+    match algorithm {
+        Algorithm::Levenshtein => levenshtein_within(value, target, limit as usize).is_some(),
+        Algorithm::ExactNormalized => value == target,
+        #[cfg(feature = "ngram")]
+        Algorithm::TrigramCoverage { pad, max_missing } => {
+            target_missing_grams(value, target, 3, *pad).0 <= *max_missing
+        }
+        #[cfg(feature = "substring")]
+        Algorithm::Lcstr { min_common_len } => lcstr_match(value, target, *min_common_len),
+        #[cfg(feature = "phonetic")]
+        Algorithm::PhoneticKey { tiebreak } => {
+            phonetic_key_match(value, target, limit as usize, *tiebreak)
+        }
+        #[cfg(feature = "jaro")]
+        Algorithm::Windowed { window } => {
+            windowed_distance(value, target, *window) <= limit as usize
+        }
+        #[cfg(feature = "ngram")]
+        Algorithm::Ngram { .. } => algorithm.similarity(value, target) >= limit,
+        #[cfg(feature = "jaro")]
+        Algorithm::Jaro | Algorithm::JaroWinkler => algorithm.similarity(value, target) >= limit,
+        #[cfg(feature = "token")]
+        Algorithm::TokenSetRatio { .. } => algorithm.similarity(value, target) >= limit,
+        #[cfg(all(feature = "phonetic", feature = "ngram"))]
+        Algorithm::NameMatch { .. } => algorithm.similarity(value, target) >= limit,
+    }
+}
+
+/// `"mode": "set_pairing"`: the field and the target are arrays of strings
+///
+/// The field is read with `get_field_array` and the target, the `target`
+/// parameter or the document's `target_field`, with `get_param_array` or
+/// `get_field_array`. They match when the cheapest pairing (see
+/// `pairing_cost`), with `unmatched_penalty` (default 1.0) per leftover
+/// element, costs at most `max_total` (default `max_distance`). A target
+/// from `targets` is text, not an array, and is an error, as is a build
+/// without the "arrays" feature.
+unsafe fn set_pairing_match(
+    ctx_id: i64,
+    field_override: Option<&str>,
+    target_override: Option<&str>,
+) -> i32 {
+    #[cfg(not(feature = "arrays"))]
+    {
+        let _ = (ctx_id, field_override, target_override);
+        log_error("\"set_pairing\" mode needs a build with the \"arrays\" feature");
+        DIAG_INVALID
+    }
+
+    #[cfg(feature = "arrays")]
+    {
+        if target_override.is_some() {
+            log_error("\"set_pairing\" mode compares one target array, not targets");
+            return DIAG_INVALID;
+        }
+        let threshold = match get_threshold() {
+            Some(threshold) => threshold,
+            None => {
+                // Invalid or ambiguous threshold, can't match
+                return 0;
+            }
+        };
+        let max_distance = get_distance_param("max_distance")
+            .or(threshold.and_then(Threshold::distance))
+            .unwrap_or(2);
+        if budget::stopped() {
+            return 0;
+        }
+
+        let normalization = get_normalization();
+        let mut name = [0u8; 256];
+        let target = match get_string_param("target_field", &mut name) {
+            Some(target_field) => get_array_field(ctx_id, target_field, normalization),
+            None => get_array_param("target", normalization),
+        };
+        let field_name = match field_override {
+            Some(field_name) => field_name,
+            None => get_string_param("field", &mut name).unwrap_or("name"),
+        };
+        let field = get_array_field(ctx_id, field_name, normalization);
+        #[cfg(feature = "stats")]
+        stats::field_examined();
+        MATCH_REASON = MatchReason::Computed;
+
+        let (field, target) = match (field, target) {
+            (Some(Ok(field)), Some(Ok(target))) => (field, target),
+            (Some(Err(ListError::TooLong)), _) | (_, Some(Err(ListError::TooLong))) => {
+                return DIAG_OVERFLOW
+            }
+            _ => {
+                // Missing, or not arrays of strings, can't match
+                return 0;
+            }
+        };
+        let unmatched = match get_f64_param("unmatched_penalty").unwrap_or(1.0) {
+            penalty if penalty.is_finite() && penalty >= 0.0 => penalty,
+            _ => {
+                // Invalid penalty, can't match
+                return 0;
+            }
+        };
+        let max_total = get_f64_param("max_total").unwrap_or(max_distance as f64);
+
+        match pairing_cost(&field, &target, unmatched) {
+            Some(cost) => {
+                EXACT_MATCH = cost == 0.0;
+                within_bound(cost, max_total) as i32
+            }
+            None => {
+                log_warn(&format!("set_pairing compares at most {} elements", MAX_ELEMENTS));
+                0
+            }
+        }
+    }
+}
+
+
+/// The `per_line` decision: the field line closest to the target
+///
+/// Each line ("\n" or "\r\n" separated) is normalized and compared on its
+/// own, each against a bound just under the best distance so far, until
+/// one is identical. Trailing blank lines are dropped (a blank line in the
+/// middle is compared like any other).
+unsafe fn per_line_match(value: &str, target: &str, max_distance: usize) -> i32 {
+    let normalization = get_normalization();
+    let target = match normalized_target(target, normalization) {
+        Some(target) => target,
+        None => return DIAG_OVERFLOW,
+    };
+
+    let lines: Vec<&str> = value.lines().collect();
+    let end = lines.iter().rposition(|line| !line.trim().is_empty()).map_or(0, |i| i + 1);
+    let mut best: Option<usize> = None;
+    for line in &lines[..end] {
+        let line = match normalize_value(line, normalization) {
+            Some(line) => line,
+            None => return DIAG_OVERFLOW,
+        };
+        let bound = best.map_or(max_distance, |distance| distance - 1);
+        if let Some(distance) = levenshtein_within(&line, target, bound) {
+            best = Some(distance);
+            if distance == 0 {
+                break;
+            }
+        }
+    }
+
+    EXACT_MATCH = best == Some(0);
+    best.is_some() as i32
+}
+
+/// The `pattern` decision: the field against the code pattern in `target`
+///
+/// Only the field is normalized, so literal chars of the pattern are
+/// compared with the normalized field (write "sku-####" with
+/// `case_insensitive`). Normalizing still helps: `collapse_whitespace`
+/// drops padding that would otherwise count as extra chars.
+unsafe fn pattern_match(value: &str, target: &str, max_distance: usize) -> i32 {
+    let value = match normalize_value(value, get_normalization()) {
+        Some(value) => value,
+        None => return DIAG_OVERFLOW,
+    };
+
+    let distance = pattern_distance(&value, target, max_distance);
+    EXACT_MATCH = distance == 0;
+    (distance <= max_distance) as i32
+}
+
+/// The `glob` decision: the field against the glob pattern in `target`
+///
+/// As for `pattern`, only the field is normalized. A budget of its own
+/// overrides `max_distance` for its segment, in both directions.
+unsafe fn glob_match(value: &str, target: &str, max_distance: usize) -> i32 {
+    let glob = match Glob::parse(target, max_distance) {
+        Some(glob) => glob,
+        None => {
+            // Malformed budget, can't match
+            return 0;
+        }
+    };
+    let value = match normalize_value(value, get_normalization()) {
+        Some(value) => value,
+        None => return DIAG_OVERFLOW,
+    };
+
+    EXACT_MATCH = glob.matches_exactly(&value);
+    (EXACT_MATCH || glob.matches(&value)) as i32
+}
+
+/// `"mode": "regex"`: the whole field matches the `pattern` regex within
+/// `max_distance` edits, 0 unless set
+///
+/// The target takes no part. Only the field is normalized, as the pattern
+/// is not text, and the edits are counted by `Regex::full_match_distance`.
+/// A missing pattern, or one malformed or over the complexity bounds of the
+/// `regex` module, is an error.
+unsafe fn regex_match(ctx_id: i64, field_override: Option<&str>) -> i32 {
+    let pattern = match get_json_param("pattern") {
+        Some(pattern) => pattern,
+        None => {
+            log_error("\"regex\" mode needs a pattern");
+            return DIAG_INVALID;
+        }
+    };
+    let regex = match Regex::new(pattern) {
+        Some(regex) => regex,
+        None => {
+            log_error("pattern is not a supported regex");
+            return DIAG_INVALID;
+        }
+    };
+    let threshold = match get_threshold() {
+        Some(threshold) => threshold,
+        None => {
+            // Invalid or ambiguous threshold, can't match
+            return 0;
+        }
+    };
+    let max_distance = get_distance_param("max_distance")
+        .or(threshold.and_then(Threshold::distance))
+        .unwrap_or(0);
+
+    let value = match get_value(ctx_id, field_override) {
+        Some(value) => value,
+        None => return 0,
+    };
+    #[cfg(feature = "stats")]
+    stats::field_examined();
+    let value = match normalize_value(&value, get_normalization()) {
+        Some(value) => value,
+        None => return DIAG_OVERFLOW,
+    };
+
+    MATCH_REASON = MatchReason::Computed;
+    let chars: Vec<char> = value.chars().collect();
+    match regex.full_match_distance(&chars, max_distance) {
+        Some(distance) => {
+            EXACT_MATCH = distance == 0;
+            1
+        }
+        None => 0,
+    }
+}
+
+/// The `token_set_ratio` decision on tokens the host already split
+///
+/// `tokens_field` names an array field holding the field's tokens, read
+/// with `get_field_array`, and `target_tokens` is an array parameter read
+/// with `get_param_array`; an operand without either is tokenized as
+/// usual. Each token is normalized on its own, so normalizations that only
+/// change chars give the tokens tokenizing the normalized text would.
+/// Returns `None` to match as usual: neither parameter set (or the field is
+/// one of `fields`), a `mode`, or another algorithm. Without the "arrays"
+/// feature the tokens can't be read, which is an error.
+#[cfg(feature = "token")]
+unsafe fn filter_tokens(
+    ctx_id: i64,
+    field_override: Option<&str>,
+    target_override: Option<&str>,
+) -> Option<i32> {
+    let mut name = [0u8; 256];
+    let tokens_field = get_string_param("tokens_field", &mut name);
+    let target_tokens = target_override.is_none() && param_is_set("target_tokens");
+    if field_override.is_some() || (tokens_field.is_none() && !target_tokens) {
+        return None;
+    }
+    if get_mode().is_some() {
+        return None;
+    }
+    let algorithm = get_algorithm()?;
+    let (tokenizer, idf, mixed_script_penalty) = match &algorithm {
+        Algorithm::TokenSetRatio { tokenizer, idf, mixed_script_penalty } => {
+            (tokenizer, idf, *mixed_script_penalty)
+        }
+        _ => return None,
+    };
+
+    #[cfg(not(feature = "arrays"))]
+    {
+        let _ = (ctx_id, tokenizer, idf, mixed_script_penalty);
+        log_error("tokens_field and target_tokens need a build with the \"arrays\" feature");
+        Some(DIAG_INVALID)
+    }
+
+    #[cfg(feature = "arrays")]
+    {
+        match limit_conflict(&algorithm) {
+            None => {}
+            Some(LimitConflict::Both(own)) => {
+                let (own, other) = (own.name(), own.other().name());
+                log_warn(&format!("{} ignored, this algorithm is matched against {}", other, own));
+            }
+            Some(LimitConflict::Wrong(own)) => {
+                let (own, other) = (own.name(), own.other().name());
+                log_error(&format!("{} doesn't apply to this algorithm, set {}", other, own));
+                return Some(DIAG_INVALID);
+            }
+        }
+        let threshold = match get_threshold() {
+            Some(threshold) => threshold,
+            None => {
+                // Invalid or ambiguous threshold, can't match
+                return Some(0);
+            }
+        };
+        let min_similarity = get_f64_param("min_similarity")
+            .or(threshold.and_then(Threshold::similarity))
+            .unwrap_or(0.8);
+
+        let normalization = get_normalization();
+        // An operand's tokens by tokenizing its text
+        let tokenized = |text: &str| -> Result<Vec<String>, ListError> {
+            let text = normalize_value(text, normalization).ok_or(ListError::Malformed)?;
+            Ok(tokenizer.tokenize(&text).into_iter().map(String::from).collect())
+        };
+
+        let field_tokens = match tokens_field {
+            Some(tokens_field) => match get_array_field(ctx_id, tokens_field, normalization) {
+                Some(tokens) => tokens,
+                None => return Some(0),
+            },
+            None => match get_value(ctx_id, None) {
+                Some(value) => tokenized(&value),
+                None => return Some(0),
+            },
+        };
+        let target_tokens = match target_override {
+            Some(target) => tokenized(target),
+            None if target_tokens => match get_array_param("target_tokens", normalization) {
+                Some(tokens) => tokens,
+                None => return Some(0),
+            },
+            None => match get_target(ctx_id) {
+                Ok(Some(target)) => tokenized(target),
+                Ok(None) => return Some(0),
+                Err(TargetTooLong) => return Some(DIAG_INVALID),
+            },
+        };
+        let (field_tokens, target_tokens) = match (field_tokens, target_tokens) {
+            (Ok(field_tokens), Ok(target_tokens)) => (field_tokens, target_tokens),
+            (Err(ListError::TooLong), _) | (_, Err(ListError::TooLong)) => {
+                return Some(DIAG_OVERFLOW)
+            }
+            _ => {
+                // Not an array of strings or an overflowing token, can't match
+                return Some(0);
+            }
+        };
+
+        // Empty tokens are no tokens, as tokenizing never gives one
+        let field: Vec<&str> =
+            field_tokens.iter().map(String::as_str).filter(|t| !t.is_empty()).collect();
+        let target: Vec<&str> =
+            target_tokens.iter().map(String::as_str).filter(|t| !t.is_empty()).collect();
+        EXACT_MATCH = field == target;
+        MATCH_REASON = MatchReason::Computed;
+        let similarity = token_set_ratio_of(field, target, idf.as_ref(), mixed_script_penalty);
+        Some((similarity >= min_similarity) as i32)
+    }
+}
+
+/// Normalize each element of an array operand
+///
+/// Arrays of more than `max_array_elements` elements are rejected before
+/// any is normalized; an element that overflows normalization makes the
+/// array malformed.
+#[cfg(feature = "arrays")]
+unsafe fn normalize_elements(
+    items: &[&str],
+    normalization: Normalization,
+) -> Result<Vec<String>, ListError> {
+    check_list_len("array operand", items.len(), get_max_array_elements())?;
+    items
+        .iter()
+        .map(|item| Some(normalize_value(item, normalization)?.into_owned()))
+        .collect::<Option<_>>()
+        .ok_or(ListError::Malformed)
+}
+
+/// `filter` for field values arriving in sorted order
+///
+/// Takes the same `field`, `target`, `max_distance` and normalization
+/// parameters as `filter`, plus `shared_prefix_len`: the number of chars
+/// the (normalized) field value shares with the previous call's. DP rows
+/// for that prefix are reused from the previous call and only the suffix
+/// is computed (see `PrefixDp` for why this is exact). The hint is checked
+/// against the cached value, so a wrong one is slow but never wrong. Only
+/// the Levenshtein distance check applies.
+///
+/// Returns:
+/// - 1 (i32) if the field value is within max_distance of target
+/// - 0 (i32) otherwise
+/// - -1 (i32) if `max_distance` is negative or `target` is longer than
+///   `max_target_len`
+/// - -2 (i32) if a normalized operand overflows, as in `filter`
+#[no_mangle]
+pub extern "C" fn filter_sorted(ctx_id: i64, shared_prefix_len: i32) -> i32 {
+    unsafe {
+        with_host_checks(DIAG_OVERFLOW, DIAG_INVALID, || {
+            let (value, target) = match get_operands(ctx_id, None, None) {
+                Ok(Some(operands)) => operands,
+                Ok(None) => return 0,
+                Err(TargetTooLong) => return DIAG_INVALID,
+            };
+
+            let max_distance = match get_max_distance() {
+                Some(max_distance) => max_distance,
+                None => return 0,
+            };
+
+            let normalization = get_normalization();
+            let (value, target) = match normalize_operands(&value, target, normalization) {
+                Some(operands) => operands,
+                None => return DIAG_OVERFLOW,
+            };
+
+            let hint = shared_prefix_len.max(0) as usize;
+            let distance = SORTED_DP.distance(&value, target, hint, max_distance);
+            (distance <= max_distance) as i32
+        })
+    }
+}
+
+/// The tier a field matches at: the smallest `d` in `[0, max_distance]`
+/// for which `max_distance: d` would match
+///
+/// Takes the same `field`, `target`, `max_distance` and normalization
+/// parameters as `filter`. Rather than retrying at distances 0, 1, 2, ...
+/// the banded DP runs once at `max_distance` and the tier is the distance
+/// it finds, which is exact whenever it is within the band. Like
+/// `filter_sorted`, only the Levenshtein distance check applies.
+///
+/// Returns:
+/// - the tier (i32), 0 for a field identical to the target after
+///   normalization
+/// - -1 (i32) if the field is farther than `max_distance`, is missing,
+///   there is no target, `max_distance` is negative (logged as an error)
+///   or `target` is longer than `max_target_len`
+/// - -2 (i32) if a normalized operand overflows, as in `filter`
+#[no_mangle]
+pub extern "C" fn filter_tier(ctx_id: i64) -> i32 {
+    unsafe {
+        with_host_checks(DIAG_OVERFLOW, DIAG_INVALID, || {
+            let (value, target) = match get_operands(ctx_id, None, None) {
+                Ok(Some(operands)) => operands,
+                Ok(None) | Err(TargetTooLong) => return -1,
+            };
+
+            let max_distance = match get_max_distance() {
+                Some(max_distance) => max_distance,
+                None => return -1,
+            };
+
+            let normalization = get_normalization();
+            let (value, target) = match normalize_operands(&value, target, normalization) {
+                Some(operands) => operands,
+                None => return DIAG_OVERFLOW,
+            };
+
+            match levenshtein_within(&value, target, max_distance) {
+                Some(distance) => distance.min(i32::MAX as usize) as i32,
+                None => -1,
+            }
+        })
+    }
+}
+
+/// The target closest to the field, by its bytes
+///
+/// Takes the same `field`, `targets` (or `target`, alone or split by
+/// `alternation_delimiter`), `max_distance` and normalization parameters
+/// as `filter`. Every target is compared with the field by Levenshtein
+/// distance after normalization, and the closest one wins, the first on a
+/// tie; only the plain distance applies, as in `filter_sorted`. The winner
+/// is written as given in the query, not normalized, to `out_ptr`, which
+/// holds `*out_len_ptr` bytes: its length goes to `out_len_ptr` and its
+/// distance to `out_distance_ptr`, whether or not it is within
+/// `max_distance`.
+///
+/// Returns:
+/// - 1 (i32) if the winner is within `max_distance`, 0 otherwise; with no
+///   target or a missing field the length is 0 and the distance -1
+/// - -1 (i32) if `max_distance` is negative, the `targets` list is
+///   malformed or `target` is longer than `max_target_len`
+/// - -2 (i32) if a normalized operand overflows or `targets` is over
+///   `max_targets`, as in `filter`
+/// - -3 (i32) if the winner doesn't fit in `*out_len_ptr` bytes; the length
+///   it needs is written to `out_len_ptr` and nothing to `out_ptr`
+///
+/// # Safety
+///
+/// `out_ptr` must be valid for writing `*out_len_ptr` bytes, and
+/// `out_len_ptr` and `out_distance_ptr` for an `i32` and an `i64`.
+#[no_mangle]
+pub unsafe extern "C" fn best_target(
+    ctx_id: i64,
+    out_ptr: *mut u8,
+    out_len_ptr: *mut i32,
+    out_distance_ptr: *mut i64,
+) -> i32 {
+    unsafe {
+        let capacity = (*out_len_ptr).max(0) as usize;
+        *out_len_ptr = 0;
+        *out_distance_ptr = -1;
+
+        let best = with_host_checks(Err(DIAG_OVERFLOW), Err(DIAG_INVALID), || {
+            let mut targets = match get_targets() {
+                Ok(Some(targets)) => targets,
+                Ok(None) => match get_target(ctx_id) {
+                    Ok(Some(target)) => vec![target.to_string()],
+                    Ok(None) => return Ok(None),
+                    Err(TargetTooLong) => return Err(DIAG_INVALID),
+                },
+                Err(ListError::Malformed) => return Err(DIAG_INVALID),
+                Err(ListError::TooLong) => return Err(DIAG_OVERFLOW),
+            };
+            let max_distance = match get_max_distance() {
+                Some(max_distance) => max_distance,
+                None => return Ok(None),
+            };
+
+            let normalization = get_normalization();
+            let mut best: Option<(usize, usize)> = None;
+            for (i, target) in targets.iter().enumerate() {
+                let (value, target) = match get_operands(ctx_id, None, Some(target)) {
+                    Ok(Some(operands)) => operands,
+                    _ => return Ok(None),
+                };
+                let (value, target) = match normalize_operands(&value, target, normalization) {
+                    Some(operands) => operands,
+                    None => return Err(DIAG_OVERFLOW),
+                };
+
+                // Only a target closer than the best so far needs its distance
+                let distance = match best {
+                    None => Some(levenshtein_distance(&value, target)),
+                    Some((_, 0)) => break,
+                    Some((_, best)) => levenshtein_within(&value, target, best - 1),
+                };
+                if let Some(distance) = distance {
+                    best = Some((i, distance));
+                }
+            }
+            Ok(best.map(|(i, distance)| (targets.swap_remove(i), distance, max_distance)))
+        });
+
+        let (target, distance, max_distance) = match best {
+            Ok(Some(best)) => best,
+            Ok(None) => return 0,
+            Err(code) => return code,
+        };
+        if target.len() > capacity {
+            *out_len_ptr = target.len().min(i32::MAX as usize) as i32;
+            return DIAG_TOO_SMALL;
+        }
+        core::ptr::copy_nonoverlapping(target.as_ptr(), out_ptr, target.len());
+        *out_len_ptr = target.len() as i32;
+        *out_distance_ptr = distance.min(i64::MAX as usize) as i64;
+        (distance <= max_distance) as i32
+    }
+}
+
+/// `filter` and the normalized similarity of `ratio` in one call
+///
+/// Takes the same `field`, `target`, `algorithm`, limit (`max_distance`,
+/// `min_similarity`, `threshold`) and normalization parameters as `filter`.
+/// The decision and the similarity come from one computation: for
+/// Levenshtein the full distance is computed once, and the document matches
+/// when it is within `max_distance` (or, with a similarity `threshold`,
+/// when `1 - distance / max_len` reaches it); every other algorithm's one
+/// similarity is compared with `min_similarity`, and `"trigram_coverage"`
+/// decides by the same count of missing trigrams its similarity is made
+/// of. Like `filter_sorted`, modes, costs and penalties don't apply.
+///
+/// Writes the similarity in [0, 1] to `score_ptr`, or -1.0 when there is
+/// none to report (any negative return, or a missing field or target).
+/// `length_penalty` (default 0) lowers the score, not the decision, by that
+/// much times the operands' length gap over the longer length.
+///
+/// Returns:
+/// - 1 (i32) if the field matches, 0 otherwise
+/// - -1 (i32) if `max_distance` is negative, `target` is longer than
+///   `max_target_len`, the algorithm is unknown, only the limit the
+///   algorithm doesn't use is set, or `length_penalty` isn't a finite,
+///   non-negative number
+/// - -2 (i32) if a normalized operand overflows, as in `filter`
+///
+/// # Safety
+///
+/// `score_ptr` must be valid for writing an `f32`.
+#[no_mangle]
+pub unsafe extern "C" fn filter_score(ctx_id: i64, score_ptr: *mut f32) -> i32 {
+    unsafe {
+        *score_ptr = -1.0;
+        let (result, score) = with_host_checks((DIAG_OVERFLOW, None), (DIAG_INVALID, None), || {
+            let (value, target) = match get_operands(ctx_id, None, None) {
+                Ok(Some(operands)) => operands,
+                Ok(None) => return (0, None),
+                Err(TargetTooLong) => return (DIAG_INVALID, None),
+            };
+
+            let threshold = match get_threshold() {
+                Some(threshold) => threshold,
+                None => return (0, None),
+            };
+            let algorithm = match get_algorithm() {
+                Some(algorithm) => algorithm,
+                None => return (DIAG_INVALID, None),
+            };
+            match limit_conflict(&algorithm) {
+                Some(LimitConflict::Wrong(own)) => {
+                    let (own, other) = (own.name(), own.other().name());
+                    log_error(&format!("{} doesn't apply to this algorithm, set {}", other, own));
+                    return (DIAG_INVALID, None);
+                }
+                Some(LimitConflict::Both(own)) => {
+                    let (own, other) = (own.name(), own.other().name());
+                    log_warn(&format!(
+                        "{} ignored, this algorithm is matched against {}",
+                        other, own
+                    ));
+                }
+                None => {}
+            }
+
+            let length_penalty = match get_length_penalty() {
+                Some(penalty) => penalty,
+                None => return (DIAG_INVALID, None),
+            };
+
+            let normalization = get_normalization();
+            let (value, target) = match normalize_operands(&value, target, normalization) {
+                Some(operands) => operands,
+                None => return (DIAG_OVERFLOW, None),
+            };
+
+            let distance_limit = get_distance_param("max_distance")
+                .or(threshold.and_then(Threshold::distance));
+            let min_similarity = get_f64_param("min_similarity")
+                .or(threshold.and_then(Threshold::similarity))
+                .unwrap_or(0.8);
+
+            // The similarity algorithms, matched against `min_similarity`
+            #[cfg(any(feature = "ngram", feature = "jaro", feature = "token"))]
+            let by_similarity = |algorithm: &Algorithm| {
+                let similarity = algorithm.similarity(&value, target);
+                (similarity >= min_similarity, similarity)
+            };
+            let (matched, similarity) = match algorithm {
+                Algorithm::Levenshtein => {
+                    let distance = levenshtein_distance(&value, target);
+                    let max_len = value.chars().count().max(target.chars().count());
+                    let similarity = match max_len {
+                        0 => 1.0,
+                        max_len => 1.0 - distance as f64 / max_len as f64,
+                    };
+                    let matched = match (distance_limit, threshold) {
+                        (None, Some(Threshold::Similarity(_))) => similarity >= min_similarity,
+                        _ => distance <= distance_limit.unwrap_or(2),
+                    };
+                    (matched, similarity)
+                }
+                #[cfg(feature = "ngram")]
+                Algorithm::TrigramCoverage { pad, max_missing } => {
+                    let (missing, total) = target_missing_grams(&value, target, 3, pad);
+                    let similarity = match total {
+                        0 => 1.0,
+                        total => 1.0 - missing as f64 / total as f64,
+                    };
+                    (missing <= max_missing, similarity)
+                }
+                #[cfg(feature = "substring")]
+                Algorithm::Lcstr { min_common_len } => {
+                    let matched = lcstr_match(&value, target, min_common_len);
+                    (matched, lcstr_similarity(&value, target))
+                }
+                Algorithm::ExactNormalized => {
+                    let matched = value == target;
+                    (matched, if matched { 1.0 } else { 0.0 })
+                }
+                #[cfg(feature = "phonetic")]
+                Algorithm::PhoneticKey { tiebreak } => {
+                    let max_distance = distance_limit.unwrap_or(2);
+                    let matched = phonetic_key_match(&value, target, max_distance, tiebreak);
+                    (matched, algorithm.similarity(&value, target))
+                }
+                #[cfg(feature = "jaro")]
+                Algorithm::Windowed { window } => {
+                    let distance = windowed_distance(&value, target, window);
+                    let matched = distance <= distance_limit.unwrap_or(2);
+                    (matched, algorithm.similarity(&value, target))
+                }
+                #[cfg(feature = "ngram")]
+                Algorithm::Ngram { .. } => by_similarity(&algorithm),
+                #[cfg(feature = "jaro")]
+                Algorithm::Jaro | Algorithm::JaroWinkler => by_similarity(&algorithm),
+                #[cfg(feature = "token")]
+                Algorithm::TokenSetRatio { .. } => by_similarity(&algorithm),
+                #[cfg(all(feature = "phonetic", feature = "ngram"))]
+                Algorithm::NameMatch { .. } => by_similarity(&algorithm),
+            };
+            let score = length_penalized(similarity, &value, target, length_penalty);
+            (matched as i32, Some(score))
+        });
+
+        if let Some(score) = score.filter(|_| result >= 0) {
+            *score_ptr = score as f32;
+        }
+        result
+    }
+}
+
+/// `filter`'s Levenshtein distance counted in chars and in grapheme clusters
+///
+/// Takes the same `field`, `target`, `max_distance`/`threshold` and
+/// normalization parameters as `filter`, and computes the distance of the
+/// normalized operands twice: over chars, as `filter` counts it, and over
+/// extended grapheme clusters (see the `graphemes` module), where a flag,
+/// an emoji sequence or a letter with its combining marks is one unit. The
+/// document matches when the distance in `unit` (`"char"`, the default, or
+/// `"grapheme"`) is within `max_distance` (default 2). Like `filter_score`,
+/// modes, algorithms, costs and penalties don't apply.
+///
+/// Writes the char distance to `char_dist_ptr` and the grapheme distance to
+/// `grapheme_dist_ptr`, or -1 to both when there are none to report (any
+/// negative return, or a missing field or target).
+///
+/// Returns:
+/// - 1 (i32) if the field matches, 0 otherwise
+/// - -1 (i32) if `max_distance` is negative, `target` is longer than
+///   `max_target_len` or `unit` is unknown
+/// - -2 (i32) if a normalized operand overflows, as in `filter`
+///
+/// # Safety
+///
+/// `char_dist_ptr` and `grapheme_dist_ptr` must be valid for writing an
+/// `i64`.
+#[no_mangle]
+pub unsafe extern "C" fn filter_units(
+    ctx_id: i64,
+    char_dist_ptr: *mut i64,
+    grapheme_dist_ptr: *mut i64,
+) -> i32 {
+    unsafe {
+        *char_dist_ptr = -1;
+        *grapheme_dist_ptr = -1;
+        let (result, distances) = with_host_checks((DIAG_OVERFLOW, None), (DIAG_INVALID, None), || {
+            let (value, target) = match get_operands(ctx_id, None, None) {
+                Ok(Some(operands)) => operands,
+                Ok(None) => return (0, None),
+                Err(TargetTooLong) => return (DIAG_INVALID, None),
+            };
+
+            let threshold = match get_threshold() {
+                Some(threshold) => threshold,
+                None => return (0, None),
+            };
+            let graphemes = match get_unit_graphemes() {
+                Some(graphemes) => graphemes,
+                None => {
+                    log_error("unit must be \"char\" or \"grapheme\"");
+                    return (DIAG_INVALID, None);
+                }
+            };
+
+            let normalization = get_normalization();
+            let (value, target) = match normalize_operands(&value, target, normalization) {
+                Some(operands) => operands,
+                None => return (DIAG_OVERFLOW, None),
+            };
+
+            let max_distance = get_distance_param("max_distance")
+                .or(threshold.and_then(Threshold::distance))
+                .unwrap_or(2);
+            let chars = levenshtein_distance(&value, target);
+            let clusters = grapheme_distance(&value, target);
+            let distance = if graphemes { clusters } else { chars };
+            ((distance <= max_distance) as i32, Some((chars, clusters)))
+        });
+
+        if let Some((chars, graphemes)) = distances.filter(|_| result >= 0) {
+            *char_dist_ptr = chars.min(i64::MAX as usize) as i64;
+            *grapheme_dist_ptr = graphemes.min(i64::MAX as usize) as i64;
+        }
+        result
+    }
+}
+
+/// Read whether `filter_units` decides by grapheme clusters (`"unit":
+/// "grapheme"`) rather than chars (`"char"`, the default)
+///
+/// Returns `None` for any other unit.
+unsafe fn get_unit_graphemes() -> Option<bool> {
+    let mut buffer = [0u8; 32];
+    match get_string_param("unit", &mut buffer) {
+        None | Some("char") => Some(false),
+        Some("grapheme") => Some(true),
+        Some(_) => None,
+    }
+}
+
+/// Similarity as an integer percentage, like fuzzywuzzy's `ratio`
+///
+/// Takes the same `field`, `target`, `algorithm` and normalization
+/// parameters as `filter` and returns `round(100 * similarity)` of the
+/// selected algorithm's normalized similarity (`1 - distance / max_len`
+/// for Levenshtein), avoiding float marshaling for legacy consumers.
+///
+/// Returns:
+/// - 0..=100 (i32), where identical strings give 100
+/// - -1 if there is no target, the field is missing, the algorithm is
+///   unknown, a normalized operand overflows, or a host import breaks its
+///   contract (see `filter`)
+#[no_mangle]
+pub extern "C" fn ratio(ctx_id: i64) -> i32 {
+    unsafe {
+        with_host_checks(-1, -1, || {
+            let (value, target) = match get_operands(ctx_id, None, None) {
+                Ok(Some(operands)) => operands,
+                Ok(None) | Err(TargetTooLong) => return -1,
+            };
+
+            let normalization = get_normalization();
+            let (value, target) = match normalize_operands(&value, target, normalization) {
+                Some(operands) => operands,
+                None => return -1,
+            };
+
+            let algorithm = match get_algorithm() {
+                Some(algorithm) => algorithm,
+                None => return -1,
+            };
+
+            (100.0 * algorithm.similarity(&value, target)).round() as i32
+        })
+    }
+}
+
+/// Similarity as an integer sort key, for ranking without floats
+///
+/// Takes the same parameters as `ratio` and returns
+/// `round(1e6 * similarity)` of the selected algorithm, so higher keys mean
+/// more similar and the order follows the float similarities down to
+/// differences of one millionth, whichever algorithm is selected.
+///
+/// Returns:
+/// - 0..=1_000_000 (i64), where identical strings give 1_000_000
+/// - -1 on the same errors as `ratio`
+#[no_mangle]
+pub extern "C" fn similarity_key(ctx_id: i64) -> i64 {
+    unsafe {
+        with_host_checks(-1, -1, || {
+            let (value, target) = match get_operands(ctx_id, None, None) {
+                Ok(Some(operands)) => operands,
+                Ok(None) | Err(TargetTooLong) => return -1,
+            };
+
+            let normalization = get_normalization();
+            let (value, target) = match normalize_operands(&value, target, normalization) {
+                Some(operands) => operands,
+                None => return -1,
+            };
+
+            let algorithm = match get_algorithm() {
+                Some(algorithm) => algorithm,
+                None => return -1,
+            };
+
+            (1e6 * algorithm.similarity(&value, target)).round() as i64
+        })
+    }
+}
+
+/// Levenshtein similarity that scores short fuzzy matches conservatively
+///
+/// Takes the same `field`, `target` and normalization parameters as
+/// `filter`, plus `short_length_penalty` (default 3.0): how much extra an
+/// edit costs in short strings, see `length_confidence`. 0 gives the plain
+/// normalized Levenshtein similarity; larger values discount short strings
+/// more, and further up the length scale.
+///
+/// Returns:
+/// - the confidence in [0, 1], where identical strings give 1.0
+/// - -1.0 if the field or target is missing, `short_length_penalty` isn't
+///   a finite, non-negative number, a normalized operand overflows, or a
+///   host import breaks its contract
+#[no_mangle]
+pub extern "C" fn length_normalized_confidence(ctx_id: i64) -> f32 {
+    unsafe {
+        with_host_checks(-1.0, -1.0, || {
+            let penalty = match get_f64_param("short_length_penalty").unwrap_or(3.0) {
+                penalty if penalty.is_finite() && penalty >= 0.0 => penalty,
+                _ => return -1.0,
+            };
+
+            let (value, target) = match get_operands(ctx_id, None, None) {
+                Ok(Some(operands)) => operands,
+                Ok(None) | Err(TargetTooLong) => return -1.0,
+            };
+
+            let normalization = get_normalization();
+            let (value, target) = match normalize_operands(&value, target, normalization) {
+                Some(operands) => operands,
+                None => return -1.0,
+            };
+
+            length_confidence(&value, target, penalty) as f32
+        })
+    }
+}
+
+/// Weighted blend of several algorithms' similarities
+///
+/// Takes the same `field`, `target` and normalization parameters as
+/// `filter`, plus `components`: a JSON array of
+/// `{"algorithm": name, "weight": w}` entries, e.g.
+/// `[{"algorithm": "jaro_winkler", "weight": 0.6},
+///   {"algorithm": "token_set_ratio", "weight": 0.4}]`, and optionally
+/// `length_penalty` as for `filter_score`.
+///
+/// Returns:
+/// - the weighted sum of component similarities divided by the total
+///   weight, less the length penalty, in [0, 1]
+/// - -1.0 if the field or target is missing, `components` or
+///   `length_penalty` is invalid (see `get_components`), a normalized
+///   operand overflows, or a host import breaks its contract
+#[no_mangle]
+pub extern "C" fn combined_score(ctx_id: i64) -> f32 {
+    unsafe {
+        with_host_checks(-1.0, -1.0, || {
+            let components = match get_components() {
+                Some(components) => components,
+                None => return -1.0,
+            };
+            let length_penalty = match get_length_penalty() {
+                Some(penalty) => penalty,
+                None => return -1.0,
+            };
+
+            let (value, target) = match get_operands(ctx_id, None, None) {
+                Ok(Some(operands)) => operands,
+                Ok(None) | Err(TargetTooLong) => return -1.0,
+            };
+
+            let normalization = get_normalization();
+            let (value, target) = match normalize_operands(&value, target, normalization) {
+                Some(operands) => operands,
+                None => return -1.0,
+            };
+
+            let total_weight: f64 = components.iter().map(|&(_, weight)| weight).sum();
+            let weighted: f64 = components
+                .iter()
+                .map(|(algorithm, weight)| weight * algorithm.similarity(&value, target))
+                .sum();
+
+            length_penalized(weighted / total_weight, &value, target, length_penalty) as f32
+        })
+    }
+}
+
+/// Similarity of the field against each of several targets
+///
+/// Takes the same `field`, `algorithm` and normalization parameters as
+/// `ratio`, but compares against every string in `targets` (a JSON array,
+/// e.g. `["iphone", "galaxy"]`, or the alternatives of a `target` with
+/// `alternation_delimiter`) instead of a single `target`. On entry
+/// `out_len_ptr` holds the capacity of `out_ptr` in `f32`s; the
+/// similarities are written in `targets` order, up to that capacity, and
+/// the number written is stored back to `out_len_ptr`.
+///
+/// Returns:
+/// - the number of similarities written (i32)
+/// - -1 if the field is missing, `targets` or `algorithm` is invalid, the
+///   normalized field overflows, or a host import breaks its contract
+///
+/// # Safety
+///
+/// `out_len_ptr` must be valid for reading and writing an `i32`, and
+/// `out_ptr` for writing that many `f32`s.
+#[no_mangle]
+pub unsafe extern "C" fn similarity_vector(
+    ctx_id: i64,
+    out_ptr: *mut f32,
+    out_len_ptr: *mut i32,
+) -> i32 {
+    unsafe {
+        with_host_checks(-1, -1, || {
+            let capacity = (*out_len_ptr).max(0) as usize;
+            *out_len_ptr = 0;
+
+            let targets = match get_targets() {
+                Ok(Some(targets)) => targets,
+                _ => return -1,
+            };
+
+            let value = match get_value(ctx_id, None) {
+                Some(value) => value,
+                None => return -1,
+            };
+
+            let algorithm = match get_algorithm() {
+                Some(algorithm) => algorithm,
+                None => return -1,
+            };
+
+            let normalization = get_normalization();
+            let value = match normalize_value(&value, normalization) {
+                Some(value) => value,
+                None => return -1,
+            };
+
+            // Nothing is written on a misbehaving host or a rejected parameter
+            if HOST_FAULT || PARAM_REJECTED {
+                return -1;
+            }
+
+            let out = slice::from_raw_parts_mut(out_ptr, capacity.min(targets.len()));
+            for (slot, target) in out.iter_mut().zip(&targets) {
+                *slot = algorithm.similarity(&value, &normalization.apply(target)) as f32;
+            }
+
+            *out_len_ptr = out.len() as i32;
+            out.len() as i32
+        })
+    }
+}
+
+/// Partial match that also reports where the target was found
+///
+/// Takes the same parameters as `filter` in `"partial"` mode. Writes the
+/// `char` offsets of the best-matching window of the (normalized) field to
+/// `start_ptr` and `end_ptr`, even when it is not within `max_distance`, so
+/// hits can be highlighted; with no normalizations enabled the offsets index
+/// the raw field value. With a `window_step` and no window within
+/// `max_distance`, both offsets are 0.
+///
+/// Returns:
+/// - 1 (i32) if the window is within `max_distance` of the target
+/// - 0 (i32) otherwise; both offsets are -1 if there is no target, the
+///   field is missing or `threshold` is invalid
+/// - -1 (i32) if `max_distance` is negative or `target` is longer than
+///   `max_target_len`; both offsets are -1
+/// - -2 (i32) if a normalized operand overflows, as in `filter`
+///
+/// # Safety
+///
+/// `start_ptr` and `end_ptr` must be valid for writing an `i32`.
+#[no_mangle]
+pub unsafe extern "C" fn partial_match_span(
+    ctx_id: i64,
+    start_ptr: *mut i32,
+    end_ptr: *mut i32,
+) -> i32 {
+    unsafe {
+        with_host_checks(DIAG_OVERFLOW, DIAG_INVALID, || {
+            *start_ptr = -1;
+            *end_ptr = -1;
+
+
+            let (value, target) = match get_operands(ctx_id, None, None) {
+                Ok(Some(operands)) => operands,
+                Ok(None) => return 0,
+                Err(TargetTooLong) => return DIAG_INVALID,
+            };
+
+            // An invalid threshold can't match, as in `filter`
+            let max_distance = match get_max_distance() {
+                Some(max_distance) => max_distance,
+                None => return 0,
+            };
+            let normalization = get_normalization();
+            let (value, target) = match normalize_operands(&value, target, normalization) {
+                Some(operands) => operands,
+                None => return DIAG_OVERFLOW,
+            };
+
+            // The offsets stay -1 on a misbehaving host or a rejected parameter
+            if HOST_FAULT || PARAM_REJECTED {
+                return DIAG_OVERFLOW;
+            }
+
+            let stepping = get_stepping(max_distance);
+            let (distance, start, end) = stepped_window(&value, target, &stepping, max_distance);
+            *start_ptr = start as i32;
+            *end_ptr = end as i32;
+
+            (distance <= max_distance) as i32
+        })
+    }
+}
+
+/// How many times the target approximately occurs in the field
+///
+/// Takes the same `field`, `target`, `max_distance`/`threshold` and
+/// normalization parameters as `filter`. Counts the windows of the
+/// normalized field within `max_distance` (default 2) of the target that
+/// don't overlap, taking them greedily left to right: each one ends at the
+/// first char where a window within the limit does, and the next starts
+/// after it (see `partial::occurrences`). Only the Levenshtein distance
+/// applies, as in `filter_sorted`.
+///
+/// Returns:
+/// - the number of occurrences (i32), 0 also for a missing field or no
+///   target
+/// - -1 (i32) if `max_distance` is negative, `target` is longer than
+///   `max_target_len` or `threshold` is invalid
+/// - -2 (i32) if a normalized operand overflows, as in `filter`
+#[no_mangle]
+pub extern "C" fn fuzzy_occurrences(ctx_id: i64) -> i32 {
+    unsafe {
+        with_host_checks(DIAG_OVERFLOW, DIAG_INVALID, || {
+            let (value, target) = match get_operands(ctx_id, None, None) {
+                Ok(Some(operands)) => operands,
+                Ok(None) => return 0,
+                Err(TargetTooLong) => return DIAG_INVALID,
+            };
+
+            let max_distance = match get_max_distance() {
+                Some(max_distance) => max_distance,
+                None => return DIAG_INVALID,
+            };
+
+            let normalization = get_normalization();
+            let (value, target) = match normalize_operands(&value, target, normalization) {
+                Some(operands) => operands,
+                None => return DIAG_OVERFLOW,
+            };
+
+            occurrences(&value, target, max_distance).min(i32::MAX as usize) as i32
+        })
+    }
+}
+
+/// Host imports this build needs, so a host can check it provides them
+///
+/// Hosts that lack an optional import (e.g. `get_field_chunk`) can load a
+/// build without the matching Cargo feature (`stream`, `f64_params`,
+/// `profile`); this lists what the loaded build links against.
+///
+/// Returns the comma-separated import names as a string in module memory,
+/// packed as `(ptr << 32) | len`.
+#[no_mangle]
+pub extern "C" fn udf_required_imports() -> i64 {
+    unsafe {
+        if REQUIRED_IMPORTS_LIST.is_empty() {
+            REQUIRED_IMPORTS_LIST = REQUIRED_IMPORTS.join(",");
+        }
+        let (ptr, len) = (REQUIRED_IMPORTS_LIST.as_ptr(), REQUIRED_IMPORTS_LIST.len());
+        ((ptr as i64) << 32) | len as i64
+    }
+}
+
+/// Tell the module that the next call's parameters differ from the last
+/// call's
+///
+/// A host that calls this before the first call of each query on an
+/// instance, and whenever it passes the instance other parameters, lets
+/// the module keep what it read from them (whether there is a `config`,
+/// what kind of query it is) for the rest of the query instead of reading
+/// it again on every call. A module that is never told reads them on every
+/// call, so hosts that don't know this export work unchanged.
+#[no_mangle]
+pub extern "C" fn params_changed() {
+    unsafe { PARAMS_CHANGED = Some(true) }
+}
+
+// The known inputs `self_test` reads, which the host supplies
+const SELF_TEST_FIELD: &str = "self_test";
+const SELF_TEST_VALUE: &str = "quidditch";
+const SELF_TEST_MISSING: &str = "self_test_missing";
+
+/// Check that the host imports behave as this module expects
+///
+/// For host integration tests. Call it like `filter` (the host passes a
+/// `ctx_id`) on a document whose `self_test` field is "quidditch", with the
+/// query parameters `self_test_string: "quidditch"`, `self_test_i64: 42`,
+/// `self_test_f64: 0.5` and `self_test_bool: true`. Every import is called
+/// with those names and with names that don't exist, and its return codes,
+/// lengths and values are checked against what the rest of the module
+/// relies on. A mismatched signature already fails when the module is
+/// linked; this catches imports that link but disagree on behavior.
+///
+/// Returns a bitmask of the imports that misbehaved, 0 when all are fine:
+/// 1 `has_field`, 2 `get_field_string`, 4 `get_field_chunk`, 8
+/// `get_param_string`, 16 `get_param_i64`, 32 `get_param_f64`, 64
+/// `get_param_bool`, 128 `get_time_ns`, 256 `get_document_id` (which must
+/// give the document a non-empty ID), 512 `get_field_doc_freq`. Imports a
+/// build leaves out (see `udf_required_imports`) are skipped, and `log` and
+/// `emit_stat` have no result to check; `log` is called once at debug
+/// level.
+#[no_mangle]
+pub extern "C" fn self_test(ctx_id: i64) -> i32 {
+    unsafe {
+        let mut failed = 0;
+        let (field, field_len) = (SELF_TEST_FIELD.as_ptr(), SELF_TEST_FIELD.len() as i32);
+        let (missing, missing_len) = (SELF_TEST_MISSING.as_ptr(), SELF_TEST_MISSING.len() as i32);
+        let mut buffer = [0u8; 32];
+
+        let found = has_field(ctx_id, field, field_len);
+        let not_found = has_field(ctx_id, missing, missing_len);
+        if (found, not_found) != (1, 0) {
+            failed |= 1;
+        }
+
+        let mut len = buffer.len() as i32;
+        let result = get_field_string(ctx_id, field, field_len, buffer.as_mut_ptr(), &mut len);
+        let found = result == 1 && holds_self_test_value(&buffer, len);
+        let mut len = buffer.len() as i32;
+        let not_found =
+            get_field_string(ctx_id, missing, missing_len, buffer.as_mut_ptr(), &mut len);
+        if !found || not_found != 0 {
+            failed |= 2;
+        }
+
+        let mut id = [0u8; 256];
+        if document_id(ctx_id, &mut id).is_none_or(str::is_empty) {
+            failed |= 256;
+        }
+
+        #[cfg(feature = "stream")]
+        {
+            // "quidditch" from byte 4, 3 bytes at a time: "dit", then nothing past the end
+            let read = |name: *const u8, name_len: i32, offset: i32, buffer: &mut [u8]| {
+                let (out, mut len) = (buffer.as_mut_ptr(), -1);
+                let result = get_field_chunk(ctx_id, name, name_len, offset, out, 3, &mut len);
+                (result, len)
+            };
+            let (chunk, len) = read(field, field_len, 4, &mut buffer);
+            let found = chunk == 0 && buffer.starts_with(b"dit");
+            let (end, end_len) = read(field, field_len, 9, &mut buffer);
+            let (not_found, _) = read(missing, missing_len, 0, &mut buffer);
+            if (found, len, end, end_len, not_found) != (true, 3, 0, 0, 1) {
+                failed |= 4;
+            }
+        }
+
+        // Too small a buffer reports the length needed
+        let (string, string_len) = ("self_test_string".as_ptr(), "self_test_string".len() as i32);
+        let mut needed = 4;
+        let too_small = get_param_string(string, string_len, buffer.as_mut_ptr(), &mut needed);
+        let mut len = buffer.len() as i32;
+        let result = get_param_string(string, string_len, buffer.as_mut_ptr(), &mut len);
+        let found = result == 0 && holds_self_test_value(&buffer, len);
+        let mut len = buffer.len() as i32;
+        let not_found = get_param_string(missing, missing_len, buffer.as_mut_ptr(), &mut len);
+        if (too_small, needed, found, not_found) != (3, SELF_TEST_VALUE.len() as i32, true, 1) {
+            failed |= 8;
+        }
+
+        // The typed getters: found, missing, and of the wrong type
+        let (int, int_len) = ("self_test_i64".as_ptr(), "self_test_i64".len() as i32);
+        let mut value = 0i64;
+        let found = get_param_i64(int, int_len, &mut value) == 0 && value == 42;
+        let not_found = get_param_i64(missing, missing_len, &mut value);
+        let wrong_type = get_param_i64(string, string_len, &mut value);
+        if (found, not_found, wrong_type) != (true, 1, 2) {
+            failed |= 16;
+        }
+
+        #[cfg(feature = "f64_params")]
+        {
+            let (float, float_len) = ("self_test_f64".as_ptr(), "self_test_f64".len() as i32);
+            let mut value = 0.0;
+            let found = get_param_f64(float, float_len, &mut value) == 0 && value == 0.5;
+            let not_found = get_param_f64(missing, missing_len, &mut value);
+            let wrong_type = get_param_f64(string, string_len, &mut value);
+            if (found, not_found, wrong_type) != (true, 1, 2) {
+                failed |= 32;
+            }
+        }
+
+        let (boolean, boolean_len) = ("self_test_bool".as_ptr(), "self_test_bool".len() as i32);
+        let mut value = 0;
+        let found = get_param_bool(boolean, boolean_len, &mut value) == 0 && value == 1;
+        let not_found = get_param_bool(missing, missing_len, &mut value);
+        let wrong_type = get_param_bool(string, string_len, &mut value);
+        if (found, not_found, wrong_type) != (true, 1, 2) {
+            failed |= 64;
+        }
+
+        #[cfg(feature = "profile")]
+        {
+            let (first, second) = (get_time_ns(), get_time_ns());
+            if first <= 0 || second < first {
+                failed |= 128;
+            }
+        }
+
+        #[cfg(feature = "doc_freq")]
+        {
+            // Any share in [0, 1] or not known for the value; the missing field has none
+            let mut freq = -1.0;
+            let found = match get_field_doc_freq(ctx_id, field, field_len, &mut freq) {
+                0 => (0.0..=1.0).contains(&freq),
+                result => result == 1,
+            };
+            let not_found = get_field_doc_freq(ctx_id, missing, missing_len, &mut freq);
+            if (found, not_found) != (true, 1) {
+                failed |= 512;
+            }
+        }
+
+        log_debug(&format!("self_test result {}", failed));
+        failed
+    }
+}
+
+/// Whether the first `len` bytes of `buffer` are `SELF_TEST_VALUE`
+fn holds_self_test_value(buffer: &[u8], len: i32) -> bool {
+    buffer.get(..len.max(0) as usize) == Some(SELF_TEST_VALUE.as_bytes())
 }
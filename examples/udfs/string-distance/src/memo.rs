@@ -0,0 +1,104 @@
+//! Field values of the current document and their normalized forms, kept
+//! across the calls a query makes for one document.
+
+use crate::normalize::Normalization;
+
+/// Most fields remembered per document
+const MAX_FIELDS: usize = 4;
+
+/// Most normalized forms remembered per field
+const MAX_FORMS: usize = 4;
+
+struct Entry {
+    name: String,
+    value: String,
+    forms: Vec<(Normalization, String)>,
+}
+
+/// The fields read from the current document
+///
+/// A query that stacks several filters on one field calls the UDF several
+/// times per document, and each call would fetch the field from the host
+/// and normalize it again. The host registers a new context id for every
+/// call, so the document is told by its id (`get_document_id`) instead, and
+/// the memo holds on to what was read until a call comes for another one.
+/// It is small and bounded: past `MAX_FIELDS` the newest field takes the
+/// last slot, so a field is still read only once while it is compared with
+/// every target, and forms past `MAX_FORMS` are simply not remembered.
+pub struct FieldMemo {
+    document: String,
+    entries: Vec<Entry>,
+}
+
+impl FieldMemo {
+    pub const fn new() -> Self {
+        FieldMemo {
+            document: String::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// The value of field `name` of the document with id `document`, read
+    /// with `fetch` unless this document's value is remembered
+    ///
+    /// A missing field isn't remembered, so it is looked up again, and
+    /// neither is anything of a document without an id.
+    pub fn value<'a>(
+        &mut self,
+        document: Option<&str>,
+        name: &str,
+        fetch: impl FnOnce() -> Option<&'a str>,
+    ) -> Option<String> {
+        let document = match document {
+            Some(document) if !document.is_empty() => document,
+            _ => {
+                self.document.clear();
+                self.entries.clear();
+                return fetch().map(str::to_string);
+            }
+        };
+        if self.document != document {
+            self.document.clear();
+            self.document.push_str(document);
+            self.entries.clear();
+        }
+        if let Some(entry) = self.entries.iter().find(|entry| entry.name == name) {
+            return Some(entry.value.clone());
+        }
+
+        let value = fetch()?;
+        if self.entries.len() == MAX_FIELDS {
+            self.entries.pop();
+        }
+        self.entries.push(Entry {
+            name: name.to_string(),
+            value: value.to_string(),
+            forms: Vec::new(),
+        });
+        Some(value.to_string())
+    }
+
+    /// The remembered form of `value` under `normalization`
+    ///
+    /// Forms are found by the value itself, not by field name, so a value
+    /// cut with `field_char_start`/`field_char_end` or joined from `fields`
+    /// just isn't found.
+    pub fn form(&self, value: &str, normalization: Normalization) -> Option<&str> {
+        let entry = self.entries.iter().find(|entry| entry.value == value)?;
+        entry
+            .forms
+            .iter()
+            .find(|(form_normalization, _)| *form_normalization == normalization)
+            .map(|(_, form)| form.as_str())
+    }
+
+    /// Remember `form` as `value` under `normalization`, if `value` is a
+    /// remembered field value
+    pub fn remember(&mut self, value: &str, normalization: Normalization, form: &str) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.value == value) {
+            if entry.forms.len() < MAX_FORMS {
+                entry.forms.push((normalization, form.to_string()));
+            }
+        }
+    }
+}
@@ -0,0 +1,449 @@
+//! Domain-specific comparison modes selected with the `mode` parameter.
+
+use crate::levenshtein_within;
+use crate::token::Tokenizer;
+
+/// Edits between two email addresses compared part by part, if within
+/// both limits
+///
+/// Both operands are split on their last '@'; the local parts must be
+/// within `max_local` edits and the domains (compared case-insensitively)
+/// within `max_domain` edits, and the distance is the edits of both.
+/// Addresses without an '@' or with an empty local part or domain never
+/// match.
+pub fn email_distance(
+    field: &str,
+    target: &str,
+    max_local: usize,
+    max_domain: usize,
+) -> Option<usize> {
+    let (field_local, field_domain) = split_email(field)?;
+    let (target_local, target_domain) = split_email(target)?;
+
+    let local = levenshtein_within(field_local, target_local, max_local)?;
+    let domain = levenshtein_within(
+        &field_domain.to_ascii_lowercase(),
+        &target_domain.to_ascii_lowercase(),
+        max_domain,
+    )?;
+    Some(local + domain)
+}
+
+/// Split an email address into (local part, domain)
+fn split_email(s: &str) -> Option<(&str, &str)> {
+    let (local, domain) = s.trim().rsplit_once('@')?;
+    if local.is_empty() || domain.is_empty() {
+        return None;
+    }
+    Some((local, domain))
+}
+
+/// Reduce a phone number to its digits
+///
+/// All non-digit characters are removed. When `country_code` is given and
+/// the digits start with it (optionally preceded by the "00" international
+/// prefix), it is dropped so "+1 555 123 4567" and "(555) 123-4567" agree.
+pub fn normalize_phone(s: &str, country_code: Option<&str>) -> String {
+    let digits: String = s.chars().filter(|c| c.is_ascii_digit()).collect();
+
+    if let Some(code) = country_code.filter(|code| !code.is_empty()) {
+        let international = digits.strip_prefix("00").unwrap_or(&digits);
+        if let Some(national) = international.strip_prefix(code) {
+            return national.to_string();
+        }
+    }
+
+    digits
+}
+
+/// Edit distance between two phone numbers over their digits (see
+/// `normalize_phone`), if within `max_distance`
+///
+/// Operands without any digits never match.
+pub fn phone_distance(
+    field: &str,
+    target: &str,
+    country_code: Option<&str>,
+    max_distance: usize,
+) -> Option<usize> {
+    let field_digits = normalize_phone(field, country_code);
+    let target_digits = normalize_phone(target, country_code);
+    if field_digits.is_empty() || target_digits.is_empty() {
+        return None;
+    }
+
+    levenshtein_within(&field_digits, &target_digits, max_distance)
+}
+
+/// Edit distance between two numbers by their digits, counting a swap of
+/// adjacent digits as one edit, if within `max_distance`
+///
+/// Manually entered numbers often have two digits swapped ("12345" vs
+/// "13245"), which plain Levenshtein counts as two edits. Operands without
+/// any digits never match.
+pub fn numeric_typo_distance(field: &str, target: &str, max_distance: usize) -> Option<usize> {
+    let field_digits: Vec<u8> = field.bytes().filter(u8::is_ascii_digit).collect();
+    let target_digits: Vec<u8> = target.bytes().filter(u8::is_ascii_digit).collect();
+    if field_digits.is_empty() || target_digits.is_empty() {
+        return None;
+    }
+
+    Some(transposition_distance(&field_digits, &target_digits)).filter(|&d| d <= max_distance)
+}
+
+/// Damerau-Levenshtein distance (optimal string alignment)
+///
+/// Levenshtein distance where swapping two adjacent symbols also costs one
+/// edit; a swapped pair is not edited again. Keeps three rows, since a
+/// transposition looks two rows back.
+fn transposition_distance(s1: &[u8], s2: &[u8]) -> usize {
+    let mut before_prev: Vec<usize> = vec![0; s2.len() + 1];
+    let mut prev: Vec<usize> = (0..=s2.len()).collect();
+    let mut curr: Vec<usize> = vec![0; s2.len() + 1];
+
+    for (i, &c1) in s1.iter().enumerate() {
+        curr[0] = i + 1;
+
+        for (j, &c2) in s2.iter().enumerate() {
+            let cost = if c1 == c2 { 0 } else { 1 };
+
+            curr[j + 1] = core::cmp::min(
+                core::cmp::min(
+                    curr[j] + 1,      // Insertion
+                    prev[j + 1] + 1,  // Deletion
+                ),
+                prev[j] + cost,       // Substitution
+            );
+
+            // Transposition of the adjacent pair ending here
+            if i > 0 && j > 0 && c1 == s2[j - 1] && s1[i - 1] == c2 {
+                curr[j + 1] = curr[j + 1].min(before_prev[j - 1] + 1);
+            }
+        }
+
+        core::mem::swap(&mut before_prev, &mut prev);
+        core::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[s2.len()]
+}
+
+/// Canonicalize a URL so trivial differences don't count as edits
+///
+/// - the `http`/`https` scheme is dropped, other schemes are lowercased
+/// - the host is lowercased and a leading "www." is removed
+/// - default ports (":80", ":443") are removed
+/// - a trailing '/' on the path is removed
+/// - query parameters are sorted and empty ones dropped
+///
+/// The fragment is kept as-is.
+pub fn normalize_url(s: &str) -> String {
+    let s = s.trim();
+
+    // Scheme
+    let (scheme, rest) = match s.find("://") {
+        Some(pos) => (s[..pos].to_ascii_lowercase(), &s[pos + 3..]),
+        None => (String::new(), s),
+    };
+
+    // Fragment and query
+    let (rest, fragment) = match rest.split_once('#') {
+        Some((before, frag)) => (before, Some(frag)),
+        None => (rest, None),
+    };
+    let (rest, query) = match rest.split_once('?') {
+        Some((before, q)) => (before, Some(q)),
+        None => (rest, None),
+    };
+
+    // Authority and path
+    let (authority, path) = match rest.find('/') {
+        Some(pos) => (&rest[..pos], &rest[pos..]),
+        None => (rest, ""),
+    };
+
+    let mut host = authority.to_ascii_lowercase();
+    if let Some(stripped) = host.strip_prefix("www.") {
+        host = stripped.to_string();
+    }
+    for default_port in [":80", ":443"] {
+        if let Some(stripped) = host.strip_suffix(default_port) {
+            host = stripped.to_string();
+            break;
+        }
+    }
+
+    let mut out = String::with_capacity(s.len());
+    if !scheme.is_empty() && scheme != "http" && scheme != "https" {
+        out.push_str(&scheme);
+        out.push_str("://");
+    }
+    out.push_str(&host);
+    out.push_str(path.trim_end_matches('/'));
+
+    if let Some(query) = query {
+        let mut params: Vec<&str> = query.split('&').filter(|p| !p.is_empty()).collect();
+        params.sort_unstable();
+        if !params.is_empty() {
+            out.push('?');
+            out.push_str(&params.join("&"));
+        }
+    }
+
+    if let Some(fragment) = fragment {
+        out.push('#');
+        out.push_str(fragment);
+    }
+
+    out
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Parse a month name or its three-letter abbreviation ("Jan", "january")
+fn parse_month_name(token: &str) -> Option<u32> {
+    let lower = token.to_ascii_lowercase();
+    if lower.len() < 3 {
+        return None;
+    }
+    let month = MONTH_NAMES.iter().position(|name| lower.starts_with(name))?;
+    Some(month as u32 + 1)
+}
+
+/// Parse a day number, allowing an ordinal suffix ("5th")
+fn parse_day(token: &str) -> Option<u32> {
+    let digits = token.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let suffix = &token[digits.len()..];
+    if !matches!(suffix.to_ascii_lowercase().as_str(), "" | "st" | "nd" | "rd" | "th") {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parse a date string into (year, month, day)
+///
+/// Accepted formats:
+/// - year first: "2024-01-05", "2024/01/05", "2024.01.05"
+/// - day first: "05/01/2024", "05-01-2024", "05.01.2024"
+/// - month names: "Jan 5 2024", "January 5th, 2024", "5 Jan 2024"
+pub fn parse_date(s: &str) -> Option<(i32, u32, u32)> {
+    let tokens: Vec<&str> = s
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .collect();
+    if tokens.len() != 3 {
+        return None;
+    }
+
+    let is_year = |t: &str| t.len() == 4 && t.bytes().all(|b| b.is_ascii_digit());
+
+    let (year, month, day) = if let Some(month_pos) =
+        tokens.iter().position(|t| parse_month_name(t).is_some())
+    {
+        let month = parse_month_name(tokens[month_pos])?;
+        let mut rest = tokens
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != month_pos)
+            .map(|(_, t)| *t);
+        let (a, b) = (rest.next()?, rest.next()?);
+        let (year, day) = if is_year(a) {
+            (a, b)
+        } else if is_year(b) {
+            (b, a)
+        } else {
+            return None;
+        };
+        (year.parse().ok()?, month, parse_day(day)?)
+    } else if is_year(tokens[0]) {
+        (tokens[0].parse().ok()?, tokens[1].parse().ok()?, tokens[2].parse().ok()?)
+    } else if is_year(tokens[2]) {
+        (tokens[2].parse().ok()?, tokens[1].parse().ok()?, tokens[0].parse().ok()?)
+    } else {
+        return None;
+    };
+
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some((year, month, day))
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year as i64 - 1 } else { year as i64 };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Days between two date strings, if at most `max_days`
+///
+/// Operands that can't be parsed never match.
+pub fn date_distance(field: &str, target: &str, max_days: usize) -> Option<u64> {
+    let field_date = parse_date(field)?;
+    let target_date = parse_date(target)?;
+
+    let field_days = days_from_civil(field_date.0, field_date.1, field_date.2);
+    let target_days = days_from_civil(target_date.0, target_date.1, target_date.2);
+    Some(field_days.abs_diff(target_days)).filter(|&days| days <= max_days as u64)
+}
+
+/// Smallest edit distance between `target` and a prefix of any token of
+/// `field`, for search-as-you-type
+///
+/// The target is what has been typed so far, so "gal" should match "Samsung
+/// Galaxy" by its second word. Once the distance is over `bound` it is only
+/// known to be over it.
+pub fn token_prefix_distance(
+    field: &str,
+    target: &str,
+    tokenizer: &Tokenizer,
+    bound: usize,
+) -> usize {
+    let target: Vec<char> = target.chars().collect();
+    let mut best = usize::MAX;
+    for token in tokenizer.tokenize(field) {
+        best = best.min(prefix_distance(token.chars(), &target, bound));
+        if best == 0 {
+            break;
+        }
+    }
+    best
+}
+
+/// Smallest edit distance between `target` and a suffix of `field`, for
+/// file names, extensions and domains
+///
+/// The target is the end that matters, so "report.pdf" should match
+/// "/home/ann/docs/report.pdf" whatever the directories before it. It is
+/// `prefix_distance` with both operands read backwards: only the last
+/// `target.len() + bound` chars of the field are read, and a shorter field
+/// is compared whole. Once the distance is over `bound` it is only known to
+/// be over it.
+pub fn suffix_distance(field: &str, target: &str, bound: usize) -> usize {
+    let target: Vec<char> = target.chars().rev().collect();
+    prefix_distance(field.chars().rev(), &target, bound)
+}
+
+/// Smallest edit distance between `target` and a prefix of the `token`
+/// chars
+///
+/// The Levenshtein DP down the chars of `token`, where every row ends in
+/// the distance of one more prefix. A prefix longer than `target.len() +
+/// bound` chars is over `bound` on length alone, so only those leading
+/// chars are read, and the DP stops as soon as a whole row is over `bound`.
+fn prefix_distance(token: impl Iterator<Item = char>, target: &[char], bound: usize) -> usize {
+    let mut prev: Vec<usize> = (0..=target.len()).collect();
+    let mut curr = vec![0; target.len() + 1];
+    let mut best = target.len();
+
+    for (i, c) in token.take(target.len().saturating_add(bound)).enumerate() {
+        curr[0] = i + 1;
+
+        for (j, &t) in target.iter().enumerate() {
+            let cost = if c == t { 0 } else { 1 };
+
+            curr[j + 1] = core::cmp::min(
+                core::cmp::min(
+                    curr[j] + 1,      // Insertion
+                    prev[j + 1] + 1,  // Deletion
+                ),
+                prev[j] + cost,       // Substitution
+            );
+        }
+        best = best.min(curr[target.len()]);
+
+        // Row minimum above the bound: longer prefixes can't get back under it
+        if curr.iter().min().is_some_and(|&min| min > bound) {
+            break;
+        }
+
+        core::mem::swap(&mut prev, &mut curr);
+    }
+
+    best
+}
+
+/// Edit distance between `field` and a two-token `target` in either order,
+/// if within `max_distance`
+///
+/// For names stored as "First Last" and searched as "Last First", or the
+/// other way round: the field is compared against the target and against
+/// its two tokens swapped, joined by a space, and the closer one counts. A
+/// target with more or fewer tokens is compared as it is.
+pub fn name_order_distance(field: &str, target: &str, max_distance: usize) -> Option<usize> {
+    let distance = levenshtein_within(field, target, max_distance);
+    let mut tokens = target.split_whitespace();
+    let swapped = match (tokens.next(), tokens.next(), tokens.next()) {
+        (Some(first), Some(last), None) => format!("{} {}", last, first),
+        _ => return distance,
+    };
+
+    match (distance, levenshtein_within(field, &swapped, max_distance)) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Edit distance between `field` and a code `pattern`, where `L` stands
+/// for any letter, `#` for any digit and `?` for any char
+///
+/// Other pattern chars stand for themselves, so "SKU-####" needs the
+/// literal "SKU-". Each char that breaks its class, and each char missing
+/// or extra, is one violation. Once the distance is over `bound` it is
+/// only known to be over it.
+pub fn pattern_distance(field: &str, pattern: &str, bound: usize) -> usize {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let mut prev: Vec<usize> = (0..=pattern.len()).collect();
+    let mut curr = vec![0; pattern.len() + 1];
+
+    for (i, c) in field.chars().enumerate() {
+        curr[0] = i + 1;
+
+        for (j, &p) in pattern.iter().enumerate() {
+            let fits = match p {
+                'L' => c.is_alphabetic(),
+                '#' => c.is_numeric(),
+                '?' => true,
+                _ => c == p,
+            };
+            let cost = if fits { 0 } else { 1 };
+
+            curr[j + 1] = core::cmp::min(
+                core::cmp::min(
+                    curr[j] + 1,      // Insertion
+                    prev[j + 1] + 1,  // Deletion
+                ),
+                prev[j] + cost,       // Substitution
+            );
+        }
+
+        // Row minimum above the bound: the rest of the field can't bring it back
+        if let Some(&min) = curr.iter().min().filter(|&&min| min > bound) {
+            return min;
+        }
+
+        core::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[pattern.len()]
+}
@@ -0,0 +1,115 @@
+//! One field against many targets, sharing the field's decoding.
+
+/// A field decoded once, with DP rows reused across targets
+///
+/// Matching a field against each of `targets` through `levenshtein_within`
+/// decodes (and normalizes) the field again for every target. Here the
+/// field is decoded into chars once and every target runs its own banded
+/// DP against that, reusing the same two row buffers.
+pub struct SharedField {
+    chars: Vec<char>,
+    prev_row: Vec<usize>,
+    curr_row: Vec<usize>,
+}
+
+impl SharedField {
+    pub fn new(field: &str) -> Self {
+        SharedField {
+            chars: field.chars().collect(),
+            prev_row: Vec::new(),
+            curr_row: Vec::new(),
+        }
+    }
+
+    /// The Levenshtein distance to `target`, or `None` if it is over `max`
+    ///
+    /// Same result as `levenshtein_within`: the length prefilter and affix
+    /// stripping run on the decoded chars, then a DP banded to `max`.
+    pub fn within(&mut self, target: &[char], max: usize) -> Option<usize> {
+        #[cfg(feature = "stats")]
+        crate::stats::field_examined();
+        let field = &self.chars[..];
+        if field.len().abs_diff(target.len()) > max {
+            #[cfg(feature = "stats")]
+            crate::stats::early_exit();
+            return None;
+        }
+
+        let prefix = field.iter().zip(target).take_while(|(c1, c2)| c1 == c2).count();
+        let (field, target) = (&field[prefix..], &target[prefix..]);
+        let suffix = field
+            .iter()
+            .rev()
+            .zip(target.iter().rev())
+            .take_while(|(c1, c2)| c1 == c2)
+            .count();
+        let field = &field[..field.len() - suffix];
+        let target = &target[..target.len() - suffix];
+
+        if field.is_empty() || target.is_empty() {
+            return Some(field.len().max(target.len()));
+        }
+        banded(field, target, max, &mut self.prev_row, &mut self.curr_row)
+    }
+}
+
+/// `levenshtein_banded` over decoded chars, with caller-owned rows
+///
+/// The same `band + 1` sentinel fills the cells outside the band, and the
+/// same argument keeps it from being read back as a distance: the result
+/// is `Some` only when at most `bound`.
+fn banded(
+    field: &[char],
+    target: &[char],
+    bound: usize,
+    prev_row: &mut Vec<usize>,
+    curr_row: &mut Vec<usize>,
+) -> Option<usize> {
+    let len2 = target.len();
+    let band = bound.min(field.len().max(len2));
+    let outside = band + 1;
+
+    // Cells past a row's band are never written, so they stay `outside`
+    prev_row.clear();
+    prev_row.extend((0..=len2).map(|j| if j <= band { j } else { outside }));
+    curr_row.clear();
+    curr_row.resize(len2 + 1, outside);
+
+    for (i, &c1) in field.iter().enumerate() {
+        let row = i + 1;
+        let lo = row.saturating_sub(band).max(1);
+        let hi = (row + band).min(len2);
+
+        curr_row[lo - 1] = if row <= band { row } else { outside };
+        #[cfg(feature = "stats")]
+        crate::stats::dp_cells(hi + 1 - lo);
+        for j in lo..=hi {
+            let cost = if c1 == target[j - 1] { 0 } else { 1 };
+
+            curr_row[j] = core::cmp::min(
+                core::cmp::min(
+                    curr_row[j - 1] + 1,  // Insertion
+                    prev_row[j] + 1,      // Deletion
+                ),
+                prev_row[j - 1] + cost,   // Substitution
+            );
+        }
+
+        // Row minimum above the bound: the result can't get back under it
+        let row_min = curr_row[lo - 1..=hi].iter().copied().min().unwrap_or(0);
+        if row_min > bound {
+            #[cfg(feature = "stats")]
+            crate::stats::early_exit();
+            return None;
+        }
+
+        core::mem::swap(prev_row, curr_row);
+    }
+
+    let distance = prev_row[len2];
+    if distance <= bound {
+        Some(distance)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,248 @@
+//! Character n-gram similarity.
+
+use std::collections::HashMap;
+
+/// Sentinel used to pad operands when `pad_ngrams` is enabled
+const PAD: char = '\u{0}';
+
+/// Bits per char in a rolling n-gram code; every char fits in 21
+const CHAR_BITS: u32 = 21;
+
+/// Longest n-gram whose code is the n-gram itself, packed into a u64
+const EXACT_MAX: usize = 3;
+
+/// Modulus of the polynomial hash of longer n-grams, the Mersenne prime
+/// 2^61 - 1
+const MODULUS: u64 = (1 << 61) - 1;
+
+/// Base of the polynomial hash, an arbitrary residue: a power of two would
+/// line several chars' bits up modulo 2^61 - 1 and make collisions common
+const BASE: u64 = 0x0A3C_59E1_7B2D_94F5;
+
+/// Sorted rolling hashes of the character n-grams of `s`
+///
+/// With `pad`, `n - 1` sentinels are added on both sides so strings shorter
+/// than `n` still produce n-grams and boundary n-grams carry weight.
+///
+/// Each window's hash is rolled from the previous one in O(1), Rabin-Karp
+/// style, instead of collecting every window as a string. Up to
+/// `EXACT_MAX` chars the hash is the chars packed 21 bits apiece, so equal
+/// hashes are equal n-grams; longer n-grams are hashed as a polynomial in
+/// `BASE` modulo `MODULUS`, where distinct n-grams of real text collide
+/// far too rarely to move a score.
+pub fn gram_hashes(s: &str, n: usize, pad: bool) -> Vec<u64> {
+    let padding = if pad { n.saturating_sub(1) } else { 0 };
+    let mut chars: Vec<u64> = Vec::with_capacity(s.len() + 2 * padding);
+    chars.extend(core::iter::repeat_n(PAD as u64, padding));
+    chars.extend(s.chars().map(|c| c as u64));
+    chars.extend(core::iter::repeat_n(PAD as u64, padding));
+
+    if n == 0 || chars.len() < n {
+        return Vec::new();
+    }
+
+    let mut hashes = Vec::with_capacity(chars.len() - n + 1);
+    let mut hash = 0;
+    if n <= EXACT_MAX {
+        // Shifting the next char in pushes the oldest one out of the mask
+        let mask = (1 << (CHAR_BITS * n as u32)) - 1;
+        for (i, &c) in chars.iter().enumerate() {
+            hash = ((hash << CHAR_BITS) | c) & mask;
+            if i + 1 >= n {
+                hashes.push(hash);
+            }
+        }
+    } else {
+        // Weight of the oldest char of a window, BASE^(n - 1)
+        let oldest = (1..n).fold(1, |weight, _| mul_mod(weight, BASE));
+        for (i, &c) in chars.iter().enumerate() {
+            if i >= n {
+                hash = (hash + MODULUS - mul_mod(chars[i - n], oldest)) % MODULUS;
+            }
+            hash = (mul_mod(hash, BASE) + c) % MODULUS;
+            if i + 1 >= n {
+                hashes.push(hash);
+            }
+        }
+    }
+
+    hashes.sort_unstable();
+    hashes
+}
+
+/// Per-n-gram weights supplied with the `ngram_idf` parameter, keyed by
+/// their `gram_hashes` hash
+pub type GramIdf = HashMap<u64, f64>;
+
+/// The `ngram_idf` weights of `n`-char n-grams, keyed by hash
+///
+/// Returns `None` when a key isn't exactly `n` chars long, since it could
+/// never weigh anything.
+pub fn gram_idf(weights: Vec<(String, f64)>, n: usize) -> Option<GramIdf> {
+    weights
+        .into_iter()
+        .map(|(gram, weight)| match gram_hashes(&gram, n, false)[..] {
+            [hash] => Some((hash, weight)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `a * b` modulo `MODULUS`
+fn mul_mod(a: u64, b: u64) -> u64 {
+    (a as u128 * b as u128 % MODULUS as u128) as u64
+}
+
+/// The hashed n-grams of the last target seen
+///
+/// The target of a query is compared against every document, so its
+/// n-grams are hashed once and only the field's are hashed per document.
+pub struct TargetGrams {
+    target: String,
+    n: usize,
+    pad: bool,
+    hashes: Vec<u64>,
+}
+
+impl TargetGrams {
+    pub const fn new() -> Self {
+        TargetGrams {
+            target: String::new(),
+            n: 0,
+            pad: false,
+            hashes: Vec::new(),
+        }
+    }
+
+    /// Sørensen–Dice coefficient over character n-grams, in [0, 1]
+    ///
+    /// `2 * |A ∩ B| / (|A| + |B|)` over n-gram multisets. When neither
+    /// operand yields any n-gram (both shorter than `n` without padding),
+    /// the result is 1.0 if they are equal and 0.0 otherwise. With `idf`
+    /// every n-gram counts its weight (1.0 when unlisted) instead of 1.
+    pub fn dice_similarity(
+        &mut self,
+        field: &str,
+        target: &str,
+        n: usize,
+        pad: bool,
+        idf: Option<&GramIdf>,
+    ) -> f64 {
+        let hashes = self.hashes(target, n, pad);
+        let field_hashes = gram_hashes(field, n, pad);
+        match idf {
+            None => dice(field, &field_hashes, target, hashes),
+            Some(idf) => weighted_dice(field, &field_hashes, target, hashes, idf),
+        }
+    }
+
+    /// Number of the target's n-grams missing from `field`, and the number
+    /// of n-grams the target has
+    ///
+    /// Unlike the Dice coefficient this only asks whether the field covers
+    /// the target: n-grams of the field the target lacks don't count. The
+    /// n-grams are multisets, so a target n-gram occurring twice needs two
+    /// occurrences in the field.
+    pub fn missing(&mut self, field: &str, target: &str, n: usize, pad: bool) -> (usize, usize) {
+        let hashes = self.hashes(target, n, pad);
+        let shared = count_shared(&gram_hashes(field, n, pad), hashes);
+        (hashes.len() - shared, hashes.len())
+    }
+
+    /// The target's n-gram hashes, hashed again only when it, `n` or `pad`
+    /// changes
+    fn hashes(&mut self, target: &str, n: usize, pad: bool) -> &[u64] {
+        if self.target != target || self.n != n || self.pad != pad {
+            self.hashes = gram_hashes(target, n, pad);
+            self.target.clear();
+            self.target.push_str(target);
+            self.n = n;
+            self.pad = pad;
+        }
+        &self.hashes
+    }
+}
+
+/// Number of n-grams shared by two sorted multisets of n-grams or their
+/// hashes
+fn count_shared<T: Ord>(a: &[T], b: &[T]) -> usize {
+    let (mut i, mut j, mut shared) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            core::cmp::Ordering::Less => i += 1,
+            core::cmp::Ordering::Greater => j += 1,
+            core::cmp::Ordering::Equal => {
+                shared += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    shared
+}
+
+/// Total weight of the n-grams shared by two sorted multisets of n-gram
+/// hashes
+fn shared_weight(a: &[u64], b: &[u64], weight: impl Fn(u64) -> f64) -> f64 {
+    let (mut i, mut j, mut shared) = (0, 0, 0.0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            core::cmp::Ordering::Less => i += 1,
+            core::cmp::Ordering::Greater => j += 1,
+            core::cmp::Ordering::Equal => {
+                shared += weight(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    shared
+}
+
+/// `dice` with each n-gram counting its `idf` weight
+///
+/// Operands whose n-grams all weigh 0 are scored like operands without
+/// any.
+fn weighted_dice(s1: &str, a: &[u64], s2: &str, b: &[u64], idf: &GramIdf) -> f64 {
+    let weight = |hash| idf.get(&hash).copied().unwrap_or(1.0);
+    let total: f64 = a.iter().chain(b).map(|&hash| weight(hash)).sum();
+    if total == 0.0 {
+        return if s1 == s2 { 1.0 } else { 0.0 };
+    }
+
+    2.0 * shared_weight(a, b, weight) / total
+}
+
+/// Dice coefficient of `s1` and `s2` from their `gram_hashes`
+fn dice(s1: &str, a: &[u64], s2: &str, b: &[u64]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return if s1 == s2 { 1.0 } else { 0.0 };
+    }
+
+    2.0 * count_shared(a, b) as f64 / (a.len() + b.len()) as f64
+}
+
+/// Dice coefficient as it was computed before `gram_hashes`, from the
+/// n-gram strings themselves, for the tests to check and time against
+#[cfg(test)]
+pub fn collected_dice(s1: &str, s2: &str, n: usize, pad: bool) -> f64 {
+    let ngrams = |s: &str| {
+        let padding = if pad { n.saturating_sub(1) } else { 0 };
+        let mut chars: Vec<char> = Vec::with_capacity(s.len() + 2 * padding);
+        chars.extend(core::iter::repeat_n(PAD, padding));
+        chars.extend(s.chars());
+        chars.extend(core::iter::repeat_n(PAD, padding));
+        if n == 0 || chars.len() < n {
+            return Vec::new();
+        }
+        let mut grams: Vec<String> = chars.windows(n).map(|w| w.iter().collect()).collect();
+        grams.sort_unstable();
+        grams
+    };
+
+    let (a, b) = (ngrams(s1), ngrams(s2));
+    if a.is_empty() && b.is_empty() {
+        return if s1 == s2 { 1.0 } else { 0.0 };
+    }
+    2.0 * count_shared(&a, &b) as f64 / (a.len() + b.len()) as f64
+}
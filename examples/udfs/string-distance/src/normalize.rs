@@ -0,0 +1,528 @@
+//! String normalization applied to both operands before comparison.
+
+use std::borrow::Cow;
+
+use crate::script::is_unknown_letter;
+use crate::translit::Transliteration;
+
+/// Normalizations applied to both operands, as selected by the query
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Normalization {
+    /// Replace chars by the user's folding table (`fold_table`)
+    pub fold_table: Option<Transliteration>,
+    /// Fold full-width/half-width variants (`fold_width`)
+    pub fold_width: bool,
+    /// Compare case-insensitively (`case_insensitive`)
+    pub case_insensitive: bool,
+    /// Replace chars by the user's map (`transliterate`)
+    pub transliteration: Option<Transliteration>,
+    /// Strip diacritics from Latin letters (`ignore_accents`)
+    pub ignore_accents: bool,
+    /// Drop ASCII digits (`mask_digits`)
+    pub mask_digits: bool,
+    /// Trim and collapse runs of whitespace to one space (`collapse_whitespace`)
+    pub collapse_whitespace: bool,
+    /// What to do with letters of unsupported scripts (`unknown_char_policy`)
+    pub unknown_chars: UnknownChars,
+    /// The order of the steps, when `normalization_pipeline` sets it
+    pub pipeline: Option<Pipeline>,
+}
+
+/// One normalization, as `normalization_pipeline` names it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    FoldTable,
+    FoldWidth,
+    CaseInsensitive,
+    Transliterate,
+    IgnoreAccents,
+    UnknownChars,
+    MaskDigits,
+    CollapseWhitespace,
+}
+
+impl Step {
+    /// Every step, in the order they run without a pipeline
+    pub const ALL: [Step; 8] = [
+        Step::FoldTable,
+        Step::FoldWidth,
+        Step::CaseInsensitive,
+        Step::Transliterate,
+        Step::IgnoreAccents,
+        Step::UnknownChars,
+        Step::MaskDigits,
+        Step::CollapseWhitespace,
+    ];
+
+    /// The step named by the parameter that enables it
+    pub fn from_name(name: &str) -> Option<Step> {
+        match name {
+            "fold_table" => Some(Step::FoldTable),
+            "fold_width" => Some(Step::FoldWidth),
+            "case_insensitive" => Some(Step::CaseInsensitive),
+            "transliterate" => Some(Step::Transliterate),
+            "ignore_accents" => Some(Step::IgnoreAccents),
+            "unknown_char_policy" => Some(Step::UnknownChars),
+            "mask_digits" => Some(Step::MaskDigits),
+            "collapse_whitespace" => Some(Step::CollapseWhitespace),
+            _ => None,
+        }
+    }
+}
+
+/// Steps in the order a query listed them, each at most once
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pipeline {
+    steps: [Step; 8],
+    len: usize,
+}
+
+impl Pipeline {
+    /// The pipeline of `steps`, or `None` if one is listed twice
+    pub fn new(steps: &[Step]) -> Option<Pipeline> {
+        let mut pipeline = Pipeline {
+            steps: Step::ALL,
+            len: 0,
+        };
+        for &step in steps {
+            if pipeline.contains(step) {
+                return None;
+            }
+            pipeline.steps[pipeline.len] = step;
+            pipeline.len += 1;
+        }
+        Some(pipeline)
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps[..self.len]
+    }
+
+    pub fn contains(&self, step: Step) -> bool {
+        self.steps().contains(&step)
+    }
+}
+
+/// Handling of letters no normalization knows how to fold
+///
+/// These are letters outside the scripts `script` recognizes (see
+/// `is_unknown_letter`); digits, punctuation and symbols are never unknown.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UnknownChars {
+    /// Pass them through unchanged (`"keep"`, default)
+    #[default]
+    Keep,
+    /// Remove them (`"drop"`)
+    Drop,
+    /// Replace each by the placeholder char (`"replace"`)
+    Replace(char),
+}
+
+impl Normalization {
+    /// Apply the enabled normalizations to `s`
+    ///
+    /// The folding table runs first so the built-in folds see its output,
+    /// then width folding so full-width letters and digits are handled like
+    /// their ASCII forms, transliteration follows case folding
+    /// so a case-insensitive map only needs lowercase sources, unknown
+    /// letters are handled once case and accents are folded, and whitespace
+    /// is collapsed last to clean up after removed chars. A `pipeline`
+    /// replaces that order by its own.
+    pub fn apply<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        // No string is longer than usize::MAX bytes
+        self.apply_within(s, usize::MAX).unwrap_or_default()
+    }
+
+    /// `self` without the normalizations that only make sense for text
+    ///
+    /// Case and accent folding can't affect digits, and masking digits would
+    /// leave nothing to compare, so modes that compare digits drop all
+    /// three. The folding table, width folding (full-width digits),
+    /// transliteration and whitespace are kept.
+    pub fn for_digits(self) -> Self {
+        Normalization {
+            case_insensitive: false,
+            ignore_accents: false,
+            mask_digits: false,
+            ..self
+        }
+    }
+
+    /// `apply`, failing if any step's output is longer than `limit` bytes
+    ///
+    /// Lowercasing can grow a string ("İ" is 2 bytes, "i̇" 3), so a value
+    /// that fit the buffer it was read into may not fit once normalized.
+    /// Each step is checked, so an overflowing form is never passed on.
+    pub fn apply_within<'a>(&self, s: &'a str, limit: usize) -> Result<Cow<'a, str>, Overflow> {
+        let steps = match &self.pipeline {
+            Some(pipeline) => pipeline.steps(),
+            None => &Step::ALL,
+        };
+        let mut s = Cow::Borrowed(s);
+        for step in steps {
+            if let Some(normalized) = self.step(*step, &s) {
+                s = Cow::Owned(within(normalized, limit)?);
+            }
+        }
+        Ok(s)
+    }
+
+    /// `s` after `step`, or `None` when the step isn't enabled
+    fn step(&self, step: Step, s: &str) -> Option<String> {
+        match step {
+            Step::FoldTable => self.fold_table.map(|t| t.apply(s)),
+            Step::FoldWidth if self.fold_width => Some(fold_width(s)),
+            Step::CaseInsensitive if self.case_insensitive => Some(s.to_lowercase()),
+            Step::Transliterate => self.transliteration.map(|t| t.apply(s)),
+            Step::IgnoreAccents if self.ignore_accents => Some(strip_accents(s)),
+            Step::UnknownChars => match self.unknown_chars {
+                UnknownChars::Keep => None,
+                UnknownChars::Drop => Some(s.chars().filter(|&c| !is_unknown_letter(c)).collect()),
+                UnknownChars::Replace(placeholder) => Some(
+                    s.chars()
+                        .map(|c| if is_unknown_letter(c) { placeholder } else { c })
+                        .collect(),
+                ),
+            },
+            Step::MaskDigits if self.mask_digits => {
+                Some(s.chars().filter(|c| !c.is_ascii_digit()).collect())
+            }
+            Step::CollapseWhitespace if self.collapse_whitespace => Some(collapse_whitespace(s)),
+            _ => None,
+        }
+    }
+}
+
+/// A normalized form longer than its limit (see `Normalization::apply_within`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Overflow {
+    /// Length of the offending form in bytes
+    pub len: usize,
+}
+
+/// Check one normalization step's output against `limit`
+fn within(s: String, limit: usize) -> Result<String, Overflow> {
+    if s.len() > limit {
+        Err(Overflow { len: s.len() })
+    } else {
+        Ok(s)
+    }
+}
+
+/// The normalized form of the last target seen
+///
+/// The target and its normalizations are the same for every document of a
+/// query, so normalizing it once and reusing the result leaves only the
+/// field to normalize per document.
+pub struct TargetCache {
+    target: String,
+    normalization: Normalization,
+    limit: usize,
+    normalized: Result<String, Overflow>,
+}
+
+impl TargetCache {
+    pub const fn new() -> Self {
+        TargetCache {
+            target: String::new(),
+            normalization: Normalization {
+                fold_table: None,
+                fold_width: false,
+                case_insensitive: false,
+                transliteration: None,
+                ignore_accents: false,
+                mask_digits: false,
+                collapse_whitespace: false,
+                unknown_chars: UnknownChars::Keep,
+                pipeline: None,
+            },
+            limit: 0,
+            normalized: Ok(String::new()),
+        }
+    }
+
+    /// `normalization.apply_within(target, limit)`, recomputed only when
+    /// any of them changes
+    pub fn get(
+        &mut self,
+        target: &str,
+        normalization: Normalization,
+        limit: usize,
+    ) -> Result<&str, Overflow> {
+        if self.target != target || self.normalization != normalization || self.limit != limit {
+            self.normalized = normalization.apply_within(target, limit).map(Cow::into_owned);
+            self.target.clear();
+            self.target.push_str(target);
+            self.normalization = normalization;
+            self.limit = limit;
+        }
+        match &self.normalized {
+            Ok(normalized) => Ok(normalized),
+            Err(overflow) => Err(*overflow),
+        }
+    }
+}
+
+/// Full-width forms of the halfwidth CJK punctuation and katakana block
+/// (U+FF61..=U+FF9F), indexed by `c - 0xFF61`.
+const HALFWIDTH_KATAKANA: [u16; 63] = [
+    0x3002, 0x300C, 0x300D, 0x3001, 0x30FB, 0x30F2, 0x30A1, 0x30A3, // 。「」、・ヲァィ
+    0x30A5, 0x30A7, 0x30A9, 0x30E3, 0x30E5, 0x30E7, 0x30C3, 0x30FC, // ゥェォャュョッー
+    0x30A2, 0x30A4, 0x30A6, 0x30A8, 0x30AA, 0x30AB, 0x30AD, 0x30AF, // アイウエオカキク
+    0x30B1, 0x30B3, 0x30B5, 0x30B7, 0x30B9, 0x30BB, 0x30BD, 0x30BF, // ケコサシスセソタ
+    0x30C1, 0x30C4, 0x30C6, 0x30C8, 0x30CA, 0x30CB, 0x30CC, 0x30CD, // チツテトナニヌネ
+    0x30CE, 0x30CF, 0x30D2, 0x30D5, 0x30D8, 0x30DB, 0x30DE, 0x30DF, // ノハヒフヘホマミ
+    0x30E0, 0x30E1, 0x30E2, 0x30E4, 0x30E6, 0x30E8, 0x30E9, 0x30EA, // ムメモヤユヨラリ
+    0x30EB, 0x30EC, 0x30ED, 0x30EF, 0x30F3, 0x3099, 0x309A, // ルレロワン + sound marks
+];
+
+/// Combining (semi-)voiced sound marks produced by U+FF9E / U+FF9F
+const VOICED_MARK: char = '\u{3099}';
+const SEMI_VOICED_MARK: char = '\u{309A}';
+
+/// Map a single character to its canonical width, if it has a width variant
+fn width_variant(c: char) -> Option<char> {
+    let code = c as u32;
+    let mapped = match code {
+        // Ideographic space
+        0x3000 => 0x0020,
+        // Full-width ASCII
+        0xFF01..=0xFF5E => code - 0xFEE0,
+        // Full-width white parentheses
+        0xFF5F => 0x2985,
+        0xFF60 => 0x2986,
+        // Halfwidth CJK punctuation and katakana
+        0xFF61..=0xFF9F => HALFWIDTH_KATAKANA[(code - 0xFF61) as usize] as u32,
+        // Halfwidth hangul filler and compatibility jamo
+        0xFFA0 => 0x3164,
+        0xFFA1..=0xFFBE => code - 0xFFA1 + 0x3131,
+        0xFFC2..=0xFFC7 => code - 0xFFC2 + 0x314F,
+        0xFFCA..=0xFFCF => code - 0xFFCA + 0x3155,
+        0xFFD2..=0xFFD7 => code - 0xFFD2 + 0x315B,
+        0xFFDA..=0xFFDC => code - 0xFFDA + 0x3161,
+        // Full-width signs
+        0xFFE0 => 0x00A2,
+        0xFFE1 => 0x00A3,
+        0xFFE2 => 0x00AC,
+        0xFFE3 => 0x00AF,
+        0xFFE4 => 0x00A6,
+        0xFFE5 => 0x00A5,
+        0xFFE6 => 0x20A9,
+        // Halfwidth symbols
+        0xFFE8 => 0x2502,
+        0xFFE9..=0xFFEC => code - 0xFFE9 + 0x2190,
+        0xFFED => 0x25A0,
+        0xFFEE => 0x25CB,
+        _ => return None,
+    };
+    char::from_u32(mapped)
+}
+
+/// Compose a katakana base with a following sound mark, as NFKC would
+fn compose_sound_mark(base: char, mark: char) -> Option<char> {
+    let code = base as u32;
+    let composed = match (code, mark) {
+        (0x30A6, VOICED_MARK) => 0x30F4,
+        (0x30EF, VOICED_MARK) => 0x30F7,
+        (0x30F2, VOICED_MARK) => 0x30FA,
+        // カ..ト: the voiced form is the next code point
+        (0x30AB..=0x30C2, VOICED_MARK) if code % 2 == 1 => code + 1,
+        (0x30C4 | 0x30C6 | 0x30C8, VOICED_MARK) => code + 1,
+        // ハ..ホ: voiced is +1, semi-voiced is +2
+        (0x30CF | 0x30D2 | 0x30D5 | 0x30D8 | 0x30DB, VOICED_MARK) => code + 1,
+        (0x30CF | 0x30D2 | 0x30D5 | 0x30D8 | 0x30DB, SEMI_VOICED_MARK) => code + 2,
+        _ => return None,
+    };
+    char::from_u32(composed)
+}
+
+/// Fold full-width and half-width variants to their canonical form
+///
+/// Applies only the width compatibility mappings of NFKC: full-width ASCII
+/// and signs become their ASCII/Latin-1 forms, halfwidth katakana and hangul
+/// become their standard (full-width) forms. Halfwidth sound marks are
+/// composed with the preceding katakana ("ｶﾞ" → "ガ").
+pub fn fold_width(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut last: Option<char> = None;
+
+    for c in s.chars() {
+        let folded = width_variant(c).unwrap_or(c);
+
+        if folded == VOICED_MARK || folded == SEMI_VOICED_MARK {
+            if let Some(composed) = last.and_then(|base| compose_sound_mark(base, folded)) {
+                out.pop();
+                out.push(composed);
+                last = Some(composed);
+                continue;
+            }
+        }
+
+        out.push(folded);
+        last = Some(folded);
+    }
+
+    out
+}
+
+/// Precomposed Latin letters whose canonical decomposition is an ASCII letter
+/// followed only by combining marks, sorted by code point (Latin-1 Supplement,
+/// Latin Extended-A/B and Latin Extended Additional).
+const ACCENTED_LATIN: &[(char, char)] = &[
+    ('\u{00C0}', 'A'), ('\u{00C1}', 'A'), ('\u{00C2}', 'A'), ('\u{00C3}', 'A'),
+    ('\u{00C4}', 'A'), ('\u{00C5}', 'A'), ('\u{00C7}', 'C'), ('\u{00C8}', 'E'),
+    ('\u{00C9}', 'E'), ('\u{00CA}', 'E'), ('\u{00CB}', 'E'), ('\u{00CC}', 'I'),
+    ('\u{00CD}', 'I'), ('\u{00CE}', 'I'), ('\u{00CF}', 'I'), ('\u{00D1}', 'N'),
+    ('\u{00D2}', 'O'), ('\u{00D3}', 'O'), ('\u{00D4}', 'O'), ('\u{00D5}', 'O'),
+    ('\u{00D6}', 'O'), ('\u{00D9}', 'U'), ('\u{00DA}', 'U'), ('\u{00DB}', 'U'),
+    ('\u{00DC}', 'U'), ('\u{00DD}', 'Y'), ('\u{00E0}', 'a'), ('\u{00E1}', 'a'),
+    ('\u{00E2}', 'a'), ('\u{00E3}', 'a'), ('\u{00E4}', 'a'), ('\u{00E5}', 'a'),
+    ('\u{00E7}', 'c'), ('\u{00E8}', 'e'), ('\u{00E9}', 'e'), ('\u{00EA}', 'e'),
+    ('\u{00EB}', 'e'), ('\u{00EC}', 'i'), ('\u{00ED}', 'i'), ('\u{00EE}', 'i'),
+    ('\u{00EF}', 'i'), ('\u{00F1}', 'n'), ('\u{00F2}', 'o'), ('\u{00F3}', 'o'),
+    ('\u{00F4}', 'o'), ('\u{00F5}', 'o'), ('\u{00F6}', 'o'), ('\u{00F9}', 'u'),
+    ('\u{00FA}', 'u'), ('\u{00FB}', 'u'), ('\u{00FC}', 'u'), ('\u{00FD}', 'y'),
+    ('\u{00FF}', 'y'), ('\u{0100}', 'A'), ('\u{0101}', 'a'), ('\u{0102}', 'A'),
+    ('\u{0103}', 'a'), ('\u{0104}', 'A'), ('\u{0105}', 'a'), ('\u{0106}', 'C'),
+    ('\u{0107}', 'c'), ('\u{0108}', 'C'), ('\u{0109}', 'c'), ('\u{010A}', 'C'),
+    ('\u{010B}', 'c'), ('\u{010C}', 'C'), ('\u{010D}', 'c'), ('\u{010E}', 'D'),
+    ('\u{010F}', 'd'), ('\u{0112}', 'E'), ('\u{0113}', 'e'), ('\u{0114}', 'E'),
+    ('\u{0115}', 'e'), ('\u{0116}', 'E'), ('\u{0117}', 'e'), ('\u{0118}', 'E'),
+    ('\u{0119}', 'e'), ('\u{011A}', 'E'), ('\u{011B}', 'e'), ('\u{011C}', 'G'),
+    ('\u{011D}', 'g'), ('\u{011E}', 'G'), ('\u{011F}', 'g'), ('\u{0120}', 'G'),
+    ('\u{0121}', 'g'), ('\u{0122}', 'G'), ('\u{0123}', 'g'), ('\u{0124}', 'H'),
+    ('\u{0125}', 'h'), ('\u{0128}', 'I'), ('\u{0129}', 'i'), ('\u{012A}', 'I'),
+    ('\u{012B}', 'i'), ('\u{012C}', 'I'), ('\u{012D}', 'i'), ('\u{012E}', 'I'),
+    ('\u{012F}', 'i'), ('\u{0130}', 'I'), ('\u{0134}', 'J'), ('\u{0135}', 'j'),
+    ('\u{0136}', 'K'), ('\u{0137}', 'k'), ('\u{0139}', 'L'), ('\u{013A}', 'l'),
+    ('\u{013B}', 'L'), ('\u{013C}', 'l'), ('\u{013D}', 'L'), ('\u{013E}', 'l'),
+    ('\u{0143}', 'N'), ('\u{0144}', 'n'), ('\u{0145}', 'N'), ('\u{0146}', 'n'),
+    ('\u{0147}', 'N'), ('\u{0148}', 'n'), ('\u{014C}', 'O'), ('\u{014D}', 'o'),
+    ('\u{014E}', 'O'), ('\u{014F}', 'o'), ('\u{0150}', 'O'), ('\u{0151}', 'o'),
+    ('\u{0154}', 'R'), ('\u{0155}', 'r'), ('\u{0156}', 'R'), ('\u{0157}', 'r'),
+    ('\u{0158}', 'R'), ('\u{0159}', 'r'), ('\u{015A}', 'S'), ('\u{015B}', 's'),
+    ('\u{015C}', 'S'), ('\u{015D}', 's'), ('\u{015E}', 'S'), ('\u{015F}', 's'),
+    ('\u{0160}', 'S'), ('\u{0161}', 's'), ('\u{0162}', 'T'), ('\u{0163}', 't'),
+    ('\u{0164}', 'T'), ('\u{0165}', 't'), ('\u{0168}', 'U'), ('\u{0169}', 'u'),
+    ('\u{016A}', 'U'), ('\u{016B}', 'u'), ('\u{016C}', 'U'), ('\u{016D}', 'u'),
+    ('\u{016E}', 'U'), ('\u{016F}', 'u'), ('\u{0170}', 'U'), ('\u{0171}', 'u'),
+    ('\u{0172}', 'U'), ('\u{0173}', 'u'), ('\u{0174}', 'W'), ('\u{0175}', 'w'),
+    ('\u{0176}', 'Y'), ('\u{0177}', 'y'), ('\u{0178}', 'Y'), ('\u{0179}', 'Z'),
+    ('\u{017A}', 'z'), ('\u{017B}', 'Z'), ('\u{017C}', 'z'), ('\u{017D}', 'Z'),
+    ('\u{017E}', 'z'), ('\u{01A0}', 'O'), ('\u{01A1}', 'o'), ('\u{01AF}', 'U'),
+    ('\u{01B0}', 'u'), ('\u{01CD}', 'A'), ('\u{01CE}', 'a'), ('\u{01CF}', 'I'),
+    ('\u{01D0}', 'i'), ('\u{01D1}', 'O'), ('\u{01D2}', 'o'), ('\u{01D3}', 'U'),
+    ('\u{01D4}', 'u'), ('\u{01D5}', 'U'), ('\u{01D6}', 'u'), ('\u{01D7}', 'U'),
+    ('\u{01D8}', 'u'), ('\u{01D9}', 'U'), ('\u{01DA}', 'u'), ('\u{01DB}', 'U'),
+    ('\u{01DC}', 'u'), ('\u{01DE}', 'A'), ('\u{01DF}', 'a'), ('\u{01E0}', 'A'),
+    ('\u{01E1}', 'a'), ('\u{01E6}', 'G'), ('\u{01E7}', 'g'), ('\u{01E8}', 'K'),
+    ('\u{01E9}', 'k'), ('\u{01EA}', 'O'), ('\u{01EB}', 'o'), ('\u{01EC}', 'O'),
+    ('\u{01ED}', 'o'), ('\u{01F0}', 'j'), ('\u{01F4}', 'G'), ('\u{01F5}', 'g'),
+    ('\u{01F8}', 'N'), ('\u{01F9}', 'n'), ('\u{01FA}', 'A'), ('\u{01FB}', 'a'),
+    ('\u{0200}', 'A'), ('\u{0201}', 'a'), ('\u{0202}', 'A'), ('\u{0203}', 'a'),
+    ('\u{0204}', 'E'), ('\u{0205}', 'e'), ('\u{0206}', 'E'), ('\u{0207}', 'e'),
+    ('\u{0208}', 'I'), ('\u{0209}', 'i'), ('\u{020A}', 'I'), ('\u{020B}', 'i'),
+    ('\u{020C}', 'O'), ('\u{020D}', 'o'), ('\u{020E}', 'O'), ('\u{020F}', 'o'),
+    ('\u{0210}', 'R'), ('\u{0211}', 'r'), ('\u{0212}', 'R'), ('\u{0213}', 'r'),
+    ('\u{0214}', 'U'), ('\u{0215}', 'u'), ('\u{0216}', 'U'), ('\u{0217}', 'u'),
+    ('\u{0218}', 'S'), ('\u{0219}', 's'), ('\u{021A}', 'T'), ('\u{021B}', 't'),
+    ('\u{021E}', 'H'), ('\u{021F}', 'h'), ('\u{0226}', 'A'), ('\u{0227}', 'a'),
+    ('\u{0228}', 'E'), ('\u{0229}', 'e'), ('\u{022A}', 'O'), ('\u{022B}', 'o'),
+    ('\u{022C}', 'O'), ('\u{022D}', 'o'), ('\u{022E}', 'O'), ('\u{022F}', 'o'),
+    ('\u{0230}', 'O'), ('\u{0231}', 'o'), ('\u{0232}', 'Y'), ('\u{0233}', 'y'),
+    ('\u{1E00}', 'A'), ('\u{1E01}', 'a'), ('\u{1E02}', 'B'), ('\u{1E03}', 'b'),
+    ('\u{1E04}', 'B'), ('\u{1E05}', 'b'), ('\u{1E06}', 'B'), ('\u{1E07}', 'b'),
+    ('\u{1E08}', 'C'), ('\u{1E09}', 'c'), ('\u{1E0A}', 'D'), ('\u{1E0B}', 'd'),
+    ('\u{1E0C}', 'D'), ('\u{1E0D}', 'd'), ('\u{1E0E}', 'D'), ('\u{1E0F}', 'd'),
+    ('\u{1E10}', 'D'), ('\u{1E11}', 'd'), ('\u{1E12}', 'D'), ('\u{1E13}', 'd'),
+    ('\u{1E14}', 'E'), ('\u{1E15}', 'e'), ('\u{1E16}', 'E'), ('\u{1E17}', 'e'),
+    ('\u{1E18}', 'E'), ('\u{1E19}', 'e'), ('\u{1E1A}', 'E'), ('\u{1E1B}', 'e'),
+    ('\u{1E1C}', 'E'), ('\u{1E1D}', 'e'), ('\u{1E1E}', 'F'), ('\u{1E1F}', 'f'),
+    ('\u{1E20}', 'G'), ('\u{1E21}', 'g'), ('\u{1E22}', 'H'), ('\u{1E23}', 'h'),
+    ('\u{1E24}', 'H'), ('\u{1E25}', 'h'), ('\u{1E26}', 'H'), ('\u{1E27}', 'h'),
+    ('\u{1E28}', 'H'), ('\u{1E29}', 'h'), ('\u{1E2A}', 'H'), ('\u{1E2B}', 'h'),
+    ('\u{1E2C}', 'I'), ('\u{1E2D}', 'i'), ('\u{1E2E}', 'I'), ('\u{1E2F}', 'i'),
+    ('\u{1E30}', 'K'), ('\u{1E31}', 'k'), ('\u{1E32}', 'K'), ('\u{1E33}', 'k'),
+    ('\u{1E34}', 'K'), ('\u{1E35}', 'k'), ('\u{1E36}', 'L'), ('\u{1E37}', 'l'),
+    ('\u{1E38}', 'L'), ('\u{1E39}', 'l'), ('\u{1E3A}', 'L'), ('\u{1E3B}', 'l'),
+    ('\u{1E3C}', 'L'), ('\u{1E3D}', 'l'), ('\u{1E3E}', 'M'), ('\u{1E3F}', 'm'),
+    ('\u{1E40}', 'M'), ('\u{1E41}', 'm'), ('\u{1E42}', 'M'), ('\u{1E43}', 'm'),
+    ('\u{1E44}', 'N'), ('\u{1E45}', 'n'), ('\u{1E46}', 'N'), ('\u{1E47}', 'n'),
+    ('\u{1E48}', 'N'), ('\u{1E49}', 'n'), ('\u{1E4A}', 'N'), ('\u{1E4B}', 'n'),
+    ('\u{1E4C}', 'O'), ('\u{1E4D}', 'o'), ('\u{1E4E}', 'O'), ('\u{1E4F}', 'o'),
+    ('\u{1E50}', 'O'), ('\u{1E51}', 'o'), ('\u{1E52}', 'O'), ('\u{1E53}', 'o'),
+    ('\u{1E54}', 'P'), ('\u{1E55}', 'p'), ('\u{1E56}', 'P'), ('\u{1E57}', 'p'),
+    ('\u{1E58}', 'R'), ('\u{1E59}', 'r'), ('\u{1E5A}', 'R'), ('\u{1E5B}', 'r'),
+    ('\u{1E5C}', 'R'), ('\u{1E5D}', 'r'), ('\u{1E5E}', 'R'), ('\u{1E5F}', 'r'),
+    ('\u{1E60}', 'S'), ('\u{1E61}', 's'), ('\u{1E62}', 'S'), ('\u{1E63}', 's'),
+    ('\u{1E64}', 'S'), ('\u{1E65}', 's'), ('\u{1E66}', 'S'), ('\u{1E67}', 's'),
+    ('\u{1E68}', 'S'), ('\u{1E69}', 's'), ('\u{1E6A}', 'T'), ('\u{1E6B}', 't'),
+    ('\u{1E6C}', 'T'), ('\u{1E6D}', 't'), ('\u{1E6E}', 'T'), ('\u{1E6F}', 't'),
+    ('\u{1E70}', 'T'), ('\u{1E71}', 't'), ('\u{1E72}', 'U'), ('\u{1E73}', 'u'),
+    ('\u{1E74}', 'U'), ('\u{1E75}', 'u'), ('\u{1E76}', 'U'), ('\u{1E77}', 'u'),
+    ('\u{1E78}', 'U'), ('\u{1E79}', 'u'), ('\u{1E7A}', 'U'), ('\u{1E7B}', 'u'),
+    ('\u{1E7C}', 'V'), ('\u{1E7D}', 'v'), ('\u{1E7E}', 'V'), ('\u{1E7F}', 'v'),
+    ('\u{1E80}', 'W'), ('\u{1E81}', 'w'), ('\u{1E82}', 'W'), ('\u{1E83}', 'w'),
+    ('\u{1E84}', 'W'), ('\u{1E85}', 'w'), ('\u{1E86}', 'W'), ('\u{1E87}', 'w'),
+    ('\u{1E88}', 'W'), ('\u{1E89}', 'w'), ('\u{1E8A}', 'X'), ('\u{1E8B}', 'x'),
+    ('\u{1E8C}', 'X'), ('\u{1E8D}', 'x'), ('\u{1E8E}', 'Y'), ('\u{1E8F}', 'y'),
+    ('\u{1E90}', 'Z'), ('\u{1E91}', 'z'), ('\u{1E92}', 'Z'), ('\u{1E93}', 'z'),
+    ('\u{1E94}', 'Z'), ('\u{1E95}', 'z'), ('\u{1E96}', 'h'), ('\u{1E97}', 't'),
+    ('\u{1E98}', 'w'), ('\u{1E99}', 'y'), ('\u{1EA0}', 'A'), ('\u{1EA1}', 'a'),
+    ('\u{1EA2}', 'A'), ('\u{1EA3}', 'a'), ('\u{1EA4}', 'A'), ('\u{1EA5}', 'a'),
+    ('\u{1EA6}', 'A'), ('\u{1EA7}', 'a'), ('\u{1EA8}', 'A'), ('\u{1EA9}', 'a'),
+    ('\u{1EAA}', 'A'), ('\u{1EAB}', 'a'), ('\u{1EAC}', 'A'), ('\u{1EAD}', 'a'),
+    ('\u{1EAE}', 'A'), ('\u{1EAF}', 'a'), ('\u{1EB0}', 'A'), ('\u{1EB1}', 'a'),
+    ('\u{1EB2}', 'A'), ('\u{1EB3}', 'a'), ('\u{1EB4}', 'A'), ('\u{1EB5}', 'a'),
+    ('\u{1EB6}', 'A'), ('\u{1EB7}', 'a'), ('\u{1EB8}', 'E'), ('\u{1EB9}', 'e'),
+    ('\u{1EBA}', 'E'), ('\u{1EBB}', 'e'), ('\u{1EBC}', 'E'), ('\u{1EBD}', 'e'),
+    ('\u{1EBE}', 'E'), ('\u{1EBF}', 'e'), ('\u{1EC0}', 'E'), ('\u{1EC1}', 'e'),
+    ('\u{1EC2}', 'E'), ('\u{1EC3}', 'e'), ('\u{1EC4}', 'E'), ('\u{1EC5}', 'e'),
+    ('\u{1EC6}', 'E'), ('\u{1EC7}', 'e'), ('\u{1EC8}', 'I'), ('\u{1EC9}', 'i'),
+    ('\u{1ECA}', 'I'), ('\u{1ECB}', 'i'), ('\u{1ECC}', 'O'), ('\u{1ECD}', 'o'),
+    ('\u{1ECE}', 'O'), ('\u{1ECF}', 'o'), ('\u{1ED0}', 'O'), ('\u{1ED1}', 'o'),
+    ('\u{1ED2}', 'O'), ('\u{1ED3}', 'o'), ('\u{1ED4}', 'O'), ('\u{1ED5}', 'o'),
+    ('\u{1ED6}', 'O'), ('\u{1ED7}', 'o'), ('\u{1ED8}', 'O'), ('\u{1ED9}', 'o'),
+    ('\u{1EDA}', 'O'), ('\u{1EDB}', 'o'), ('\u{1EDC}', 'O'), ('\u{1EDD}', 'o'),
+    ('\u{1EDE}', 'O'), ('\u{1EDF}', 'o'), ('\u{1EE0}', 'O'), ('\u{1EE1}', 'o'),
+    ('\u{1EE2}', 'O'), ('\u{1EE3}', 'o'), ('\u{1EE4}', 'U'), ('\u{1EE5}', 'u'),
+    ('\u{1EE6}', 'U'), ('\u{1EE7}', 'u'), ('\u{1EE8}', 'U'), ('\u{1EE9}', 'u'),
+    ('\u{1EEA}', 'U'), ('\u{1EEB}', 'u'), ('\u{1EEC}', 'U'), ('\u{1EED}', 'u'),
+    ('\u{1EEE}', 'U'), ('\u{1EEF}', 'u'), ('\u{1EF0}', 'U'), ('\u{1EF1}', 'u'),
+    ('\u{1EF2}', 'Y'), ('\u{1EF3}', 'y'), ('\u{1EF4}', 'Y'), ('\u{1EF5}', 'y'),
+    ('\u{1EF6}', 'Y'), ('\u{1EF7}', 'y'), ('\u{1EF8}', 'Y'), ('\u{1EF9}', 'y'),
+];
+
+/// Whether `c` is in the Combining Diacritical Marks block
+fn is_combining_mark(c: char) -> bool {
+    ('\u{0300}'..='\u{036F}').contains(&c)
+}
+
+/// Strip diacritics from Latin letters
+///
+/// Precomposed letters are replaced by their base letter ("é" → "e") and
+/// combining marks are dropped, so precomposed and decomposed input agree.
+/// Letters that don't decompose canonically ("ø", "ł", "ß") are kept.
+pub fn strip_accents(s: &str) -> String {
+    s.chars().filter(|&c| !is_combining_mark(c)).map(strip_accent).collect()
+}
+
+/// The base letter of a precomposed accented Latin letter, else `c` itself
+pub fn strip_accent(c: char) -> char {
+    match ACCENTED_LATIN.binary_search_by_key(&c, |&(accented, _)| accented) {
+        Ok(i) => ACCENTED_LATIN[i].1,
+        Err(_) => c,
+    }
+}
+
+/// Trim `s` and collapse each run of whitespace to a single space
+pub fn collapse_whitespace(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for word in s.split_whitespace() {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    out
+}
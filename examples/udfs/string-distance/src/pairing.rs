@@ -0,0 +1,96 @@
+//! Order-independent matching of two string sets, for the `set_pairing` mode.
+
+use crate::levenshtein_within;
+
+/// Most elements per side, which bounds the O(n³) assignment
+pub const MAX_ELEMENTS: usize = 64;
+
+/// Cost of the cheapest one-to-one pairing of `field` with `target`
+///
+/// A pair costs the Levenshtein distance of its elements, and every element
+/// left without a partner costs `unmatched`. Leaving both elements of a pair
+/// unmatched is always an option, so no pair costs more than
+/// `2 * unmatched`; with that cap, padding the smaller side with
+/// unmatched-cost dummies turns the problem into a square assignment,
+/// solved exactly by `min_assignment`. Sides longer than `MAX_ELEMENTS`
+/// give `None`.
+pub fn pairing_cost(field: &[String], target: &[String], unmatched: f64) -> Option<f64> {
+    if field.len() > MAX_ELEMENTS || target.len() > MAX_ELEMENTS {
+        return None;
+    }
+
+    let n = field.len().max(target.len());
+    let cap = 2.0 * unmatched;
+    // No distance above the cap matters, so the DP can stop there
+    let bound = cap as usize;
+    let mut cost = vec![vec![unmatched; n]; n];
+    for (i, f) in field.iter().enumerate() {
+        for (j, t) in target.iter().enumerate() {
+            cost[i][j] = levenshtein_within(f, t, bound).map_or(cap, |d| (d as f64).min(cap));
+        }
+    }
+
+    Some(min_assignment(&cost))
+}
+
+/// Minimum total cost of assigning each row of a square matrix to a
+/// distinct column (Hungarian algorithm with potentials, O(n³))
+fn min_assignment(cost: &[Vec<f64>]) -> f64 {
+    let n = cost.len();
+    // 1-based, with row/column 0 as the sentinel; `row_of[j]` is the row
+    // assigned to column j
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; n + 1];
+    let mut row_of = vec![0; n + 1];
+    let mut way = vec![0; n + 1];
+
+    for i in 1..=n {
+        row_of[0] = i;
+        let mut j0 = 0;
+        let mut min_v = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+
+        // Grow an alternating tree from row i until it reaches a free column
+        loop {
+            used[j0] = true;
+            let i0 = row_of[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=n {
+                if used[j] {
+                    continue;
+                }
+                let reduced = cost[i0 - 1][j - 1] - u[i0] - v[j];
+                if reduced < min_v[j] {
+                    min_v[j] = reduced;
+                    way[j] = j0;
+                }
+                if min_v[j] < delta {
+                    delta = min_v[j];
+                    j1 = j;
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_v[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if row_of[j0] == 0 {
+                break;
+            }
+        }
+
+        // Flip the augmenting path back to the root
+        while j0 != 0 {
+            let j1 = way[j0];
+            row_of[j0] = row_of[j1];
+            j0 = j1;
+        }
+    }
+
+    (1..=n).map(|j| cost[row_of[j] - 1][j - 1]).sum()
+}
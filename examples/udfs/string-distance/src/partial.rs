@@ -0,0 +1,193 @@
+//! Partial (substring) matching.
+
+/// Best approximate occurrence of `target` inside `field`
+///
+/// Runs the Levenshtein DP with a free start and end in `field` (Sellers'
+/// algorithm), so the result is the minimum edit distance between `target`
+/// and any substring of `field`. Returns `(distance, start, end)` where
+/// `start..end` is the matching window in `char` offsets. Ties prefer the
+/// earliest end and then the shortest window.
+pub fn best_window(field: &str, target: &str) -> (usize, usize, usize) {
+    let field: Vec<char> = field.chars().collect();
+
+    // Each cell holds (distance, window start) for the best path so far
+    let mut prev: Vec<(usize, usize)> = (0..=field.len()).map(|j| (0, j)).collect();
+    let mut curr = vec![(0, 0); field.len() + 1];
+
+    for (i, t) in target.chars().enumerate() {
+        curr[0] = (i + 1, 0);
+        for (j, &f) in field.iter().enumerate() {
+            let cost = if t == f { 0 } else { 1 };
+            let substitute = (prev[j].0 + cost, prev[j].1);
+            let delete = (prev[j + 1].0 + 1, prev[j + 1].1);
+            let insert = (curr[j].0 + 1, curr[j].1);
+
+            // Lowest distance first, then the latest start (shortest window)
+            curr[j + 1] = [substitute, delete, insert]
+                .into_iter()
+                .min_by_key(|&(distance, start)| (distance, usize::MAX - start))
+                .unwrap_or(substitute);
+        }
+        core::mem::swap(&mut prev, &mut curr);
+    }
+
+    let (end, &(distance, start)) = prev
+        .iter()
+        .enumerate()
+        .min_by_key(|&(end, &(distance, _))| (distance, end))
+        .unwrap_or((0, &(0, 0)));
+    (distance, start, end)
+}
+
+/// Number of non-overlapping windows of `field` within `max` edits of
+/// `target`, taken greedily left to right
+///
+/// Runs `best_window`'s free-start DP over a column of the target, keeping
+/// only the rows within `max` (Ukkonen's cutoff): a row over `max` can't
+/// lead to a window within it, and the rows past the last one within `max`
+/// aren't computed. The first char at which some window ends within `max`
+/// closes an occurrence, and the DP starts afresh after it, so a window
+/// never overlaps the previous one; ending each one as early as possible
+/// leaves the most room after it, which makes the count the largest any
+/// choice of non-overlapping windows gives. An empty target occurs
+/// nowhere, and with `max` at least the target's length every char is an
+/// occurrence.
+pub fn occurrences(field: &str, target: &str, max: usize) -> usize {
+    let target: Vec<char> = target.chars().collect();
+    if target.is_empty() {
+        return 0;
+    }
+
+    // Distances are capped at `max + 1`, which is as good as any above it
+    let over = max.saturating_add(1);
+    let fresh: Vec<usize> = (0..=target.len()).map(|i| i.min(over)).collect();
+    let first_last = max.min(target.len());
+    let mut column = fresh.clone();
+    // The last row within `max`; every row past the one after it is `over`
+    let mut last = first_last;
+    let mut count = 0;
+
+    for c in field.chars() {
+        let rows = (last + 1).min(target.len());
+        let mut diagonal = column[0];
+        for (i, &t) in target[..rows].iter().enumerate() {
+            let cost = if c == t { 0 } else { 1 };
+            let next = core::cmp::min(
+                core::cmp::min(
+                    column[i] + 1,     // Insertion
+                    column[i + 1] + 1, // Deletion
+                ),
+                diagonal + cost,       // Substitution
+            );
+            diagonal = column[i + 1];
+            column[i + 1] = next.min(over);
+        }
+        last = rows;
+        while column[last] > max {
+            last -= 1;
+        }
+
+        if last == target.len() {
+            count += 1;
+            column.copy_from_slice(&fresh);
+            last = first_last;
+        }
+    }
+    count
+}
+
+/// How `stepped_window` searches, from `window_step`, `window_overlap` and
+/// `window_refine`
+pub struct Stepping {
+    /// Chars between the starts of consecutive windows
+    pub step: usize,
+    /// Chars past the target's length a window reaches
+    pub overlap: usize,
+    /// Search the neighborhood of the best window exhaustively
+    pub refine: bool,
+}
+
+/// Approximate `best_window` from windows starting every `stepping.step`
+/// chars
+///
+/// Each window starts at a multiple of the step and ends at most
+/// `target.len() + stepping.overlap` chars later; it is scored by the
+/// closest prefix of it to `target`, with a DP that stops once every
+/// prefix is over `bound`. That skips most of the field, but a match
+/// starting `a` chars past a window start costs `a` more edits there, so it
+/// is only found when its distance plus `a` is within `bound`. With
+/// `stepping.refine` the chars around the best window, `step - 1` to each
+/// side, are then searched exhaustively, which recovers the exact distance
+/// and window of a match near it. With no window within `bound`, the
+/// result is `(bound + 1, 0, 0)`. A step of 1 is `best_window` itself.
+pub fn stepped_window(
+    field: &str,
+    target: &str,
+    stepping: &Stepping,
+    bound: usize,
+) -> (usize, usize, usize) {
+    if stepping.step <= 1 {
+        return best_window(field, target);
+    }
+
+    let offsets: Vec<usize> = field.char_indices().map(|(i, _)| i).chain([field.len()]).collect();
+    let chars: Vec<char> = field.chars().collect();
+    let target_chars: Vec<char> = target.chars().collect();
+    let span = target_chars.len().saturating_add(stepping.overlap);
+
+    let mut best: Option<(usize, usize, usize)> = None;
+    // An empty field still has its one empty window
+    for start in (0..chars.len().max(1)).step_by(stepping.step) {
+        let window = &chars[start..chars.len().min(start.saturating_add(span))];
+        if let Some((distance, len)) = closest_prefix(window, &target_chars, bound) {
+            if best.is_none_or(|(best, _, _)| distance < best) {
+                best = Some((distance, start, start + len));
+            }
+            if distance == 0 {
+                break;
+            }
+        }
+    }
+    let (distance, start, end) = match best {
+        Some(best) => best,
+        None => return (bound.saturating_add(1), 0, 0),
+    };
+    if !stepping.refine || distance == 0 {
+        return (distance, start, end);
+    }
+
+    // The coarse window lies inside the neighborhood, so this is no worse
+    let from = start.saturating_sub(stepping.step - 1);
+    let to = chars.len().min(start.saturating_add(span).saturating_add(stepping.step - 1));
+    let (distance, start, end) = best_window(&field[offsets[from]..offsets[to]], target);
+    (distance, from + start, from + end)
+}
+
+/// Smallest edit distance between `target` and a prefix of `window`, with
+/// the shortest such prefix's length, if within `bound`
+///
+/// The Levenshtein DP down the chars of `window`, where every row ends in
+/// the distance of one more prefix; it stops as soon as a whole row is over
+/// `bound`, since longer prefixes can't get back under it.
+fn closest_prefix(window: &[char], target: &[char], bound: usize) -> Option<(usize, usize)> {
+    let mut prev: Vec<usize> = (0..=target.len()).collect();
+    let mut curr = vec![0; target.len() + 1];
+    let mut best = (target.len(), 0);
+
+    for (i, &c) in window.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, &t) in target.iter().enumerate() {
+            let cost = if c == t { 0 } else { 1 };
+            curr[j + 1] = (curr[j] + 1).min(prev[j + 1] + 1).min(prev[j] + cost);
+        }
+        if curr[target.len()] < best.0 {
+            best = (curr[target.len()], i + 1);
+        }
+        if curr.iter().min().is_some_and(|&min| min > bound) {
+            break;
+        }
+        core::mem::swap(&mut prev, &mut curr);
+    }
+
+    Some(best).filter(|&(distance, _)| distance <= bound)
+}
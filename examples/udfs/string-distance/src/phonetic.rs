@@ -0,0 +1,691 @@
+//! Double Metaphone phonetic codes, for the `name_match` and `phonetic_key`
+//! algorithms.
+
+use crate::{levenshtein_distance, levenshtein_within};
+
+/// Longest code kept, as in Philips' reference implementation
+const MAX_CODE_LEN: usize = 4;
+
+/// Primary and alternate Double Metaphone codes of one word
+///
+/// The alternate code differs from the primary one where a spelling has a
+/// second common pronunciation, mostly in names from other languages:
+/// "Schmidt" is XMT / SMT, "Jose" is HS / HS.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Codes {
+    pub primary: String,
+    pub alternate: String,
+}
+
+/// Double Metaphone codes of `word`
+///
+/// Letters are compared case-insensitively; chars that aren't Latin letters
+/// are skipped. A word without any coded letter gives two empty codes.
+pub fn double_metaphone(word: &str) -> Codes {
+    let chars: Vec<char> = word.chars().flat_map(char::to_uppercase).collect();
+    let mut encoder = Encoder {
+        slavo_germanic: is_slavo_germanic(&chars),
+        chars,
+        primary: String::new(),
+        alternate: String::new(),
+    };
+    encoder.encode();
+
+    let truncate = |code: String| code.chars().take(MAX_CODE_LEN).collect();
+    Codes {
+        primary: truncate(encoder.primary),
+        alternate: truncate(encoder.alternate),
+    }
+}
+
+/// Whether the word looks Slavic or Germanic (W, K, CZ or WITZ), which
+/// changes several rules
+fn is_slavo_germanic(chars: &[char]) -> bool {
+    chars.contains(&'W') || chars.contains(&'K') || chars.windows(2).any(|w| w == ['C', 'Z'])
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'A' | 'E' | 'I' | 'O' | 'U' | 'Y')
+}
+
+struct Encoder {
+    chars: Vec<char>,
+    slavo_germanic: bool,
+    primary: String,
+    alternate: String,
+}
+
+impl Encoder {
+    fn len(&self) -> isize {
+        self.chars.len() as isize
+    }
+
+    /// The char at `i`, or `'\0'` outside the word
+    fn at(&self, i: isize) -> char {
+        if i < 0 {
+            return '\0';
+        }
+        self.chars.get(i as usize).copied().unwrap_or('\0')
+    }
+
+    /// Whether one of `options` (all of one length) occurs at `start`
+    fn is(&self, start: isize, options: &[&str]) -> bool {
+        if start < 0 {
+            return false;
+        }
+        let start = start as usize;
+        options.iter().any(|option| {
+            let len = option.chars().count();
+            start + len <= self.chars.len()
+                && self.chars[start..start + len].iter().copied().eq(option.chars())
+        })
+    }
+
+    fn push(&mut self, primary: &str, alternate: &str) {
+        self.primary.push_str(primary);
+        self.alternate.push_str(alternate);
+    }
+
+    fn push_both(&mut self, code: &str) {
+        self.push(code, code);
+    }
+
+    fn encode(&mut self) {
+        // Silent first letters
+        let mut i = if self.is(0, &["GN", "KN", "PN", "WR", "PS"]) { 1 } else { 0 };
+
+        while (self.primary.len() < MAX_CODE_LEN || self.alternate.len() < MAX_CODE_LEN)
+            && i < self.len()
+        {
+            i = match self.at(i) {
+                c if is_vowel(c) => {
+                    // Only an initial vowel is coded
+                    if i == 0 {
+                        self.push_both("A");
+                    }
+                    i + 1
+                }
+                'B' => {
+                    self.push_both("P");
+                    i + if self.at(i + 1) == 'B' { 2 } else { 1 }
+                }
+                'Ç' => {
+                    self.push_both("S");
+                    i + 1
+                }
+                'C' => self.c(i),
+                'D' => self.d(i),
+                'F' => self.simple(i, "F", 'F'),
+                'G' => self.g(i),
+                'H' => self.h(i),
+                'J' => self.j(i),
+                'K' => self.simple(i, "K", 'K'),
+                'L' => self.l(i),
+                'M' => {
+                    self.push_both("M");
+                    let silent_b = self.is(i - 1, &["UMB"])
+                        && (i + 1 == self.len() - 1 || self.is(i + 2, &["ER"]));
+                    i + if self.at(i + 1) == 'M' || silent_b { 2 } else { 1 }
+                }
+                'N' => self.simple(i, "N", 'N'),
+                'Ñ' => {
+                    self.push_both("N");
+                    i + 1
+                }
+                'P' => self.p(i),
+                'Q' => self.simple(i, "K", 'Q'),
+                'R' => self.r(i),
+                'S' => self.s(i),
+                'T' => self.t(i),
+                'V' => self.simple(i, "F", 'V'),
+                'W' => self.w(i),
+                'X' => self.x(i),
+                'Z' => self.z(i),
+                _ => i + 1,
+            };
+        }
+    }
+
+    /// A letter with a single code, whose doubling is coded once
+    fn simple(&mut self, i: isize, code: &str, double: char) -> isize {
+        self.push_both(code);
+        i + if self.at(i + 1) == double { 2 } else { 1 }
+    }
+
+    fn c(&mut self, i: isize) -> isize {
+        if self.germanic_ch(i) {
+            self.push_both("K");
+            i + 2
+        } else if i == 0 && self.is(i, &["CAESAR"]) {
+            self.push_both("S");
+            i + 2
+        } else if self.is(i, &["CH"]) {
+            self.ch(i)
+        } else if self.is(i, &["CZ"]) && !self.is(i - 2, &["WICZ"]) {
+            // "Czerny"
+            self.push("S", "X");
+            i + 2
+        } else if self.is(i + 1, &["CIA"]) {
+            // "Focaccia"
+            self.push_both("X");
+            i + 3
+        } else if self.is(i, &["CC"]) && !(i == 1 && self.at(0) == 'M') {
+            self.cc(i)
+        } else if self.is(i, &["CK", "CG", "CQ"]) {
+            self.push_both("K");
+            i + 2
+        } else if self.is(i, &["CI", "CE", "CY"]) {
+            // Italian vs English
+            if self.is(i, &["CIO", "CIE", "CIA"]) {
+                self.push("S", "X");
+            } else {
+                self.push_both("S");
+            }
+            i + 2
+        } else {
+            self.push_both("K");
+            if self.is(i + 1, &[" C", " Q", " G"]) {
+                // "Mac Caffrey", "Mac Gregor"
+                i + 3
+            } else if self.is(i + 1, &["C", "K", "Q"]) && !self.is(i + 1, &["CE", "CI"]) {
+                i + 2
+            } else {
+                i + 1
+            }
+        }
+    }
+
+    /// A hard "ch" in Germanic words ("Bacher", "Macher"), but not "Bach"
+    /// followed by E or I
+    fn germanic_ch(&self, i: isize) -> bool {
+        if self.is(i, &["CHIA"]) {
+            return true;
+        }
+        if i <= 1 || is_vowel(self.at(i - 2)) || !self.is(i - 1, &["ACH"]) {
+            return false;
+        }
+        let next = self.at(i + 2);
+        (next != 'I' && next != 'E') || self.is(i - 2, &["BACHER", "MACHER"])
+    }
+
+    fn ch(&mut self, i: isize) -> isize {
+        if i > 0 && self.is(i, &["CHAE"]) {
+            // "Michael"
+            self.push("K", "X");
+        } else if self.greek_initial_ch(i) || self.hard_ch(i) {
+            self.push_both("K");
+        } else if i > 0 {
+            if self.is(0, &["MC"]) {
+                // "McHugh"
+                self.push_both("K");
+            } else {
+                self.push("X", "K");
+            }
+        } else {
+            self.push_both("X");
+        }
+        i + 2
+    }
+
+    /// Greek roots at the start of a word ("Chemistry", "Chorus")
+    fn greek_initial_ch(&self, i: isize) -> bool {
+        i == 0
+            && (self.is(i + 1, &["HARAC", "HARIS"])
+                || self.is(i + 1, &["HOR", "HYM", "HIA", "HEM"]))
+            && !self.is(0, &["CHORE"])
+    }
+
+    fn hard_ch(&self, i: isize) -> bool {
+        self.is(0, &["VAN ", "VON "])
+            || self.is(0, &["SCH"])
+            || self.is(i - 2, &["ORCHES", "ARCHIT", "ORCHID"])
+            || self.is(i + 2, &["T", "S"])
+            || ((self.is(i - 1, &["A", "O", "U", "E"]) || i == 0)
+                && (self.is(i + 2, &["L", "R", "N", "M", "B", "H", "F", "V", "W", " "])
+                    || i + 1 == self.len() - 1))
+    }
+
+    fn cc(&mut self, i: isize) -> isize {
+        if self.is(i + 2, &["I", "E", "H"]) && !self.is(i + 2, &["HU"]) {
+            if (i == 1 && self.at(i - 1) == 'A') || self.is(i - 1, &["UCCEE", "UCCES"]) {
+                // "Accident", "Succeed"
+                self.push_both("KS");
+            } else {
+                // "Bacci", "Bertucci"
+                self.push_both("X");
+            }
+            i + 3
+        } else {
+            self.push_both("K");
+            i + 2
+        }
+    }
+
+    fn d(&mut self, i: isize) -> isize {
+        if self.is(i, &["DG"]) {
+            if self.is(i + 2, &["I", "E", "Y"]) {
+                // "Edge"
+                self.push_both("J");
+                i + 3
+            } else {
+                // "Edgar"
+                self.push_both("TK");
+                i + 2
+            }
+        } else if self.is(i, &["DT", "DD"]) {
+            self.push_both("T");
+            i + 2
+        } else {
+            self.push_both("T");
+            i + 1
+        }
+    }
+
+    fn g(&mut self, i: isize) -> isize {
+        let next = self.at(i + 1);
+        if next == 'H' {
+            return self.gh(i);
+        }
+        if next == 'N' {
+            if i == 1 && is_vowel(self.at(0)) && !self.slavo_germanic {
+                self.push("KN", "N");
+            } else if !self.is(i + 2, &["EY"]) && next != 'Y' && !self.slavo_germanic {
+                self.push("N", "KN");
+            } else {
+                self.push_both("KN");
+            }
+            return i + 2;
+        }
+        if self.is(i + 1, &["LI"]) && !self.slavo_germanic {
+            // "Tagliaro"
+            self.push("KL", "L");
+            return i + 2;
+        }
+
+        let soft_initial = ["ES", "EP", "EB", "EL", "EY", "IB", "IL", "IN", "IE", "EI", "ER"];
+        if i == 0 && (next == 'Y' || self.is(i + 1, &soft_initial)) {
+            self.push("K", "J");
+            i + 2
+        } else if (self.is(i + 1, &["ER"]) || next == 'Y')
+            && !self.is(0, &["DANGER", "RANGER", "MANGER"])
+            && !self.is(i - 1, &["E", "I"])
+            && !self.is(i - 1, &["RGY", "OGY"])
+        {
+            // "Rogier"
+            self.push("K", "J");
+            i + 2
+        } else if self.is(i + 1, &["E", "I", "Y"]) || self.is(i - 1, &["AGGI", "OGGI"]) {
+            if self.is(0, &["VAN ", "VON "]) || self.is(0, &["SCH"]) || self.is(i + 1, &["ET"]) {
+                // Germanic
+                self.push_both("K");
+            } else if self.is(i + 1, &["IER"]) {
+                self.push_both("J");
+            } else {
+                self.push("J", "K");
+            }
+            i + 2
+        } else {
+            self.push_both("K");
+            i + if next == 'G' { 2 } else { 1 }
+        }
+    }
+
+    fn gh(&mut self, i: isize) -> isize {
+        if i > 0 && !is_vowel(self.at(i - 1)) {
+            self.push_both("K");
+        } else if i == 0 {
+            // "Ghislane", "Ghiradelli"
+            self.push_both(if self.at(i + 2) == 'I' { "J" } else { "K" });
+        } else if (i > 1 && self.is(i - 2, &["B", "H", "D"]))
+            || (i > 2 && self.is(i - 3, &["B", "H", "D"]))
+            || (i > 3 && self.is(i - 4, &["B", "H"]))
+        {
+            // Silent: "Hugh", "bough", "broughton"
+        } else if i > 2 && self.at(i - 1) == 'U' && self.is(i - 3, &["C", "G", "L", "R", "T"]) {
+            // "Laugh", "McLaughlin", "cough", "tough"
+            self.push_both("F");
+        } else if i > 0 && self.at(i - 1) != 'I' {
+            self.push_both("K");
+        }
+        i + 2
+    }
+
+    fn h(&mut self, i: isize) -> isize {
+        // Only coded between vowels or before one at the start
+        if (i == 0 || is_vowel(self.at(i - 1))) && is_vowel(self.at(i + 1)) {
+            self.push_both("H");
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn j(&mut self, i: isize) -> isize {
+        if self.is(i, &["JOSE"]) || self.is(0, &["SAN "]) {
+            // Spanish
+            if (i == 0 && self.at(i + 4) == ' ') || self.len() == 4 || self.is(0, &["SAN "]) {
+                self.push_both("H");
+            } else {
+                self.push("J", "H");
+            }
+            return i + 1;
+        }
+
+        if i == 0 {
+            // "Jankelowicz"
+            self.push("J", "A");
+        } else if is_vowel(self.at(i - 1))
+            && !self.slavo_germanic
+            && matches!(self.at(i + 1), 'A' | 'O')
+        {
+            // "Bajador"
+            self.push("J", "H");
+        } else if i == self.len() - 1 {
+            self.push("J", "");
+        } else if !self.is(i + 1, &["L", "T", "K", "S", "N", "M", "B", "Z"])
+            && !self.is(i - 1, &["S", "K", "L"])
+        {
+            self.push_both("J");
+        }
+        i + if self.at(i + 1) == 'J' { 2 } else { 1 }
+    }
+
+    fn l(&mut self, i: isize) -> isize {
+        if self.at(i + 1) != 'L' {
+            self.push_both("L");
+            return i + 1;
+        }
+        if self.spanish_ll(i) {
+            // "Cabrillo", "Gallegos"
+            self.push("L", "");
+        } else {
+            self.push_both("L");
+        }
+        i + 2
+    }
+
+    fn spanish_ll(&self, i: isize) -> bool {
+        let len = self.len();
+        (i == len - 3 && self.is(i - 1, &["ILLO", "ILLA", "ALLE"]))
+            || ((self.is(len - 2, &["AS", "OS"]) || self.is(len - 1, &["A", "O"]))
+                && self.is(i - 1, &["ALLE"]))
+    }
+
+    fn p(&mut self, i: isize) -> isize {
+        if self.at(i + 1) == 'H' {
+            self.push_both("F");
+            return i + 2;
+        }
+        // "Campbell", "raspberry"
+        self.push_both("P");
+        i + if self.is(i + 1, &["P", "B"]) { 2 } else { 1 }
+    }
+
+    fn r(&mut self, i: isize) -> isize {
+        if i == self.len() - 1
+            && !self.slavo_germanic
+            && self.is(i - 2, &["IE"])
+            && !self.is(i - 4, &["ME", "MA"])
+        {
+            // French "Rogier"
+            self.push("", "R");
+        } else {
+            self.push_both("R");
+        }
+        i + if self.at(i + 1) == 'R' { 2 } else { 1 }
+    }
+
+    fn s(&mut self, i: isize) -> isize {
+        if self.is(i - 1, &["ISL", "YSL"]) {
+            // Silent: "Island", "Carlisle"
+            i + 1
+        } else if i == 0 && self.is(i, &["SUGAR"]) {
+            self.push("X", "S");
+            i + 1
+        } else if self.is(i, &["SH"]) {
+            // Germanic
+            if self.is(i + 1, &["HEIM", "HOEK", "HOLM", "HOLZ"]) {
+                self.push_both("S");
+            } else {
+                self.push_both("X");
+            }
+            i + 2
+        } else if self.is(i, &["SIO", "SIA"]) || self.is(i, &["SIAN"]) {
+            // Italian and Armenian
+            if self.slavo_germanic {
+                self.push_both("S");
+            } else {
+                self.push("S", "X");
+            }
+            i + 3
+        } else if (i == 0 && self.is(i + 1, &["M", "N", "L", "W"])) || self.is(i + 1, &["Z"]) {
+            // "Smith" / "Schmidt", "Snider" / "Schneider"
+            self.push("S", "X");
+            i + if self.is(i + 1, &["Z"]) { 2 } else { 1 }
+        } else if self.is(i, &["SC"]) {
+            self.sc(i)
+        } else {
+            if i == self.len() - 1 && self.is(i - 2, &["AI", "OI"]) {
+                // French "Resnais", "Artois"
+                self.push("", "S");
+            } else {
+                self.push_both("S");
+            }
+            i + if self.is(i + 1, &["S", "Z"]) { 2 } else { 1 }
+        }
+    }
+
+    fn sc(&mut self, i: isize) -> isize {
+        if self.at(i + 2) == 'H' {
+            if self.is(i + 3, &["OO", "ER", "EN", "UY", "ED", "EM"]) {
+                // Dutch "Schooner", "Schermerhorn"
+                if self.is(i + 3, &["ER", "EN"]) {
+                    self.push("X", "SK");
+                } else {
+                    self.push_both("SK");
+                }
+            } else if i == 0 && !is_vowel(self.at(3)) && self.at(3) != 'W' {
+                // "Schmidt"
+                self.push("X", "S");
+            } else {
+                self.push_both("X");
+            }
+        } else if self.is(i + 2, &["I", "E", "Y"]) {
+            self.push_both("S");
+        } else {
+            self.push_both("SK");
+        }
+        i + 3
+    }
+
+    fn t(&mut self, i: isize) -> isize {
+        if self.is(i, &["TION"]) || self.is(i, &["TIA", "TCH"]) {
+            self.push_both("X");
+            i + 3
+        } else if self.is(i, &["TH"]) || self.is(i, &["TTH"]) {
+            let germanic = self.is(0, &["VAN ", "VON "]) || self.is(0, &["SCH"]);
+            if self.is(i + 2, &["OM", "AM"]) || germanic {
+                // "Thomas", "Thames"
+                self.push_both("T");
+            } else {
+                self.push("0", "T");
+            }
+            i + 2
+        } else {
+            self.push_both("T");
+            i + if self.is(i + 1, &["T", "D"]) { 2 } else { 1 }
+        }
+    }
+
+    fn w(&mut self, i: isize) -> isize {
+        if self.is(i, &["WR"]) {
+            self.push_both("R");
+            return i + 2;
+        }
+
+        if i == 0 && (is_vowel(self.at(i + 1)) || self.is(i, &["WH"])) {
+            // "Wasserman" may start with a V
+            if is_vowel(self.at(i + 1)) {
+                self.push("A", "F");
+            } else {
+                self.push_both("A");
+            }
+            i + 1
+        } else if (i == self.len() - 1 && is_vowel(self.at(i - 1)))
+            || self.is(i - 1, &["EWSKI", "EWSKY", "OWSKI", "OWSKY"])
+            || self.is(0, &["SCH"])
+        {
+            // Polish "Filipowicz", Germanic "Arnow"
+            self.push("", "F");
+            i + 1
+        } else if self.is(i, &["WICZ", "WITZ"]) {
+            self.push("TS", "FX");
+            i + 4
+        } else {
+            i + 1
+        }
+    }
+
+    fn x(&mut self, i: isize) -> isize {
+        if i == 0 {
+            // "Xavier"
+            self.push_both("S");
+            return i + 1;
+        }
+        // French "Breaux" is silent
+        let silent = i == self.len() - 1
+            && (self.is(i - 3, &["IAU", "EAU"]) || self.is(i - 2, &["AU", "OU"]));
+        if !silent {
+            self.push_both("KS");
+        }
+        i + if self.is(i + 1, &["C", "X"]) { 2 } else { 1 }
+    }
+
+    fn z(&mut self, i: isize) -> isize {
+        if self.at(i + 1) == 'H' {
+            // Chinese "Zhao"
+            self.push_both("J");
+            return i + 2;
+        }
+        if self.is(i + 1, &["ZO", "ZI", "ZA"])
+            || (self.slavo_germanic && i > 0 && self.at(i - 1) != 'T')
+        {
+            self.push("S", "TS");
+        } else {
+            self.push_both("S");
+        }
+        i + if self.at(i + 1) == 'Z' { 2 } else { 1 }
+    }
+}
+
+/// Phonetic agreement of two names in [0, 1]
+///
+/// Each word of either name is paired with the word of the other name it
+/// sounds most like: 1.0 when their primary codes agree, 0.5 when only an
+/// alternate code agrees with the other word's codes, 0.0 otherwise. The
+/// score averages over the words of both names, so word order doesn't
+/// matter and an extra word only costs its share. Names without any coded
+/// word agree when they are equal. Only `name_match` uses it, so it needs
+/// the `ngram` feature as well.
+#[cfg(feature = "ngram")]
+pub fn phonetic_similarity(s1: &str, s2: &str) -> f64 {
+    let codes = |s: &str| -> Vec<Codes> {
+        s.split_whitespace()
+            .map(double_metaphone)
+            .filter(|codes| !codes.primary.is_empty() || !codes.alternate.is_empty())
+            .collect()
+    };
+    let (a, b) = (codes(s1), codes(s2));
+    if a.is_empty() || b.is_empty() {
+        return if a.is_empty() && b.is_empty() && s1 == s2 { 1.0 } else { 0.0 };
+    }
+
+    let best = |word: &Codes, other: &[Codes]| {
+        other.iter().map(|o| agreement(word, o)).fold(0.0, f64::max)
+    };
+    let total: f64 = a.iter().map(|w| best(w, &b)).sum::<f64>()
+        + b.iter().map(|w| best(w, &a)).sum::<f64>();
+    total / (a.len() + b.len()) as f64
+}
+
+#[cfg(feature = "ngram")]
+fn agreement(a: &Codes, b: &Codes) -> f64 {
+    let agree = |x: &String, y: &String| !x.is_empty() && x == y;
+    if agree(&a.primary, &b.primary) {
+        1.0
+    } else if agree(&a.primary, &b.alternate)
+        || agree(&a.alternate, &b.primary)
+        || agree(&a.alternate, &b.alternate)
+    {
+        0.5
+    } else {
+        0.0
+    }
+}
+
+/// Edit distance between the phonetic keys of two names
+///
+/// A name's keys are the primary and the alternate codes of its words,
+/// each joined by spaces, so "Jon Smith" is "JN SM0" or "AN XMT"; the
+/// distance is the smallest between a key of one name and a key of the
+/// other. Names without any coded word are compared by their plain
+/// Levenshtein distance instead.
+pub fn phonetic_key_distance(s1: &str, s2: &str) -> usize {
+    match (keys(s1), keys(s2)) {
+        (Some(a), Some(b)) => a
+            .iter()
+            .flat_map(|x| b.iter().map(move |y| levenshtein_distance(x, y)))
+            .min()
+            .unwrap_or(0),
+        (None, None) => levenshtein_distance(s1, s2),
+        (Some(a), None) | (None, Some(a)) => a[0].chars().count().min(a[1].chars().count()),
+    }
+}
+
+/// Phonetic key similarity in [0, 1]: `1 - distance / max_key_len`
+pub fn phonetic_key_similarity(s1: &str, s2: &str) -> f64 {
+    let len = |s: &str| keys(s).map(|[p, a]| p.chars().count().max(a.chars().count()));
+    let max_len = match (len(s1), len(s2)) {
+        (None, None) => core::cmp::max(s1.chars().count(), s2.chars().count()),
+        (a, b) => a.unwrap_or(0).max(b.unwrap_or(0)),
+    };
+    if max_len == 0 {
+        return 1.0;
+    }
+    (1.0 - phonetic_key_distance(s1, s2) as f64 / max_len as f64).max(0.0)
+}
+
+/// The `phonetic_key` decision: the key distance within `max_distance`
+///
+/// The keys decide on their own except exactly at the limit, where with a
+/// `tiebreak` the spelling decides: the names must also be within that many
+/// plain Levenshtein edits.
+pub fn phonetic_key_match(
+    field: &str,
+    target: &str,
+    max_distance: usize,
+    tiebreak: Option<usize>,
+) -> bool {
+    match phonetic_key_distance(field, target).cmp(&max_distance) {
+        core::cmp::Ordering::Less => true,
+        core::cmp::Ordering::Greater => false,
+        core::cmp::Ordering::Equal => match tiebreak {
+            Some(bound) => levenshtein_within(field, target, bound).is_some(),
+            None => true,
+        },
+    }
+}
+
+/// The primary and the alternate key of `s`, `None` without a coded word
+fn keys(s: &str) -> Option<[String; 2]> {
+    let codes: Vec<Codes> = s
+        .split_whitespace()
+        .map(double_metaphone)
+        .filter(|codes| !codes.primary.is_empty() || !codes.alternate.is_empty())
+        .collect();
+    if codes.is_empty() {
+        return None;
+    }
+    let join = |code: fn(&Codes) -> &str| codes.iter().map(code).collect::<Vec<_>>().join(" ");
+    Some([join(|c| c.primary.as_str()), join(|c| c.alternate.as_str())])
+}
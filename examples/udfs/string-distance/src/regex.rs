@@ -0,0 +1,619 @@
+//! Small regular-expression engine for query-supplied patterns.
+//!
+//! Patterns compile to a Thompson NFA that is run by a Pike VM, so matching
+//! time is linear in the input and no pattern can cause catastrophic
+//! backtracking inside the sandbox. Pattern size is bounded by
+//! `MAX_PATTERN_LEN`, `MAX_INSTS` and `MAX_DEPTH`; the last also bounds the
+//! recursion of the parser and compiler, so no pattern can exhaust the
+//! WASM stack either. Approximate matching (`Regex::full_match_distance`)
+//! runs a DP over the same program, just as linear in the input.
+//!
+//! Supported syntax: literals, `.`, classes `[a-z_]` / `[^...]`, the
+//! escapes `\d \w \s \D \W \S \n \t \r` and escaped metacharacters, groups
+//! `(...)` / `(?:...)`, alternation `|`, and the greedy quantifiers
+//! `* + ? {m} {m,} {m,n}`. Anchors and backreferences are not supported.
+//! Only the first `(...)` group captures (see `Regex::extract`); later ones
+//! just group.
+
+/// Longest accepted pattern, in chars
+const MAX_PATTERN_LEN: usize = 256;
+
+/// Largest compiled program, in instructions
+const MAX_INSTS: usize = 1024;
+
+/// Deepest nesting of groups and of quantifiers stacked on one atom
+/// (`a**`), which each add a level to the syntax tree
+const MAX_DEPTH: usize = 16;
+
+/// Largest bound in a counted repetition `{m,n}`
+const MAX_REPEAT: u32 = 32;
+
+/// A set of character ranges, possibly negated
+#[derive(Clone, Debug)]
+struct Class {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+#[derive(Clone, Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat(Box<Node>, u32, Option<u32>),
+    /// The first capturing group
+    Capture(Box<Node>),
+}
+
+#[derive(Clone, Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Split(usize, usize),
+    Jmp(usize),
+    /// Record the current position as the group's start (0) or end (1)
+    Save(usize),
+    Match,
+}
+
+/// Char offsets of the capturing group's bounds, `NONE` until reached
+type Group = [usize; 2];
+
+const NONE: usize = usize::MAX;
+
+/// A thread of the Pike VM: its instruction, where its match started, and
+/// its group bounds
+#[derive(Clone, Copy)]
+struct Thread {
+    pc: usize,
+    start: usize,
+    group: Group,
+}
+
+/// A compiled pattern
+#[derive(Clone, Debug)]
+pub struct Regex {
+    insts: Vec<Inst>,
+}
+
+impl Regex {
+    /// Compile a pattern
+    ///
+    /// Returns `None` if the pattern is malformed, uses unsupported syntax,
+    /// or exceeds the complexity bounds.
+    pub fn new(pattern: &str) -> Option<Regex> {
+        let chars: Vec<char> = pattern.chars().collect();
+        if chars.len() > MAX_PATTERN_LEN {
+            return None;
+        }
+
+        let mut parser = Parser { chars, pos: 0, captured: false };
+        let node = parser.alternation(0)?;
+        if parser.pos != parser.chars.len() {
+            return None;
+        }
+
+        let mut insts = Vec::new();
+        compile(&node, &mut insts)?;
+        insts.push(Inst::Match);
+        Some(Regex { insts })
+    }
+
+    /// Find the leftmost match starting at or after char index `start`
+    ///
+    /// `input` is the subject as decoded chars. Returns the char span
+    /// `[start, end)` of the match; among matches at the same start the
+    /// usual leftmost-first (Perl-style) priority applies, so greedy
+    /// quantifiers take as much as they can.
+    pub fn find_at(&self, input: &[char], start: usize) -> Option<(usize, usize)> {
+        self.run(input, start).map(|(start, end, _)| (start, end))
+    }
+
+    /// The first capturing group of the leftmost match in `s`, or the whole
+    /// match when the pattern has no group or the group took no part in it
+    ///
+    /// Returns `None` when nothing in `s` matches.
+    pub fn extract<'a>(&self, s: &'a str) -> Option<&'a str> {
+        let offsets: Vec<usize> = s.char_indices().map(|(i, _)| i).collect();
+        let chars: Vec<char> = s.chars().collect();
+        let byte_at = |i: usize| offsets.get(i).copied().unwrap_or(s.len());
+
+        let (start, end, group) = self.run(&chars, 0)?;
+        let (start, end) = match group {
+            [group_start, group_end] if group_start != NONE && group_end != NONE => {
+                (group_start, group_end)
+            }
+            _ => (start, end),
+        };
+        Some(&s[byte_at(start)..byte_at(end)])
+    }
+
+    /// Run the Pike VM from char index `start`, returning the leftmost
+    /// match's span and group bounds
+    fn run(&self, input: &[char], start: usize) -> Option<(usize, usize, Group)> {
+        let mut clist: Vec<Thread> = Vec::new();
+        let mut nlist: Vec<Thread> = Vec::new();
+        let mut seen = vec![usize::MAX; self.insts.len()];
+        let mut matched = None;
+
+        for pos in start..=input.len() {
+            if matched.is_none() {
+                let thread = Thread { pc: 0, start: pos, group: [NONE; 2] };
+                self.add_thread(&mut clist, &mut seen, pos, thread);
+            }
+            if clist.is_empty() {
+                break;
+            }
+
+            let c = input.get(pos).copied();
+            nlist.clear();
+            for &thread in clist.iter() {
+                let advance = match (&self.insts[thread.pc], c) {
+                    (Inst::Match, _) => {
+                        // Lower-priority threads are cut
+                        matched = Some((thread.start, pos, thread.group));
+                        break;
+                    }
+                    (Inst::Char(expected), Some(c)) => *expected == c,
+                    (Inst::Any, Some(_)) => true,
+                    (Inst::Class(class), Some(c)) => class.matches(c),
+                    _ => false,
+                };
+                if advance {
+                    let next = Thread { pc: thread.pc + 1, ..thread };
+                    self.add_thread(&mut nlist, &mut seen, pos + 1, next);
+                }
+            }
+            core::mem::swap(&mut clist, &mut nlist);
+        }
+
+        matched
+    }
+
+    /// All non-empty, non-overlapping matches in `s`, left to right
+    pub fn find_all<'a>(&self, s: &'a str) -> Vec<&'a str> {
+        let offsets: Vec<usize> = s.char_indices().map(|(i, _)| i).collect();
+        let chars: Vec<char> = s.chars().collect();
+        let byte_at = |i: usize| offsets.get(i).copied().unwrap_or(s.len());
+
+        let mut matches = Vec::new();
+        let mut pos = 0;
+        while pos <= chars.len() {
+            match self.find_at(&chars, pos) {
+                Some((start, end)) if end > start => {
+                    matches.push(&s[byte_at(start)..byte_at(end)]);
+                    pos = end;
+                }
+                Some((start, _)) => pos = start + 1,
+                None => break,
+            }
+        }
+        matches
+    }
+
+    /// Fewest edits (inserted, deleted or substituted chars) turning all of
+    /// `input` into a string the pattern matches, `None` when that takes
+    /// more than `max`
+    ///
+    /// Unlike the searches, the pattern has to match the whole input. The
+    /// DP keeps the cheapest cost of reaching each instruction, so every
+    /// input char is one pass over the program whatever the pattern: a
+    /// consumed char moves past a char instruction (free if it matches, a
+    /// substitution if not) or is deleted in place, and between chars an
+    /// instruction can be passed by inserting a char it matches.
+    pub fn full_match_distance(&self, input: &[char], max: usize) -> Option<usize> {
+        let mut costs = vec![NONE; self.insts.len()];
+        costs[0] = 0;
+        self.close(&mut costs, max);
+
+        let mut next = vec![NONE; self.insts.len()];
+        for &c in input {
+            next.fill(NONE);
+            for (pc, &cost) in costs.iter().enumerate() {
+                if cost == NONE {
+                    continue;
+                }
+                let consumed = match &self.insts[pc] {
+                    Inst::Char(expected) => Some(*expected == c),
+                    Inst::Any => Some(true),
+                    Inst::Class(class) => Some(class.matches(c)),
+                    _ => None,
+                };
+                if let Some(matched) = consumed {
+                    relax(&mut next[pc + 1], cost + !matched as usize, max);
+                }
+                relax(&mut next[pc], cost + 1, max);
+            }
+            self.close(&mut next, max);
+            core::mem::swap(&mut costs, &mut next);
+            if costs.iter().all(|&cost| cost == NONE) {
+                return None;
+            }
+        }
+
+        // `Match` is always the last instruction
+        Some(costs[costs.len() - 1]).filter(|&cost| cost != NONE)
+    }
+
+    /// Extend `costs` over the epsilon moves (free) and inserted chars (one
+    /// edit each) available without consuming input
+    ///
+    /// The costs are settled level by level, cheapest first, so each
+    /// instruction is expanded once per level at most.
+    fn close(&self, costs: &mut [usize], max: usize) {
+        for level in 0..=max {
+            let mut stack: Vec<usize> = (0..costs.len()).filter(|&pc| costs[pc] == level).collect();
+            if stack.is_empty() && costs.iter().all(|&cost| cost == NONE || cost < level) {
+                return;
+            }
+            while let Some(pc) = stack.pop() {
+                let mut free = |to: usize, stack: &mut Vec<usize>| {
+                    if costs[to] > level {
+                        costs[to] = level;
+                        stack.push(to);
+                    }
+                };
+                match self.insts[pc] {
+                    Inst::Jmp(target) => free(target, &mut stack),
+                    Inst::Split(first, second) => {
+                        free(first, &mut stack);
+                        free(second, &mut stack);
+                    }
+                    Inst::Save(_) => free(pc + 1, &mut stack),
+                    Inst::Char(_) | Inst::Any | Inst::Class(_) => {
+                        relax(&mut costs[pc + 1], level + 1, max)
+                    }
+                    Inst::Match => {}
+                }
+            }
+        }
+    }
+
+    /// Add a thread and everything reachable from it by epsilon moves,
+    /// in priority order
+    ///
+    /// `generation` is the input position: a `Save` records it, and each
+    /// instruction is entered at most once per position, by the
+    /// highest-priority thread reaching it.
+    fn add_thread(
+        &self,
+        list: &mut Vec<Thread>,
+        seen: &mut [usize],
+        generation: usize,
+        thread: Thread,
+    ) {
+        let mut stack = vec![thread];
+        while let Some(thread) = stack.pop() {
+            if seen[thread.pc] == generation {
+                continue;
+            }
+            seen[thread.pc] = generation;
+
+            match self.insts[thread.pc] {
+                Inst::Jmp(target) => stack.push(Thread { pc: target, ..thread }),
+                Inst::Split(first, second) => {
+                    stack.push(Thread { pc: second, ..thread });
+                    stack.push(Thread { pc: first, ..thread });
+                }
+                Inst::Save(slot) => {
+                    let mut group = thread.group;
+                    group[slot] = generation;
+                    stack.push(Thread { pc: thread.pc + 1, group, ..thread });
+                }
+                _ => list.push(thread),
+            }
+        }
+    }
+}
+
+/// Lower `slot` to `cost` unless it is already cheaper or `cost` is over
+/// `max`
+fn relax(slot: &mut usize, cost: usize, max: usize) {
+    if cost <= max && cost < *slot {
+        *slot = cost;
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Whether the capturing group has been parsed
+    captured: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn alternation(&mut self, depth: usize) -> Option<Node> {
+        let mut branches = vec![self.concatenation(depth)?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.concatenation(depth)?);
+        }
+
+        if branches.len() == 1 {
+            branches.pop()
+        } else {
+            Some(Node::Alt(branches))
+        }
+    }
+
+    fn concatenation(&mut self, depth: usize) -> Option<Node> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            items.push(self.repetition(depth)?);
+        }
+
+        match items.len() {
+            0 => Some(Node::Empty),
+            1 => items.pop(),
+            _ => Some(Node::Concat(items)),
+        }
+    }
+
+    fn repetition(&mut self, depth: usize) -> Option<Node> {
+        let mut node = self.atom(depth)?;
+        let mut depth = depth;
+        loop {
+            let (min, max) = match self.peek() {
+                Some('{') => self.counted()?,
+                Some(c @ ('*' | '+' | '?')) => {
+                    self.pos += 1;
+                    match c {
+                        '*' => (0, None),
+                        '+' => (1, None),
+                        _ => (0, Some(1)),
+                    }
+                }
+                _ => return Some(node),
+            };
+            depth += 1;
+            if depth > MAX_DEPTH {
+                return None;
+            }
+            node = Node::Repeat(Box::new(node), min, max);
+        }
+    }
+
+    /// Parse a counted repetition `{m}`, `{m,}` or `{m,n}`
+    fn counted(&mut self) -> Option<(u32, Option<u32>)> {
+        self.pos += 1;
+        let min = self.number()?;
+        let max = match self.next()? {
+            '}' => return Some((min, Some(min))).filter(|_| min <= MAX_REPEAT),
+            ',' if self.peek() == Some('}') => None,
+            ',' => Some(self.number()?),
+            _ => return None,
+        };
+        if self.next()? != '}' {
+            return None;
+        }
+
+        if min > MAX_REPEAT || max.is_some_and(|max| max < min || max > MAX_REPEAT) {
+            return None;
+        }
+        Some((min, max))
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if self.pos == start || self.pos - start > 3 {
+            return None;
+        }
+        self.chars[start..self.pos].iter().collect::<String>().parse().ok()
+    }
+
+    fn atom(&mut self, depth: usize) -> Option<Node> {
+        match self.next()? {
+            '(' => {
+                if depth + 1 > MAX_DEPTH {
+                    return None;
+                }
+                let mut capture = !self.captured;
+                if self.peek() == Some('?') {
+                    self.pos += 1;
+                    if self.next()? != ':' {
+                        return None;
+                    }
+                    capture = false;
+                }
+                self.captured |= capture;
+                let inner = self.alternation(depth + 1)?;
+                if self.next()? != ')' {
+                    return None;
+                }
+                if capture {
+                    Some(Node::Capture(Box::new(inner)))
+                } else {
+                    Some(inner)
+                }
+            }
+            '.' => Some(Node::Any),
+            '[' => self.class().map(Node::Class),
+            '\\' => match self.next()? {
+                c if escape_class(c).is_some() => escape_class(c).map(Node::Class),
+                c => escape_char(c).map(Node::Char),
+            },
+            ')' | '*' | '+' | '?' | '{' | '}' | '^' | '$' => None,
+            c => Some(Node::Char(c)),
+        }
+    }
+
+    /// Parse a bracket class (after the '[')
+    fn class(&mut self) -> Option<Class> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.next()?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let lo = if c == '\\' {
+                let escaped = self.next()?;
+                if let Some(class) = escape_class(escaped) {
+                    // Negated shorthands can't be merged into a range list
+                    if class.negated {
+                        return None;
+                    }
+                    ranges.extend(class.ranges);
+                    continue;
+                }
+                escape_char(escaped)?
+            } else {
+                c
+            };
+
+            // Range "a-z", unless the '-' is the last char before ']'
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let hi = match self.next()? {
+                    '\\' => escape_char(self.next()?)?,
+                    c => c,
+                };
+                if hi < lo {
+                    return None;
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+
+        Some(Class { negated, ranges })
+    }
+}
+
+/// Shorthand classes `\d \w \s` and their negations
+fn escape_class(c: char) -> Option<Class> {
+    let (ranges, negated) = match c {
+        'd' => (DIGIT, false),
+        'w' => (WORD, false),
+        's' => (SPACE, false),
+        'D' => (DIGIT, true),
+        'W' => (WORD, true),
+        'S' => (SPACE, true),
+        _ => return None,
+    };
+    Some(Class { negated, ranges: ranges.to_vec() })
+}
+
+/// Escaped single characters: control escapes and literal punctuation
+fn escape_char(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        c if c.is_ascii_punctuation() || c == ' ' => Some(c),
+        _ => None,
+    }
+}
+
+fn push(insts: &mut Vec<Inst>, inst: Inst) -> Option<usize> {
+    if insts.len() >= MAX_INSTS {
+        return None;
+    }
+    insts.push(inst);
+    Some(insts.len() - 1)
+}
+
+fn compile(node: &Node, insts: &mut Vec<Inst>) -> Option<()> {
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => {
+            push(insts, Inst::Char(*c))?;
+        }
+        Node::Any => {
+            push(insts, Inst::Any)?;
+        }
+        Node::Class(class) => {
+            push(insts, Inst::Class(class.clone()))?;
+        }
+        Node::Concat(items) => {
+            for item in items {
+                compile(item, insts)?;
+            }
+        }
+        Node::Alt(branches) => {
+            let mut jumps = Vec::new();
+            for (i, branch) in branches.iter().enumerate() {
+                if i + 1 == branches.len() {
+                    compile(branch, insts)?;
+                    break;
+                }
+                let split = push(insts, Inst::Split(0, 0))?;
+                compile(branch, insts)?;
+                jumps.push(push(insts, Inst::Jmp(0))?);
+                insts[split] = Inst::Split(split + 1, insts.len());
+            }
+            let end = insts.len();
+            for jump in jumps {
+                insts[jump] = Inst::Jmp(end);
+            }
+        }
+        Node::Capture(inner) => {
+            push(insts, Inst::Save(0))?;
+            compile(inner, insts)?;
+            push(insts, Inst::Save(1))?;
+        }
+        Node::Repeat(inner, min, max) => {
+            for _ in 0..*min {
+                compile(inner, insts)?;
+            }
+            match max {
+                None => {
+                    let split = push(insts, Inst::Split(0, 0))?;
+                    compile(inner, insts)?;
+                    push(insts, Inst::Jmp(split))?;
+                    insts[split] = Inst::Split(split + 1, insts.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(push(insts, Inst::Split(0, 0))?);
+                        compile(inner, insts)?;
+                    }
+                    let end = insts.len();
+                    for split in splits {
+                        insts[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    Some(())
+}
@@ -0,0 +1,40 @@
+//! Detection of repeated substrings, for the `penalize_repeats` option.
+
+/// Shortest repeating unit counted, so doubled letters ("oo") are ignored
+const MIN_UNIT: usize = 2;
+/// Longest repeating unit looked for, which bounds the work per char
+const MAX_UNIT: usize = 16;
+
+/// Count the extra copies in runs of a repeated substring
+///
+/// Scans left to right for a unit of `MIN_UNIT..=MAX_UNIT` chars that is
+/// immediately repeated, preferring the shortest, and counts every copy
+/// after the first; the scan then resumes after the run. "freefreefree
+/// shipping" has 2 extra copies of "free". Each position tries at most
+/// `MAX_UNIT` unit lengths of at most `MAX_UNIT` comparisons, so the scan is
+/// linear in the length of `s`.
+pub fn extra_copies(s: &str) -> usize {
+    let chars: Vec<char> = s.chars().collect();
+    let mut copies = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let unit = (MIN_UNIT..=MAX_UNIT)
+            .take_while(|&unit| i + 2 * unit <= chars.len())
+            .find(|&unit| chars[i..i + unit] == chars[i + unit..i + 2 * unit]);
+
+        match unit {
+            Some(unit) => {
+                let mut end = i + 2 * unit;
+                while end + unit <= chars.len() && chars[i..i + unit] == chars[end..end + unit] {
+                    end += unit;
+                }
+                copies += (end - i) / unit - 1;
+                i = end;
+            }
+            None => i += 1,
+        }
+    }
+
+    copies
+}
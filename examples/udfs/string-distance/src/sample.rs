@@ -0,0 +1,35 @@
+//! Deterministic target sampling for `target_sample_rate`.
+
+// 64-bit FNV-1a
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &b| (hash ^ b as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// SplitMix64's finalizer, spreading every input bit over the whole hash
+fn mix(mut hash: u64) -> u64 {
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// Whether `target` is among the targets compared for the document `key`
+/// names, at `rate`
+///
+/// The pair is hashed and kept when the hash falls in the lowest `rate`
+/// share of its range, so a document compares about `rate` of the targets:
+/// always the same ones for the same key, and other ones for another key,
+/// so every target is still compared against some documents. A rate of
+/// 1.0 keeps every target.
+pub fn sampled(key: &str, target: &str, rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    // The separator keeps ("ab", "c") and ("a", "bc") apart
+    let hash = fnv1a(fnv1a(fnv1a(FNV_OFFSET, key.as_bytes()), &[0xff]), target.as_bytes());
+    // The top 53 bits, as a fraction in [0, 1)
+    let share = (mix(hash) >> 11) as f64 / (1u64 << 53) as f64;
+    share < rate
+}
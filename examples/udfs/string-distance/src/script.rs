@@ -0,0 +1,112 @@
+//! Coarse Unicode script detection for cross-script comparisons.
+
+/// Scripts told apart by `dominant_script`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Thai,
+    Hangul,
+    Kana,
+    Han,
+}
+
+/// Every `Script`, in declaration (tie-break) order
+const SCRIPTS: [Script; 11] = [
+    Script::Latin,
+    Script::Greek,
+    Script::Cyrillic,
+    Script::Armenian,
+    Script::Hebrew,
+    Script::Arabic,
+    Script::Devanagari,
+    Script::Thai,
+    Script::Hangul,
+    Script::Kana,
+    Script::Han,
+];
+
+/// Classify a character by code point range
+///
+/// Digits, punctuation, spaces, combining marks and unlisted scripts
+/// return `None` and don't count towards any script.
+fn script_of(c: char) -> Option<Script> {
+    let script = match c as u32 {
+        0x41..=0x5A | 0x61..=0x7A => Script::Latin,
+        0xC0..=0xD6 | 0xD8..=0xF6 | 0xF8..=0x24F => Script::Latin,
+        0x1E00..=0x1EFF | 0x2C60..=0x2C7F | 0xA720..=0xA7FF => Script::Latin,
+        0xFF21..=0xFF3A | 0xFF41..=0xFF5A => Script::Latin,
+        0x370..=0x3FF | 0x1F00..=0x1FFF => Script::Greek,
+        0x400..=0x52F | 0x1C80..=0x1C8F | 0x2DE0..=0x2DFF | 0xA640..=0xA69F => Script::Cyrillic,
+        0x530..=0x58F => Script::Armenian,
+        0x590..=0x5FF => Script::Hebrew,
+        0x600..=0x6FF | 0x750..=0x77F | 0x8A0..=0x8FF => Script::Arabic,
+        0xFB50..=0xFDFF | 0xFE70..=0xFEFF => Script::Arabic,
+        0x900..=0x97F => Script::Devanagari,
+        0xE00..=0xE7F => Script::Thai,
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Script::Hangul,
+        0x3040..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F => Script::Kana,
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FFFF => Script::Han,
+        _ => return None,
+    };
+    if c.is_alphabetic() {
+        Some(script)
+    } else {
+        None
+    }
+}
+
+/// Whether `c` is a letter of a script not listed in `Script`
+pub fn is_unknown_letter(c: char) -> bool {
+    c.is_alphabetic() && script_of(c).is_none()
+}
+
+/// The script most of the letters in `s` belong to
+///
+/// Ties go to the script listed first in `Script`, so the result is
+/// deterministic. Returns `None` if `s` has no classified letters.
+pub fn dominant_script(s: &str) -> Option<Script> {
+    let mut counts = [0usize; SCRIPTS.len()];
+    for script in s.chars().filter_map(script_of) {
+        counts[script as usize] += 1;
+    }
+
+    let (index, &count) = counts
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|&(_, &count)| count)?;
+    if count == 0 {
+        return None;
+    }
+    Some(SCRIPTS[index])
+}
+
+/// Whether the letters of `token` belong to more than one script
+///
+/// Han, kana and Hangul count as one script here, since Japanese and Korean
+/// are written mixing them.
+#[cfg(feature = "token")]
+pub fn mixes_scripts(token: &str) -> bool {
+    let mut scripts = token.chars().filter_map(script_of).map(|script| match script {
+        Script::Kana | Script::Hangul => Script::Han,
+        script => script,
+    });
+    match scripts.next() {
+        Some(first) => scripts.any(|script| script != first),
+        None => false,
+    }
+}
+
+/// Whether both strings have a dominant script and they differ
+pub fn scripts_differ(s1: &str, s2: &str) -> bool {
+    match (dominant_script(s1), dominant_script(s2)) {
+        (Some(a), Some(b)) => a != b,
+        _ => false,
+    }
+}
@@ -0,0 +1,53 @@
+//! Work counters `filter` reports through `emit_stat` with `stats`.
+//!
+//! The counters are bumped whether or not a query asks for them, one add
+//! per field or DP row rather than per cell; a build without the `stats`
+//! feature leaves them out entirely.
+
+/// What `filter` computed since the counters were last taken
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Counters {
+    /// Field values compared with a target
+    pub fields_examined: u64,
+    /// Cells of the Levenshtein DPs computed
+    pub dp_cells: u64,
+    /// Comparisons given up once the distance had to exceed the limit
+    pub early_exits: u64,
+}
+
+impl Counters {
+    /// Each counter with the key `emit_stat` reports it under
+    pub fn named(&self) -> [(&'static str, u64); 3] {
+        [
+            ("fields_examined", self.fields_examined),
+            ("dp_cells", self.dp_cells),
+            ("early_exits", self.early_exits),
+        ]
+    }
+}
+
+static mut COUNTERS: Counters = Counters {
+    fields_examined: 0,
+    dp_cells: 0,
+    early_exits: 0,
+};
+
+/// Count one field value compared
+pub fn field_examined() {
+    unsafe { COUNTERS.fields_examined += 1 }
+}
+
+/// Count `cells` DP cells computed
+pub fn dp_cells(cells: usize) {
+    unsafe { COUNTERS.dp_cells += cells as u64 }
+}
+
+/// Count one comparison cut short
+pub fn early_exit() {
+    unsafe { COUNTERS.early_exits += 1 }
+}
+
+/// The counters so far, resetting them to 0
+pub fn take() -> Counters {
+    unsafe { core::mem::take(&mut COUNTERS) }
+}
@@ -0,0 +1,47 @@
+//! Online Levenshtein distance for fields streamed in chunks.
+
+/// Levenshtein DP fed one field char at a time
+///
+/// Only the two rows over the target are kept, so the field never has to
+/// be resident. Like `levenshtein_banded`, it reports when the distance
+/// must exceed `bound` so the caller can stop reading.
+pub struct StreamingLevenshtein {
+    target: Vec<char>,
+    prev: Vec<usize>,
+    curr: Vec<usize>,
+    bound: usize,
+}
+
+impl StreamingLevenshtein {
+    pub fn new(target: &str, bound: usize) -> Self {
+        let target: Vec<char> = target.chars().collect();
+        let prev = (0..=target.len()).collect();
+        let curr = vec![0; target.len() + 1];
+        StreamingLevenshtein { target, prev, curr, bound }
+    }
+
+    /// Feed the next field char
+    ///
+    /// Returns `false` once the distance is known to exceed the bound; the
+    /// remaining chars can then be skipped.
+    pub fn push(&mut self, c: char) -> bool {
+        self.curr[0] = self.prev[0] + 1;
+        #[cfg(feature = "stats")]
+        crate::stats::dp_cells(self.target.len());
+        for (j, &t) in self.target.iter().enumerate() {
+            let cost = if c == t { 0 } else { 1 };
+            self.curr[j + 1] = core::cmp::min(
+                core::cmp::min(self.curr[j] + 1, self.prev[j + 1] + 1),
+                self.prev[j] + cost,
+            );
+        }
+        core::mem::swap(&mut self.prev, &mut self.curr);
+
+        self.prev.iter().copied().min().unwrap_or(0) <= self.bound
+    }
+
+    /// Distance of the field fed so far (exact when at most the bound)
+    pub fn distance(&self) -> usize {
+        self.prev[self.target.len()]
+    }
+}
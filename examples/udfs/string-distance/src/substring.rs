@@ -0,0 +1,43 @@
+//! Longest common substring, for `"algorithm": "lcstr"`.
+
+/// Length in chars of the longest run of chars `s1` and `s2` both contain
+///
+/// The textbook DP over pairs of positions, keeping a single row over the
+/// shorter string: memory is linear in the shorter length and time in the
+/// product of the two.
+pub fn longest_common_substring(s1: &str, s2: &str) -> usize {
+    let a: Vec<char> = s1.chars().collect();
+    let b: Vec<char> = s2.chars().collect();
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+
+    // row[j]: length of the common run ending at the current char of `long`
+    // and at short[j - 1], filled right to left so row[j - 1] is still the
+    // previous char's
+    let mut row = vec![0usize; short.len() + 1];
+    let mut longest = 0;
+    for c in long {
+        for j in (1..=short.len()).rev() {
+            row[j] = if short[j - 1] == c { row[j - 1] + 1 } else { 0 };
+            longest = longest.max(row[j]);
+        }
+    }
+    longest
+}
+
+/// The share of `target`'s chars its longest common substring with `field`
+/// covers, 1.0 for an empty target
+pub fn lcstr_similarity(field: &str, target: &str) -> f64 {
+    match target.chars().count() {
+        0 => 1.0,
+        len => longest_common_substring(field, target) as f64 / len as f64,
+    }
+}
+
+/// Whether `field` and `target` share a run of at least `min_common_len`
+/// chars
+///
+/// A target shorter than that can't, so it matches nothing, not even
+/// itself.
+pub fn lcstr_match(field: &str, target: &str, min_common_len: usize) -> bool {
+    longest_common_substring(field, target) >= min_common_len
+}
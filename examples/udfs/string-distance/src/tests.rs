@@ -0,0 +1,8065 @@
+mod host;
+
+use crate::{combined_score, get_f64_param, levenshtein_distance, partial_match_span, ratio};
+use host::{flag, float, int, lock, run, setup, text, Param, LOGS};
+
+/// Textbook full-matrix Levenshtein, to check the optimised paths against
+fn naive_levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = d[i - 1][j - 1] + (a[i - 1] != b[j - 1]) as usize;
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(substitution);
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// `count` pseudo-random strings of up to `max_len` chars from `alphabet`
+fn random_strings(seed: u64, count: usize, alphabet: &[char], max_len: usize) -> Vec<String> {
+    let mut x = seed;
+    let mut next = move || {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        x
+    };
+    (0..count)
+        .map(|_| {
+            let len = (next() % (max_len as u64 + 1)) as usize;
+            (0..len)
+                .map(|_| alphabet[(next() % alphabet.len() as u64) as usize])
+                .collect()
+        })
+        .collect()
+}
+
+/// The result of a `stream_field` query: `result`, or -1 in a build
+/// without the `stream` feature
+fn streamed(result: i32) -> i32 {
+    if cfg!(feature = "stream") {
+        result
+    } else {
+        -1
+    }
+}
+
+#[test]
+fn close_value_matches_and_distant_one_does_not() {
+    let _host = lock();
+    assert_eq!(
+        run(&[("target", text("iPhone"))], &[("name", "iPhonne")]),
+        1
+    );
+    assert_eq!(
+        run(&[("target", text("iPhone"))], &[("name", "Android")]),
+        0
+    );
+}
+
+#[test]
+fn fold_width_maps_full_and_half_width_forms() {
+    let _host = lock();
+    use crate::normalize::fold_width;
+    assert_eq!(fold_width("ＡＢＣ１２３"), "ABC123");
+    assert_eq!(fold_width("ｶﾞｷﾞｸﾞﾊﾟﾎﾞｳﾞ"), "ガギグパボヴ");
+    assert_eq!(fold_width("ｱｲｳ　x"), "アイウ x");
+    let exact = [("target", text("ABC123")), ("max_distance", int(0))];
+    assert_eq!(run(&exact, &[("name", "ＡＢＣ１２３")]), 0);
+    let folded = [
+        exact[0].clone(),
+        exact[1].clone(),
+        ("fold_width", flag(true)),
+    ];
+    assert_eq!(run(&folded, &[("name", "ＡＢＣ１２３")]), 1);
+}
+
+#[test]
+fn short_target_path_agrees_with_naive_levenshtein() {
+    let strings = random_strings(7, 300, &['a', 'b', 'c', 'é', '日'], 40);
+    for pair in strings.windows(2) {
+        let expected = naive_levenshtein(&pair[0], &pair[1]);
+        assert_eq!(
+            levenshtein_distance(&pair[0], &pair[1]),
+            expected,
+            "{pair:?}"
+        );
+    }
+}
+
+#[test]
+fn email_mode_limits_local_part_and_domain_separately() {
+    let _host = lock();
+    let email = |field: &str, target: &str, max_local: i64, max_domain: i64| {
+        run(
+            &[
+                ("target", text(target)),
+                ("mode", text("email")),
+                ("max_local", int(max_local)),
+                ("max_domain", int(max_domain)),
+            ],
+            &[("name", field)],
+        )
+    };
+    assert_eq!(email("jhon@example.com", "john@example.com", 2, 0), 1);
+    assert_eq!(email("jhon@example.com", "john@example.com", 1, 0), 0);
+    assert_eq!(email("john@exmaple.com", "john@example.com", 0, 2), 1);
+    assert_eq!(email("john@exmaple.com", "john@example.com", 0, 1), 0);
+    assert_eq!(email("johnexample.com", "john@example.com", 5, 5), 0);
+}
+
+#[test]
+fn phone_mode_ignores_formatting_and_country_code() {
+    let _host = lock();
+    let check = |field: &str, target: &str| {
+        run(
+            &[
+                ("target", text(target)),
+                ("mode", text("phone")),
+                ("country_code", int(1)),
+                ("max_distance", int(0)),
+            ],
+            &[("name", field)],
+        )
+    };
+    assert_eq!(check("(555) 123-4567", "555.123.4567"), 1);
+    assert_eq!(check("+1 555 123 4567", "555-123-4567"), 1);
+    assert_eq!(check("001 555 123 4567", "555-123-4567"), 1);
+    assert_eq!(check("555-123-4568", "555-123-4567"), 0);
+    assert_eq!(check("abc", "def"), 0);
+}
+
+#[test]
+fn url_mode_canonicalizes_before_comparing() {
+    use crate::modes::normalize_url;
+    let _host = lock();
+    assert_eq!(
+        normalize_url("https://WWW.Example.COM:443/docs/?b=2&a=1"),
+        "example.com/docs?a=1&b=2"
+    );
+    assert_eq!(normalize_url("http://example.com/"), "example.com");
+    assert_eq!(normalize_url("ftp://Host/x"), "ftp://host/x");
+    let check = |field: &str, target: &str| {
+        run(
+            &[
+                ("target", text(target)),
+                ("mode", text("url")),
+                ("max_distance", int(0)),
+            ],
+            &[("name", field)],
+        )
+    };
+    assert_eq!(
+        check(
+            "http://example.com/docs?a=1&b=2",
+            "https://www.example.com/docs/?b=2&a=1"
+        ),
+        1
+    );
+    assert_eq!(
+        check(
+            "example.com:80/docs/?b=2&a=1",
+            "https://www.example.com/docs/?b=2&a=1"
+        ),
+        1
+    );
+    assert_eq!(check("example.com/doc", "https://www.example.com/docs/"), 0);
+}
+
+#[test]
+fn date_mode_matches_the_same_day_across_formats() {
+    use crate::modes::parse_date;
+    let _host = lock();
+    assert_eq!(parse_date("2024-01-05"), Some((2024, 1, 5)));
+    assert_eq!(parse_date("Jan 5 2024"), Some((2024, 1, 5)));
+    assert_eq!(parse_date("January 5th, 2024"), Some((2024, 1, 5)));
+    assert_eq!(parse_date("05/01/2024"), Some((2024, 1, 5)));
+    assert_eq!(parse_date("2023-02-29"), None);
+    assert_eq!(parse_date("2024-02-29"), Some((2024, 2, 29)));
+    let check = |field: &str, target: &str, max_days: Option<i64>| {
+        let mut params = vec![("target", text(target)), ("mode", text("date"))];
+        if let Some(days) = max_days {
+            params.push(("max_distance", int(days)))
+        }
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(check("Jan 5 2024", "2024-01-05", None), 1);
+    assert_eq!(check("5th January 2024", "2024-01-05", None), 1);
+    assert_eq!(check("2024-01-06", "2024-01-05", None), 0);
+    assert_eq!(check("2024-01-01", "2023-12-30", Some(2)), 1);
+    assert_eq!(check("soon", "2024-01-05", Some(100)), 0);
+}
+
+#[test]
+fn ratio_is_an_integer_percentage() {
+    let _host = lock();
+    setup(&[("target", text("iPhone"))], &[("name", "iPhone")]);
+    assert_eq!(ratio(1), 100);
+    setup(&[("target", text("kitten"))], &[("name", "sitting")]);
+    assert_eq!(ratio(1), 57);
+    setup(&[("target", text("kitten"))], &[]);
+    assert_eq!(ratio(1), -1);
+}
+
+#[test]
+#[cfg(feature = "ngram")]
+fn padding_gives_short_strings_trigrams() {
+    use crate::target_dice_similarity;
+    let _host = lock();
+    assert_eq!(target_dice_similarity("cat", "cot", 3, false, None), 0.0);
+    assert!((target_dice_similarity("cat", "cot", 3, true, None) - 0.4).abs() < 1e-9);
+    assert_eq!(
+        target_dice_similarity("night", "night", 3, false, None),
+        1.0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("cat")),
+                ("algorithm", text("ngram")),
+                ("pad_ngrams", flag(true)),
+                ("min_similarity", text("0.4"))
+            ],
+            &[("name", "cot")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("cat")),
+                ("algorithm", text("ngram")),
+                ("min_similarity", text("0.4"))
+            ],
+            &[("name", "cot")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[("target", text("cat")), ("algorithm", text("nope"))],
+            &[("name", "cat")]
+        ),
+        0
+    );
+    setup(
+        &[
+            ("target", text("cat")),
+            ("algorithm", text("ngram")),
+            ("pad_ngrams", flag(true)),
+        ],
+        &[("name", "cot")],
+    );
+    assert_eq!(ratio(1), 40);
+}
+
+#[test]
+fn require_prefix_len_blocks_before_scoring() {
+    let _host = lock();
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("require_prefix_len", int(1))],
+            &[("name", "uPhone")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("require_prefix_len", int(1))],
+            &[("name", "iPhome")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("require_prefix_len", int(3))],
+            &[("name", "iPhome")]
+        ),
+        1
+    );
+    assert_eq!(run(&[("target", text("iPhone"))], &[("name", "uPhone")]), 1);
+}
+
+#[test]
+#[cfg(all(feature = "jaro", feature = "token"))]
+fn combined_score_blends_weighted_components() {
+    use crate::jaro::{jaro_similarity, jaro_winkler_similarity};
+    use crate::token::{token_set_ratio, Tokenizer};
+    let _host = lock();
+    assert!((jaro_similarity("MARTHA", "MARHTA") - 0.9444444).abs() < 1e-6);
+    assert!((jaro_winkler_similarity("MARTHA", "MARHTA") - 0.9611111).abs() < 1e-6);
+    assert!((jaro_winkler_similarity("DIXON", "DICKSONX") - 0.8133333).abs() < 1e-6);
+    assert_eq!(
+        token_set_ratio(
+            "fuzzy wuzzy was a bear",
+            "fuzzy fuzzy was a bear",
+            &Tokenizer::Whitespace,
+            None,
+            0.0,
+        ),
+        1.0
+    );
+    let components = r#"[{"algorithm": "jaro_winkler", "weight": 0.6}, {"algorithm": "token_set_ratio", "weight": 0.4}]"#;
+    setup(
+        &[("target", text("MARTHA")), ("components", text(components))],
+        &[("name", "MARHTA")],
+    );
+    let expected = 0.6 * jaro_winkler_similarity("MARTHA", "MARHTA")
+        + 0.4 * token_set_ratio("MARTHA", "MARHTA", &Tokenizer::Whitespace, None, 0.0);
+    assert!(
+        (combined_score(1) as f64 - expected).abs() < 1e-6,
+        "{}",
+        combined_score(1)
+    );
+    setup(
+        &[
+            ("target", text("x")),
+            (
+                "components",
+                text(r#"[{"algorithm": "jaro", "weight": -1}]"#),
+            ),
+        ],
+        &[("name", "x")],
+    );
+    assert_eq!(combined_score(1), -1.0);
+    assert_eq!(
+        crate::json::parse(r#"{"a": [1, "xé😀", true, null]}"#)
+            .unwrap()
+            .get("a")
+            .unwrap()
+            .as_array()
+            .unwrap()[1]
+            .as_str(),
+        Some("xé😀")
+    );
+    assert!(crate::json::parse("[1,]").is_none());
+}
+
+#[test]
+#[cfg(feature = "token")]
+fn tokenizers_split_tokens_differently() {
+    use crate::regex::Regex;
+    use crate::token::Tokenizer;
+    let _host = lock();
+    let r = Regex::new(r"[A-Za-z0-9]+").unwrap();
+    assert_eq!(r.find_all("AB-12_x y"), vec!["AB", "12", "x", "y"]);
+    let r = Regex::new(r"\d{2,3}|x+").unwrap();
+    assert_eq!(r.find_all("1 12 12345 xxa"), vec!["12", "123", "45", "xx"]);
+    let r = Regex::new(r"(?:ab|a)c*").unwrap();
+    assert_eq!(r.find_all("abccac"), vec!["abcc", "ac"]);
+    assert!(Regex::new("(a").is_none());
+    assert!(Regex::new("a{99}").is_none());
+    assert!(Regex::new("^a").is_none());
+    assert!(Regex::new("((a{32}){32}){32}").is_none());
+    let r = Regex::new("(a*)*b").unwrap();
+    let long = "a".repeat(5000);
+    assert_eq!(r.find_all(&long).len(), 0);
+    assert_eq!(
+        Tokenizer::Whitespace.tokenize("東京 タワー"),
+        vec!["東京", "タワー"]
+    );
+    assert_eq!(Tokenizer::Chars.tokenize("東京 タ"), vec!["東", "京", "タ"]);
+    assert_eq!(
+        Tokenizer::Regex(Regex::new("[^-]+").unwrap()).tokenize("ab-cd-ef"),
+        vec!["ab", "cd", "ef"]
+    );
+    let check = |tokenizer: &str, pattern: &str, field: &str, target: &str| {
+        run(
+            &[
+                ("target", text(target)),
+                ("algorithm", text("token_set_ratio")),
+                ("tokenizer", text(tokenizer)),
+                ("token_pattern", text(pattern)),
+                ("min_similarity", text("1.0")),
+            ],
+            &[("name", field)],
+        )
+    };
+    assert_eq!(check("whitespace", "", "ab-cd", "cd-ab"), 0);
+    assert_eq!(check("regex", "[^-]+", "ab-cd", "cd-ab"), 1);
+    assert_eq!(check("chars", "", "東京タワー", "タワー東京"), 1);
+    assert_eq!(check("regex", "(", "ab", "ab"), 0);
+}
+
+#[test]
+fn exact_normalized_compares_after_normalizing() {
+    use crate::normalize::{collapse_whitespace, strip_accents};
+    let _host = lock();
+    assert_eq!(
+        strip_accents("São Paulo Crème Brûlée Łódź ǅ Ấ"),
+        "Sao Paulo Creme Brulee Łodz ǅ A"
+    );
+    assert_eq!(strip_accents("Cafe\u{0301}"), "Cafe");
+    assert_eq!(collapse_whitespace("  a \t b\n"), "a b");
+    let check = |field: &str| {
+        run(
+            &[
+                ("target", text("sao paulo")),
+                ("algorithm", text("exact_normalized")),
+                ("case_insensitive", flag(true)),
+                ("ignore_accents", flag(true)),
+                ("collapse_whitespace", flag(true)),
+            ],
+            &[("name", field)],
+        )
+    };
+    assert_eq!(check("São Paulo"), 1);
+    assert_eq!(check("  SAO   PAULO "), 1);
+    assert_eq!(check("São\tPaulo"), 1);
+    assert_eq!(check("Sao Paolo"), 0);
+    assert_eq!(
+        run(
+            &[
+                ("target", text("sao paulo")),
+                ("algorithm", text("exact_normalized"))
+            ],
+            &[("name", "São Paulo")]
+        ),
+        0
+    );
+}
+
+#[test]
+#[cfg(feature = "token")]
+fn idf_weights_scale_token_contributions() {
+    let _host = lock();
+    let ratio_with = |idf: Option<&str>| {
+        let mut params = vec![
+            ("target", text("globex corp")),
+            ("algorithm", text("token_set_ratio")),
+        ];
+        if let Some(idf) = idf {
+            params.push(("idf", text(idf)));
+        }
+        setup(&params, &[("name", "acme corp")]);
+        ratio(1)
+    };
+    let unweighted = ratio_with(None);
+    let weighted = ratio_with(Some(r#"{"corp": 0.1}"#));
+    let rare = ratio_with(Some(r#"{"corp": 10}"#));
+    assert!(weighted < unweighted);
+    assert!(rare > unweighted);
+    assert_eq!(ratio_with(Some("[1]")), -1);
+    assert_eq!(ratio_with(Some(r#"{"corp": -1}"#)), -1);
+    setup(
+        &[
+            ("target", text("acme")),
+            ("algorithm", text("token_set_ratio")),
+            ("idf", text("{}")),
+        ],
+        &[("name", "acme corp")],
+    );
+    assert_eq!(ratio(1), 100);
+}
+
+#[test]
+fn partial_match_span_reports_char_offsets() {
+    use crate::partial::best_window;
+    let _host = lock();
+    assert_eq!(best_window("xxabcxx", "abc"), (0, 2, 5));
+    assert_eq!(best_window("the iphnoe 12", "iphone"), (2, 4, 8));
+    assert_eq!(best_window("日本のiPhone", "iPhone"), (0, 3, 9));
+    assert_eq!(best_window("abc", ""), (0, 0, 0));
+    assert_eq!(best_window("", "ab"), (2, 0, 0));
+    let (mut start, mut end) = (0i32, 0i32);
+    setup(
+        &[("target", text("iPhone")), ("max_distance", int(1))],
+        &[("name", "Apple 日本 iPhone 15 Pro")],
+    );
+    assert_eq!(unsafe { partial_match_span(1, &mut start, &mut end) }, 1);
+    assert_eq!((start, end), (9, 15));
+    let chars: Vec<char> = "Apple 日本 iPhone 15 Pro".chars().collect();
+    assert_eq!(
+        chars[start as usize..end as usize]
+            .iter()
+            .collect::<String>(),
+        "iPhone"
+    );
+    setup(
+        &[("target", text("Galaxy")), ("max_distance", int(1))],
+        &[("name", "Apple iPhone")],
+    );
+    assert_eq!(unsafe { partial_match_span(1, &mut start, &mut end) }, 0);
+    assert!(start >= 0);
+    setup(&[("target", text("x"))], &[]);
+    assert_eq!(unsafe { partial_match_span(1, &mut start, &mut end) }, 0);
+    assert_eq!((start, end), (-1, -1));
+    // A distance `threshold` stands in for `max_distance`, as in `filter`
+    let threshold = |threshold| [("target", text("iPhone")), ("threshold", text(threshold))];
+    setup(&threshold("1"), &[("name", "the iPhaxe 15")]);
+    assert_eq!(unsafe { partial_match_span(1, &mut start, &mut end) }, 0);
+    setup(&threshold("2"), &[("name", "the iPhaxe 15")]);
+    assert_eq!(unsafe { partial_match_span(1, &mut start, &mut end) }, 1);
+    setup(&threshold("1.5"), &[("name", "the iPhaxe 15")]);
+    assert_eq!(unsafe { partial_match_span(1, &mut start, &mut end) }, 0);
+    assert_eq!(
+        run(
+            &[
+                ("target", text("iPhone")),
+                ("mode", text("partial")),
+                ("max_distance", int(0))
+            ],
+            &[("name", "new iPhone 15")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("max_distance", int(0))],
+            &[("name", "new iPhone 15")]
+        ),
+        0
+    );
+}
+
+#[test]
+fn empty_field_behavior_decides_blank_fields() {
+    let _host = lock();
+    let check = |behavior: Option<&str>, field: &str, target: &str| {
+        let mut params = vec![("target", text(target))];
+        if let Some(behavior) = behavior {
+            params.push(("empty_field_behavior", text(behavior)));
+        }
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(check(None, "   ", "ab"), 0);
+    assert_eq!(check(Some("nomatch"), " \t", "ab"), 0);
+    assert_eq!(check(Some("match"), "   ", "iPhone"), 1);
+    assert_eq!(check(Some("skip"), "  ", "ab"), 1);
+    assert_eq!(check(Some("skip"), "  ", "iPhone"), 0);
+    assert_eq!(check(Some("bogus"), "", "ab"), 0);
+    assert_eq!(check(None, "ab", "ab"), 1);
+    assert_eq!(
+        run(
+            &[
+                ("target", text("ab")),
+                ("collapse_whitespace", flag(true)),
+                ("empty_field_behavior", text("match"))
+            ],
+            &[("name", " \n ")]
+        ),
+        1
+    );
+}
+
+// Without `f64_params` a numeric `threshold` is unreadable, only text
+#[test]
+#[cfg(feature = "f64_params")]
+fn threshold_routes_to_distance_or_similarity() {
+    let _host = lock();
+    let logs = || LOGS.lock().unwrap().clone();
+    // distance
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("threshold", int(2))],
+            &[("name", "iPhxxe")]
+        ),
+        1
+    );
+    assert!(
+        logs()
+            .iter()
+            .any(|l| l.contains("max_distance") && l.contains("auto")),
+        "{:?}",
+        logs()
+    );
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("threshold", int(1))],
+            &[("name", "iPhxxe")]
+        ),
+        0
+    );
+    // ratio: iPhxxe vs iPhone = 1-2/6=0.667
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("threshold", text("0.8"))],
+            &[("name", "iPhxxe")]
+        ),
+        0
+    );
+    assert!(logs().iter().any(|l| l.contains("min_similarity")));
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("threshold", text("0.6"))],
+            &[("name", "iPhxxe")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[("target", text("abcdefghij")), ("threshold", text("0.8"))],
+            &[("name", "abcdefghxx")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[("target", text("abcdefghij")), ("threshold", int(1))],
+            &[("name", "abcdefghxx")]
+        ),
+        0
+    );
+    // jaro with ratio threshold
+    assert_eq!(
+        run(
+            &[
+                ("target", text("MARTHA")),
+                ("algorithm", text("jaro")),
+                ("threshold", text("0.95"))
+            ],
+            &[("name", "MARHTA")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("MARTHA")),
+                ("algorithm", text("jaro")),
+                ("threshold", text("0.9"))
+            ],
+            &[("name", "MARHTA")]
+        ),
+        1
+    );
+    // ambiguous / explicit
+    assert_eq!(
+        run(
+            &[("target", text("abc")), ("threshold", int(0))],
+            &[("name", "abc")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("abc")),
+                ("threshold", int(0)),
+                ("threshold_kind", text("distance"))
+            ],
+            &[("name", "abc")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("abc")),
+                ("threshold", int(1)),
+                ("threshold_kind", text("similarity"))
+            ],
+            &[("name", "abd")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[("target", text("abc")), ("threshold", text("1.5"))],
+            &[("name", "abc")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("abcdefghij")),
+                ("threshold", text("0.8")),
+                ("max_distance", int(1))
+            ],
+            &[("name", "abcdefghxx")]
+        ),
+        0
+    );
+}
+
+#[test]
+fn bounded_levenshtein_is_exact_up_to_the_bound() {
+    use crate::levenshtein_within;
+    let words = [
+        "",
+        "a",
+        "ab",
+        "kitten",
+        "sitting",
+        "iPhone",
+        "iPhonne",
+        "Android",
+        "abcdefghijklmnopqrstuvwxyz0123456789",
+        "zyxwvutsrqponmlkjihgfedcba9876543210xx",
+    ];
+    for a in words {
+        for b in words {
+            let distance = naive_levenshtein(a, b);
+            for bound in 0..12 {
+                let expected = Some(distance).filter(|&d| d <= bound);
+                assert_eq!(levenshtein_within(a, b, bound), expected, "{a} {b} {bound}");
+            }
+        }
+    }
+    let (long_a, long_b) = ("ab".repeat(5000), "cd".repeat(5000));
+    assert_eq!(levenshtein_within(&long_a, &long_b, 3), None);
+}
+
+#[test]
+#[cfg(not(feature = "f64_params"))]
+fn float_params_are_only_read_from_text_without_f64_params() {
+    let _host = lock();
+    setup(
+        &[("a", float(0.25)), ("b", text(" 0.5 ")), ("c", int(2))],
+        &[],
+    );
+    unsafe {
+        assert_eq!(get_f64_param("a"), None);
+        assert_eq!(get_f64_param("b"), Some(0.5));
+        assert_eq!(get_f64_param("c"), None);
+    }
+}
+
+#[test]
+#[cfg(feature = "f64_params")]
+fn f64_params_read_natively_or_from_text() {
+    let _host = lock();
+    setup(
+        &[
+            ("a", float(0.25)),
+            ("b", text(" 0.5 ")),
+            ("c", int(2)),
+            ("d", text("x")),
+            ("e", flag(true)),
+        ],
+        &[],
+    );
+    unsafe {
+        assert_eq!(get_f64_param("a"), Some(0.25));
+        assert_eq!(get_f64_param("b"), Some(0.5));
+        assert_eq!(get_f64_param("c"), Some(2.0));
+        assert_eq!(get_f64_param("d"), None);
+        assert_eq!(get_f64_param("e"), None);
+        assert_eq!(get_f64_param("zz"), None);
+    }
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("threshold", float(0.6))],
+            &[("name", "iPhxxe")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("threshold", float(0.8))],
+            &[("name", "iPhxxe")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("MARTHA")),
+                ("algorithm", text("jaro")),
+                ("min_similarity", float(0.9))
+            ],
+            &[("name", "MARHTA")]
+        ),
+        1
+    );
+}
+
+#[test]
+fn script_mismatch_penalty_costs_cross_script_pairs() {
+    use crate::script::{dominant_script, Script};
+    let _host = lock();
+    assert_eq!(dominant_script("Paypal"), Some(Script::Latin));
+    // Р а у р а are Cyrillic, only the l is Latin
+    assert_eq!(dominant_script("Раураl"), Some(Script::Cyrillic));
+    assert_eq!(dominant_script("aб"), Some(Script::Latin));
+    assert_eq!(dominant_script("123 !"), None);
+    let check = |penalty: Option<Param>, field: &str, target: &str| {
+        let mut params = vec![("target", text(target)), ("max_distance", int(2))];
+        if let Some(penalty) = penalty {
+            params.push(("script_mismatch_penalty", penalty));
+        }
+        run(&params, &[("name", field)])
+    };
+    // One Cyrillic look-alike leaves the field Latin, so no penalty applies
+    assert_eq!(check(Some(text("nomatch")), "Pаypal", "Paypal"), 1);
+    assert_eq!(check(Some(int(2)), "Paypel", "Paypal"), 1);
+    assert_eq!(check(Some(text("nomatch")), "Москва", "Moskva"), 0);
+    let transliterated = |penalty: Option<Param>| {
+        let mut params = vec![("target", text("Moskva")), ("max_distance", int(6))];
+        if let Some(penalty) = penalty {
+            params.push(("script_mismatch_penalty", penalty));
+        }
+        run(&params, &[("name", "Москва")])
+    };
+    assert_eq!(transliterated(None), 1);
+    assert_eq!(transliterated(Some(int(1))), 0);
+    assert_eq!(transliterated(Some(text("nomatch"))), 0);
+}
+
+#[test]
+#[cfg(feature = "stream")]
+fn streamed_field_larger_than_the_buffer_is_compared() {
+    use host::CHUNK_CALLS;
+    let _host = lock();
+    let check = |field: &str, target: &str, max_distance: i64| {
+        run(
+            &[
+                ("target", text(target)),
+                ("stream_field", flag(true)),
+                ("max_distance", int(max_distance)),
+            ],
+            &[("name", field)],
+        )
+    };
+    // 2000 bytes, more than the 768-byte chunk buffer holds
+    let long = "ab".repeat(1000);
+    let short = "ab".repeat(100);
+    assert_eq!(naive_levenshtein(&long, &short), 1800);
+    assert_eq!(check(&long, &short, 1800), 1);
+    assert_eq!(check(&long, &short, 1799), 0);
+    // Two-byte chars, so chunk boundaries fall inside a char
+    let accented = format!("{}x", "é".repeat(500));
+    let target = "é".repeat(100);
+    assert_eq!(check(&accented, &target, 401), 1);
+    assert_eq!(check(&accented, &target, 400), 0);
+    // A hopeless comparison stops after the first chunk
+    let japanese = "日本語のテキストé ".repeat(2000);
+    assert_eq!(check(&japanese, "zzz", 1), 0);
+    assert_eq!(*CHUNK_CALLS.lock().unwrap(), 1);
+    assert_eq!(
+        run(&[("target", text("ab")), ("stream_field", flag(true))], &[]),
+        0
+    );
+    assert_eq!(
+        run(
+            &[("target", text("ab")), ("stream_field", flag(true))],
+            &[("name", "ax")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("AB")),
+                ("stream_field", flag(true)),
+                ("case_insensitive", flag(true)),
+                ("max_distance", int(0))
+            ],
+            &[("name", "ab")]
+        ),
+        1
+    );
+}
+
+#[test]
+#[cfg(feature = "stream")]
+fn streamed_char_split_across_chunks_is_carried_over() {
+    let _host = lock();
+    let field = format!("x{}", "é".repeat(500));
+    let target = "é".repeat(100);
+    let check = |max_distance: i64| {
+        run(
+            &[
+                ("target", text(&target)),
+                ("stream_field", flag(true)),
+                ("max_distance", int(max_distance)),
+            ],
+            &[("name", &field)],
+        )
+    };
+    assert_eq!(check(401), 1);
+    assert_eq!(check(400), 0);
+}
+
+#[test]
+fn sub_costs_make_listed_confusions_cheap() {
+    use crate::weighted::{weighted_levenshtein, Costs, SubCosts};
+    let _host = lock();
+    let costs = r#"[{"from": "O", "to": "0", "cost": 0.25}, {"from": "l", "to": "1", "cost": 0.25}, {"from": "m", "to": "n", "cost": 0.5}]"#;
+    let check = |sub_costs: Option<&str>, field: &str, target: &str, max_distance: i64| {
+        let mut params = vec![
+            ("target", text(target)),
+            ("max_distance", int(max_distance)),
+        ];
+        if let Some(sub_costs) = sub_costs {
+            params.push(("sub_costs", text(sub_costs)));
+        }
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(check(None, "B0RDER C0NTR0L", "BORDER CONTROL", 2), 0);
+    // Three O -> 0 confusions at 0.25 each
+    assert_eq!(check(Some(costs), "B0RDER C0NTR0L", "BORDER CONTROL", 1), 1);
+    assert_eq!(check(Some(costs), "B0RDER C0NTR0L", "BORDER CONTROL", 0), 0);
+    // Costs are directional: target char first, then how the field reads it
+    assert_eq!(check(Some(costs), "BORDER CONTROL", "B0RDER C0NTR0L", 1), 0);
+    assert_eq!(check(Some(costs), "sane", "same", 0), 0);
+    assert_eq!(check(Some(costs), "sane", "same", 1), 1);
+    assert_eq!(
+        check(
+            Some(r#"[{"from": "ab", "to": "c", "cost": 0.1}]"#),
+            "x",
+            "x",
+            0
+        ),
+        0
+    );
+    assert_eq!(
+        check(
+            Some(r#"[{"from": "a", "to": "c", "cost": -1}]"#),
+            "x",
+            "x",
+            0
+        ),
+        0
+    );
+    let mut table = SubCosts::new();
+    table.insert(('a', 'b'), 0.5);
+    let costs = Costs {
+        table: Some(table),
+        ..Costs::default()
+    };
+    assert_eq!(weighted_levenshtein("b", "a", &costs, 10.0), 0.5);
+    assert_eq!(
+        weighted_levenshtein("kitten", "sitting", &Costs::default(), 10.0),
+        3.0
+    );
+}
+
+#[test]
+fn require_equal_length_allows_substitutions_only() {
+    let _host = lock();
+    let check = |field: &str, target: &str, max_distance: i64, extra: Option<(&str, Param)>| {
+        let mut params = vec![
+            ("target", text(target)),
+            ("max_distance", int(max_distance)),
+            ("require_equal_length", flag(true)),
+        ];
+        params.extend(extra);
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(check("AB-1234", "AB-1243", 2, None), 1);
+    assert_eq!(check("AB-1234", "AB-12345", 2, None), 0);
+    assert_eq!(
+        run(
+            &[("target", text("AB-12345")), ("max_distance", int(2))],
+            &[("name", "AB-1234")]
+        ),
+        1
+    );
+    // Two indels would do, but it takes six substitutions
+    assert_eq!(check("abcdef", "bcdefa", 2, None), 0);
+    assert_eq!(
+        run(
+            &[("target", text("bcdefa")), ("max_distance", int(2))],
+            &[("name", "abcdef")]
+        ),
+        1
+    );
+    assert_eq!(
+        check("ＡＢ１２", "AB12", 0, Some(("fold_width", flag(true)))),
+        1
+    );
+    assert_eq!(
+        check(
+            "A0",
+            "AO",
+            0,
+            Some((
+                "sub_costs",
+                text(r#"[{"from": "O", "to": "0", "cost": 0}]"#)
+            ))
+        ),
+        1
+    );
+}
+
+#[test]
+#[cfg(feature = "profile")]
+fn profile_logs_the_measured_duration() {
+    use host::{CLOCK_READS, CLOCK_STEP_NS};
+    let _host = lock();
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("profile", flag(true))],
+            &[("name", "iPhonne")]
+        ),
+        1
+    );
+    assert_eq!(
+        *LOGS.lock().unwrap(),
+        [format!("filter took {CLOCK_STEP_NS} ns (result 1)")]
+    );
+    assert_eq!(
+        run(&[("target", text("iPhone"))], &[("name", "iPhonne")]),
+        1
+    );
+    assert!(LOGS.lock().unwrap().is_empty());
+    assert_eq!(*CLOCK_READS.lock().unwrap(), 0);
+}
+
+#[test]
+fn negative_distance_limits_are_rejected() {
+    let _host = lock();
+    let params = [("target", text("iPhone")), ("max_distance", int(-1))];
+    assert_eq!(run(&params, &[("name", "Android")]), -1);
+    assert_eq!(*LOGS.lock().unwrap(), ["max_distance is negative"]);
+
+    let (mut start, mut end) = (0, 0);
+    setup(&params, &[("name", "Android")]);
+    assert_eq!(unsafe { partial_match_span(1, &mut start, &mut end) }, -1);
+    assert_eq!((start, end), (-1, -1));
+
+    let email = [
+        ("target", text("jane@example.com")),
+        ("mode", text("email")),
+        ("max_local", int(-3)),
+    ];
+    assert_eq!(run(&email, &[("name", "john@example.org")]), -1);
+    assert_eq!(*LOGS.lock().unwrap(), ["max_local is negative"]);
+
+    // Each limit is checked where it is read, and only the mode reads its own
+    let plain = [("target", text("iPhone")), ("max_distance", int(1))];
+    assert_eq!(run(&plain, &[("name", "iPhone")]), 1);
+    let reads = host::PARAM_READS.lock().unwrap().clone();
+    assert!(!reads.contains(&"max_local".to_string()), "{:?}", reads);
+    assert!(!reads.contains(&"max_domain".to_string()), "{:?}", reads);
+}
+
+#[test]
+fn concatenated_fields_match_where_neither_alone_does() {
+    let _host = lock();
+    let f = [
+        ("first_name", "John"),
+        ("last_name", "Smith"),
+        ("name", "zzz"),
+    ];
+    assert_eq!(
+        run(
+            &[
+                ("target", text("John Smith")),
+                ("field", text("first_name"))
+            ],
+            &f
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[("target", text("John Smith")), ("field", text("last_name"))],
+            &f
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("John Smith")),
+                ("concat_fields", text(r#"["first_name", "last_name"]"#)),
+                ("max_distance", int(0))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("Smith, John")),
+                ("concat_fields", text(r#"["last_name", "first_name"]"#)),
+                ("concat_separator", text(", ")),
+                ("max_distance", int(0))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("John Smith")),
+                (
+                    "concat_fields",
+                    text(r#"["first_name", "middle", "last_name"]"#)
+                ),
+                ("max_distance", int(1))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[("target", text("John")), ("concat_fields", text(r#"[1]"#))],
+            &f
+        ),
+        0
+    );
+    setup(
+        &[
+            ("target", text("John Smith")),
+            ("concat_fields", text(r#"["first_name", "last_name"]"#)),
+        ],
+        &f,
+    );
+    assert_eq!(ratio(1), 100);
+}
+
+#[test]
+fn a_target_emptied_by_normalization_short_circuits() {
+    let _host = lock();
+    assert_eq!(
+        run(
+            &[("target", text("12345")), ("mask_digits", flag(true))],
+            &[("name", "x")]
+        ),
+        0
+    );
+    assert!(LOGS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|l| l.contains("empty after normalization")));
+    assert_eq!(
+        run(
+            &[
+                ("target", text("12345")),
+                ("mask_digits", flag(true)),
+                ("empty_target_matches", flag(true))
+            ],
+            &[("name", "ab")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("12345")),
+                ("mask_digits", flag(true)),
+                ("empty_field_behavior", text("skip"))
+            ],
+            &[("name", "a")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("Room 12 A")),
+                ("mask_digits", flag(true)),
+                ("collapse_whitespace", flag(true)),
+                ("max_distance", int(0))
+            ],
+            &[("name", "Room 7 A")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[("target", text("Room 12 A")), ("max_distance", int(0))],
+            &[("name", "Room 7 A")]
+        ),
+        0
+    );
+    assert!(LOGS.lock().unwrap().is_empty());
+}
+
+#[test]
+fn sorted_prefix_reuse_agrees_with_the_full_dp() {
+    use crate::incremental::PrefixDp;
+    let _host = lock();
+    let mut words = vec![
+        "apple",
+        "applesauce",
+        "applet",
+        "application",
+        "apply",
+        "apricot",
+        "banana",
+        "band",
+        "bandana",
+        "bandit",
+        "a",
+        "zz",
+        "iphone",
+        "iphonne",
+        "iphone 15",
+        "iphone 15 pro",
+    ];
+    words.sort();
+    let shared = |a: &str, b: &str| a.chars().zip(b.chars()).take_while(|(x, y)| x == y).count();
+
+    for target in ["apple", "band", "iphone", "", "x"] {
+        for bound in [0, 1, 2, 5, 100] {
+            let check = |d: usize, word: &str| {
+                let expected = naive_levenshtein(word, target);
+                if expected <= bound {
+                    assert_eq!(d, expected, "{word} vs {target} within {bound}");
+                } else {
+                    assert!(d > bound, "{word} vs {target} within {bound}");
+                }
+            };
+            let mut dp = PrefixDp::new();
+            let mut previous = "";
+            for word in &words {
+                check(
+                    dp.distance(word, target, shared(previous, word), bound),
+                    word,
+                );
+                previous = word;
+            }
+            // A hint longer than the real shared prefix is only slower
+            let mut dp = PrefixDp::new();
+            for word in &words {
+                check(dp.distance(word, target, 1000, bound), word);
+            }
+        }
+    }
+
+    let mut previous = "";
+    for word in &words {
+        setup(
+            &[("target", text("iphone")), ("max_distance", int(1))],
+            &[("name", word)],
+        );
+        let hint = shared(previous, word) as i32;
+        assert_eq!(crate::filter_sorted(1, hint), crate::filter(1), "{word}");
+        previous = word;
+    }
+}
+
+#[test]
+fn target_field_compares_two_fields_of_one_document() {
+    let _host = lock();
+    let f = [
+        ("name", "Jon Smith"),
+        ("billing_name", "John Smith"),
+        ("other", "Alice"),
+    ];
+    assert_eq!(run(&[("target_field", text("billing_name"))], &f), 1);
+    assert_eq!(run(&[("target_field", text("other"))], &f), 0);
+    assert_eq!(
+        run(
+            &[
+                ("target_field", text("missing")),
+                ("target", text("Jon Smith"))
+            ],
+            &f
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target_field", text("billing_name")),
+                ("field", text("missing"))
+            ],
+            &f
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target_field", text("billing_name")),
+                ("max_distance", int(0))
+            ],
+            &f
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target_field", text("billing_name")),
+                ("stream_field", flag(true))
+            ],
+            &f
+        ),
+        streamed(1)
+    );
+}
+
+#[test]
+fn negate_inverts_single_and_multi_field_results() {
+    let _host = lock();
+    let f = [("name", "iphone"), ("title", "galaxy"), ("alt", "iphonne")];
+    assert_eq!(run(&[("target", text("iphone"))], &f), 1);
+    assert_eq!(
+        run(&[("target", text("iphone")), ("negate", flag(true))], &f),
+        0
+    );
+    assert_eq!(
+        run(&[("target", text("pixel")), ("negate", flag(true))], &f),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("pixel")),
+                ("field", text("missing")),
+                ("negate", flag(true))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("iphone")),
+                ("fields", text(r#"["title","alt"]"#))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("iphone")),
+                ("fields", text(r#"["title","alt"]"#)),
+                ("negate", flag(true))
+            ],
+            &f
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("iphone")),
+                ("fields", text(r#"["title","missing"]"#))
+            ],
+            &f
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("iphone")),
+                ("fields", text(r#"["title","missing"]"#)),
+                ("negate", flag(true))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("iphone")),
+                ("fields", text(r#"["title""#)),
+                ("negate", flag(true))
+            ],
+            &f
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("iphone")),
+                ("fields", text(r#"["title","alt"]"#)),
+                ("stream_field", flag(true))
+            ],
+            &f
+        ),
+        streamed(1)
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("iphone")),
+                ("fields", text("[]")),
+                ("negate", flag(true))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("pixel")),
+                ("max_distance", int(-1)),
+                ("negate", flag(true))
+            ],
+            &f
+        ),
+        -1
+    );
+}
+
+#[test]
+#[cfg(all(feature = "jaro", feature = "ngram", feature = "token"))]
+fn similarity_vector_matches_each_targets_ratio() {
+    let _host = lock();
+    let targets = ["iphone", "IPHONE 15", "galaxy", "", "ïphone"];
+    let quoted: Vec<String> = targets.iter().map(|target| format!("{target:?}")).collect();
+    let list = format!("[{}]", quoted.join(","));
+    for algorithm in ["levenshtein", "jaro_winkler", "ngram", "token_set_ratio"] {
+        let base = vec![
+            ("algorithm", text(algorithm)),
+            ("case_insensitive", flag(true)),
+            ("ignore_accents", flag(true)),
+        ];
+        let mut params = base.clone();
+        params.push(("targets", text(&list)));
+        setup(&params, &[("name", "iPhone 15")]);
+        let mut out = [9f32; 8];
+        let mut len = 8;
+        let count = unsafe { crate::similarity_vector(1, out.as_mut_ptr(), &mut len) };
+        assert_eq!((count, len), (5, 5));
+        assert_eq!(out[5], 9.0);
+
+        for (i, target) in targets.iter().enumerate().filter(|(_, t)| !t.is_empty()) {
+            let mut single = base.clone();
+            single.push(("target", text(target)));
+            setup(&single, &[("name", "iPhone 15")]);
+            assert_eq!(
+                (out[i] * 100.0).round() as i32,
+                ratio(1),
+                "{algorithm} {target}"
+            );
+        }
+
+        // A short buffer gets the leading entries
+        setup(&params, &[("name", "iPhone 15")]);
+        let mut short = [0f32; 2];
+        let mut len = 2;
+        assert_eq!(
+            unsafe { crate::similarity_vector(1, short.as_mut_ptr(), &mut len) },
+            2
+        );
+        assert_eq!(short, [out[0], out[1]]);
+    }
+
+    let mut out = [0f32; 4];
+    let mut len = 4;
+    setup(&[("targets", text("[1]"))], &[("name", "x")]);
+    assert_eq!(
+        unsafe { crate::similarity_vector(1, out.as_mut_ptr(), &mut len) },
+        -1
+    );
+    setup(&[("targets", text(r#"["x"]"#))], &[]);
+    assert_eq!(
+        unsafe { crate::similarity_vector(1, out.as_mut_ptr(), &mut len) },
+        -1
+    );
+    assert_eq!(len, 0);
+}
+
+#[test]
+fn targets_over_the_buffer_are_read_in_full_up_to_max_target_len() {
+    let _host = lock();
+    let long: String = "abcdefghij".repeat(40);
+    let mut near = long.clone();
+    near.replace_range(5..6, "X");
+    assert_eq!(run(&[("target", text(&long))], &[("name", &near)]), 1);
+    assert_eq!(
+        run(
+            &[("target", text(&long)), ("max_distance", int(0))],
+            &[("name", &near)]
+        ),
+        0
+    );
+    assert!(LOGS.lock().unwrap().is_empty());
+    assert_eq!(
+        run(
+            &[("target", text(&long)), ("max_target_len", int(300))],
+            &[("name", &near)]
+        ),
+        -1
+    );
+    assert!(LOGS.lock().unwrap().iter().any(|l| l.contains("400 bytes")));
+    assert_eq!(
+        run(
+            &[
+                ("target", text(&long)),
+                ("max_target_len", int(300)),
+                ("negate", flag(true))
+            ],
+            &[("name", &near)]
+        ),
+        -1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text(&long)),
+                ("max_target_len", int(300)),
+                ("fields", text("[\"a\",\"name\"]"))
+            ],
+            &[("name", &near)]
+        ),
+        -1
+    );
+    assert_eq!(
+        run(
+            &[("target", text("abc")), ("max_target_len", int(2))],
+            &[("name", "abc")]
+        ),
+        -1
+    );
+    assert_eq!(
+        run(
+            &[("target", text(&long)), ("stream_field", flag(true))],
+            &[("name", &near)]
+        ),
+        streamed(1)
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text(&long)),
+                ("stream_field", flag(true)),
+                ("max_target_len", int(10))
+            ],
+            &[("name", &near)]
+        ),
+        -1
+    );
+    setup(
+        &[("target", text(&long)), ("max_target_len", int(300))],
+        &[("name", &near)],
+    );
+    assert_eq!(ratio(1), -1);
+    setup(&[("target", text(&long))], &[("name", &near)]);
+    assert_eq!(ratio(1), 100);
+}
+
+#[test]
+fn cached_target_normalization_follows_query_changes() {
+    let _host = lock();
+    let f = [("name", "cafe")];
+    assert_eq!(
+        run(
+            &[
+                ("target", text("CAFÉ")),
+                ("case_insensitive", flag(true)),
+                ("ignore_accents", flag(true)),
+                ("max_distance", int(0))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("CAFÉ")),
+                ("case_insensitive", flag(true)),
+                ("ignore_accents", flag(true)),
+                ("max_distance", int(0))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("CAFÉ")),
+                ("case_insensitive", flag(true)),
+                ("max_distance", int(0))
+            ],
+            &f
+        ),
+        0
+    );
+    assert_eq!(
+        run(&[("target", text("CAFÉ")), ("max_distance", int(0))], &f),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("CAFE")),
+                ("case_insensitive", flag(true)),
+                ("max_distance", int(0))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("CAFX")),
+                ("case_insensitive", flag(true)),
+                ("max_distance", int(0))
+            ],
+            &f
+        ),
+        0
+    );
+    setup(
+        &[("target", text("CAFE")), ("case_insensitive", flag(true))],
+        &f,
+    );
+    assert_eq!(ratio(1), 100);
+}
+
+#[test]
+fn levenshtein_within_agrees_with_the_full_dp() {
+    use crate::levenshtein_within;
+    let check = |a: &str, b: &str, max: usize| {
+        let distance = naive_levenshtein(a, b);
+        let expected = Some(distance).filter(|&d| d <= max);
+        assert_eq!(levenshtein_within(a, b, max), expected, "{a} {b} {max}");
+    };
+
+    let alphabets: [(u64, &[char], usize); 3] = [
+        (1, &['a', 'b'], 12),
+        (7, &['a', 'b', 'c', 'é', '日'], 45),
+        (99, &['x', 'y', 'z', 'w'], 80),
+    ];
+    for (seed, alphabet, max_len) in alphabets {
+        let words = random_strings(seed, 40, alphabet, max_len);
+        for a in &words {
+            for b in &words {
+                for max in [0, 1, 2, 3, 5, 8, 20, 100, usize::MAX] {
+                    check(a, b, max);
+                }
+            }
+        }
+    }
+
+    // Near-identical pairs, where the band and the affix stripping matter
+    for (i, a) in random_strings(5, 300, &['a', 'b', 'c', 'd'], 70)
+        .iter()
+        .enumerate()
+    {
+        let mut chars: Vec<char> = a.chars().collect();
+        if !chars.is_empty() {
+            let k = i % chars.len();
+            chars[k] = 'q';
+            if i % 3 == 0 {
+                chars.remove((k * 7) % chars.len());
+            }
+            if i % 5 == 0 {
+                chars.insert(k / 2, 'é');
+            }
+        }
+        let b: String = chars.into_iter().collect();
+        for max in 0..6 {
+            check(a, &b, max);
+        }
+    }
+
+    assert_eq!(
+        levenshtein_within("prefix-kitten-suffix", "prefix-sitting-suffix", 3),
+        Some(3)
+    );
+    assert_eq!(levenshtein_within("abc", "abcdefg", 3), None);
+    assert_eq!(levenshtein_within("", "", 0), Some(0));
+}
+
+#[test]
+fn normalization_that_outgrows_its_buffer_reports_overflow() {
+    let _host = lock();
+    let grow = "İ".repeat(380); // 760 bytes, 1140 once lowercased
+    assert_eq!(
+        run(
+            &[("target", text("i")), ("case_insensitive", flag(true))],
+            &[("name", &grow)]
+        ),
+        -2
+    );
+    assert!(LOGS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|l| l.contains("normalized field is 1140 bytes")));
+    assert_eq!(
+        run(
+            &[
+                ("target", text("i")),
+                ("case_insensitive", flag(true)),
+                ("negate", flag(true))
+            ],
+            &[("name", &grow)]
+        ),
+        -2
+    );
+    assert_eq!(run(&[("target", text("i"))], &[("name", &grow)]), 0);
+    let fits = "İ".repeat(200);
+    assert_eq!(
+        run(
+            &[
+                ("target", text(&"i̇".repeat(200))),
+                ("case_insensitive", flag(true)),
+                ("max_distance", int(0))
+            ],
+            &[("name", &fits)]
+        ),
+        1
+    );
+    // target: 120 İ = 240 bytes, 360 lowercased > 256 when max_target_len is small
+    let t = "İ".repeat(120);
+    assert_eq!(
+        run(
+            &[
+                ("target", text(&t)),
+                ("case_insensitive", flag(true)),
+                ("max_target_len", int(250))
+            ],
+            &[("name", "x")]
+        ),
+        -2
+    );
+    assert_eq!(
+        run(
+            &[("target", text(&t)), ("case_insensitive", flag(true))],
+            &[("name", "x")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text(&t)),
+                ("case_insensitive", flag(true)),
+                ("max_target_len", int(250)),
+                ("stream_field", flag(true))
+            ],
+            &[("name", "x")]
+        ),
+        streamed(-2)
+    );
+    setup(
+        &[("target", text("i")), ("case_insensitive", flag(true))],
+        &[("name", &grow)],
+    );
+    assert_eq!(ratio(1), -1);
+}
+
+#[test]
+fn numeric_typo_counts_an_adjacent_digit_swap_as_one_edit() {
+    let _host = lock();
+    let check = |field: &str, target: &str, max_distance: i64| {
+        run(
+            &[
+                ("target", text(target)),
+                ("mode", text("numeric_typo")),
+                ("max_distance", int(max_distance)),
+            ],
+            &[("name", field)],
+        )
+    };
+    assert_eq!(check("13245", "12345", 1), 1);
+    assert_eq!(
+        run(
+            &[("target", text("12345")), ("max_distance", int(1))],
+            &[("name", "13245")]
+        ),
+        0
+    );
+    assert_eq!(check("4021 5538 1190", "4021-5583-1190", 1), 1);
+    assert_eq!(check("402155831190", "4021-5583-1190", 1), 1);
+    assert_eq!(check("4021-5583-1191", "4021-5583-1190", 1), 1);
+    assert_eq!(check("4012-5538-1190", "4021-5583-1190", 1), 0);
+    assert_eq!(check("4012-5538-1190", "4021-5583-1190", 2), 1);
+    assert_eq!(check("abc", "abc", 3), 0);
+    assert_eq!(check("21", "12", 0), 0);
+    assert_eq!(check("12", "12", 0), 1);
+    assert_eq!(check("1234", "124", 1), 1);
+}
+
+#[test]
+fn text_normalizations_are_ignored_in_digit_modes() {
+    let _host = lock();
+    for mode in ["phone", "numeric_typo"] {
+        for normalization in ["mask_digits", "case_insensitive", "ignore_accents"] {
+            let params = [
+                ("target", text("555-123-4567")),
+                ("mode", text(mode)),
+                ("max_distance", int(0)),
+                (normalization, flag(true)),
+            ];
+            assert_eq!(
+                run(&params, &[("name", "(555) 123 4567")]),
+                1,
+                "{mode} {normalization}"
+            );
+            assert!(
+                LOGS.lock()
+                    .unwrap()
+                    .iter()
+                    .any(|l| l.contains("ignored in")),
+                "{mode} {normalization}"
+            );
+            assert_eq!(run(&params, &[("name", "(555) 123 4568")]), 0);
+        }
+        assert_eq!(
+            run(
+                &[
+                    ("target", text("555-123-4567")),
+                    ("mode", text(mode)),
+                    ("max_distance", int(0)),
+                    ("fold_width", flag(true))
+                ],
+                &[("name", "５５５ １２３ ４５６７")]
+            ),
+            1
+        );
+        assert!(LOGS.lock().unwrap().is_empty());
+    }
+    // still applies to text
+    assert_eq!(
+        run(
+            &[
+                ("target", text("Room 12")),
+                ("mask_digits", flag(true)),
+                ("max_distance", int(0))
+            ],
+            &[("name", "Room 7")]
+        ),
+        1
+    );
+}
+
+#[test]
+fn repeated_substrings_are_charged_against_the_budget() {
+    use crate::repeat::extra_copies;
+    let _host = lock();
+    assert_eq!(extra_copies("freefreefree shipping"), 2);
+    assert_eq!(extra_copies("bookkeeper"), 0);
+    assert_eq!(extra_copies("banana"), 1);
+    assert_eq!(extra_copies("abababab"), 3);
+    assert_eq!(extra_copies(""), 0);
+    assert_eq!(extra_copies("x"), 0);
+    assert_eq!(extra_copies(&"ab".repeat(10000)), 9999);
+
+    let check = |extra: &[(&str, Param)], field: &str| {
+        let mut params = vec![("target", text("free shipping")), ("max_distance", int(4))];
+        params.extend_from_slice(extra);
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(check(&[], "freefree shipping"), 1);
+    assert_eq!(
+        check(&[("penalize_repeats", flag(true))], "freefree shipping"),
+        0
+    );
+    assert_eq!(
+        check(
+            &[("penalize_repeats", flag(true)), ("repeat_cost", int(0))],
+            "freefree shipping"
+        ),
+        1
+    );
+    assert_eq!(
+        check(&[("penalize_repeats", flag(true))], "free shippin"),
+        1
+    );
+}
+
+#[test]
+fn field_char_range_slices_the_value_by_chars() {
+    let _host = lock();
+    let f = [("name", "PROD-iPhone 15")];
+    let with = |extra: Vec<(&'static str, Param)>| {
+        let mut params = vec![("target", text("iPhone 15")), ("max_distance", int(2))];
+        params.extend(extra);
+        params
+    };
+    assert_eq!(run(&with(vec![]), &f), 0);
+    assert_eq!(run(&with(vec![("field_char_start", int(5))]), &f), 1);
+    assert_eq!(
+        run(
+            &with(vec![("field_char_start", int(5)), ("max_distance", int(0))]),
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &with(vec![
+                ("field_char_start", int(5)),
+                ("field_char_end", int(1000))
+            ]),
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &with(vec![
+                ("field_char_start", int(5)),
+                ("field_char_end", int(11))
+            ]),
+            &f
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("iPhone")),
+                ("max_distance", int(0)),
+                ("field_char_start", int(5)),
+                ("field_char_end", int(11))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(run(&with(vec![("field_char_start", int(50))]), &f), 0);
+    assert_eq!(
+        run(
+            &[
+                ("target", text("x")),
+                ("field_char_start", int(50)),
+                ("empty_field_behavior", text("match"))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("x")),
+                ("field_char_start", int(4)),
+                ("field_char_end", int(2)),
+                ("empty_field_behavior", text("match"))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("PROD")),
+                ("field_char_end", int(4)),
+                ("max_distance", int(0))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("PROD")),
+                ("field_char_start", int(-3)),
+                ("field_char_end", int(4)),
+                ("max_distance", int(0))
+            ],
+            &f
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("日本")),
+                ("field_char_start", int(2)),
+                ("max_distance", int(0))
+            ],
+            &[("name", "ÉÉ日本")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("b c")),
+                ("concat_fields", text(r#"["a","b","c"]"#)),
+                ("field_char_start", int(2)),
+                ("max_distance", int(0))
+            ],
+            &[("a", "a"), ("b", "b"), ("c", "c")]
+        ),
+        1
+    );
+}
+
+#[test]
+fn required_imports_list_only_the_enabled_features() {
+    let packed = crate::udf_required_imports();
+    let list = unsafe { crate::REQUIRED_IMPORTS_LIST.as_str() };
+    assert_eq!(packed & 0xffff_ffff, list.len() as i64);
+
+    let optional = |enabled: bool, name: &'static str| if enabled { Some(name) } else { None };
+    let expected: Vec<&str> = [
+        Some("has_field"),
+        Some("get_field_string"),
+        Some("get_document_id"),
+        optional(cfg!(feature = "stream"), "get_field_chunk"),
+        optional(cfg!(feature = "arrays"), "get_field_array"),
+        optional(cfg!(feature = "doc_freq"), "get_field_doc_freq"),
+        Some("get_param_i64"),
+        optional(cfg!(feature = "f64_params"), "get_param_f64"),
+        Some("get_param_bool"),
+        Some("get_param_string"),
+        optional(cfg!(feature = "arrays"), "get_param_array"),
+        optional(cfg!(feature = "param_names"), "get_param_names"),
+        optional(cfg!(feature = "params_blob"), "get_params_blob"),
+        optional(cfg!(feature = "profile"), "get_time_ns"),
+        optional(cfg!(feature = "stats"), "emit_stat"),
+        Some("log"),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    assert_eq!(list, expected.join(","));
+}
+
+#[test]
+fn weighted_costs_saturate_instead_of_overflowing() {
+    use crate::weighted::{
+        substitution_distance, weighted_levenshtein, within_bound, Costs, SubCosts,
+    };
+    let _host = lock();
+    let mut table = SubCosts::new();
+    table.insert(('a', 'b'), 0.1);
+    table.insert(('x', 'y'), 1e308);
+    let costs = Costs {
+        table: Some(table),
+        ..Costs::default()
+    };
+
+    // 30 costs of 0.1 sum to a hair over 3.0 in floating point
+    let (field, target) = ("b".repeat(30), "a".repeat(30));
+    let distance = weighted_levenshtein(&field, &target, &costs, 3.0);
+    assert!(distance > 2.99 && within_bound(distance, 3.0), "{distance}");
+    assert!(within_bound(
+        substitution_distance(&field, &target, &costs, 3.0),
+        3.0
+    ));
+    assert!(!within_bound(
+        weighted_levenshtein(&field, &target, &costs, 2.9),
+        2.9
+    ));
+
+    // Costs that overflow saturate at infinity, which is never NaN and is
+    // over any bound
+    let (field, target) = ("y".repeat(1000), "x".repeat(1000));
+    let distance = weighted_levenshtein(&field, &target, &costs, 5.0);
+    assert!(!distance.is_nan() && distance > 5.0);
+    assert!(!weighted_levenshtein(&field, &target, &costs, 1e301).is_nan());
+    let distance = substitution_distance(&field, &target, &costs, 1.7e308);
+    assert!(
+        !distance.is_nan() && !within_bound(distance, 1.7e308),
+        "{distance}"
+    );
+    assert!(!substitution_distance(&field, &target, &costs, f64::MAX).is_nan());
+
+    let cheap = r#"[{"from": "a", "to": "b", "cost": 0.1}]"#;
+    let check = |length: usize, extra: Option<(&str, Param)>| {
+        let mut params = vec![
+            ("target", text(&"a".repeat(length))),
+            ("sub_costs", text(cheap)),
+            ("max_distance", int(3)),
+        ];
+        params.extend(extra);
+        run(&params, &[("name", &"b".repeat(length))])
+    };
+    assert_eq!(check(30, None), 1);
+    assert_eq!(check(30, Some(("require_equal_length", flag(true)))), 1);
+    assert_eq!(check(31, None), 0);
+
+    let huge = r#"[{"from": "x", "to": "y", "cost": 1e308}]"#;
+    let params = [
+        ("target", text(&"x".repeat(200))),
+        ("sub_costs", text(huge)),
+        ("max_distance", int(100)),
+        ("require_equal_length", flag(true)),
+    ];
+    assert_eq!(run(&params, &[("name", &"y".repeat(200))]), 0);
+}
+
+#[test]
+fn all_targets_requires_every_target_to_match() {
+    let _host = lock();
+    let targets = text(r#"["Samsung", "Galaxy"]"#);
+    let check = |all: bool, field: &str| {
+        run(
+            &[
+                ("targets", targets.clone()),
+                ("mode", text("partial")),
+                ("max_distance", int(1)),
+                ("all_targets", flag(all)),
+            ],
+            &[("name", field)],
+        )
+    };
+    assert_eq!(check(true, "Samsung Galaxy S24"), 1);
+    assert_eq!(check(true, "Samsng Galaxy"), 1);
+    assert_eq!(check(true, "Samsung Note"), 0);
+    assert_eq!(check(false, "Samsung Note"), 1);
+    assert_eq!(check(false, "Pixel"), 0);
+    assert_eq!(
+        run(
+            &[
+                ("targets", text("[]")),
+                ("max_distance", int(1)),
+                ("all_targets", flag(true))
+            ],
+            &[("name", "x")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[("targets", text("[1]")), ("max_distance", int(1))],
+            &[("name", "x")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("targets", text(r#"["abc", "abd"]"#)),
+                ("target", text("zzz")),
+                ("max_distance", int(1)),
+                ("all_targets", flag(true))
+            ],
+            &[("name", "abc")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("targets", text(r#"["abc", "xyz"]"#)),
+                ("max_distance", int(1)),
+                ("all_targets", flag(true)),
+                ("negate", flag(true))
+            ],
+            &[("name", "abc")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("targets", text(r#"["abc", "xyz"]"#)),
+                ("fields", text(r#"["a", "b"]"#)),
+                ("max_distance", int(1)),
+                ("all_targets", flag(true))
+            ],
+            &[("a", "abc"), ("b", "xyy")]
+        ),
+        1
+    );
+}
+
+#[test]
+fn case_mismatch_cost_charges_less_than_a_substitution() {
+    use crate::weighted::{weighted_levenshtein, Costs};
+    let _host = lock();
+    let costs = Costs {
+        case_mismatch: Some(0.25),
+        ..Costs::default()
+    };
+    assert_eq!(weighted_levenshtein("iphone", "iPhone", &costs, 1.0), 0.25);
+    assert!(weighted_levenshtein("iPhome", "iPhone", &costs, 1.0) >= 1.0);
+
+    let check = |field: &str| {
+        run(
+            &[
+                ("target", text("iPhone")),
+                ("max_distance", int(1)),
+                ("case_mismatch_cost", text("0.25")),
+            ],
+            &[("name", field)],
+        )
+    };
+    assert_eq!(check("iphone"), 1);
+    assert_eq!(check("iphoNE"), 1);
+    assert_eq!(check("iphome"), 0);
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("max_distance", int(1))],
+            &[("name", "iphoNE")]
+        ),
+        0
+    );
+    // A negative cost is invalid, and a listed pair keeps its own cost
+    assert_eq!(
+        run(
+            &[
+                ("target", text("iPhone")),
+                ("max_distance", int(1)),
+                ("case_mismatch_cost", text("-1")),
+            ],
+            &[("name", "iPhone")]
+        ),
+        0
+    );
+    let costs = r#"[{"from": "P", "to": "p", "cost": 1.0}]"#;
+    assert_eq!(
+        run(
+            &[
+                ("target", text("iPhone")),
+                ("max_distance", int(1)),
+                ("case_mismatch_cost", text("0.25")),
+                ("sub_costs", text(costs)),
+            ],
+            &[("name", "iphoNE")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("ÄBC")),
+                ("max_distance", int(0)),
+                ("case_mismatch_cost", text("0")),
+                ("require_equal_length", flag(true)),
+            ],
+            &[("name", "äbc")]
+        ),
+        1
+    );
+}
+
+#[test]
+fn filter_ex_reports_exact_matches() {
+    let _host = lock();
+    let check = |params: &[(&str, Param)], fields: &[(&str, &str)]| {
+        setup(params, fields);
+        let mut exact = -5;
+        let result = unsafe { crate::filter_ex(1, &mut exact) };
+        (result, exact)
+    };
+    assert_eq!(
+        check(&[("target", text("iPhone"))], &[("name", "iPhone")]),
+        (1, 1)
+    );
+    assert_eq!(
+        check(&[("target", text("iPhone"))], &[("name", "iPhne")]),
+        (1, 0)
+    );
+    assert_eq!(
+        check(&[("target", text("iPhone"))], &[("name", "Android")]),
+        (0, 0)
+    );
+    assert_eq!(
+        check(
+            &[("target", text("iPhone")), ("case_insensitive", flag(true))],
+            &[("name", "IPHONE")]
+        ),
+        (1, 1)
+    );
+    assert_eq!(
+        check(
+            &[("target", text("iPhone")), ("negate", flag(true))],
+            &[("name", "Android")]
+        ),
+        (1, 0)
+    );
+    assert_eq!(
+        check(
+            &[("target", text("iPhone")), ("mode", text("partial"))],
+            &[("name", "Apple iPhone 15")]
+        ),
+        (1, 1)
+    );
+    assert_eq!(
+        check(
+            &[
+                ("targets", text(r#"["abc", "abd"]"#)),
+                ("all_targets", flag(true))
+            ],
+            &[("name", "abc")]
+        ),
+        (1, 0)
+    );
+    assert_eq!(
+        check(
+            &[("targets", text(r#"["abd", "abc"]"#))],
+            &[("name", "abc")]
+        ),
+        (1, 0)
+    );
+    assert_eq!(
+        check(
+            &[("targets", text(r#"["abc", "abd"]"#))],
+            &[("name", "abc")]
+        ),
+        (1, 1)
+    );
+    assert_eq!(
+        check(
+            &[("target", text("iPhone")), ("max_distance", int(-1))],
+            &[("name", "iPhone")]
+        ),
+        (-1, 0)
+    );
+
+    // The canonicalizing modes compare canonical forms, so those decide
+    let mode = |mode: &str, target: &str, field: &str| {
+        check(
+            &[("target", text(target)), ("mode", text(mode))],
+            &[("name", field)],
+        )
+    };
+    let url = "https://example.com/docs";
+    assert_eq!(mode("url", url, "HTTP://www.Example.COM:80/docs/"), (1, 1));
+    assert_eq!(mode("url", url, "https://example.com/doc"), (1, 0));
+    assert_eq!(mode("url", url, "https://example.org/api"), (0, 0));
+    assert_eq!(mode("phone", "+1 555 123 4567", "555-123-4567"), (1, 0));
+    assert_eq!(mode("phone", "(555) 123-4567", "555.123.4567"), (1, 1));
+    assert_eq!(mode("numeric_typo", "12345", "no. 12345"), (1, 1));
+    assert_eq!(mode("numeric_typo", "12345", "13245"), (1, 0));
+    assert_eq!(
+        mode("email", "jane@example.com", "jane@EXAMPLE.com"),
+        (1, 1)
+    );
+    assert_eq!(mode("date", "2024-03-01", "2024-03-01"), (1, 1));
+}
+
+#[test]
+#[cfg(feature = "stream")]
+fn filter_ex_reports_exact_streamed_matches() {
+    let _host = lock();
+    let check = |field: &str| {
+        setup(
+            &[("target", text("iPhone")), ("stream_field", flag(true))],
+            &[("name", field)],
+        );
+        let mut exact = -5;
+        let result = unsafe { crate::filter_ex(1, &mut exact) };
+        (result, exact)
+    };
+    assert_eq!(check("iPhone"), (1, 1));
+    assert_eq!(check("iPhonee"), (1, 0));
+}
+
+#[test]
+fn unknown_char_policy_drops_or_replaces_unfoldable_chars() {
+    let _host = lock();
+    let check = |extra: &[(&str, Param)], field: &str| {
+        let mut params = vec![
+            ("target", text("Yirgacheffe Coffee")),
+            ("max_distance", int(0)),
+            ("collapse_whitespace", flag(true)),
+        ];
+        params.extend_from_slice(extra);
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(check(&[], "Yirgacheffe ቡና Coffee"), 0);
+    assert_eq!(
+        check(
+            &[("unknown_char_policy", text("drop"))],
+            "Yirgacheffe ቡና Coffee"
+        ),
+        1
+    );
+    assert_eq!(
+        check(
+            &[("unknown_char_policy", text("keep"))],
+            "Yirgacheffe ቡና Coffee"
+        ),
+        0
+    );
+    assert_eq!(
+        check(
+            &[("unknown_char_policy", text("bogus"))],
+            "Yirgacheffe ቡና Coffee"
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("ab ቡ")),
+                ("max_distance", int(0)),
+                ("unknown_char_policy", text("replace"))
+            ],
+            &[("name", "ab ና")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[("target", text("ab ቡ")), ("max_distance", int(0))],
+            &[("name", "ab ና")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("ab ?")),
+                ("max_distance", int(0)),
+                ("unknown_char_policy", text("replace")),
+                ("unknown_char_placeholder", text("?"))
+            ],
+            &[("name", "ab ና")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("ab ቡ")),
+                ("max_distance", int(0)),
+                ("unknown_char_policy", text("replace")),
+                ("unknown_char_placeholder", text("??"))
+            ],
+            &[("name", "ab ና")]
+        ),
+        0
+    );
+    // known scripts, digits and symbols are untouched
+    assert_eq!(
+        run(
+            &[
+                ("target", text("Café 日本 €5")),
+                ("max_distance", int(0)),
+                ("unknown_char_policy", text("drop"))
+            ],
+            &[("name", "Café 日本 €5")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("Café")),
+                ("max_distance", int(0)),
+                ("unknown_char_policy", text("drop"))
+            ],
+            &[("name", "Caf")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("ab")),
+                ("max_distance", int(0)),
+                ("unknown_char_policy", text("drop")),
+                ("stream_field", flag(true))
+            ],
+            &[("name", "aቡb")]
+        ),
+        streamed(1)
+    );
+}
+
+#[test]
+fn shared_field_agrees_with_levenshtein_within() {
+    use crate::levenshtein_within;
+    use crate::multi::SharedField;
+    let words = random_strings(7, 60, &['a', 'b', 'c', 'd', 'é', ' '], 14);
+    for field in &words {
+        let mut shared = SharedField::new(field);
+        for target in &words {
+            let chars: Vec<char> = target.chars().collect();
+            for max in 0..5 {
+                assert_eq!(
+                    shared.within(&chars, max),
+                    levenshtein_within(field, target, max),
+                    "{field:?} {target:?} {max}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn shared_targets_match_like_one_target_at_a_time() {
+    let _host = lock();
+    let words = random_strings(11, 120, &['a', 'b', 'c', 'd', 'é', ' '], 10);
+    let targets = &words[..20];
+    let targets_json = format!(
+        "[{}]",
+        targets
+            .iter()
+            .map(|target| format!("\"{}\"", target))
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    let options: [Vec<(&str, Param)>; 3] = [
+        vec![],
+        vec![("case_insensitive", flag(true))],
+        vec![("collapse_whitespace", flag(true))],
+    ];
+    for field in &words[20..] {
+        for extra in &options {
+            let mut params = vec![("targets", text(&targets_json)), ("max_distance", int(2))];
+            params.extend_from_slice(extra);
+            let expected = targets.iter().any(|target| {
+                let mut params = vec![("target", text(target)), ("max_distance", int(2))];
+                params.extend_from_slice(extra);
+                run(&params, &[("name", field)]) == 1
+            });
+            assert_eq!(
+                run(&params, &[("name", field)]),
+                expected as i32,
+                "{field:?}"
+            );
+        }
+    }
+
+    // The first matching target decides whether the match was exact
+    setup(
+        &[
+            ("targets", text(r#"["abd", "abc"]"#)),
+            ("max_distance", int(1)),
+        ],
+        &[("name", "abc")],
+    );
+    let mut exact = -5;
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 0);
+
+    // Empty targets and fields keep their own rules
+    let blank_targets = text(r#"["  ", "zzz"]"#);
+    assert_eq!(
+        run(
+            &[
+                ("targets", blank_targets.clone()),
+                ("collapse_whitespace", flag(true)),
+                ("empty_target_matches", flag(true))
+            ],
+            &[("name", "abc")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("targets", blank_targets),
+                ("collapse_whitespace", flag(true))
+            ],
+            &[("name", "abc")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("targets", text(r#"["abc"]"#)),
+                ("empty_field_behavior", text("match"))
+            ],
+            &[("name", "  ")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(&[("targets", text(r#"["abc"]"#))], &[("other", "abc")]),
+        0
+    );
+}
+
+#[test]
+#[cfg(all(feature = "jaro", feature = "ngram", feature = "token"))]
+fn similarity_key_orders_like_the_float_similarity() {
+    use crate::similarity_key;
+    let _host = lock();
+    let target = "abcab dc";
+    let words = random_strings(11, 80, &['a', 'b', 'c', 'd', ' '], 10);
+    for name in ["levenshtein", "jaro_winkler", "ngram", "token_set_ratio"] {
+        let mut pairs = Vec::new();
+        for field in words.iter().filter(|word| !word.is_empty()) {
+            setup(
+                &[("target", text(target)), ("algorithm", text(name))],
+                &[("name", field)],
+            );
+            let algorithm = unsafe { crate::get_algorithm() }.unwrap();
+            pairs.push((algorithm.similarity(field, target), similarity_key(1)));
+        }
+        for a in &pairs {
+            for b in &pairs {
+                if a.0 < b.0 - 1e-6 {
+                    assert!(a.1 <= b.1, "{name} {a:?} {b:?}");
+                }
+                if a.0 == b.0 {
+                    assert_eq!(a.1, b.1, "{name}");
+                }
+            }
+        }
+    }
+
+    let check = |params: &[(&str, Param)], fields: &[(&str, &str)]| {
+        setup(params, fields);
+        similarity_key(1)
+    };
+    assert_eq!(
+        check(&[("target", text("abc"))], &[("name", "abc")]),
+        1_000_000
+    );
+    assert_eq!(
+        check(&[("target", text("abcd"))], &[("name", "abce")]),
+        750_000
+    );
+    assert_eq!(check(&[("target", text("abc"))], &[("other", "abc")]), -1);
+    assert_eq!(
+        check(
+            &[("target", text("abc")), ("algorithm", text("nope"))],
+            &[("name", "abc")]
+        ),
+        -1
+    );
+}
+
+#[test]
+fn banded_dp_is_exact_at_the_band_boundary() {
+    use crate::levenshtein_within;
+    use crate::multi::SharedField;
+    // Long strings take the banded path; check bounds just under, at and
+    // just over the true distance
+    let words = random_strings(19, 400, &['a', 'b', 'c'], 48);
+    let long: Vec<&String> = words.iter().filter(|s| s.chars().count() > 33).collect();
+    let mut at_bound = 0;
+    let mut over_bound = 0;
+    for (i, a) in long.iter().enumerate() {
+        for b in long.iter().skip(i).take(12) {
+            let distance = naive_levenshtein(a, b);
+            let chars: Vec<char> = b.chars().collect();
+            for max in [distance.saturating_sub(1), distance, distance + 1] {
+                let expected = Some(distance).filter(|&d| d <= max);
+                assert_eq!(levenshtein_within(a, b, max), expected, "{a} {b} {max}");
+                assert_eq!(SharedField::new(a).within(&chars, max), expected);
+                if max == distance {
+                    at_bound += 1;
+                } else if max + 1 == distance {
+                    over_bound += 1;
+                }
+            }
+        }
+    }
+    assert!(at_bound > 100 && over_bound > 100);
+
+    // Lengths differing by exactly the bound put every edit on the band edge
+    let a = "x".repeat(40);
+    let b = "x".repeat(43);
+    assert_eq!(levenshtein_within(&a, &b, 3), Some(3));
+    assert_eq!(levenshtein_within(&a, &b, 2), None);
+    let a = format!("{}q", "ab".repeat(20));
+    let b = format!("zzz{}", "ab".repeat(20));
+    let distance = naive_levenshtein(&a, &b);
+    assert_eq!(levenshtein_within(&a, &b, distance), Some(distance));
+    assert_eq!(levenshtein_within(&a, &b, distance - 1), None);
+    assert_eq!(levenshtein_distance(&a, &b), distance);
+}
+
+/// `params` as the members of a `config` object, with JSON lists and maps
+/// given as JSON values when `native` and as strings otherwise
+fn to_config(params: &[(&str, Param)], native: bool) -> String {
+    use crate::json::{parse, Value};
+    let members = params.iter().map(|(name, value)| {
+        let value = match value {
+            Param::Str(text) => match parse(text) {
+                Some(json @ (Value::Array(_) | Value::Object(_))) if native => json,
+                _ => Value::String(text.clone()),
+            },
+            Param::Int(value) => Value::Number(*value as f64),
+            Param::Float(value) => Value::Number(*value),
+            Param::Bool(value) => Value::Bool(*value),
+        };
+        (name.to_string(), value)
+    });
+    Value::Object(members.collect()).to_json()
+}
+
+#[test]
+fn config_matches_like_the_individual_params() {
+    use crate::similarity_key;
+    let _host = lock();
+    let queries: Vec<Vec<(&str, Param)>> = vec![
+        vec![("target", text("iPhone")), ("max_distance", int(1))],
+        vec![
+            ("target", text("iPhone")),
+            ("case_insensitive", flag(true)),
+            ("max_distance", int(0)),
+        ],
+        vec![
+            ("target", text("iPhone")),
+            ("algorithm", text("jaro_winkler")),
+            ("min_similarity", text("0.85")),
+        ],
+        vec![("target", text("iPhone")), ("threshold", text("0.7"))],
+        vec![
+            ("targets", text(r#"["Android", "iPhone"]"#)),
+            ("max_distance", int(1)),
+        ],
+        vec![
+            ("targets", text(r#"["iPhone", "iPhone 15"]"#)),
+            ("all_targets", flag(true)),
+            ("max_distance", int(3)),
+        ],
+        vec![
+            ("target", text("BORDER")),
+            (
+                "sub_costs",
+                text(r#"[{"from": "O", "to": "0", "cost": 0.25}]"#),
+            ),
+            ("max_distance", int(0)),
+        ],
+        vec![
+            ("target", text("iPhone")),
+            ("mode", text("partial")),
+            ("max_distance", int(0)),
+            ("negate", flag(true)),
+        ],
+        vec![
+            ("target", text("iPhone")),
+            ("field", text("title")),
+            ("max_distance", int(1)),
+        ],
+    ];
+    let docs: [&[(&str, &str)]; 4] = [
+        &[("name", "iPhone")],
+        &[("name", "iphone")],
+        &[("name", "B0RDER"), ("title", "Apple iPhone")],
+        &[("other", "x")],
+    ];
+    for query in &queries {
+        for doc in docs {
+            let expected = run(query, doc);
+            for native in [false, true] {
+                let config = to_config(query, native);
+                assert_eq!(run(&[("config", text(&config))], doc), expected, "{config}");
+            }
+            setup(query, doc);
+            let key = similarity_key(1);
+            setup(&[("config", text(&to_config(query, true)))], doc);
+            assert_eq!(similarity_key(1), key);
+        }
+    }
+}
+
+#[test]
+fn shared_targets_are_decided_once_per_query() {
+    use host::PARAM_READS;
+    let _host = lock();
+    let doc = [("name", "lorem ipsum")];
+    let query = [
+        ("targets", text(r#"["Android", "iPhone", "Pixel"]"#)),
+        ("max_distance", int(1)),
+    ];
+    // What a later document of the query asks the host for
+    let later_document = |extra: &[(&str, Param)]| {
+        let mut params = query.to_vec();
+        params.extend_from_slice(extra);
+        assert_eq!(run(&params, &doc), 0);
+        PARAM_READS.lock().unwrap().clear();
+        assert_eq!(crate::filter(1), 0);
+        let reads = PARAM_READS.lock().unwrap().clone();
+        reads
+    };
+    let shared = later_document(&[]);
+    assert!(
+        !shared.contains(&"require_prefix_len".to_string()),
+        "{:?}",
+        shared
+    );
+
+    // Turned off, or with a parameter the shared path doesn't honor, each
+    // target is matched on its own and reads its parameters again
+    for extra in [
+        ("share_field_decoding", flag(false)),
+        ("require_equal_length", flag(false)),
+    ] {
+        let per_target = later_document(&[extra]);
+        assert!(per_target.contains(&"require_prefix_len".to_string()));
+        assert!(per_target.len() > shared.len());
+    }
+}
+
+#[test]
+fn config_is_read_once_per_query() {
+    use host::{set_params, PARAM_CALLS, PARAM_READS};
+    let _host = lock();
+    let query = [
+        ("targets", text(r#"["Android", "iPhone"]"#)),
+        ("case_insensitive", flag(true)),
+        ("collapse_whitespace", flag(true)),
+        ("max_distance", int(1)),
+    ];
+    let doc = [("name", "iphone")];
+    // The first document of the query reads the config, if any
+    let later_document = |params: &[(&str, Param)]| {
+        assert_eq!(run(params, &doc), 1);
+        assert!(PARAM_READS.lock().unwrap().contains(&"config".to_string()));
+        *PARAM_CALLS.lock().unwrap() = 0;
+        PARAM_READS.lock().unwrap().clear();
+        assert_eq!(crate::filter(1), 1);
+        let reads = PARAM_READS.lock().unwrap().clone();
+        reads
+    };
+    assert!(later_document(&[("config", text(&to_config(&query, true)))]).is_empty());
+
+    // Without a config, no call asks for one once the first has
+    let reads = later_document(&query);
+    assert!(reads.len() > 3);
+    assert!(!reads.contains(&"config".to_string()), "{:?}", reads);
+
+    // A host that never calls `params_changed` gets it read for each call
+    setup(&query, &doc);
+    unsafe { crate::PARAMS_CHANGED = None };
+    for _ in 0..2 {
+        PARAM_READS.lock().unwrap().clear();
+        assert_eq!(crate::filter(1), 1);
+        assert!(PARAM_READS.lock().unwrap().contains(&"config".to_string()));
+    }
+    set_params(&[("config", text(&to_config(&query, true)))]);
+    *PARAM_CALLS.lock().unwrap() = 0;
+    assert_eq!(crate::filter(1), 1);
+    assert_eq!(*PARAM_CALLS.lock().unwrap(), 1);
+}
+
+#[test]
+fn malformed_config_matches_nothing() {
+    let _host = lock();
+    let doc = [("name", "iPhone")];
+    assert_eq!(
+        run(&[("config", text("[1]")), ("target", text("iPhone"))], &doc),
+        0
+    );
+    assert!(LOGS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|log| log.contains("config is not a JSON object")));
+    assert_eq!(
+        run(&[("config", text("{")), ("target", text("iPhone"))], &doc),
+        0
+    );
+    // Without a config the individual params apply again
+    assert_eq!(run(&[("target", text("iPhone"))], &doc), 1);
+
+    // Targets from a config still obey max_target_len
+    let long = "x".repeat(5000);
+    let config = format!(r#"{{"target": "{long}", "max_target_len": 10000}}"#);
+    assert_eq!(run(&[("config", text(&config))], &[("name", "x")]), 0);
+    let config = format!(r#"{{"target": "{long}"}}"#);
+    assert_eq!(run(&[("config", text(&config))], &[("name", "x")]), -1);
+}
+
+#[test]
+fn json_values_round_trip_through_to_json() {
+    use crate::json::parse;
+    let value = parse(r#"{"a": [1, 2.5, "q\"\n\u0001é"], "b": {"c": null, "d": true}}"#).unwrap();
+    assert_eq!(parse(&value.to_json()).unwrap(), value);
+}
+
+#[test]
+#[cfg(feature = "token")]
+fn stacked_quantifiers_are_bounded_like_groups() {
+    use crate::regex::Regex;
+    let _host = lock();
+    assert!(Regex::new("((a*)*)+").is_some());
+    assert!(Regex::new(&format!("a{}", "?".repeat(16))).is_some());
+    assert!(Regex::new(&format!("a{}", "?".repeat(17))).is_none());
+    assert!(Regex::new(&format!("a{}", "*".repeat(200))).is_none());
+
+    let check = |pattern: &str| {
+        run(
+            &[
+                ("target", text("a")),
+                ("algorithm", text("token_set_ratio")),
+                ("tokenizer", text("regex")),
+                ("token_pattern", text(pattern)),
+            ],
+            &[("name", "a")],
+        )
+    };
+    assert_eq!(check("a*"), 1);
+    assert_eq!(check(&format!("a{}", "*".repeat(200))), 0);
+}
+
+#[test]
+fn position_weighting_makes_leading_edits_cost_more() {
+    let _host = lock();
+    let check = |field: &str, extra: &[(&str, Param)]| {
+        let mut params = vec![
+            ("target", text("Marion")),
+            ("max_distance", int(1)),
+            ("position_weighting", text("linear")),
+        ];
+        params.extend_from_slice(extra);
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(check("Marion", &[]), 1);
+    assert_eq!(check("Mariom", &[]), 1);
+    assert_eq!(check("Marin", &[]), 1);
+    assert_eq!(check("MarionX", &[]), 1);
+    assert_eq!(check("Narion", &[]), 0);
+    assert_eq!(check("arion", &[]), 0);
+    assert_eq!(check("XMarion", &[]), 0);
+    assert_eq!(check("Mraion", &[]), 0);
+    // A decay of 0 charges double everywhere; negative decays are invalid
+    assert_eq!(check("Mariom", &[("position_decay", text("0"))]), 0);
+    assert_eq!(check("Mariom", &[("position_decay", text("-1"))]), 0);
+    assert_eq!(
+        check(
+            "Narion",
+            &[("position_decay", text("1")), ("max_distance", int(2))]
+        ),
+        1
+    );
+    // Substitution-only comparison is weighted too
+    assert_eq!(check("Narion", &[("require_equal_length", flag(true))]), 0);
+    assert_eq!(check("Mariom", &[("require_equal_length", flag(true))]), 1);
+
+    assert_eq!(
+        run(
+            &[
+                ("target", text("Marion")),
+                ("max_distance", int(1)),
+                ("position_weighting", text("quadratic"))
+            ],
+            &[("name", "Marion")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("Marion")),
+                ("max_distance", int(1)),
+                ("position_weighting", text("none"))
+            ],
+            &[("name", "Narion")]
+        ),
+        1
+    );
+}
+
+#[test]
+fn position_weights_scale_the_plain_distance() {
+    use crate::weighted::{weighted_levenshtein, Costs};
+    let words = random_strings(7, 300, &['a', 'b', 'c'], 8);
+    // Every weight lies within [1, 2], so the weighted distance does too
+    let costs = Costs {
+        position_decay: Some(0.25),
+        ..Costs::default()
+    };
+    for pair in words.chunks(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let distance = naive_levenshtein(a, b) as f64;
+        let weighted = weighted_levenshtein(a, b, &costs, 100.0);
+        assert!(
+            weighted >= distance && weighted <= 2.0 * distance,
+            "{a} {b} {weighted}"
+        );
+    }
+    // With no decay every edit costs exactly double
+    let costs = Costs {
+        position_decay: Some(0.0),
+        ..Costs::default()
+    };
+    for pair in words.chunks(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let distance = naive_levenshtein(a, b) as f64;
+        assert_eq!(weighted_levenshtein(a, b, &costs, 100.0), 2.0 * distance);
+    }
+}
+
+#[test]
+fn length_normalized_confidence_discounts_short_strings() {
+    use crate::length_normalized_confidence;
+    let _host = lock();
+    let check = |field: &str, target: &str, extra: &[(&str, Param)]| {
+        let mut params = vec![("target", text(target))];
+        params.extend_from_slice(extra);
+        setup(&params, &[("name", field)]);
+        length_normalized_confidence(1)
+    };
+    let short = check("cut", "cat", &[]);
+    let long = check(
+        "iphone 15 pro max 256gb blacks",
+        "iphone 15 pro max 256gb black",
+        &[],
+    );
+    assert!((short - 1.0 / 3.0).abs() < 1e-6, "{short}");
+    assert!((long - (1.0 - 1.1 / 30.0)).abs() < 1e-6, "{long}");
+    assert_eq!(check("cat", "cat", &[]), 1.0);
+    assert_eq!(check("ab", "xy", &[]), 0.0);
+
+    // 0 gives the plain similarity, a large penalty clamps at 0
+    let plain = check("cut", "cat", &[("short_length_penalty", text("0"))]);
+    assert!((plain - 2.0 / 3.0).abs() < 1e-6, "{plain}");
+    assert_eq!(
+        check("cut", "cat", &[("short_length_penalty", text("9"))]),
+        0.0
+    );
+    assert_eq!(
+        check("cut", "cat", &[("short_length_penalty", text("-1"))]),
+        -1.0
+    );
+    let folded = check(
+        "cut",
+        "Cat",
+        &[
+            ("case_insensitive", flag(true)),
+            ("short_length_penalty", text("0")),
+        ],
+    );
+    assert!((folded - 2.0 / 3.0).abs() < 1e-6, "{folded}");
+
+    setup(&[("target", text("cat"))], &[("other", "cat")]);
+    assert_eq!(length_normalized_confidence(1), -1.0);
+}
+
+#[test]
+#[cfg(feature = "arrays")]
+fn set_pairing_pairs_tags_regardless_of_order() {
+    let _host = lock();
+    let check = |field: &str, extra: &[(&str, Param)]| {
+        let mut params = vec![
+            ("target", text(r#"["wireless", "headphones"]"#)),
+            ("mode", text("set_pairing")),
+            ("max_total", text("1")),
+        ];
+        params.extend_from_slice(extra);
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(check(r#"["headphones", "wireless"]"#, &[]), 1);
+    assert_eq!(check(r#"["wireless", "headphone"]"#, &[]), 1);
+    assert_eq!(check(r#"["wireless", "headphones", "bluetooth"]"#, &[]), 1);
+    assert_eq!(check(r#"["headphones"]"#, &[]), 1);
+    assert_eq!(check(r#"["wireless", "earbuds"]"#, &[]), 0);
+    assert_eq!(check(r#"["headphones", "bluetooth", "usb-c"]"#, &[]), 0);
+    assert_eq!(
+        check(
+            r#"["headphones", "bluetooth", "usb-c"]"#,
+            &[("max_total", text("3"))]
+        ),
+        1
+    );
+    assert_eq!(
+        check(
+            r#"["wireless", "headphones", "bluetooth"]"#,
+            &[
+                ("unmatched_penalty", text("0.5")),
+                ("max_total", text("0.5"))
+            ]
+        ),
+        1
+    );
+    assert_eq!(
+        check(
+            r#"["wireless", "earbuds"]"#,
+            &[("unmatched_penalty", text("-1")), ("max_total", text("5"))]
+        ),
+        0
+    );
+    assert_eq!(
+        check(
+            r#"["Wireless", "HEADPHONES"]"#,
+            &[("max_total", text("0")), ("case_insensitive", flag(true))]
+        ),
+        1
+    );
+    assert_eq!(
+        check(r#"["Wireless", "HEADPHONES"]"#, &[("max_total", text("0"))]),
+        0
+    );
+    // Anything but an array of strings doesn't match
+    assert_eq!(check("wireless headphones", &[]), 0);
+    assert_eq!(check(r#"["wireless", 5]"#, &[]), 0);
+    assert_eq!(check("[]", &[("max_total", text("2"))]), 1);
+    assert_eq!(check("[]", &[]), 0);
+
+    // max_total defaults to max_distance
+    let params = [
+        ("target", text(r#"["ab", "cd"]"#)),
+        ("mode", text("set_pairing")),
+        ("max_distance", int(0)),
+    ];
+    assert_eq!(run(&params, &[("name", r#"["cd", "ab"]"#)]), 1);
+    assert_eq!(run(&params, &[("name", r#"["cd", "ab", "ab"]"#)]), 0);
+    let many: Vec<String> = (0..65).map(|i| format!("\"t{i}\"")).collect();
+    let many = format!("[{}]", many.join(","));
+    assert_eq!(run(&params, &[("name", &many)]), 0);
+}
+
+#[test]
+#[cfg(feature = "arrays")]
+fn set_pairing_reads_arrays_through_the_array_imports() {
+    use crate::array::{encode, parse};
+    use host::{ARRAY_CALLS, FIELD_CALLS, MISBEHAVE};
+    let _host = lock();
+    let encoded = encode(&["a", "", "héllo"]);
+    assert_eq!(parse(&encoded), Some(vec!["a", "", "héllo"]));
+    assert_eq!(parse(&encoded[..encoded.len() - 1]), None);
+    let mut extra = encoded.clone();
+    extra.push(0);
+    assert_eq!(parse(&extra), None);
+    assert_eq!(parse(&[1, 0, 0]), None);
+
+    let query = |extra: &[(&'static str, Param)]| {
+        let mut params = vec![
+            ("target", text(r#"["wireless", "headphones"]"#)),
+            ("mode", text("set_pairing")),
+        ];
+        params.extend_from_slice(extra);
+        params
+    };
+    let tags = [("name", r#"["headphones", "wireless"]"#)];
+    assert_eq!(run(&query(&[]), &tags), 1);
+    // The field is read once, as an array and never as text
+    assert_eq!(*ARRAY_CALLS.lock().unwrap(), 1);
+    assert_eq!(*FIELD_CALLS.lock().unwrap(), 0);
+    // A field that isn't an array doesn't match, nor does a missing one
+    assert_eq!(run(&query(&[]), &[("name", "wireless headphones")]), 0);
+    assert_eq!(*ARRAY_CALLS.lock().unwrap(), 1);
+    assert_eq!(run(&query(&[]), &[("other", r#"["wireless"]"#)]), 0);
+    assert_eq!(run(&query(&[("target", text("wireless"))]), &tags), 0);
+
+    // The target may be another array field, or an array in the config
+    let wanted = query(&[("target_field", text("wanted"))]);
+    let fields = [tags[0], ("wanted", r#"["wireless", "headphone"]"#)];
+    assert_eq!(run(&wanted, &fields), 1);
+    assert_eq!(*ARRAY_CALLS.lock().unwrap(), 2);
+    let config = r#"{"mode": "set_pairing", "target": ["headphones", "wireless"]}"#;
+    assert_eq!(run(&[("config", text(config))], &tags), 1);
+
+    // Arrays too long for the first read are read again in full
+    let long: Vec<String> = (0..60).map(|i| format!("{i:0>200}")).collect();
+    let long = format!("{:?}", long);
+    let params = [
+        ("target", text("[]")),
+        ("mode", text("set_pairing")),
+        ("max_total", text("60")),
+    ];
+    assert_eq!(run(&params, &[("name", &long)]), 1);
+    assert_eq!(*ARRAY_CALLS.lock().unwrap(), 2);
+    assert_eq!(run(&params, &[("name", &format!("{long} "))]), 1);
+    assert_eq!(*ARRAY_CALLS.lock().unwrap(), 1);
+
+    // A `targets` list is text, not arrays
+    let targets = [
+        ("targets", text(r#"["wireless", "headphones"]"#)),
+        ("mode", text("set_pairing")),
+    ];
+    assert_eq!(run(&targets, &tags), -1);
+
+    // An array that doesn't decode is a host fault
+    setup(&query(&[]), &tags);
+    *MISBEHAVE.lock().unwrap() = Some(("get_field_array", "", 0, 3));
+    assert_eq!(crate::filter(1), -2);
+    assert!(host::LOGS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|log| log.contains("get_field_array wrote an array that doesn't decode")));
+}
+
+#[test]
+#[cfg(not(feature = "arrays"))]
+fn set_pairing_needs_the_arrays_feature() {
+    let _host = lock();
+    let params = [
+        ("target", text(r#"["wireless", "headphones"]"#)),
+        ("mode", text("set_pairing")),
+    ];
+    assert_eq!(
+        run(&params, &[("name", r#"["wireless", "headphones"]"#)]),
+        -1
+    );
+    assert!(host::LOGS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|log| log.contains("needs a build with the \"arrays\" feature")));
+}
+
+#[test]
+#[cfg(feature = "arrays")]
+fn pairing_cost_agrees_with_brute_force() {
+    use crate::pairing::pairing_cost;
+    // Every injection of field elements into unused targets, or leftovers
+    fn brute(field: &[String], target: &[String], unmatched: f64, used: &mut [bool]) -> f64 {
+        if field.is_empty() {
+            return used.iter().filter(|&&used| !used).count() as f64 * unmatched;
+        }
+        let mut best = unmatched + brute(&field[1..], target, unmatched, used);
+        for j in 0..target.len() {
+            if used[j] {
+                continue;
+            }
+            used[j] = true;
+            let pair = naive_levenshtein(&field[0], &target[j]) as f64;
+            best = best.min(pair + brute(&field[1..], target, unmatched, used));
+            used[j] = false;
+        }
+        best
+    }
+
+    let pool = random_strings(151, 400, &['a', 'b', 'c'], 5);
+    let mut next = 0;
+    for round in 0..150 {
+        let (field_len, target_len) = (round % 5, (round / 5) % 6);
+        let field = &pool[next..next + field_len];
+        next = (next + field_len) % 300;
+        let target = &pool[next..next + target_len];
+        next = (next + target_len) % 300;
+        for unmatched in [0.0, 0.5, 1.0, 1.5, 3.0] {
+            let cost = pairing_cost(field, target, unmatched).unwrap();
+            let expected = brute(field, target, unmatched, &mut vec![false; target.len()]);
+            assert!(
+                (cost - expected).abs() < 1e-9,
+                "{field:?} {target:?} {unmatched} {cost} {expected}"
+            );
+        }
+    }
+}
+
+#[test]
+#[cfg(all(feature = "jaro", feature = "ngram", feature = "token"))]
+fn identical_bytes_match_without_scoring() {
+    use host::PARAM_CALLS;
+    let _host = lock();
+    setup(
+        &[("target", text("iPhone 15")), ("max_distance", int(0))],
+        &[("name", "iPhone 15")],
+    );
+    let mut exact = 0;
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+
+    // The parameters of the scoring steps are not read
+    let param_calls = |field: &str| {
+        setup(&[("target", text("iPhone 15"))], &[("name", field)]);
+        crate::filter(1);
+        let calls = *PARAM_CALLS.lock().unwrap();
+        calls
+    };
+    let (fast, slow) = (param_calls("iPhone 15"), param_calls("iPhone 16"));
+    assert!(fast < slow, "{fast} {slow}");
+
+    // Nor is the field normalized, which would remember its form
+    let remembered = |field: &str| {
+        let params = [
+            ("target", text("iPhone 15")),
+            ("case_insensitive", flag(true)),
+        ];
+        setup(&params, &[("name", field)]);
+        crate::filter(1);
+        unsafe {
+            crate::FIELD_MEMO
+                .form(field, crate::get_normalization())
+                .is_some()
+        }
+    };
+    assert!(!remembered("iPhone 15"));
+    assert!(remembered("iPhone 16"));
+
+    // Invalid settings still match nothing
+    assert_eq!(
+        run(
+            &[
+                ("target", text("iPhone 15")),
+                ("sub_costs", text("nope")),
+                ("max_distance", int(0))
+            ],
+            &[("name", "iPhone 15")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[("target", text("ab")), ("case_mismatch_cost", text("-1"))],
+            &[("name", "ab")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[("target", text("abc")), ("algorithm", text("bogus"))],
+            &[("name", "abc")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[("target", text("ab")), ("case_mismatch_cost", text("0.5"))],
+            &[("name", "ab")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("ab")),
+                (
+                    "sub_costs",
+                    text(r#"[{"from": "a", "to": "b", "cost": 0.5}]"#)
+                )
+            ],
+            &[("name", "ab")]
+        ),
+        1
+    );
+
+    // Modes that parse their operands, blank values and repeats still decide
+    assert_eq!(
+        run(
+            &[("target", text("someone")), ("mode", text("email"))],
+            &[("name", "someone")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[("target", text("soon")), ("mode", text("date"))],
+            &[("name", "soon")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[("target", text("iPhone")), ("mode", text("partial"))],
+            &[("name", "iPhone")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("freefreefree")),
+                ("penalize_repeats", flag(true)),
+                ("max_distance", int(1))
+            ],
+            &[("name", "freefreefree")]
+        ),
+        0
+    );
+    assert_eq!(run(&[("target", text("  "))], &[("name", "  ")]), 0);
+    assert_eq!(
+        run(
+            &[("target", text("abc")), ("negate", flag(true))],
+            &[("name", "abc")]
+        ),
+        0
+    );
+    for algorithm in [
+        "jaro_winkler",
+        "ngram",
+        "token_set_ratio",
+        "exact_normalized",
+    ] {
+        assert_eq!(
+            run(
+                &[
+                    ("target", text("abc def")),
+                    ("algorithm", text(algorithm)),
+                    ("min_similarity", text("1"))
+                ],
+                &[("name", "abc def")]
+            ),
+            1,
+            "{algorithm}"
+        );
+    }
+}
+
+#[test]
+#[cfg(all(feature = "jaro", feature = "ngram", feature = "token"))]
+fn identical_bytes_still_obey_similarity_and_empty_target_rules() {
+    let _host = lock();
+    // Similarity algorithms score equal strings like any other
+    for algorithm in ["jaro_winkler", "ngram", "token_set_ratio"] {
+        assert_eq!(
+            run(
+                &[
+                    ("target", text("abc def")),
+                    ("algorithm", text(algorithm)),
+                    ("min_similarity", text("1.5"))
+                ],
+                &[("name", "abc def")]
+            ),
+            0,
+            "{algorithm}"
+        );
+    }
+    assert_eq!(
+        run(
+            &[
+                ("target", text("abc")),
+                ("algorithm", text("exact_normalized"))
+            ],
+            &[("name", "abc")]
+        ),
+        1
+    );
+
+    // A target emptied by normalization follows empty_target_matches
+    let emptied = |extra: &[(&str, Param)]| {
+        let mut params = vec![
+            ("target", text("ቡቡ")),
+            ("unknown_char_policy", text("drop")),
+        ];
+        params.extend_from_slice(extra);
+        run(&params, &[("name", "ቡቡ")])
+    };
+    assert_eq!(emptied(&[]), 0);
+    assert!(LOGS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|log| log.contains("is empty after normalization")));
+    assert_eq!(emptied(&[("empty_target_matches", flag(true))]), 1);
+}
+
+#[test]
+#[cfg(all(feature = "jaro", feature = "ngram", feature = "token"))]
+fn limits_follow_the_algorithm_kind() {
+    let _host = lock();
+    let logged = |text: &str| LOGS.lock().unwrap().iter().any(|log| log.contains(text));
+    let check = |algorithm: &str, extra: &[(&str, Param)], field: &str| {
+        let mut params = vec![
+            ("target", text("abcdefghij")),
+            ("algorithm", text(algorithm)),
+        ];
+        params.extend_from_slice(extra);
+        run(&params, &[("name", field)])
+    };
+
+    // Levenshtein: max_distance wins over min_similarity
+    let both = [("max_distance", int(1)), ("min_similarity", text("0.5"))];
+    assert_eq!(check("levenshtein", &both, "abcdefghxx"), 0);
+    assert!(logged("min_similarity ignored"));
+    let both = [("max_distance", int(2)), ("min_similarity", text("0.99"))];
+    assert_eq!(check("levenshtein", &both, "abcdefghxx"), 1);
+
+    // Levenshtein with only min_similarity is invalid, even for equal
+    // strings, several targets or a negated query
+    let similarity = [("min_similarity", text("0.5"))];
+    assert_eq!(check("levenshtein", &similarity, "abcdefghxx"), -1);
+    assert!(logged("min_similarity doesn't apply"));
+    assert_eq!(check("levenshtein", &similarity, "abcdefghij"), -1);
+    assert_eq!(
+        run(
+            &[
+                ("targets", text(r#"["abc", "abcdefghij"]"#)),
+                ("min_similarity", text("0.5"))
+            ],
+            &[("name", "abcdefghij")]
+        ),
+        -1
+    );
+    let negated = [("min_similarity", text("0.5")), ("negate", flag(true))];
+    assert_eq!(check("levenshtein", &negated, "abcdefghxx"), -1);
+
+    // Similarity algorithms: min_similarity wins, max_distance alone is invalid
+    let both = [("max_distance", int(0)), ("min_similarity", text("0.5"))];
+    assert_eq!(check("jaro_winkler", &both, "abcdefghxx"), 1);
+    assert!(logged("max_distance ignored"));
+    let both = [("max_distance", int(5)), ("min_similarity", text("0.99"))];
+    assert_eq!(check("jaro_winkler", &both, "abcdefghxx"), 0);
+    for algorithm in ["jaro", "jaro_winkler", "ngram", "token_set_ratio"] {
+        let distance = [("max_distance", int(2))];
+        assert_eq!(check(algorithm, &distance, "abcdefghxx"), -1, "{algorithm}");
+        assert_eq!(check(algorithm, &distance, "abcdefghij"), -1, "{algorithm}");
+        assert_eq!(
+            check(algorithm, &similarity, "abcdefghxx"),
+            1,
+            "{algorithm}"
+        );
+    }
+
+    // No conflict: one limit, threshold, exact_normalized, modes
+    assert_eq!(
+        check("levenshtein", &[("max_distance", int(2))], "abcdefghxx"),
+        1
+    );
+    assert_eq!(
+        check("levenshtein", &[("threshold", text("0.8"))], "abcdefghxx"),
+        1
+    );
+    assert_eq!(
+        check("jaro", &[("threshold", text("0.8"))], "abcdefghxx"),
+        1
+    );
+    let both = [("max_distance", int(2)), ("min_similarity", text("0.5"))];
+    assert_eq!(check("exact_normalized", &both, "abcdefghij"), 1);
+    assert_eq!(
+        run(
+            &[
+                ("target", text("2024-01-05")),
+                ("mode", text("date")),
+                ("max_distance", int(1)),
+                ("algorithm", text("jaro"))
+            ],
+            &[("name", "2024-01-06")]
+        ),
+        1
+    );
+}
+
+#[test]
+fn alternation_delimiter_splits_the_target_into_alternatives() {
+    let _host = lock();
+    let check = |target: &str, field: &str, extra: &[(&str, Param)]| {
+        let mut params = vec![
+            ("target", text(target)),
+            ("alternation_delimiter", text("|")),
+            ("max_distance", int(1)),
+        ];
+        params.extend_from_slice(extra);
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(check("color|colour", "color", &[]), 1);
+    assert_eq!(check("color|colour", "colour", &[]), 1);
+    assert_eq!(check("color|colour", "colours", &[]), 1);
+    assert_eq!(check("color|colour", "coloring", &[]), 0);
+    assert_eq!(check("color|colour", "color|colour", &[]), 0);
+    assert_eq!(check("color||colour|", "colour", &[]), 1);
+    assert_eq!(check("|", "x", &[]), 0);
+    assert_eq!(
+        check("color|colour", "colour", &[("negate", flag(true))]),
+        0
+    );
+    // targets takes precedence
+    assert_eq!(
+        check(
+            "color|colour",
+            "colour",
+            &[("targets", text(r#"["grey"]"#))]
+        ),
+        0
+    );
+
+    // Without the delimiter in the target, or without a delimiter at all,
+    // the target is literal
+    assert_eq!(check("color", "colour", &[]), 1);
+    let slash = [("alternation_delimiter", text("/"))];
+    assert_eq!(check("a|b", "a|b", &slash), 1);
+    let empty = [("alternation_delimiter", text(""))];
+    assert_eq!(check("color|colour", "color", &empty), 0);
+    assert_eq!(
+        run(
+            &[("target", text("color|colour")), ("max_distance", int(1))],
+            &[("name", "color")]
+        ),
+        0
+    );
+
+    // all_targets and modes apply to the alternatives
+    let all_partial = [
+        ("alternation_delimiter", text(", ")),
+        ("mode", text("partial")),
+        ("all_targets", flag(true)),
+    ];
+    assert_eq!(
+        check("Samsung, Galaxy", "Samsng Galaxy S24", &all_partial),
+        1
+    );
+    assert_eq!(check("Samsung, Galaxy", "Samsung Note", &all_partial), 0);
+
+    // A too long target still reports -1
+    assert_eq!(check(&"x".repeat(5000), "x", &[]), -1);
+
+    // similarity_vector scores each alternative
+    setup(
+        &[
+            ("target", text("color|colour")),
+            ("alternation_delimiter", text("|")),
+        ],
+        &[("name", "color")],
+    );
+    let mut out = [0f32; 4];
+    let mut len = 4;
+    assert_eq!(
+        unsafe { crate::similarity_vector(1, out.as_mut_ptr(), &mut len) },
+        2
+    );
+    assert_eq!(len, 2);
+    assert_eq!(out[0], 1.0);
+}
+
+#[test]
+fn token_prefix_matches_the_start_of_any_token() {
+    let _host = lock();
+    let check = |target: &str, field: &str, extra: &[(&str, Param)]| {
+        let mut params = vec![
+            ("target", text(target)),
+            ("mode", text("token_prefix")),
+            ("max_distance", int(1)),
+        ];
+        params.extend_from_slice(extra);
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(check("Galx", "Samsung Galaxy S24", &[]), 1);
+    assert_eq!(check("Galx", "Galxy Buds", &[]), 1);
+    assert_eq!(check("Galx", "Samsung Note", &[]), 0);
+    assert_eq!(check("Galx", "Legal Xpress", &[]), 0);
+    assert_eq!(check("Galaxyy", "Galaxy", &[]), 1);
+    assert_eq!(check("Galaxyyy", "Galaxy", &[]), 0);
+    assert_eq!(check("Samsung Gal", "Samsung Galaxy", &[]), 0);
+
+    // "g" vs "G" is the one edit
+    let exact = [("max_distance", int(0))];
+    assert_eq!(check("gal", "Samsung Galaxy", &[]), 1);
+    assert_eq!(check("gal", "Samsung Galaxy", &exact), 0);
+    assert_eq!(
+        check(
+            "gal",
+            "Samsung Galaxy",
+            &[("max_distance", int(0)), ("case_insensitive", flag(true))]
+        ),
+        1
+    );
+    assert_eq!(check("Gal", "Samsung Galaxy", &exact), 1);
+    assert_eq!(check("Sams", "Samsung Galaxy", &exact), 1);
+    assert_eq!(check("axy", "Samsung Galaxy", &exact), 0);
+
+    let dashes = [
+        ("tokenizer", text("regex")),
+        ("token_pattern", text("[^-]+")),
+        ("max_distance", int(0)),
+    ];
+    assert_eq!(check("Gal", "Samsung-Galaxy", &dashes), 1);
+    assert_eq!(check("Gal", "x", &[("tokenizer", text("bogus"))]), 0);
+
+    setup(
+        &[
+            ("target", text("Gal")),
+            ("mode", text("token_prefix")),
+            ("max_distance", int(1)),
+        ],
+        &[("name", "Samsung Galaxy")],
+    );
+    let mut exact = 0;
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+}
+
+#[test]
+fn token_prefix_distance_agrees_with_every_prefix() {
+    use crate::modes::token_prefix_distance;
+    use crate::token::Tokenizer;
+    let words = random_strings(155, 600, &['a', 'b', ' '], 12);
+    for pair in words.chunks(2) {
+        let (field, target) = (&pair[0], pair[1].replace(' ', ""));
+        if target.is_empty() {
+            continue;
+        }
+        let expected = field
+            .split_whitespace()
+            .flat_map(|token| {
+                let chars: Vec<char> = token.chars().collect();
+                (0..=chars.len()).map(move |k| chars[..k].iter().collect::<String>())
+            })
+            .map(|prefix| naive_levenshtein(&prefix, &target))
+            .min()
+            .unwrap_or(usize::MAX);
+        for bound in 0..4 {
+            let distance = token_prefix_distance(field, &target, &Tokenizer::Whitespace, bound);
+            assert_eq!(
+                distance <= bound,
+                expected <= bound,
+                "{field:?} {target:?} {bound} {distance} {expected}"
+            );
+            if expected <= bound {
+                assert_eq!(distance, expected, "{field:?} {target:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn self_test_reports_misbehaving_imports() {
+    use crate::self_test;
+    let _host = lock();
+    let params = [
+        ("self_test_string", text("quidditch")),
+        ("self_test_i64", int(42)),
+        ("self_test_f64", float(0.5)),
+        ("self_test_bool", flag(true)),
+    ];
+    let document = [("self_test", "quidditch")];
+    setup(&params, &document);
+    assert_eq!(self_test(1), 0);
+
+    // Missing inputs show up per import: the params, or the field
+    let f64_bit = if cfg!(feature = "f64_params") { 32 } else { 0 };
+    setup(&[], &document);
+    assert_eq!(self_test(1), 8 | 16 | f64_bit | 64);
+    let stream_bit = if cfg!(feature = "stream") { 4 } else { 0 };
+    setup(&params, &[]);
+    assert_eq!(self_test(1), 1 | 2 | stream_bit);
+    setup(&params, &[("self_test", "quidditcH")]);
+    assert_eq!(self_test(1), 2);
+
+    let mut wrong = params.to_vec();
+    wrong[1] = ("self_test_i64", int(41));
+    setup(&wrong, &document);
+    assert_eq!(self_test(1), 16);
+}
+
+#[test]
+fn class_budgets_reject_an_extra_digit_edit() {
+    let _host = lock();
+    let check = |field: &str, digits: Option<i64>, letters: Option<i64>, max_distance: i64| {
+        let mut params = vec![
+            ("target", text("SKU-4471B")),
+            ("max_distance", int(max_distance)),
+        ];
+        params.extend(digits.map(|max| ("max_digit_edits", int(max))));
+        params.extend(letters.map(|max| ("max_letter_edits", int(max))));
+        run(&params, &[("name", field)])
+    };
+    // Two letter edits and one digit edit, then one digit edit too many
+    assert_eq!(check("SKV-4472C", Some(1), None, 3), 1);
+    assert_eq!(check("SKV-4482B", Some(1), None, 3), 0);
+    assert_eq!(check("SKV-4482B", None, None, 3), 1);
+    assert_eq!(check("SKV-4471C", Some(0), Some(1), 3), 0);
+    assert_eq!(check("SKV-4471C", Some(0), Some(2), 3), 1);
+    // Punctuation only counts towards max_distance, "O" for "0" is both
+    assert_eq!(check("SKU 4471B", Some(0), Some(0), 1), 1);
+    assert_eq!(check("SKU-O471B", Some(0), None, 2), 0);
+
+    // Equal lengths compare position by position
+    let params = [
+        ("target", text("AB12")),
+        ("max_distance", int(2)),
+        ("max_digit_edits", int(0)),
+        ("require_equal_length", flag(true)),
+    ];
+    assert_eq!(run(&params, &[("name", "XB12")]), 1);
+    assert_eq!(run(&params, &[("name", "AB13")]), 0);
+}
+
+#[test]
+fn class_budgets_agree_with_every_alignment() {
+    use crate::classes::ClassBudgets;
+    // Whether any alignment of the rests fits the budgets, having spent
+    // `spent` (digit, letter, total) edits
+    fn brute(
+        field: &[char],
+        target: &[char],
+        budgets: ClassBudgets,
+        max: usize,
+        spent: (usize, usize, usize),
+    ) -> bool {
+        let (digits, letters, total) = spent;
+        if total > max
+            || budgets.digits.is_some_and(|max| digits > max)
+            || budgets.letters.is_some_and(|max| letters > max)
+        {
+            return false;
+        }
+        if field.is_empty() && target.is_empty() {
+            return true;
+        }
+        let digit = |c: char| c.is_numeric() as usize;
+        let letter = |c: char| c.is_alphabetic() as usize;
+        if let (Some(&f), Some(&t)) = (field.first(), target.first()) {
+            let spent = if f == t {
+                spent
+            } else {
+                (
+                    digits + (digit(f) | digit(t)),
+                    letters + (letter(f) | letter(t)),
+                    total + 1,
+                )
+            };
+            if brute(&field[1..], &target[1..], budgets, max, spent) {
+                return true;
+            }
+        }
+        if let Some(&f) = field.first() {
+            let spent = (digits + digit(f), letters + letter(f), total + 1);
+            if brute(&field[1..], target, budgets, max, spent) {
+                return true;
+            }
+        }
+        if let Some(&t) = target.first() {
+            let spent = (digits + digit(t), letters + letter(t), total + 1);
+            if brute(field, &target[1..], budgets, max, spent) {
+                return true;
+            }
+        }
+        false
+    }
+
+    let pool = random_strings(157, 600, &['a', 'b', '1', '2', '-'], 5);
+    for pair in pool.chunks(2) {
+        let (field, target) = (&pair[0], &pair[1]);
+        let field_chars: Vec<char> = field.chars().collect();
+        let target_chars: Vec<char> = target.chars().collect();
+        for (digits, letters) in [
+            (Some(0), None),
+            (Some(1), Some(0)),
+            (None, Some(1)),
+            (Some(1), Some(2)),
+            (Some(2), Some(2)),
+        ] {
+            let budgets = ClassBudgets { digits, letters };
+            for max in 0..5 {
+                assert_eq!(
+                    budgets.allow(field, target, max),
+                    brute(&field_chars, &target_chars, budgets, max, (0, 0, 0)),
+                    "{field:?} {target:?} {budgets:?} {max}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "phonetic")]
+fn double_metaphone_codes_common_names() {
+    use crate::phonetic::double_metaphone;
+    let cases = [
+        ("Smith", "SM0", "XMT"),
+        ("Schmidt", "XMT", "SMT"),
+        ("Thompson", "TMPS", "TMPS"),
+        ("Catherine", "K0RN", "KTRN"),
+        ("Kathryn", "K0RN", "KTRN"),
+        ("Jose", "HS", "HS"),
+        ("Michael", "MKL", "MXL"),
+        ("Xavier", "SF", "SFR"),
+        ("Gough", "KF", "KF"),
+        ("Knight", "NT", "NT"),
+        ("Wright", "RT", "RT"),
+        ("Philip", "FLP", "FLP"),
+        ("Caesar", "SSR", "SSR"),
+        ("Edgar", "ATKR", "ATKR"),
+        ("Zhao", "J", "J"),
+        ("Cabrillo", "KPRL", "KPR"),
+        ("Czerny", "SRN", "XRN"),
+        ("Jankelowicz", "JNKL", "ANKL"),
+        ("Sugar", "XKR", "SKR"),
+        ("Horowitz", "HRTS", "HRFX"),
+        ("Arnow", "ARN", "ARNF"),
+    ];
+    for (name, primary, alternate) in cases {
+        let codes = double_metaphone(name);
+        assert_eq!(
+            (codes.primary.as_str(), codes.alternate.as_str()),
+            (primary, alternate),
+            "{name}"
+        );
+    }
+    // Tokens agree in any order
+    #[cfg(feature = "ngram")]
+    assert_eq!(
+        crate::phonetic::phonetic_similarity("Jon Smith", "Smyth John"),
+        1.0
+    );
+}
+
+#[test]
+#[cfg(all(feature = "phonetic", feature = "ngram"))]
+fn name_match_blends_sound_and_spelling() {
+    let _host = lock();
+    let check = |field: &str, target: &str, min_similarity: &str, extra: &[(&str, Param)]| {
+        let mut params = vec![
+            ("target", text(target)),
+            ("algorithm", text("name_match")),
+            ("min_similarity", text(min_similarity)),
+        ];
+        params.extend_from_slice(extra);
+        run(&params, &[("name", field)])
+    };
+
+    // Sound rescues a low lexical score
+    let bigrams = [
+        ("target", text("Catherine")),
+        ("algorithm", text("ngram")),
+        ("ngram_size", int(2)),
+        ("pad_ngrams", flag(true)),
+        ("min_similarity", text("0.55")),
+    ];
+    assert_eq!(run(&bigrams, &[("name", "Kathryn")]), 0);
+    assert_eq!(check("Kathryn", "Catherine", "0.55", &[]), 1);
+
+    // Spelling rescues a phonetic disagreement
+    assert_eq!(
+        crate::phonetic::phonetic_similarity("Gonzales", "Xonzales"),
+        0.0
+    );
+    assert_eq!(check("Xonzales", "Gonzales", "0.35", &[]), 1);
+    assert_eq!(
+        check(
+            "Xonzales",
+            "Gonzales",
+            "0.35",
+            &[("ngram_weight", text("0"))]
+        ),
+        0
+    );
+    assert_eq!(check("Brown", "Gonzales", "0.2", &[]), 0);
+
+    // Weights that don't weigh anything match nothing
+    let zero = [("phonetic_weight", text("0")), ("ngram_weight", text("0"))];
+    assert_eq!(check("Smith", "Smith", "0.5", &zero), 0);
+    assert_eq!(
+        check("Smyth", "Smith", "0.5", &[("phonetic_weight", text("-1"))]),
+        0
+    );
+}
+
+#[test]
+fn variant_cost_ranks_accent_variants_below_exact_matches() {
+    use crate::weighted::{weighted_levenshtein, Costs};
+    let costs = Costs {
+        variant: Some(0.1),
+        ..Costs::default()
+    };
+    let close = |a: &str, b: &str, costs: &Costs, expected: f64| {
+        let distance = weighted_levenshtein(a, b, costs, 10.0);
+        assert!((distance - expected).abs() < 1e-9, "{a} {b} {distance}");
+    };
+    close("cafe", "café", &costs, 0.1);
+    close("café", "café", &costs, 0.0);
+    close("CAFE", "café", &costs, 0.4);
+    close("cafa", "café", &costs, 1.0);
+    // case_mismatch_cost wins for a pure case difference
+    let costs = Costs {
+        case_mismatch: Some(0.25),
+        variant: Some(0.1),
+        ..Costs::default()
+    };
+    close("Café", "café", &costs, 0.25);
+    close("CafÉ", "cafe", &costs, 0.35);
+
+    let _host = lock();
+    let params = |max_distance: i64| {
+        vec![
+            ("target", text("café")),
+            ("variant_cost", text("0.1")),
+            ("max_distance", int(max_distance)),
+        ]
+    };
+    assert_eq!(run(&params(0), &[("name", "café")]), 1);
+    assert_eq!(run(&params(0), &[("name", "cafe")]), 0);
+    assert_eq!(run(&params(1), &[("name", "cafe")]), 1);
+    assert_eq!(run(&params(1), &[("name", "cafo")]), 1);
+    assert_eq!(run(&params(1), &[("name", "cafoo")]), 0);
+    let negative = [("target", text("café")), ("variant_cost", text("-0.1"))];
+    assert_eq!(run(&negative, &[("name", "café")]), 0);
+}
+
+#[test]
+#[cfg(feature = "ngram")]
+fn rolling_ngram_hashes_agree_with_collected_ngrams() {
+    use crate::ngram::collected_dice;
+    let _host = lock();
+    // The highest char and the padding sentinel itself included
+    let alphabet = ['a', 'b', 'c', 'é', '\u{10FFFF}', ' ', '\u{0}'];
+    let words = random_strings(160, 2000, &alphabet, 12);
+    for pair in words.chunks(2) {
+        let (field, target) = (&pair[0], &pair[1]);
+        for n in 1..8 {
+            for pad in [false, true] {
+                assert_eq!(
+                    crate::target_dice_similarity(field, target, n, pad, None),
+                    collected_dice(field, target, n, pad),
+                    "{field:?} {target:?} {n} {pad}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "ngram")]
+fn rolling_ngram_hashes_are_faster_than_collected_ngrams() {
+    use crate::ngram::{collected_dice, TargetGrams};
+    use std::time::{Duration, Instant};
+    let _host = lock();
+
+    // A query's documents against one target, as in BenchmarkStringDistanceNgram
+    let alphabet: Vec<char> = ('a'..='z').chain([' ', 'é']).collect();
+    let fields = random_strings(200, 200, &alphabet, 200);
+    let target = "pack my box with five dozen liquor jugs ".repeat(4);
+    // The fastest of a few rounds, so a busy machine doesn't decide
+    let fastest = |score: &mut dyn FnMut(&str) -> f64| {
+        (0..5)
+            .map(|_| {
+                let start = Instant::now();
+                let total: f64 = fields.iter().map(|field| score(field)).sum();
+                assert!(total >= 0.0);
+                start.elapsed()
+            })
+            .min()
+            .unwrap_or(Duration::ZERO)
+    };
+
+    for n in [3, 5] {
+        let mut grams = TargetGrams::new();
+        let rolling = fastest(&mut |field| grams.dice_similarity(field, &target, n, false, None));
+        let collected = fastest(&mut |field| collected_dice(field, &target, n, false));
+        assert!(
+            rolling < collected,
+            "n={n}: {rolling:?} rolling, {collected:?} collected"
+        );
+    }
+}
+
+#[test]
+#[cfg(feature = "jaro")]
+fn distance_or_similarity_accepts_either_condition() {
+    use crate::jaro::jaro_winkler_similarity;
+    let _host = lock();
+    let params = |extra: &[(&'static str, Param)]| {
+        let mut params = vec![
+            ("target", text("Johnson")),
+            ("max_distance", int(1)),
+            ("min_similarity", text("0.9")),
+            ("match_policy", text("distance_or_similarity")),
+        ];
+        params.extend_from_slice(extra);
+        params
+    };
+    // One edit away, then two edits away but similar enough
+    assert_eq!(run(&params(&[]), &[("name", "Jonson")]), 1);
+    assert!(jaro_winkler_similarity("Jhonsonn", "Johnson") >= 0.9);
+    assert_eq!(run(&params(&[]), &[("name", "Jhonsonn")]), 1);
+    assert_eq!(run(&params(&[]), &[("name", "Jo")]), 0);
+
+    // Within the distance though not similar enough
+    assert!(jaro_winkler_similarity("xb", "ab") < 0.99);
+    let short = [
+        ("target", text("ab")),
+        ("max_distance", int(1)),
+        ("min_similarity", text("0.99")),
+        ("match_policy", text("distance_or_similarity")),
+    ];
+    assert_eq!(run(&short, &[("name", "xb")]), 1);
+
+    // Without the policy Levenshtein decides alone
+    assert_eq!(run(&params(&[])[..3], &[("name", "Jhonsonn")]), 0);
+
+    // The policy overrides the algorithm, with a warning
+    assert_eq!(
+        run(
+            &params(&[("algorithm", text("jaro"))]),
+            &[("name", "Jhonsonn")]
+        ),
+        1
+    );
+    assert!(LOGS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|log| log.contains("algorithm ignored")));
+
+    // A later entry replaces the policy
+    assert_eq!(
+        run(
+            &params(&[("match_policy", text("nope"))]),
+            &[("name", "Johnson")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &params(&[("match_policy", text("algorithm"))]),
+            &[("name", "Johnson")]
+        ),
+        1
+    );
+}
+
+#[test]
+fn redacted_logs_carry_no_operand_contents() {
+    use crate::{length_normalized_confidence, ratio, similarity_key};
+    let _host = lock();
+    let (target, field) = ("40417", "Zq8-40417-Zq8");
+    // A target masked away is quoted in the warning, unless redacted
+    run(
+        &[("target", text(target)), ("mask_digits", flag(true))],
+        &[("name", field)],
+    );
+    assert!(LOGS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|log| log.contains("\"40417\"")));
+
+    let settings: Vec<Vec<(&str, Param)>> = vec![
+        vec![("mask_digits", flag(true))],
+        vec![
+            ("mask_digits", flag(true)),
+            ("targets", text(r#"["40417", "555"]"#)),
+        ],
+        vec![("max_distance", int(1)), ("min_similarity", text("0.5"))],
+        vec![("min_similarity", text("0.5"))],
+        vec![("threshold", text("0.5"))],
+        vec![("mode", text("phone")), ("case_insensitive", flag(true))],
+        vec![("unknown_char_policy", text("bogus"))],
+        vec![("max_target_len", int(2))],
+        vec![("mode", text("set_pairing"))],
+        vec![
+            ("match_policy", text("distance_or_similarity")),
+            ("algorithm", text("jaro")),
+        ],
+        vec![("stream_field", flag(true)), ("profile", flag(true))],
+        vec![("profile", flag(true))],
+    ];
+    for setting in settings {
+        let mut params = vec![("target", text(target)), ("redact_logs", flag(true))];
+        params.extend_from_slice(&setting);
+        run(&params, &[("name", field)]);
+        ratio(1);
+        similarity_key(1);
+        length_normalized_confidence(1);
+        for log in LOGS.lock().unwrap().iter() {
+            assert!(
+                !log.contains(target) && !log.contains("Zq8"),
+                "{setting:?}: {log}"
+            );
+        }
+    }
+}
+
+#[test]
+fn per_line_matches_the_closest_line() {
+    let _host = lock();
+    let params = |max_distance: i64| {
+        vec![
+            ("target", text("Springfield, IL 62704")),
+            ("mode", text("per_line")),
+            ("max_distance", int(max_distance)),
+        ]
+    };
+    let address = "Jane Doe\n742 Evergreen Terrace\nSpringfeld, IL 62704\n\n  \n";
+    assert_eq!(run(&params(2), &[("name", address)]), 1);
+    assert_eq!(run(&params(0), &[("name", address)]), 0);
+    let crlf = "Jane Doe\r\n742 Evergreen Terrace\r\nSpringfield, IL 62704";
+    assert_eq!(run(&params(2), &[("name", crlf)]), 1);
+    let other = "Jane Doe\n742 Evergreen Terrace\nShelbyville, IL 62565";
+    assert_eq!(run(&params(2), &[("name", other)]), 0);
+    assert_eq!(run(&params(2), &[("name", "\n\n")]), 0);
+    // The field as a whole is far from the target
+    let whole = [
+        ("target", text("Springfield, IL 62704")),
+        ("max_distance", int(2)),
+    ];
+    assert_eq!(run(&whole, &[("name", address)]), 0);
+
+    // collapse_whitespace applies to each line
+    let mut collapsed = params(0);
+    collapsed.push(("collapse_whitespace", flag(true)));
+    assert_eq!(
+        run(&collapsed, &[("name", "a\n  Springfield,   IL 62704  \n")]),
+        1
+    );
+
+    // The closest line decides, not the first one within the bound
+    let mut exact = 0;
+    setup(
+        &params(3),
+        &[("name", "Springfeld, IL 6270\nSpringfield, IL 62704")],
+    );
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+}
+
+#[test]
+fn field_is_fetched_once_per_document() {
+    use crate::filter;
+    use host::{set_params, FIELD_CALLS};
+    let _host = lock();
+    let fetches = || *FIELD_CALLS.lock().unwrap();
+    let folded = |target: &str, mode: Option<&str>, max_distance: i64| {
+        let mut params = vec![
+            ("target", text(target)),
+            ("case_insensitive", flag(true)),
+            ("ignore_accents", flag(true)),
+            ("max_distance", int(max_distance)),
+        ];
+        params.extend(mode.map(|mode| ("mode", text(mode))));
+        params
+    };
+    setup(
+        &folded("crème brûlée", None, 1),
+        &[("name", "CRÈME BRULEE")],
+    );
+    assert_eq!(filter(7), 1);
+    assert_eq!(filter(7), 1);
+    assert_eq!(fetches(), 1);
+
+    // Another filter on the same document, with the same and other settings
+    set_params(&folded("creme", Some("partial"), 0));
+    assert_eq!(filter(7), 1);
+    set_params(&[
+        ("target", text("CREME")),
+        ("mode", text("partial")),
+        ("max_distance", int(0)),
+    ]);
+    assert_eq!(filter(10), 0);
+    let calls = fetches();
+    assert_eq!(calls, 1);
+
+    // Another document is fetched again, under any context id
+    setup(&folded("crème brûlée", None, 1), &[("name", "CREME")]);
+    assert_eq!(filter(10), 0);
+    let calls = fetches();
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn pattern_mode_counts_class_violations() {
+    let _host = lock();
+    let params = |pattern: &str, max_distance: i64| {
+        vec![
+            ("target", text(pattern)),
+            ("mode", text("pattern")),
+            ("max_distance", int(max_distance)),
+        ]
+    };
+    let mut exact = 0;
+    setup(&params("L####", 0), &[("name", "A1234")]);
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+    // One violation, then two
+    assert_eq!(run(&params("L####", 1), &[("name", "AB234")]), 1);
+    assert_eq!(run(&params("L####", 0), &[("name", "AB234")]), 0);
+    assert_eq!(run(&params("L####", 1), &[("name", "ABC34")]), 0);
+    assert_eq!(run(&params("L####", 1), &[("name", "A123")]), 1);
+    assert_eq!(run(&params("L####", 1), &[("name", "12E4")]), 0);
+    // The pattern chars don't match themselves, other chars do
+    assert_eq!(run(&params("L####", 0), &[("name", "L####")]), 0);
+    assert_eq!(run(&params("SKU-####", 0), &[("name", "SKU-0042")]), 1);
+    assert_eq!(run(&params("SKU-####", 0), &[("name", "SKU_0042")]), 0);
+    assert_eq!(run(&params("??#", 0), &[("name", "-x9")]), 1);
+
+    // Only the field is normalized
+    let mut collapsed = params("L####", 0);
+    collapsed.push(("collapse_whitespace", flag(true)));
+    assert_eq!(run(&collapsed, &[("name", "  A1234 ")]), 1);
+
+    // Past the bound the distance is only known to be over it
+    assert_eq!(crate::modes::pattern_distance("ABCDEFGH", "####", 1), 2);
+}
+
+#[test]
+#[cfg(feature = "jaro")]
+fn min_margin_rejects_near_tied_targets() {
+    use crate::filter_ex;
+    let _host = lock();
+    let categories = |min_margin: &str| {
+        vec![
+            (
+                "targets",
+                text(r#"["electronics", "electronic", "appliances"]"#),
+            ),
+            ("algorithm", text("jaro_winkler")),
+            ("min_similarity", text("0.85")),
+            ("min_margin", text(min_margin)),
+        ]
+    };
+    // A clear winner matches, two close targets are ambiguous
+    let mut exact = 1;
+    setup(&categories("0.05"), &[("name", "appliance")]);
+    assert_eq!(unsafe { filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 0);
+    assert_eq!(run(&categories("0.05"), &[("name", "electronics")]), 0);
+    setup(&categories("0"), &[("name", "electronics")]);
+    assert_eq!(unsafe { filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+    // The winner still has to pass the threshold
+    assert_eq!(run(&categories("0.05"), &[("name", "furniture")]), 0);
+    // Without min_margin either close target matches
+    let close = [
+        ("targets", text(r#"["electronics", "electronic"]"#)),
+        ("algorithm", text("jaro_winkler")),
+    ];
+    assert_eq!(run(&close, &[("name", "electronics")]), 1);
+
+    // Ranked by Levenshtein similarity: "colr" is 1 edit from either
+    let shapes = |min_margin: &str| {
+        vec![
+            ("targets", text(r#"["color", "colr2", "shape"]"#)),
+            ("max_distance", int(1)),
+            ("min_margin", text(min_margin)),
+        ]
+    };
+    assert_eq!(run(&shapes("0.1"), &[("name", "colr")]), 0);
+    assert_eq!(run(&shapes("0.1"), &[("name", "shap")]), 1);
+    assert_eq!(run(&shapes("-1"), &[("name", "shap")]), 0);
+    assert_eq!(run(&shapes("0.1"), &[("other", "shap")]), 0);
+    let single = [
+        ("targets", text(r#"["shape"]"#)),
+        ("min_margin", text("0.5")),
+    ];
+    assert_eq!(run(&single, &[("name", "shap")]), 1);
+    let mut negated = shapes("0.1");
+    negated.push(("negate", flag(true)));
+    assert_eq!(run(&negated, &[("name", "colr")]), 1);
+}
+
+#[test]
+fn filter_tier_is_the_smallest_matching_distance() {
+    use crate::filter_tier;
+    let _host = lock();
+    let tier = |params: &[(&str, Param)], field: &str| {
+        setup(params, &[("name", field)]);
+        filter_tier(1)
+    };
+    let iphone = |max_distance: i64| {
+        vec![
+            ("target", text("iPhone")),
+            ("max_distance", int(max_distance)),
+        ]
+    };
+    assert_eq!(tier(&iphone(2), "iPhone"), 0);
+    assert_eq!(tier(&iphone(2), "iPhne"), 1);
+    assert_eq!(tier(&iphone(2), "iPhoen"), 2);
+    assert_eq!(tier(&iphone(2), "Android"), -1);
+    assert_eq!(tier(&iphone(-1), "iPhone"), -1);
+    assert!(LOGS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|log| log.contains("is negative")));
+    let mut folded = iphone(0);
+    folded.push(("case_insensitive", flag(true)));
+    assert_eq!(tier(&folded, "IPHONE"), 0);
+    setup(&iphone(1), &[("other", "iPhone")]);
+    assert_eq!(filter_tier(1), -1);
+
+    // The tier is the true distance whenever that is within max_distance
+    let words = random_strings(7, 120, &['a', 'b', 'c', 'é'], 8);
+    for pair in words.chunks(2) {
+        let (field, target) = (&pair[0], &pair[1]);
+        if field.is_empty() || target.is_empty() {
+            // An empty operand reads as missing
+            continue;
+        }
+        let distance = naive_levenshtein(field, target);
+        for max_distance in [0, 1, 2, 3, 5] {
+            let params = [
+                ("target", text(target)),
+                ("max_distance", int(max_distance as i64)),
+            ];
+            let expected = if distance <= max_distance {
+                distance as i32
+            } else {
+                -1
+            };
+            assert_eq!(
+                tier(&params, field),
+                expected,
+                "{field:?} {target:?} {max_distance}"
+            );
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "ngram")]
+fn trigram_coverage_allows_max_missing_trigrams() {
+    let _host = lock();
+    let coverage = |target: &str, max_missing: Option<i64>| {
+        let mut params = vec![
+            ("target", text(target)),
+            ("algorithm", text("trigram_coverage")),
+        ];
+        params.extend(max_missing.map(|max| ("max_missing", int(max))));
+        params
+    };
+    // "l" for "I" loses 2 trigrams, "1" for "I" in the middle loses 3
+    let invoice = |max_missing| coverage("INVOICE TOTAL", Some(max_missing));
+    assert_eq!(run(&invoice(2), &[("name", "lNVOICE TOTAL 42.00")]), 1);
+    assert_eq!(run(&invoice(0), &[("name", "lNVOICE TOTAL 42.00")]), 0);
+    assert_eq!(run(&invoice(3), &[("name", "INVO1CE TOTAL")]), 1);
+    assert_eq!(run(&invoice(2), &[("name", "INVO1CE TOTAL")]), 0);
+    assert_eq!(run(&invoice(0), &[("name", "page 1 INVOICE TOTAL: 42")]), 1);
+    assert_eq!(run(&invoice(0), &[("name", "INVOICE TOTAL")]), 1);
+
+    // The field has to cover the target, not the other way around
+    assert_eq!(
+        run(&coverage("TOTAL", None), &[("name", "INVOICE TOTAL")]),
+        1
+    );
+    assert_eq!(
+        run(&coverage("INVOICE TOTAL", None), &[("name", "TOTAL")]),
+        0
+    );
+    // A trigram the target has twice is needed twice
+    assert_eq!(run(&coverage("aaaa", Some(0)), &[("name", "aaa")]), 0);
+    assert_eq!(run(&coverage("aaaa", Some(1)), &[("name", "aaa")]), 1);
+    // Unpadded, a target shorter than a trigram has none to miss
+    assert_eq!(run(&coverage("ab", None), &[("name", "xyz")]), 1);
+    let mut padded = coverage("ab", None);
+    padded.push(("pad_ngrams", flag(true)));
+    assert_eq!(run(&padded, &[("name", "xyz")]), 0);
+
+    // max_distance doesn't apply and isn't an error
+    let mut distance = invoice(2);
+    distance.push(("max_distance", int(0)));
+    assert_eq!(run(&distance, &[("name", "lNVOICE TOTAL 42.00")]), 1);
+    // The similarity is the share of the target's trigrams covered
+    setup(&invoice(2), &[("name", "INVO1CE TOTAL")]);
+    assert_eq!(ratio(1), (100.0 * 8.0 / 11.0f64).round() as i32);
+}
+
+#[test]
+fn broken_host_contracts_return_the_error_value() {
+    use host::MISBEHAVE;
+    let _host = lock();
+    let misbehave = |import| *MISBEHAVE.lock().unwrap() = import;
+    let params = [("target", text("iPhone")), ("max_distance", int(1))];
+    let fields = [("name", "iPhone")];
+    let broken: &[(&str, &str, i32, i32)] = &[
+        ("get_field_string", "name", 1, 1 << 20),
+        ("get_field_string", "name", 1, -5),
+        ("get_field_string", "name", -1, 3),
+        ("get_field_string", "name", 2, 0),
+        ("get_document_id", "", 1, 1 << 20),
+        ("get_document_id", "", 4, 0),
+        ("has_field", "name", -1, 0),
+        ("has_field", "name", 2, 0),
+        ("get_param_string", "target", 0, 100_000),
+        ("get_param_string", "target", 7, 6),
+        ("get_param_string", "target", 3, 10),
+        ("get_param_string", "config", 0, 1 << 24),
+        ("get_param_string", "config", 3, -1),
+        ("get_param_i64", "max_distance", -3, 1),
+        ("get_param_bool", "", -1, 0),
+    ];
+    for &import in broken {
+        setup(&params, &fields);
+        misbehave(Some(import));
+        assert_eq!(crate::filter(1), -2, "{:?}", import);
+        let logs = LOGS.lock().unwrap().clone();
+        assert!(logs.iter().any(|log| log.contains(import.0)), "{:?}", logs);
+
+        let mut exact = 7;
+        setup(&params, &fields);
+        misbehave(Some(import));
+        let result = unsafe { crate::filter_ex(1, &mut exact) };
+        assert_eq!((result, exact), (-2, 0), "{:?}", import);
+    }
+
+    // The documented codes aren't violations
+    let documented: &[(&str, &str, i32, i32)] = &[
+        ("get_field_string", "name", 0, 0),
+        ("get_field_string", "name", 1, 0),
+        ("get_document_id", "", 0, 0),
+        ("has_field", "name", 0, 0),
+        ("get_param_i64", "max_distance", 2, 0),
+        ("get_param_string", "target", 1, 0),
+        ("get_param_string", "mode", 3, 100),
+    ];
+    for &import in documented {
+        setup(&params, &fields);
+        misbehave(Some(import));
+        let result = crate::filter(1);
+        assert!(result == 0 || result == 1, "{:?} {}", import, result);
+    }
+
+    // A violation doesn't carry over to the next call
+    setup(&params, &fields);
+    misbehave(Some(("get_field_string", "name", 1, 1 << 20)));
+    assert_eq!(crate::filter(1), -2);
+    misbehave(None);
+    assert_eq!(crate::filter(1), 1);
+
+    setup(&params, &fields);
+    misbehave(Some(("get_field_string", "name", 1, -1)));
+    assert_eq!(ratio(1), -1);
+    assert_eq!(crate::similarity_key(1), -1);
+    assert_eq!(crate::length_normalized_confidence(1), -1.0);
+    assert_eq!(crate::filter_tier(1), -2);
+    assert_eq!(crate::filter_sorted(1, 0), -2);
+    let (mut start, mut end) = (5, 5);
+    assert_eq!(unsafe { partial_match_span(1, &mut start, &mut end) }, -2);
+    assert_eq!((start, end), (-1, -1));
+
+    if cfg!(feature = "stream") {
+        let streamed = [("target", text("iPhone")), ("stream_field", flag(true))];
+        for (code, len, expected) in [(0, 1 << 20, -2), (-4, 0, -2), (3, 0, 0)] {
+            setup(&streamed, &fields);
+            misbehave(Some(("get_field_chunk", "name", code, len)));
+            assert_eq!(crate::filter(1), expected, "{} {}", code, len);
+        }
+        setup(&streamed, &fields);
+        assert_eq!(crate::filter(1), 1);
+    }
+}
+
+#[test]
+fn missing_field_never_reads_a_stale_buffer() {
+    use host::MISBEHAVE;
+    let _host = lock();
+    let params = [("target", text("iPhone")), ("max_distance", int(0))];
+    assert_eq!(run(&params, &[("name", "iPhone")]), 1);
+
+    // 0 is "not found", whatever length comes with it and whatever the
+    // previous document left in the buffer
+    setup(&params, &[("name", "iPhone")]);
+    *MISBEHAVE.lock().unwrap() = Some(("get_field_string", "name", 0, 6));
+    assert_eq!(crate::filter(1), 0);
+    assert!(LOGS.lock().unwrap().is_empty());
+
+    // Negated, a missing field is kept where the stale match would not be
+    let params = [("target", text("iPhone")), ("negate", flag(true))];
+    setup(&params, &[("name", "iPhone")]);
+    *MISBEHAVE.lock().unwrap() = Some(("get_field_string", "name", 0, 6));
+    assert_eq!(crate::filter(1), 1);
+}
+
+#[test]
+fn field_extract_pattern_compares_the_first_capture() {
+    let _host = lock();
+    let params = |pattern: &str| {
+        [
+            ("target", text("ABC-123")),
+            ("field_extract_pattern", text(pattern)),
+            ("max_distance", int(1)),
+        ]
+    };
+    let mut exact = 0;
+    setup(
+        &params(r"SKU: ?([A-Z]+-\d+)"),
+        &[("name", "SKU: ABC-123 (blue)")],
+    );
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+    assert_eq!(
+        run(&params(r"SKU: ?([A-Z]+-\d+)"), &[("name", "SKU:ABC-124")]),
+        1
+    );
+
+    // Without a group the whole match is compared, and a non-capturing
+    // group doesn't count as the first
+    assert_eq!(
+        run(&params(r"[A-Z]+-\d+"), &[("name", "item ABC-123 x")]),
+        1
+    );
+    assert_eq!(
+        run(
+            &params(r"(?:SKU|ID): (\w+-\d+)"),
+            &[("name", "ID: ABC-123")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(&params(r"(\w+): (\w+-\d+)"), &[("name", "SKU: ABC-123")]),
+        0
+    );
+
+    // The pattern applies after the char cut
+    let mut cut = params(r"[A-Z]+-\d+").to_vec();
+    cut.push(("field_char_start", int(4)));
+    assert_eq!(run(&cut, &[("name", "XYZ-999 ABC-123")]), 1);
+
+    use crate::regex::Regex;
+    assert_eq!(
+        Regex::new(r"a(b*)c").unwrap().extract("xxabbbc"),
+        Some("bbb")
+    );
+    assert_eq!(Regex::new(r"(\d)+").unwrap().extract("ab 123 c"), Some("3"));
+    assert_eq!(Regex::new(r"é(ü+)").unwrap().extract("aéüüb"), Some("üü"));
+}
+
+#[test]
+fn field_extract_pattern_without_a_match_is_a_missing_field() {
+    let _host = lock();
+    let params = |pattern: &str| {
+        vec![
+            ("target", text("ABC-123")),
+            ("field_extract_pattern", text(pattern)),
+        ]
+    };
+    assert_eq!(
+        run(&params(r"SKU: ([A-Z]+-\d+)"), &[("name", "ABC-123")]),
+        0
+    );
+    let mut negated = params(r"SKU: ([A-Z]+-\d+)");
+    negated.push(("negate", flag(true)));
+    assert_eq!(run(&negated, &[("name", "ABC-123")]), 1);
+
+    // Malformed or too complex patterns match nothing
+    assert_eq!(run(&params("(abc"), &[("name", "ABC-123")]), 0);
+    let logs = LOGS.lock().unwrap().clone();
+    assert!(logs.iter().any(|log| log.contains("field_extract_pattern")));
+    assert_eq!(run(&params("a{99}"), &[("name", "ABC-123")]), 0);
+}
+
+#[test]
+#[cfg(feature = "jaro")]
+fn short_targets_match_exactly_only() {
+    let _host = lock();
+    let params = |target: &str| {
+        vec![
+            ("target", text(target)),
+            ("max_distance", int(2)),
+            ("min_target_len_for_fuzzy", int(4)),
+        ]
+    };
+    assert_eq!(run(&params("TV"), &[("name", "TV")]), 1);
+    assert_eq!(run(&params("TV"), &[("name", "TB")]), 0);
+    let logs = LOGS.lock().unwrap().clone();
+    assert!(logs
+        .iter()
+        .any(|log| log.contains("min_target_len_for_fuzzy")));
+    assert_eq!(run(&params("abc"), &[("name", "abx")]), 0);
+    assert_eq!(
+        run(
+            &[("target", text("TV")), ("max_distance", int(2))],
+            &[("name", "PC")]
+        ),
+        1
+    );
+
+    // Targets at the length keep their edits
+    assert_eq!(run(&params("abcd"), &[("name", "abxd")]), 1);
+    assert_eq!(run(&params("iPhone"), &[("name", "iPhne")]), 1);
+    let logs = LOGS.lock().unwrap().clone();
+    assert!(!logs
+        .iter()
+        .any(|log| log.contains("min_target_len_for_fuzzy")));
+
+    // Similarity algorithms need 1.0
+    let jaro_winkler = |target: &str, field: &str| {
+        run(
+            &[
+                ("target", text(target)),
+                ("algorithm", text("jaro_winkler")),
+                ("min_similarity", text("0.5")),
+                ("min_target_len_for_fuzzy", int(4)),
+            ],
+            &[("name", field)],
+        )
+    };
+    assert_eq!(jaro_winkler("TV", "TVs"), 0);
+    assert_eq!(jaro_winkler("TV", "TV"), 1);
+    assert_eq!(jaro_winkler("TVset", "TVsets"), 1);
+
+    // Each of several targets on its own length
+    let targets = [
+        ("targets", text(r#"["TV", "Radio"]"#)),
+        ("max_distance", int(1)),
+        ("min_target_len_for_fuzzy", int(3)),
+    ];
+    assert_eq!(run(&targets, &[("name", "TB")]), 0);
+    assert_eq!(run(&targets, &[("name", "Radia")]), 1);
+
+    if cfg!(feature = "stream") {
+        let mut streamed = params("TV");
+        streamed.push(("stream_field", flag(true)));
+        assert_eq!(run(&streamed, &[("name", "TB")]), 0);
+        assert_eq!(run(&streamed, &[("name", "TV")]), 1);
+    }
+}
+
+#[test]
+#[cfg(all(feature = "jaro", feature = "ngram"))]
+fn filter_score_agrees_with_filter_and_ratio() {
+    use crate::{filter_score, similarity_key};
+    let _host = lock();
+    let filter_score_of = |params: &[(&str, Param)], field: &str| {
+        setup(params, &[("name", field)]);
+        let mut score = 9.0f32;
+        let result = unsafe { filter_score(1, &mut score) };
+        (result, score)
+    };
+
+    let words = random_strings(11, 80, &['a', 'b', 'c', 'd'], 7);
+    for pair in words.chunks(2) {
+        let (field, target) = (pair[0].as_str(), pair[1].as_str());
+        // Empty operands read as missing
+        if field.is_empty() || target.is_empty() {
+            continue;
+        }
+        let queries = [
+            vec![("target", text(target)), ("max_distance", int(1))],
+            vec![("target", text(target)), ("threshold", text("0.6"))],
+            vec![
+                ("target", text(target)),
+                ("algorithm", text("jaro_winkler")),
+                ("min_similarity", text("0.8")),
+            ],
+            vec![
+                ("target", text(target)),
+                ("algorithm", text("trigram_coverage")),
+                ("max_missing", int(1)),
+            ],
+        ];
+        for params in queries {
+            let (result, score) = filter_score_of(&params, field);
+            assert_eq!(
+                result,
+                run(&params, &[("name", field)]),
+                "{field:?} {params:?}"
+            );
+            setup(&params, &[("name", field)]);
+            assert_eq!(ratio(1), (100.0 * score as f64).round() as i32);
+            setup(&params, &[("name", field)]);
+            assert!((similarity_key(1) as f64 / 1e6 - score as f64).abs() < 1e-6);
+        }
+    }
+
+    let params = [("target", text("iPhone"))];
+    assert_eq!(
+        filter_score_of(&params, "iPhne"),
+        (1, (1.0 - 1.0 / 6.0) as f32)
+    );
+    setup(&params, &[("other", "x")]);
+    let mut score = 3.0;
+    assert_eq!(unsafe { filter_score(1, &mut score) }, 0);
+    assert_eq!(score, -1.0);
+
+    let unknown = [("target", text("iPhone")), ("algorithm", text("nope"))];
+    assert_eq!(filter_score_of(&unknown, "iPhone"), (-1, -1.0));
+    let negative = [("target", text("iPhone")), ("max_distance", int(-1))];
+    assert_eq!(filter_score_of(&negative, "iPhone"), (-1, -1.0));
+}
+
+#[test]
+fn unicode_words_splits_text_without_spaces() {
+    use crate::words::unicode_words;
+    assert_eq!(
+        unicode_words("東京タワーに行きました"),
+        ["東", "京", "タワー", "に", "行", "き", "ま", "し", "た"]
+    );
+    assert_eq!(unicode_words("iPhone15ケース"), ["iPhone15", "ケース"]);
+    assert_eq!(unicode_words("ภาษาไทย").len(), 7);
+    assert_eq!(unicode_words("ที่"), ["ที่"]);
+    assert_eq!(
+        unicode_words("The quick (\"brown\") fox can't jump 32.3 feet, right?"),
+        ["The", "quick", "brown", "fox", "can't", "jump", "32.3", "feet", "right"]
+    );
+    assert_eq!(unicode_words("a_b x.y. 1,000 ..."), ["a_b", "x.y", "1,000"]);
+    assert_eq!(unicode_words("cafe\u{301} ok"), ["cafe\u{301}", "ok"]);
+    assert!(unicode_words("  ").is_empty());
+}
+
+#[test]
+#[cfg(feature = "token")]
+fn unicode_words_tokenizer_reorders_japanese_tokens() {
+    let _host = lock();
+    let check = |field: &str, target: &str, tokenizer: &str| {
+        let params = [
+            ("target", text(target)),
+            ("algorithm", text("token_set_ratio")),
+            ("tokenizer", text(tokenizer)),
+            ("min_similarity", text("1.0")),
+        ];
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(check("タワー 東京", "東京タワー", "unicode_words"), 1);
+    assert_eq!(check("タワー 東京", "東京タワー", "whitespace"), 0);
+    assert_eq!(
+        check("ケース iPhone15", "iPhone15ケース", "unicode_words"),
+        1
+    );
+}
+
+#[test]
+fn name_order_matches_either_token_order() {
+    use crate::modes::name_order_distance;
+    let _host = lock();
+    let check = |field: &str, target: &str, mode: Option<&str>| {
+        let mut params = vec![("target", text(target)), ("max_distance", int(1))];
+        params.extend(mode.map(|mode| ("mode", text(mode))));
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(check("John Smith", "Smith John", Some("name_order")), 1);
+    assert_eq!(check("John Smith", "Smith John", None), 0);
+    assert_eq!(check("Jon Smith", "Smith John", Some("name_order")), 1);
+    assert_eq!(check("Smith Jon", "Smith John", Some("name_order")), 1);
+    assert_eq!(check("John Smithson", "Smith John", Some("name_order")), 0);
+    // Only two-token targets are swapped
+    assert_eq!(
+        check("John Smith Jr", "Smith John Jr", Some("name_order")),
+        0
+    );
+
+    let params = [
+        ("target", text("Smith John")),
+        ("mode", text("name_order")),
+        ("case_insensitive", flag(true)),
+    ];
+    let mut exact = 0;
+    setup(&params, &[("name", "JOHN SMITH")]);
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+
+    assert_eq!(name_order_distance("mary ann", "ann mary", 0), Some(0));
+    assert_eq!(name_order_distance("mary anne", "ann mary", 1), Some(1));
+}
+
+#[test]
+fn common_values_need_a_closer_match() {
+    use host::DOC_FREQ;
+    let _host = lock();
+    // "Sprngfeld" is 2 edits from "Springfield"
+    let check = |field: &str, freq: Option<(i32, f64)>, weight: Option<&str>| {
+        let mut params = vec![
+            ("field", text("city")),
+            ("target", text("Sprngfeld")),
+            ("max_distance", int(2)),
+        ];
+        params.extend(weight.map(|weight| ("doc_freq_weight", text(weight))));
+        setup(&params, &[("city", field)]);
+        *DOC_FREQ.lock().unwrap() = freq.map(|freq| [("city".to_string(), freq)].into());
+        crate::filter(1)
+    };
+    assert_eq!(check("Springfield", None, None), 1);
+    assert_eq!(check("Springfield", Some((0, 0.6)), None), 1);
+    assert_eq!(check("Springfield", Some((0, 0.6)), Some("1.5")), 0);
+    let logs = LOGS.lock().unwrap().clone();
+    assert!(logs.iter().any(|log| log.contains("doc_freq_weight")));
+
+    if cfg!(feature = "doc_freq") {
+        // A rare value keeps its 2 edits, a common one gets 1
+        assert_eq!(check("Springfield", Some((0, 0.01)), Some("1.0")), 1);
+        assert_eq!(check("Springfield", Some((0, 0.2)), Some("1.0")), 1);
+        assert_eq!(check("Springfield", Some((0, 0.6)), Some("1.0")), 0);
+        assert_eq!(check("Springfield", Some((0, 0.6)), Some("0.25")), 1);
+        assert_eq!(check("Sprngfeld", Some((0, 1.0)), Some("1.0")), 1);
+        assert_eq!(check("Springfeld", Some((0, 1.0)), Some("1.0")), 0);
+        // Unknown frequencies leave the limit, broken ones are host faults
+        assert_eq!(check("Springfield", Some((1, 0.9)), Some("1.0")), 1);
+        // A host that knows no field's frequency answers 1 for each
+        assert_eq!(check("Springfield", None, Some("1.0")), 1);
+        assert!(!LOGS
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|log| log.contains("allows")));
+        assert_eq!(check("Springfield", Some((0, 1.5)), Some("1.0")), -2);
+        assert_eq!(check("Springfield", Some((7, 0.5)), Some("1.0")), -2);
+    } else {
+        assert_eq!(check("Springfield", Some((0, 0.6)), Some("1.0")), 1);
+        let logs = LOGS.lock().unwrap().clone();
+        assert!(logs
+            .iter()
+            .any(|log| log.contains("doc_freq_weight is ignored")));
+    }
+}
+
+#[test]
+fn known_params_cover_every_parameter_read() {
+    let src = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+    let mut files = vec![std::path::PathBuf::from(src)];
+    while let Some(path) = files.pop() {
+        // The tests read parameters of their own
+        if path.file_stem().is_some_and(|stem| stem == "tests") {
+            continue;
+        }
+        if path.is_dir() {
+            files.extend(
+                std::fs::read_dir(path)
+                    .unwrap()
+                    .map(|entry| entry.unwrap().path()),
+            );
+            continue;
+        }
+        let text = std::fs::read_to_string(&path).unwrap();
+        for read in text.split("_param(\"").skip(1) {
+            let name = &read[..read.find('"').unwrap()];
+            // A message for `reject_param`, not a name
+            if name.contains(' ') {
+                continue;
+            }
+            assert!(
+                crate::KNOWN_PARAMS.contains(&name),
+                "{} in {:?}",
+                name,
+                path
+            );
+        }
+    }
+}
+
+#[test]
+fn strict_params_rejects_misspelled_parameters() {
+    let _host = lock();
+    let configured = |config: &str| run(&[("config", text(config))], &[("name", "iPhone")]);
+    assert_eq!(configured(r#"{"target": "iPhone", "max_distnace": 0}"#), 1);
+    let strict = r#"{"target": "iPhone", "max_distnace": 0, "strict_params": true}"#;
+    assert_eq!(configured(strict), -1);
+    assert_eq!(*LOGS.lock().unwrap(), ["unknown parameter max_distnace"]);
+    let negated =
+        r#"{"target": "iPhone", "max_distnace": 0, "strict_params": true, "negate": true}"#;
+    assert_eq!(configured(negated), -1);
+    assert_eq!(
+        configured(r#"{"target": "iPhone", "max_distance": 0, "strict_params": true}"#),
+        1
+    );
+
+    let individual = |strict: bool| {
+        let mut params = vec![("target", text("iPhne")), ("max_distnace", int(0))];
+        params.extend(strict.then(|| ("strict_params", flag(true))));
+        run(&params, &[("name", "iPhone")])
+    };
+    assert_eq!(individual(false), 1);
+    if cfg!(feature = "param_names") {
+        assert_eq!(individual(true), -1);
+        let spelled = [
+            ("target", text("iPhne")),
+            ("max_distance", int(1)),
+            ("strict_params", flag(true)),
+        ];
+        assert_eq!(run(&spelled, &[("name", "iPhone")]), 1);
+
+        // A list past the first buffer is read whole
+        let mut many: Vec<(&str, Param)> = crate::KNOWN_PARAMS
+            .iter()
+            .filter(|name| !["config", "strict_params", "target", "stream_field"].contains(name))
+            .map(|&name| (name, text("")))
+            .collect();
+        many.extend([
+            ("target", text("x")),
+            ("strict_params", flag(true)),
+            ("zzz_bogus", int(1)),
+        ]);
+        assert_eq!(run(&many, &[("name", "x")]), -1);
+        let logs = LOGS.lock().unwrap().clone();
+        assert!(
+            logs.contains(&"unknown parameter zzz_bogus".to_string()),
+            "{:?}",
+            logs
+        );
+
+        setup(
+            &[
+                ("target", text("iPhne")),
+                ("max_distnace", int(0)),
+                ("strict_params", flag(true)),
+            ],
+            &[("name", "iPhone")],
+        );
+        *host::MISBEHAVE.lock().unwrap() = Some(("get_param_names", "", 0, 5000));
+        assert_eq!(crate::filter(1), -2);
+    } else {
+        assert_eq!(individual(true), 1);
+        let logs = LOGS.lock().unwrap().clone();
+        assert!(logs
+            .iter()
+            .any(|log| log.contains("strict_params can't check")));
+    }
+}
+
+#[test]
+fn stepped_windows_never_beat_the_exhaustive_slide() {
+    use crate::partial::{best_window, stepped_window, Stepping};
+    let alphabet = ['a', 'b', 'c', 'd', 'e'];
+    let fields = random_strings(5, 300, &alphabet, 40);
+    let targets = random_strings(9, 300, &alphabet, 6);
+    for (field, target) in fields.iter().zip(&targets) {
+        if target.is_empty() {
+            continue;
+        }
+        let exhaustive = best_window(field, target);
+        for k in 0..3 {
+            let exact = Stepping {
+                step: 1,
+                overlap: k,
+                refine: true,
+            };
+            assert_eq!(stepped_window(field, target, &exact, k), exhaustive);
+
+            for step in 2..5 {
+                let coarse = Stepping {
+                    step,
+                    overlap: k,
+                    refine: false,
+                };
+                let coarse = stepped_window(field, target, &coarse, k);
+                let refined = Stepping {
+                    step,
+                    overlap: k,
+                    refine: true,
+                };
+                let refined = stepped_window(field, target, &refined, k);
+                assert!(refined.0 <= coarse.0);
+
+                for (found, refine) in [(coarse, false), (refined, true)] {
+                    let (distance, start, end) = found;
+                    if distance > k {
+                        assert_eq!(found, (k + 1, 0, 0));
+                        continue;
+                    }
+                    assert!(distance >= exhaustive.0);
+                    // The span reported really is that close
+                    let window: String = field.chars().skip(start).take(end - start).collect();
+                    let actual = levenshtein_distance(&window, target);
+                    if refine {
+                        assert_eq!(actual, distance, "{field} {target} {found:?}");
+                    } else {
+                        assert!(actual <= distance);
+                    }
+                }
+                // A window within k - (step - 1) can't be stepped over
+                if exhaustive.0 + step - 1 <= k {
+                    assert!(
+                        coarse.0 <= k,
+                        "{field} {target} {exhaustive:?} step {step} k {k}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn window_step_trades_accuracy_for_speed() {
+    let _host = lock();
+    let check = |field: &str, stepping: &[(&'static str, Param)]| {
+        let mut params = vec![
+            ("target", text("waterproof")),
+            ("mode", text("partial")),
+            ("max_distance", int(2)),
+        ];
+        params.extend_from_slice(stepping);
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(
+        check("a nice waterproof jacket", &[("window_step", int(4))]),
+        1
+    );
+    assert_eq!(check("watreproof", &[("window_step", int(4))]), 1);
+    // The only close window starts between two steps
+    assert_eq!(check("xwatreproof", &[]), 1);
+    assert_eq!(check("xwatreproof", &[("window_step", int(4))]), 0);
+
+    let field = [("name", "Apple 日本 iPhone 15 Pro")];
+    for refine in [true, false] {
+        let params = [
+            ("target", text("iPhone")),
+            ("max_distance", int(1)),
+            ("window_step", int(3)),
+            ("window_refine", flag(refine)),
+        ];
+        setup(&params, &field);
+        let (mut start, mut end) = (0, 0);
+        assert_eq!(unsafe { partial_match_span(1, &mut start, &mut end) }, 1);
+        assert_eq!((start, end), (9, 15));
+    }
+}
+
+#[test]
+fn best_target_writes_the_closest_target() {
+    use crate::best_target;
+    let _host = lock();
+    let closest = |params: &[(&str, Param)], field: &str, capacity: i32| {
+        setup(params, &[("name", field)]);
+        let mut buffer = vec![0u8; 64];
+        let (mut len, mut distance) = (capacity, 7);
+        let result = unsafe { best_target(1, buffer.as_mut_ptr(), &mut len, &mut distance) };
+        let written = String::from_utf8(buffer[..len.max(0) as usize].to_vec()).unwrap();
+        (
+            result,
+            if result >= 0 { written } else { String::new() },
+            len,
+            distance,
+        )
+    };
+    let targets = [("targets", text(r#"["laptop", "desktop", "tablet"]"#))];
+    assert_eq!(closest(&targets, "labtop", 64), (1, "laptop".into(), 6, 1));
+    assert_eq!(closest(&targets, "deskop", 64), (1, "desktop".into(), 7, 1));
+    let exact = [targets[0].clone(), ("max_distance", int(0))];
+    assert_eq!(closest(&exact, "labtop", 64), (0, "laptop".into(), 6, 1));
+    // The first of tied targets wins
+    let tied = [("targets", text(r#"["ab", "ba", "ab"]"#))];
+    assert_eq!(closest(&tied, "aa", 64), (1, "ab".into(), 2, 1));
+
+    // Written as given, not normalized
+    let folded = [("target", text("TabLet")), ("case_insensitive", flag(true))];
+    assert_eq!(closest(&folded, "tablet", 64), (1, "TabLet".into(), 6, 0));
+    let alternatives = [
+        ("target", text("color|colour")),
+        ("alternation_delimiter", text("|")),
+    ];
+    assert_eq!(
+        closest(&alternatives, "colour", 64),
+        (1, "colour".into(), 6, 0)
+    );
+
+    // Too small a buffer gets the length needed
+    assert_eq!(closest(&targets, "labtop", 3), (-3, String::new(), 6, -1));
+    let none = [("targets", text("[]"))];
+    assert_eq!(closest(&none, "labtop", 64), (0, String::new(), 0, -1));
+    assert_eq!(closest(&[("targets", text("[1]"))], "labtop", 64).0, -1);
+    let negative = [targets[0].clone(), ("max_distance", int(-1))];
+    assert_eq!(closest(&negative, "labtop", 64).0, -1);
+
+    let words = random_strings(3, 60, &['a', 'b', 'c'], 6);
+    for group in words.chunks(6) {
+        let (field, targets) = (&group[0], &group[1..]);
+        if field.is_empty() {
+            continue;
+        }
+        let list = format!(
+            "[{}]",
+            targets
+                .iter()
+                .map(|t| format!("{t:?}"))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let (_, written, _, distance) = closest(&[("targets", text(&list))], field, 64);
+        let best = targets
+            .iter()
+            .map(|t| naive_levenshtein(field, t))
+            .min()
+            .unwrap();
+        let first = targets
+            .iter()
+            .find(|t| naive_levenshtein(field, t) == best)
+            .unwrap();
+        assert_eq!(
+            (written.as_str(), distance as usize),
+            (first.as_str(), best)
+        );
+    }
+}
+
+#[test]
+fn target_sample_rate_keeps_a_sample_per_document() {
+    use crate::sample::sampled;
+    let _host = lock();
+    let targets: Vec<String> = (0..80).map(|i| format!("item{:04}", i)).collect();
+    let list = format!(
+        "[{}]",
+        targets
+            .iter()
+            .map(|t| format!("{t:?}"))
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    let params = |rate: Option<&str>| {
+        let mut params = vec![("targets", text(&list)), ("max_distance", int(0))];
+        params.extend(rate.map(|rate| ("target_sample_rate", text(rate))));
+        params
+    };
+    for field in ["item0042", "itemx042", "zzzz", "item0079x"] {
+        let exhaustive = run(&params(None), &[("name", field)]);
+        assert_eq!(
+            run(&params(Some("1.0")), &[("name", field)]),
+            exhaustive,
+            "{field}"
+        );
+    }
+
+    // The same key keeps the same sample, another key another one
+    let kept = |key: &str| -> Vec<bool> { targets.iter().map(|t| sampled(key, t, 0.1)).collect() };
+    assert_eq!(kept("abc"), kept("abc"));
+    assert_ne!(kept("abc"), kept("abd"));
+    let count = kept("abc").into_iter().filter(|&kept| kept).count();
+    assert!((2..20).contains(&count), "{count}");
+
+    let mut hits = 0;
+    for target in &targets {
+        let result = run(&params(Some("0.3")), &[("name", target)]);
+        assert_eq!(run(&params(Some("0.3")), &[("name", target)]), result);
+        hits += result;
+    }
+    assert!((10..40).contains(&hits), "{hits}");
+    let logs = LOGS.lock().unwrap().clone();
+    assert!(logs
+        .iter()
+        .any(|log| log.starts_with("comparing ") && log.ends_with(" of 80 targets")));
+
+    assert_eq!(run(&params(Some("0")), &[("name", "item0001")]), 0);
+    assert_eq!(run(&params(Some("1.5")), &[("name", "item0001")]), 0);
+    let logs = LOGS.lock().unwrap().clone();
+    assert!(logs.iter().any(|log| log.contains("target_sample_rate")));
+    // A single target isn't sampled
+    let single = [
+        ("target", text("iPhone")),
+        ("target_sample_rate", text("0.01")),
+    ];
+    assert_eq!(run(&single, &[("name", "iPhone")]), 1);
+}
+
+#[test]
+fn targets_over_the_param_buffer_are_read_whole() {
+    let _host = lock();
+    let targets: Vec<String> = (0..200).map(|i| format!("item{:04}", i)).collect();
+    let list = format!(
+        "[{}]",
+        targets
+            .iter()
+            .map(|t| format!("{t:?}"))
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    assert!(list.len() > 1024);
+
+    // Read as unset, the list would leave only `target` to compare
+    let params = [
+        ("targets", text(&list)),
+        ("target", text("other")),
+        ("max_distance", int(0)),
+    ];
+    assert_eq!(run(&params, &[("name", "item0199")]), 1);
+    assert_eq!(run(&params, &[("name", "other")]), 0);
+
+    let config = format!(r#"{{"targets": {list}, "target": "other", "max_distance": 0}}"#);
+    let configured = [("config", text(&config))];
+    assert_eq!(run(&configured, &[("name", "item0199")]), 1);
+    assert_eq!(run(&configured, &[("name", "other")]), 0);
+
+    let sampled = [
+        ("targets", text(&list)),
+        ("target_sample_rate", text("1.0")),
+        ("max_distance", int(0)),
+    ];
+    assert_eq!(run(&sampled, &[("name", "item0150")]), 1);
+    let logs = LOGS.lock().unwrap().clone();
+    assert!(
+        logs.contains(&"comparing 200 of 200 targets".to_string()),
+        "{:?}",
+        logs
+    );
+}
+
+#[test]
+fn transliterate_maps_cyrillic_onto_latin() {
+    let _host = lock();
+    let map = r#"{"а": "a", "в": "v", "к": "k", "м": "m", "о": "o", "с": "s", "щ": "shch", "ш": "sh", "ё": "yo", "\u0435\u0308": "yo"}"#;
+    let params = |target: &str, max_distance: i64| {
+        [
+            ("target", text(target)),
+            ("max_distance", int(max_distance)),
+            ("case_insensitive", flag(true)),
+            ("transliterate", text(map)),
+        ]
+    };
+    assert_eq!(run(&params("Moskva", 0), &[("name", "Москва")]), 1);
+    assert_eq!(run(&params("Moskva", 0), &[("name", "МОСКВА")]), 1);
+    assert_eq!(run(&params("Moskva", 1), &[("name", "Moskwa")]), 1);
+    // Multi-char replacements, and unmapped chars pass through
+    assert_eq!(run(&params("shchshi", 0), &[("name", "щшi")]), 1);
+    assert_eq!(run(&params("yozh", 0), &[("name", "ёzh")]), 1);
+    // A decomposed "ё" is a source of two chars
+    assert_eq!(run(&params("yozh", 0), &[("name", "е\u{308}zh")]), 1);
+    let plain = [("target", text("Moskva")), ("max_distance", int(1))];
+    assert_eq!(run(&plain, &[("name", "Москва")]), 0);
+
+    // Another map in between replaces the table
+    let other = [
+        ("target", text("б")),
+        ("max_distance", int(0)),
+        ("transliterate", text(r#"{"a": "б"}"#)),
+    ];
+    assert_eq!(run(&other, &[("name", "a")]), 1);
+    assert_eq!(run(&params("Moskva", 0), &[("name", "Москва")]), 1);
+    let exact = [
+        ("target", text("mo")),
+        ("algorithm", text("exact_normalized")),
+        ("transliterate", text(map)),
+    ];
+    assert_eq!(run(&exact, &[("name", "мо")]), 1);
+
+    let malformed = [
+        ("target", text("a")),
+        ("max_distance", int(0)),
+        ("transliterate", text(r#"{"": "x"}"#)),
+    ];
+    assert_eq!(run(&malformed, &[("name", "ab")]), 0);
+    let logs = LOGS.lock().unwrap().clone();
+    assert!(logs.iter().any(|log| log.contains("transliterate is not")));
+}
+
+#[test]
+fn empty_fields_get_empty_field_behavior() {
+    let _host = lock();
+    let params = |behavior: Option<&str>, stream: bool| {
+        let mut params = vec![("target", text("ab")), ("max_distance", int(2))];
+        params.extend(behavior.map(|behavior| ("empty_field_behavior", text(behavior))));
+        params.extend(stream.then(|| ("stream_field", flag(true))));
+        params
+    };
+    let streams: &[bool] = if cfg!(feature = "stream") {
+        &[false, true]
+    } else {
+        &[false]
+    };
+    for &stream in streams {
+        // "" is within 2 edits of "ab", but an empty field isn't compared
+        assert_eq!(run(&params(None, stream), &[("name", "")]), 0, "{stream}");
+        assert_eq!(
+            run(&params(Some("match"), stream), &[("name", "")]),
+            1,
+            "{stream}"
+        );
+        assert_eq!(
+            run(&params(Some("skip"), stream), &[("name", "")]),
+            1,
+            "{stream}"
+        );
+        assert_eq!(
+            run(&params(Some("match"), stream), &[("name", "  ")]),
+            1,
+            "{stream}"
+        );
+        assert_eq!(run(&params(None, stream), &[("name", "  ")]), 0, "{stream}");
+        // A missing field is still missing
+        assert_eq!(
+            run(&params(Some("match"), stream), &[("other", "")]),
+            0,
+            "{stream}"
+        );
+        assert_eq!(
+            run(&params(Some("match"), stream), &[("name", "zzzzzzz")]),
+            0,
+            "{stream}"
+        );
+    }
+
+    // Written, if 0 bytes long, is an empty field
+    setup(&params(Some("match"), false), &[("name", "xy")]);
+    *host::MISBEHAVE.lock().unwrap() = Some(("get_field_string", "name", 1, 0));
+    assert_eq!(crate::filter(1), 1);
+    setup(&params(None, false), &[("name", "xy")]);
+    *host::MISBEHAVE.lock().unwrap() = Some(("get_field_string", "name", 1, 0));
+    assert_eq!(crate::filter(1), 0);
+
+    // A parameter written 0 bytes long is unset
+    setup(&params(Some("match"), false), &[("name", "ab")]);
+    *host::MISBEHAVE.lock().unwrap() = Some(("get_param_string", "target", 0, 0));
+    assert_eq!(crate::filter(1), 0);
+    setup(&params(Some("match"), false), &[("name", "")]);
+    *host::MISBEHAVE.lock().unwrap() = Some(("get_param_string", "empty_field_behavior", 0, 0));
+    assert_eq!(crate::filter(1), 0);
+    let mut none: [u8; 0] = [];
+    assert_eq!(
+        unsafe { crate::get_string_param("target", &mut none) },
+        None
+    );
+
+    // An empty target field is no target
+    let target_field = [
+        ("target_field", text("t")),
+        ("max_distance", int(5)),
+        ("empty_target_matches", flag(true)),
+    ];
+    assert_eq!(run(&target_field, &[("name", "ab"), ("t", "")]), 0);
+}
+
+#[test]
+fn max_total_distance_shares_one_budget_across_fields() {
+    let _host = lock();
+    let params = |total: i64| {
+        vec![
+            ("fields", text(r#"["first", "last"]"#)),
+            ("targets", text(r#"["john", "smith"]"#)),
+            ("max_distance", int(2)),
+            ("max_total_distance", int(total)),
+        ]
+    };
+    let document = |first, last| [("first", first), ("last", last)];
+
+    // Each field passes on its own, but not both together
+    let per_field = &params(0)[..3];
+    assert_eq!(run(per_field, &document("jhon", "smiht")), 1);
+    assert_eq!(run(&params(3), &document("jhon", "smiht")), 0);
+    assert_eq!(run(&params(4), &document("jhon", "smiht")), 1);
+    assert_eq!(run(&params(3), &document("jon", "smyth")), 1);
+    assert_eq!(run(&params(3), &document("joan", "smith")), 1);
+    let mut negated = params(3);
+    negated.push(("negate", flag(true)));
+    assert_eq!(run(&negated, &document("jhon", "smiht")), 1);
+
+    // max_distance still holds each field, and unset only the total does
+    assert_eq!(run(&params(10), &document("xxxxx", "smith")), 0);
+    let mut uncapped = params(10);
+    uncapped.remove(2);
+    assert_eq!(run(&uncapped, &document("jxxxx", "smith")), 1);
+    assert_eq!(run(&params(10), &[("first", "john")]), 0);
+
+    let mut exact = 0;
+    setup(&params(0), &document("john", "smith"));
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+    setup(&params(2), &document("jon", "smith"));
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 0);
+
+    // One field, or one joined value, is its own total
+    let single = |total| {
+        [
+            ("target", text("smith")),
+            ("max_distance", int(3)),
+            ("max_total_distance", int(total)),
+        ]
+    };
+    assert_eq!(run(&single(1), &[("name", "smiht")]), 0);
+    assert_eq!(run(&single(2), &[("name", "smiht")]), 1);
+    let joined = [
+        ("target", text("john smith")),
+        ("concat_fields", text(r#"["first", "last"]"#)),
+        ("max_total_distance", int(1)),
+    ];
+    assert_eq!(run(&joined, &document("jon", "smith")), 1);
+
+    assert_eq!(run(&params(-1), &document("john", "smith")), -1);
+    assert_eq!(*LOGS.lock().unwrap(), ["max_total_distance is negative"]);
+}
+
+#[cfg(feature = "params_blob")]
+#[test]
+fn params_blob_agrees_with_the_per_parameter_reads() {
+    use crate::blob::parse;
+    use crate::json::Value;
+    use host::{encode_blob, BLOB_CALLS, BLOB_ON, MISBEHAVE, PARAM_CALLS};
+    let _host = lock();
+
+    let blob = encode_blob(&[
+        ("target", text("iPhone")),
+        ("max_distance", int(2)),
+        ("min_similarity", float(0.5)),
+        ("negate", flag(true)),
+        ("targets", text("[\"a\"]")),
+    ]);
+    let params = parse(&blob).unwrap();
+    assert_eq!(
+        params.get("target").and_then(|t| t.as_str()),
+        Some("iPhone")
+    );
+    assert_eq!(
+        params.get("max_distance").and_then(|t| t.as_f64()),
+        Some(2.0)
+    );
+    assert_eq!(
+        params.get("min_similarity").and_then(|t| t.as_f64()),
+        Some(0.5)
+    );
+    assert!(matches!(params.get("negate"), Some(Value::Bool(true))));
+
+    // Truncated, trailing bytes, unknown tag, bad boolean, overlong name
+    let mut extra = blob.clone();
+    extra.push(0);
+    assert!(parse(&blob[..blob.len() - 1]).is_none());
+    assert!(parse(&extra).is_none());
+    assert!(parse(&[]).is_none());
+    assert!(parse(&[0, 0, 0, 0]).is_some());
+    assert!(parse(&[1, 0, 0, 0, 1, 0, 0, 0, b'a', 9]).is_none());
+    assert!(parse(&[1, 0, 0, 0, 1, 0, 0, 0, b'a', 3, 2]).is_none());
+    assert!(parse(&[1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]).is_none());
+
+    let queries = [
+        (
+            vec![
+                ("field", text("title")),
+                ("target", text("iPhone")),
+                ("max_distance", int(1)),
+                ("case_insensitive", flag(true)),
+            ],
+            vec![("title", "iphone")],
+        ),
+        (
+            vec![
+                ("field", text("title")),
+                ("target", text("iPhone")),
+                ("max_distance", int(1)),
+            ],
+            vec![("title", "iPad")],
+        ),
+        (
+            vec![
+                ("targets", text("[\"galaxy\", \"pixel\"]")),
+                ("min_similarity", float(0.7)),
+                ("algorithm", text("jaro_winkler")),
+            ],
+            vec![("name", "pixle")],
+        ),
+        (
+            vec![
+                ("target", text("abc")),
+                ("negate", flag(true)),
+                ("max_distance", int(0)),
+            ],
+            vec![("name", "abd")],
+        ),
+        (
+            vec![("config", text("{\"target\": \"abc\", \"max_distance\": 1}"))],
+            vec![("name", "abd")],
+        ),
+        (
+            vec![("config", text("nope")), ("target", text("abc"))],
+            vec![("name", "abc")],
+        ),
+    ];
+    for (params, fields) in &queries {
+        let individually = run(params, fields);
+        let param_calls = *PARAM_CALLS.lock().unwrap();
+        assert!(param_calls > 0);
+
+        // The first blob read may only size the buffer
+        setup(params, fields);
+        *BLOB_ON.lock().unwrap() = true;
+        assert_eq!(crate::filter(1), individually, "{:?}", fields);
+        setup(params, fields);
+        *BLOB_ON.lock().unwrap() = true;
+        assert_eq!(crate::filter(1), individually, "{:?}", fields);
+        let calls = (*PARAM_CALLS.lock().unwrap(), *BLOB_CALLS.lock().unwrap());
+        assert_eq!(calls, (0, 1), "{} calls individually", param_calls);
+    }
+
+    // A blob too short to parse is a host fault
+    setup(&[("target", text("abc"))], &[("name", "abc")]);
+    *MISBEHAVE.lock().unwrap() = Some(("get_params_blob", "", 0, 2));
+    *BLOB_ON.lock().unwrap() = true;
+    assert_eq!(crate::filter(1), -2);
+}
+
+#[cfg(feature = "params_blob")]
+#[test]
+fn params_blob_takes_one_host_call_per_query() {
+    use host::{next_document, BLOB_CALLS, BLOB_ON, PARAM_CALLS};
+    let _host = lock();
+
+    let params = [
+        ("target", text("iPhone 15 Pro")),
+        ("max_distance", int(2)),
+        ("case_insensitive", flag(true)),
+        ("collapse_whitespace", flag(true)),
+    ];
+    let documents = ["iphone 15 pro", "iPhone  15", "Galaxy S24", "iphone 15 pr"];
+
+    // The host calls for the parameters on each of a query's documents
+    let calls_per_document = |blob: bool| {
+        setup(&params, &[]);
+        *BLOB_ON.lock().unwrap() = blob;
+        documents
+            .iter()
+            .map(|name| {
+                next_document(&[("name", name)]);
+                let result = crate::filter(1);
+                let calls = *PARAM_CALLS.lock().unwrap() + *BLOB_CALLS.lock().unwrap();
+                (result, calls)
+            })
+            .collect::<Vec<_>>()
+    };
+    let individually = calls_per_document(false);
+    let blob = calls_per_document(true);
+
+    // The same results; one read of the blob (two if it sizes the buffer) for
+    // the query instead of every parameter for every document
+    let results = |calls: &[(i32, usize)]| calls.iter().map(|c| c.0).collect::<Vec<_>>();
+    assert_eq!(results(&blob), results(&individually));
+    assert_eq!(results(&blob), [1, 0, 0, 1]);
+    assert!(blob[0].1 <= 2, "{:?}", blob);
+    assert!(blob[1..].iter().all(|c| c.1 == 0), "{:?}", blob);
+    assert!(individually.iter().all(|c| c.1 >= 50), "{:?}", individually);
+}
+
+#[test]
+fn min_match_prefix_rejects_short_common_prefixes() {
+    let _host = lock();
+    let query = |k: i64| {
+        vec![
+            ("target", text("SKU-10442")),
+            ("min_match_prefix", int(k)),
+            ("max_distance", int(1)),
+        ]
+    };
+    for (field, expected) in [
+        ("SKU-10443", 1),
+        ("SKU-1044", 1),
+        ("SKV-10442", 0),
+        ("SK-10442", 0),
+        ("SKU-10442", 1),
+        ("xKU-10442", 0),
+    ] {
+        assert_eq!(run(&query(4), &[("name", field)]), expected, "{}", field);
+    }
+    // boundary: common prefix exactly k passes, k-1 fails
+    assert_eq!(run(&query(3), &[("name", "SKUx10442")]), 1);
+    assert_eq!(run(&query(4), &[("name", "SKUx10442")]), 0);
+    assert_eq!(run(&query(9), &[("name", "SKU-10442")]), 1);
+    assert_eq!(run(&query(10), &[("name", "SKU-10442")]), 0);
+    assert_eq!(run(&query(9), &[("name", "SKU-1044")]), 0);
+    assert_eq!(run(&query(8), &[("name", "SKU-1044")]), 1);
+    assert_eq!(run(&query(0), &[("name", "xKU-10442")]), 1);
+    assert_eq!(run(&query(-3), &[("name", "xKU-10442")]), 1);
+    // after normalization
+    let mut case_insensitive = query(4);
+    case_insensitive.push(("case_insensitive", flag(true)));
+    assert_eq!(run(&case_insensitive, &[("name", "sku-10443")]), 1);
+    assert_eq!(run(&query(4), &[("name", "sku-10443")]), 0);
+    // vs require_prefix_len on short operands
+    assert_eq!(
+        run(
+            &[("target", text("ab")), ("require_prefix_len", int(3))],
+            &[("name", "AB")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("ab")),
+                ("require_prefix_len", int(3)),
+                ("case_insensitive", flag(true))
+            ],
+            &[("name", "AB")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &[
+                ("target", text("ab")),
+                ("min_match_prefix", int(3)),
+                ("case_insensitive", flag(true))
+            ],
+            &[("name", "AB")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[("target", text("ab")), ("min_match_prefix", int(3))],
+            &[("name", "ab")]
+        ),
+        0
+    );
+    // targets shared path gated
+    assert_eq!(
+        run(
+            &[
+                ("targets", text(r#"["SKU-10442", "ZZ"]"#)),
+                ("min_match_prefix", int(4)),
+                ("max_distance", int(1))
+            ],
+            &[("name", "SKV-10442")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &[
+                ("targets", text(r#"["SKU-10442", "ZZ"]"#)),
+                ("min_match_prefix", int(4)),
+                ("max_distance", int(1))
+            ],
+            &[("name", "SKU-10441")]
+        ),
+        1
+    );
+}
+
+#[test]
+fn suffix_mode_matches_the_end_of_the_field() {
+    use crate::modes::suffix_distance;
+    let _host = lock();
+    let query = |target: &str, max_distance: i64| {
+        vec![
+            ("target", text(target)),
+            ("mode", text("suffix")),
+            ("max_distance", int(max_distance)),
+        ]
+    };
+    for (field, expected) in [
+        ("/home/ann/docs/report.pdf", 1),
+        ("/var/other/dir/report.pdf", 1),
+        ("/tmp/report.pdf", 1),
+        ("C:\\archive\\reprt.pdf", 1),
+        ("report.pdf", 1),
+        ("report.pdf.bak", 0),
+        ("/home/ann/docs/report.docx", 0),
+        ("port.pdf", 0),
+        ("pdf", 0),
+        ("", 0),
+    ] {
+        assert_eq!(
+            run(&query("report.pdf", 1), &[("name", field)]),
+            expected,
+            "{}",
+            field
+        );
+    }
+    assert_eq!(run(&query("report.pdf", 2), &[("name", "port.pdf")]), 1);
+    assert_eq!(
+        run(&query("example.com", 1), &[("name", "mail.exmple.com")]),
+        1
+    );
+
+    // The distance to the closest suffix, once within the bound
+    let words = random_strings(185, 300, &['a', 'b', 'c'], 9);
+    for pair in words.chunks(2) {
+        let (field, target) = (&pair[0], &pair[1]);
+        if target.is_empty() {
+            continue;
+        }
+        let best = (0..=field.chars().count())
+            .map(|i| naive_levenshtein(&field.chars().skip(i).collect::<String>(), target))
+            .min()
+            .unwrap();
+        for bound in 0..3 {
+            let distance = suffix_distance(field, target, bound);
+            assert_eq!(
+                distance <= bound,
+                best <= bound,
+                "{} {} {}",
+                field,
+                target,
+                bound
+            );
+            if best <= bound {
+                assert_eq!(distance, best, "{} {} {}", field, target, bound);
+            }
+        }
+    }
+
+    let mut exact = 0;
+    setup(&query("report.pdf", 1), &[("name", "/x/report.pdf")]);
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+    setup(&query("report.pdf", 1), &[("name", "/x/reprt.pdf")]);
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 0);
+}
+
+#[test]
+fn normalization_pipeline_runs_the_steps_in_its_order() {
+    let _host = lock();
+    let query = |pipeline: &str, max_distance: i64| {
+        vec![
+            ("target", text("Room A")),
+            ("max_distance", int(max_distance)),
+            ("normalization_pipeline", text(pipeline)),
+        ]
+    };
+    // Masking "Room 12 A" first leaves "Room  A" to collapse to "Room A";
+    // collapsing first leaves nothing to collapse once it is masked
+    let collapse_first = r#"["collapse_whitespace", "mask_digits"]"#;
+    let mask_first = r#"["mask_digits", "collapse_whitespace"]"#;
+    assert_eq!(run(&query(collapse_first, 0), &[("name", "Room 12 A")]), 0);
+    assert_eq!(run(&query(collapse_first, 1), &[("name", "Room 12 A")]), 1);
+    assert_eq!(run(&query(mask_first, 0), &[("name", "Room 12 A")]), 1);
+
+    // The switches keep the fixed order, which masks first
+    let switches = [
+        ("target", text("Room A")),
+        ("max_distance", int(0)),
+        ("mask_digits", flag(true)),
+        ("collapse_whitespace", flag(true)),
+    ];
+    assert_eq!(run(&switches, &[("name", "Room 12 A")]), 1);
+
+    // A pipeline overrides the switches: unlisted steps don't run
+    let switched_off = [
+        ("target", text("Room A")),
+        ("max_distance", int(0)),
+        ("mask_digits", flag(false)),
+        ("normalization_pipeline", text(mask_first)),
+    ];
+    assert_eq!(run(&switched_off, &[("name", "Room 12 A")]), 1);
+    let unlisted = [
+        ("target", text("room a")),
+        ("max_distance", int(0)),
+        ("case_insensitive", flag(true)),
+        ("normalization_pipeline", text(mask_first)),
+    ];
+    assert_eq!(run(&unlisted, &[("name", "Room 12 A")]), 0);
+
+    let transliterated = |pipeline: &str| {
+        vec![
+            ("target", text("x")),
+            ("max_distance", int(0)),
+            ("transliterate", text(r#"{"A": "x"}"#)),
+            ("normalization_pipeline", text(pipeline)),
+        ]
+    };
+    let late_case = transliterated(r#"["transliterate", "case_insensitive"]"#);
+    let early_case = transliterated(r#"["case_insensitive", "transliterate"]"#);
+    assert_eq!(run(&late_case, &[("name", "A")]), 1);
+    assert_eq!(run(&early_case, &[("name", "A")]), 0);
+
+    // The target takes the pipeline too, and cached forms don't carry over
+    // from one pipeline to the other
+    for _ in 0..2 {
+        let target = |pipeline: &str| {
+            vec![
+                ("target", text("Room 9 A")),
+                ("max_distance", int(0)),
+                ("normalization_pipeline", text(pipeline)),
+            ]
+        };
+        let mut streamed = target(collapse_first);
+        streamed.push(("stream_field", flag(true)));
+        assert_eq!(run(&target(mask_first), &[("name", "Room A")]), 1);
+        assert_eq!(run(&target(collapse_first), &[("name", "Room A")]), 0);
+        let expected = if cfg!(feature = "stream") { 1 } else { -1 };
+        assert_eq!(run(&streamed, &[("name", "Room 7 A")]), expected);
+    }
+    assert_eq!(run(&query("[]", 0), &[("name", "Room A")]), 1);
+
+    // An invalid pipeline is rejected, even negated
+    for (pipeline, message) in [
+        (
+            r#"["mask_digits", "strip_punctuation"]"#,
+            "unknown normalization_pipeline step \"strip_punctuation\"",
+        ),
+        (r#"["mask_digits", "mask_digits"]"#, "lists a step twice"),
+        ("mask_digits", "not a JSON array"),
+        ("[1]", "not a JSON array"),
+    ] {
+        assert_eq!(
+            run(&query(pipeline, 5), &[("name", "Room A")]),
+            -1,
+            "{}",
+            pipeline
+        );
+        let logs = LOGS.lock().unwrap().clone();
+        assert_eq!(logs.len(), 1, "{:?}", logs);
+        assert!(logs[0].contains(message), "{:?}", logs);
+
+        let mut negated = query(pipeline, 0);
+        negated.push(("negate", flag(true)));
+        assert_eq!(run(&negated, &[("name", "zzz")]), -1, "{}", pipeline);
+    }
+}
+
+#[test]
+fn invalid_pipeline_is_rejected_by_every_export() {
+    use crate::{best_target, filter_score, fuzzy_occurrences, similarity_key, similarity_vector};
+    let _host = lock();
+    let params = [
+        ("target", text("iPhone")),
+        ("targets", text(r#"["iPhone", "Galaxy"]"#)),
+        (
+            "normalization_pipeline",
+            text(r#"["case_insensitive", "nope"]"#),
+        ),
+        ("components", text(r#"[{"algorithm": "levenshtein"}]"#)),
+    ];
+    let doc = [("name", "iphone")];
+    for export in [
+        || ratio(1) as f64,
+        || similarity_key(1) as f64,
+        || combined_score(1) as f64,
+        || fuzzy_occurrences(1) as f64,
+        || crate::filter_tier(1) as f64,
+        || crate::length_normalized_confidence(1) as f64,
+    ] {
+        setup(&params, &doc);
+        assert_eq!(export(), -1.0);
+        // Each of them returns -1 for this pipeline only
+        let mut valid = params.to_vec();
+        valid[2] = ("normalization_pipeline", text(r#"["case_insensitive"]"#));
+        setup(&valid, &doc);
+        assert_ne!(export(), -1.0);
+    }
+
+    setup(&params, &doc);
+    let mut score = 9.0f32;
+    assert_eq!(unsafe { filter_score(1, &mut score) }, -1);
+    assert_eq!(score, -1.0);
+
+    setup(&params, &doc);
+    let (mut out, mut len, mut distance) = ([0u8; 16], 16, 0i64);
+    assert_eq!(
+        unsafe { best_target(1, out.as_mut_ptr(), &mut len, &mut distance) },
+        -1
+    );
+    assert_eq!((len, distance), (0, -1));
+
+    setup(&params, &doc);
+    let (mut scores, mut len) = ([0f32; 4], 4);
+    assert_eq!(
+        unsafe { similarity_vector(1, scores.as_mut_ptr(), &mut len) },
+        -1
+    );
+    assert_eq!(len, 0);
+
+    let (mut start, mut end) = (0, 0);
+    setup(&params, &doc);
+    assert_eq!(unsafe { partial_match_span(1, &mut start, &mut end) }, -1);
+    assert_eq!((start, end), (-1, -1));
+}
+
+#[test]
+fn length_penalty_lowers_the_score_as_the_length_gap_grows() {
+    use crate::filter_score;
+    let _host = lock();
+    let filter_score_of = |params: &[(&str, Param)], field: &str| {
+        setup(params, &[("name", field)]);
+        let mut score = 9.0f32;
+        let result = unsafe { filter_score(1, &mut score) };
+        (result, score)
+    };
+    let query = |penalty: f64| {
+        vec![
+            ("target", text("apple")),
+            ("max_distance", int(1)),
+            ("length_penalty", text(&penalty.to_string())),
+        ]
+    };
+
+    // "apples" is 1/6 short of similar and 1/6 longer; "apple pie" 4/9
+    let (result, unpenalized) = filter_score_of(&query(0.0), "apples");
+    assert_eq!(result, 1);
+    assert!((unpenalized - 5.0 / 6.0).abs() < 1e-6, "{}", unpenalized);
+    let (result, score) = filter_score_of(&query(0.5), "apples");
+    assert_eq!(result, 1);
+    assert!((score - 0.75).abs() < 1e-6, "{}", score);
+    let (result, score) = filter_score_of(&query(0.5), "apple pie");
+    assert_eq!(result, 0);
+    assert!((score - 1.0 / 3.0).abs() < 1e-6, "{}", score);
+
+    let mut last = f32::INFINITY;
+    for field in ["apple", "apples", "applesx", "applesxy", "applesxyz"] {
+        let (_, score) = filter_score_of(&query(0.5), field);
+        assert!(score < last, "{}: {} after {}", field, score, last);
+        last = score;
+    }
+    assert_eq!(filter_score_of(&query(0.5), "apple"), (1, 1.0));
+    assert_eq!(filter_score_of(&query(10.0), "apple pie").1, 0.0);
+
+    // Unset is no penalty; a negative or NaN one is invalid
+    let unset = [("target", text("apple")), ("max_distance", int(1))];
+    assert_eq!(filter_score_of(&unset, "apples").1, unpenalized);
+    assert_eq!(filter_score_of(&query(-1.0), "apples"), (-1, -1.0));
+    assert_eq!(filter_score_of(&query(f64::NAN), "apples"), (-1, -1.0));
+
+    let combined = |penalty: Option<f64>, field: &str| {
+        let mut params = vec![
+            ("target", text("apple")),
+            (
+                "components",
+                text(r#"[{"algorithm": "levenshtein", "weight": 1}]"#),
+            ),
+        ];
+        params.extend(penalty.map(|penalty| ("length_penalty", text(&penalty.to_string()))));
+        setup(&params, &[("name", field)]);
+        combined_score(1)
+    };
+    assert!((combined(None, "apples") - 5.0 / 6.0).abs() < 1e-6);
+    assert!((combined(Some(0.5), "apples") - 0.75).abs() < 1e-6);
+    assert!(combined(Some(0.5), "apples") > combined(Some(0.5), "applesxyz"));
+    assert!(combined(Some(1.0), "applesxyz") < combined(Some(0.5), "applesxyz"));
+    assert_eq!(combined(Some(-0.1), "apples"), -1.0);
+}
+
+#[test]
+fn glob_segments_match_within_their_own_budgets() {
+    use crate::glob::Glob;
+    let _host = lock();
+    let budgeted = |pattern: &str| {
+        vec![
+            ("target", text(pattern)),
+            ("mode", text("glob")),
+            ("case_insensitive", flag(true)),
+        ]
+    };
+    for (field, expected) in [
+        ("iPhone 13", 1),
+        ("iPhone Pro 13", 1),
+        ("iphne 13", 1),
+        ("iphone13", 1),
+        ("iPhone 12", 0),
+        ("ipne 13", 0),
+        ("iphne 12", 0),
+        ("my iPhone 13", 0),
+        ("iPhone 13 mini", 0),
+    ] {
+        assert_eq!(
+            run(&budgeted("iph{1}one*13{0}"), &[("name", field)]),
+            expected,
+            "{}",
+            field
+        );
+        assert_eq!(
+            run(&budgeted("iphone{1}*13{0}"), &[("name", field)]),
+            expected,
+            "{}",
+            field
+        );
+    }
+
+    // One segment's budget is spent while the other's allows an edit
+    assert_eq!(
+        run(&budgeted("iphone{0}*13{1}"), &[("name", "iphone 12")]),
+        1
+    );
+    assert_eq!(
+        run(&budgeted("iphone{0}*13{1}"), &[("name", "iphne 12")]),
+        0
+    );
+    assert_eq!(
+        run(&budgeted("*iphone{1}*"), &[("name", "my iphne pro")]),
+        1
+    );
+
+    // A segment without a budget has max_distance
+    let query = |pattern: &str, max_distance: i64| {
+        vec![
+            ("target", text(pattern)),
+            ("mode", text("glob")),
+            ("max_distance", int(max_distance)),
+        ]
+    };
+    assert_eq!(run(&query("abcdef*xyz", 1), &[("name", "abcdf--xyq")]), 1);
+    assert_eq!(run(&query("abcdef*xyz", 0), &[("name", "abcdf--xyq")]), 0);
+    assert_eq!(
+        run(&query("abcdef*xyz{2}", 1), &[("name", "abcdf--xqq")]),
+        1
+    );
+    assert_eq!(run(&query("*", 0), &[("name", "anything")]), 1);
+    assert_eq!(run(&query("a\\*b", 0), &[("name", "a*b")]), 1);
+    assert_eq!(run(&query("a\\*b", 0), &[("name", "axb")]), 0);
+
+    // Without a star it is Levenshtein over the whole field
+    for (field, target) in [
+        ("kitten", "sitting"),
+        ("abc", "abd"),
+        ("", "x"),
+        ("flaw", "lawn"),
+    ] {
+        for max_distance in 0..4 {
+            let expected = (naive_levenshtein(field, target) <= max_distance as usize) as i32;
+            let result = run(&query(target, max_distance), &[("name", field)]);
+            assert_eq!(result, expected, "{} {} {}", field, target, max_distance);
+        }
+    }
+
+    for pattern in ["a{x}*b", "a{1}{2}", "*{1}*", "ab{", "ab\\", "a{}b"] {
+        assert_eq!(run(&query(pattern, 5), &[("name", "ab")]), 0, "{}", pattern);
+        assert!(Glob::parse(pattern, 1).is_none(), "{}", pattern);
+    }
+
+    let mut exact = 0;
+    setup(&query("iphone*13", 1), &[("name", "iphone 13")]);
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+    setup(&query("iphone*13", 1), &[("name", "iphne 13")]);
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 0);
+
+    // "a{1}*b{0}" against every split of the field into a head within one
+    // edit of `a`, any middle, and a tail equal to `b`
+    let words = random_strings(188, 300, &['a', 'b', 'c'], 7);
+    for words in words.chunks(3) {
+        let (field, a, b) = (&words[0], &words[1], &words[2]);
+        if a.is_empty() || b.is_empty() {
+            continue;
+        }
+        let glob = Glob::parse(&format!("{}{{1}}*{}{{0}}", a, b), 9).unwrap();
+        let chars: Vec<char> = field.chars().collect();
+        let expected = (0..=chars.len()).any(|i| {
+            let head: String = chars[..i].iter().collect();
+            naive_levenshtein(&head, a) <= 1
+                && (i..=chars.len()).any(|j| chars[j..].iter().collect::<String>() == *b)
+        });
+        assert_eq!(glob.matches(field), expected, "{} {} {}", field, a, b);
+    }
+}
+
+#[test]
+fn stats_reports_the_work_of_the_call() {
+    use host::STATS;
+    let _host = lock();
+    let stat = |key: &str| {
+        let stats = STATS.lock().unwrap().clone();
+        stats
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|&(_, value)| value)
+    };
+    let query = |stats: bool, extra: &[(&'static str, Param)]| {
+        let mut params = vec![("target", text("kitten")), ("max_distance", int(2))];
+        if stats {
+            params.push(("stats", flag(true)));
+        }
+        params.extend_from_slice(extra);
+        params
+    };
+
+    assert_eq!(run(&query(false, &[]), &[("name", "sittin")]), 1);
+    assert!(STATS.lock().unwrap().is_empty());
+    assert_eq!(run(&query(true, &[]), &[("name", "sittin")]), 1);
+    if !cfg!(feature = "stats") {
+        assert!(STATS.lock().unwrap().is_empty());
+        let logs = LOGS.lock().unwrap().clone();
+        assert!(
+            logs.iter().any(|log| log.contains("stats is ignored")),
+            "{:?}",
+            logs
+        );
+        return;
+    }
+
+    let stats = STATS.lock().unwrap().clone();
+    let keys: Vec<&str> = stats.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, ["fields_examined", "dp_cells", "early_exits"]);
+    assert_eq!(stat("fields_examined"), Some(1));
+    assert!(stat("dp_cells").unwrap() > 0);
+    assert_eq!(stat("early_exits"), Some(0));
+
+    // Given up on the length gap, before any DP, or on a row minimum
+    assert_eq!(run(&query(true, &[]), &[("name", "kittenxyzw")]), 0);
+    assert_eq!((stat("early_exits"), stat("dp_cells")), (Some(1), Some(0)));
+    assert_eq!(run(&query(true, &[]), &[("name", "zzzzzz")]), 0);
+    assert_eq!(stat("early_exits"), Some(1));
+    assert!(stat("dp_cells").unwrap() < 36);
+
+    // Fields up to the first match
+    let fields = [("fields", text(r#"["a", "b", "c"]"#))];
+    let document = [("a", "zzz"), ("b", "kitten"), ("c", "x")];
+    assert_eq!(run(&query(true, &fields), &document), 1);
+    assert_eq!(stat("fields_examined"), Some(2));
+
+    // Calls without stats don't add to the next one's
+    for _ in 0..3 {
+        run(&query(false, &[]), &[("name", "sittin")]);
+    }
+    run(&query(true, &[]), &[("name", "kitten")]);
+    assert_eq!(
+        (stat("fields_examined"), stat("dp_cells")),
+        (Some(1), Some(0))
+    );
+
+    if cfg!(feature = "stream") {
+        let streamed = [("stream_field", flag(true))];
+        assert_eq!(run(&query(true, &streamed), &[("name", "sittin")]), 1);
+        assert_eq!(
+            (stat("fields_examined"), stat("dp_cells")),
+            (Some(1), Some(36))
+        );
+        assert_eq!(
+            run(&query(true, &streamed), &[("name", "zzzzzzzzzzzzzzz")]),
+            0
+        );
+        assert_eq!(stat("early_exits"), Some(1));
+    }
+
+    // Shared targets count each one compared
+    let targets = [
+        (
+            "targets",
+            text(r#"["zzzzzzzzzzzz", "abcdefgh", "abcdefgx"]"#),
+        ),
+        ("max_distance", int(1)),
+        ("stats", flag(true)),
+    ];
+    assert_eq!(run(&targets, &[("name", "abcdefgy")]), 1);
+    assert_eq!(
+        (stat("fields_examined"), stat("early_exits")),
+        (Some(2), Some(1))
+    );
+}
+
+#[test]
+#[cfg(feature = "phonetic")]
+fn phonetic_key_matches_names_that_sound_alike() {
+    use crate::filter_score;
+    use crate::phonetic::{double_metaphone, phonetic_key_distance};
+    let _host = lock();
+    let query = |target: &str, max_distance: i64| {
+        vec![
+            ("target", text(target)),
+            ("algorithm", text("phonetic_key")),
+            ("max_distance", int(max_distance)),
+        ]
+    };
+    for (field, target) in [
+        ("Kathryn", "Catherine"),
+        ("Schmidt", "Smith"),
+        ("Stephen", "Steven"),
+        ("Katrina", "Catherine"),
+        ("John Smyth", "Jon Smith"),
+    ] {
+        assert!(
+            naive_levenshtein(field, target) >= 2,
+            "{} {}",
+            field,
+            target
+        );
+        assert_eq!(
+            run(&query(target, 0), &[("name", field)]),
+            1,
+            "{} {}",
+            field,
+            target
+        );
+        let lexical = [("target", text(target)), ("max_distance", int(1))];
+        assert_eq!(run(&lexical, &[("name", field)]), 0, "{} {}", field, target);
+    }
+    for (field, target) in [
+        ("Jones", "Smith"),
+        ("Tomson", "Thompson"),
+        ("Smith Jones", "Smith"),
+    ] {
+        assert_eq!(
+            run(&query(target, 1), &[("name", field)]),
+            0,
+            "{} {}",
+            field,
+            target
+        );
+    }
+    assert_eq!(run(&query("Thompson", 2), &[("name", "Tomson")]), 1);
+
+    // "Caroline" is KRLN, two edits from Catherine's K0RN
+    assert_eq!(double_metaphone("Caroline").primary, "KRLN");
+    assert_eq!(phonetic_key_distance("Caroline", "Catherine"), 2);
+    assert_eq!(run(&query("Catherine", 0), &[("name", "Caroline")]), 0);
+
+    // The tiebreak only decides fields exactly at the limit
+    let tiebreak = |target: &str, max_distance: i64, bound: i64| {
+        let mut params = query(target, max_distance);
+        params.push(("phonetic_tiebreak", int(bound)));
+        params
+    };
+    assert_eq!(run(&tiebreak("Thompson", 2, 1), &[("name", "Tomson")]), 0);
+    assert_eq!(run(&tiebreak("Thompson", 2, 2), &[("name", "Tomson")]), 1);
+    assert_eq!(run(&tiebreak("Thompson", 3, 0), &[("name", "Tomson")]), 1);
+    assert_eq!(run(&tiebreak("Catherine", 0, 2), &[("name", "Kathryn")]), 0);
+    assert_eq!(run(&tiebreak("Catherine", 1, 0), &[("name", "Kathryn")]), 1);
+    assert_eq!(
+        run(&tiebreak("Catherine", 0, -1), &[("name", "Catherine")]),
+        0
+    );
+
+    // Operands without coded letters are compared as they are
+    assert_eq!(run(&query("1234", 1), &[("name", "1235")]), 1);
+    assert_eq!(run(&query("1234", 1), &[("name", "1255")]), 0);
+    assert_eq!(phonetic_key_distance("Smith", ""), 3);
+
+    // Only the limit the algorithm doesn't use
+    let similarity = [
+        ("target", text("Smith")),
+        ("algorithm", text("phonetic_key")),
+        ("min_similarity", text("0.5")),
+    ];
+    assert_eq!(run(&similarity, &[("name", "Smith")]), -1);
+
+    let filter_score_of = |params: &[(&str, Param)], field: &str| {
+        setup(params, &[("name", field)]);
+        let mut score = 9.0f32;
+        let result = unsafe { filter_score(1, &mut score) };
+        (result, score)
+    };
+    assert_eq!(filter_score_of(&query("Catherine", 0), "Kathryn"), (1, 1.0));
+    let (result, score) = filter_score_of(&query("Smith", 0), "Jones");
+    assert_eq!(result, 0);
+    assert!(score < 0.5, "{}", score);
+    setup(
+        &[
+            ("target", text("Catherine")),
+            ("algorithm", text("phonetic_key")),
+        ],
+        &[("name", "Kathryn")],
+    );
+    assert_eq!(ratio(1), 100);
+}
+
+#[test]
+#[cfg(all(feature = "token", feature = "arrays"))]
+fn host_tokens_match_like_the_udf_tokenizer() {
+    let _host = lock();
+    let query = |extra: &[(&'static str, Param)]| {
+        let mut params = vec![
+            ("algorithm", text("token_set_ratio")),
+            ("min_similarity", text("0.8")),
+        ];
+        params.extend_from_slice(extra);
+        params
+    };
+    let array = |tokens: &[&str]| {
+        let tokens: Vec<String> = tokens.iter().map(|token| format!("{:?}", token)).collect();
+        format!("[{}]", tokens.join(","))
+    };
+
+    // The same decisions whichever operands come tokenized
+    let words = [
+        "apple", "iphone", "13", "pro", "max", "case", "blue", "appel",
+    ];
+    let phrases = random_strings(191, 400, &['0', '1', '2', '3', '4', '5', '6', '7'], 4);
+    for pair in phrases.chunks(2) {
+        let split = |phrase: &str| -> Vec<&str> {
+            let tokens = phrase
+                .chars()
+                .map(|c| words[c.to_digit(10).unwrap() as usize]);
+            tokens.collect()
+        };
+        let (field, target) = (split(&pair[0]), split(&pair[1]));
+        if field.is_empty() || target.is_empty() {
+            continue;
+        }
+        let (field_text, target_text) = (field.join(" "), target.join(" "));
+        let (field_array, target_array) = (array(&field), array(&target));
+        for min_similarity in ["0.5", "0.8", "0.95"] {
+            let similarity = ("min_similarity", text(min_similarity));
+            let plain = [similarity.clone(), ("target", text(&target_text))];
+            let host_field = [
+                similarity.clone(),
+                ("target", text(&target_text)),
+                ("tokens_field", text("tokens")),
+            ];
+            let host_both = [
+                similarity.clone(),
+                ("target_tokens", text(&target_array)),
+                ("tokens_field", text("tokens")),
+            ];
+            let host_target = [similarity, ("target_tokens", text(&target_array))];
+            let expected = run(&query(&plain), &[("name", &field_text)]);
+            assert_eq!(
+                [
+                    run(&query(&host_field), &[("tokens", &field_array)]),
+                    run(&query(&host_both), &[("tokens", &field_array)]),
+                    run(&query(&host_target), &[("name", &field_text)]),
+                ],
+                [expected; 3],
+                "{} | {} | {}",
+                field_text,
+                target_text,
+                min_similarity
+            );
+        }
+    }
+
+    // Host tokens aren't split again, and are normalized one by one
+    let tokens = [
+        ("target", text("new york")),
+        ("tokens_field", text("tokens")),
+    ];
+    assert_eq!(run(&query(&tokens), &[("tokens", r#"["new york"]"#)]), 1);
+    let mut folded = vec![
+        ("target", text("apple iphone")),
+        ("tokens_field", text("tokens")),
+    ];
+    assert_eq!(
+        run(&query(&folded), &[("tokens", r#"["APPLE", "iPhone"]"#)]),
+        0
+    );
+    folded.push(("case_insensitive", flag(true)));
+    assert_eq!(
+        run(&query(&folded), &[("tokens", r#"["APPLE", "iPhone"]"#)]),
+        1
+    );
+
+    // A missing or malformed array matches nothing
+    let tokens = [("target", text("a")), ("tokens_field", text("tokens"))];
+    assert_eq!(run(&query(&tokens), &[("name", "a")]), 0);
+    assert_eq!(run(&query(&tokens), &[("tokens", "a")]), 0);
+    assert_eq!(
+        run(&query(&[("target_tokens", text("[1]"))]), &[("name", "a")]),
+        0
+    );
+
+    // Other algorithms read the field as usual
+    let levenshtein = [
+        ("target", text("abc")),
+        ("tokens_field", text("tokens")),
+        ("max_distance", int(0)),
+    ];
+    assert_eq!(
+        run(&levenshtein, &[("name", "abc"), ("tokens", r#"["zzz"]"#)]),
+        1
+    );
+    let wrong_limit = [
+        ("algorithm", text("token_set_ratio")),
+        ("max_distance", int(1)),
+        ("target", text("a")),
+        ("tokens_field", text("tokens")),
+    ];
+    assert_eq!(run(&wrong_limit, &[("tokens", r#"["a"]"#)]), -1);
+
+    let idf = ("idf", text(r#"{"acme": 0.05}"#));
+    let weighted = [
+        ("target", text("acme corp")),
+        ("tokens_field", text("tokens")),
+        idf.clone(),
+        ("min_similarity", text("0.5")),
+    ];
+    let tokenized = [
+        ("target", text("acme corp")),
+        idf,
+        ("min_similarity", text("0.5")),
+    ];
+    assert_eq!(
+        run(&query(&weighted), &[("tokens", r#"["acme", "widgets"]"#)]),
+        run(&query(&tokenized), &[("name", "acme widgets")])
+    );
+
+    let mut exact = 0;
+    let both = [
+        ("target_tokens", text(r#"["a", "b"]"#)),
+        ("tokens_field", text("tokens")),
+    ];
+    setup(&query(&both), &[("tokens", r#"["a", "b"]"#)]);
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+
+    // The tokens are read as an array, never as the field's text
+    assert_eq!(*host::ARRAY_CALLS.lock().unwrap(), 1);
+    assert_eq!(*host::FIELD_CALLS.lock().unwrap(), 0);
+    let config = r#"{
+        "algorithm": "token_set_ratio",
+        "target_tokens": ["b", "a"],
+        "tokens_field": "tokens"
+    }"#;
+    assert_eq!(
+        run(&[("config", text(config))], &[("tokens", r#"["a", "b"]"#)]),
+        1
+    );
+}
+
+#[test]
+#[cfg(all(feature = "token", not(feature = "arrays")))]
+fn host_tokens_need_the_arrays_feature() {
+    let _host = lock();
+    let params = [
+        ("algorithm", text("token_set_ratio")),
+        ("target", text("a b")),
+        ("tokens_field", text("tokens")),
+    ];
+    assert_eq!(run(&params, &[("tokens", r#"["a", "b"]"#)]), -1);
+    assert!(host::LOGS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|log| log.contains("need a build with the \"arrays\" feature")));
+    // Without either parameter the field is tokenized as usual
+    assert_eq!(run(&params[..2], &[("name", "b a")]), 1);
+}
+
+#[test]
+fn filter_reason_names_the_prefilter_that_decided() {
+    use crate::filter_reason;
+    let _host = lock();
+    let reason_of = |params: &[(&str, Param)], fields: &[(&str, &str)]| {
+        setup(params, fields);
+        let mut reason = -5;
+        let result = unsafe { filter_reason(1, &mut reason) };
+        (result, reason)
+    };
+    let target = |target: &str| ("target", text(target));
+
+    assert_eq!(reason_of(&[target("apple")], &[("name", "apple")]), (1, 1));
+    let bounded = [target("apple"), ("max_distance", int(1))];
+    assert_eq!(reason_of(&bounded, &[("name", "applesauce")]), (0, 2));
+    assert_eq!(reason_of(&bounded, &[("name", "appel")]), (0, 5));
+    let loose = [target("apple"), ("max_distance", int(2))];
+    assert_eq!(reason_of(&loose, &[("name", "appel")]), (1, 5));
+    let prefix = [target("apple"), ("require_prefix_len", int(2))];
+    assert_eq!(reason_of(&prefix, &[("name", "xpple")]), (0, 3));
+    let common_prefix = [target("apple"), ("min_match_prefix", int(3))];
+    assert_eq!(reason_of(&common_prefix, &[("name", "apxle")]), (0, 3));
+    let equal_length = [target("apple"), ("require_equal_length", flag(true))];
+    assert_eq!(reason_of(&equal_length, &[("name", "appl")]), (0, 4));
+    assert_eq!(reason_of(&[target("apple")], &[("other", "apple")]), (0, 0));
+    let negated = [target("apple"), ("negate", flag(true))];
+    assert_eq!(reason_of(&negated, &[("name", "apple")]), (0, 1));
+
+    // Equal only once normalized isn't byte-equal
+    let folded = [target("apple"), ("case_insensitive", flag(true))];
+    assert_eq!(reason_of(&folded, &[("name", "APPLE")]), (1, 5));
+
+    let targets = |targets: &str| [("targets", text(targets)), ("max_distance", int(1))];
+    assert_eq!(
+        reason_of(&targets(r#"["zz", "apple"]"#), &[("name", "apple")]),
+        (1, 5)
+    );
+    assert_eq!(
+        reason_of(&targets(r#"["zz", "applesauce"]"#), &[("name", "apple")]),
+        (0, 2)
+    );
+
+    // No prefilter turns down a field the DP accepts
+    let words = random_strings(7, 120, &['a', 'b', 'c'], 6);
+    for (field, target) in words.iter().zip(&words[1..]) {
+        if field.is_empty() || target.is_empty() {
+            continue;
+        }
+        for max_distance in 0..3 {
+            let params = [
+                ("target", text(target)),
+                ("max_distance", int(max_distance)),
+            ];
+            let (result, reason) = reason_of(&params, &[("name", field)]);
+            let within = naive_levenshtein(field, target) <= max_distance as usize;
+            assert_eq!(
+                result == 1,
+                within,
+                "{:?} {:?} {} {}",
+                field,
+                target,
+                max_distance,
+                reason
+            );
+            if reason == 2 {
+                assert!(!within, "{:?} {:?} {}", field, target, max_distance);
+            }
+            if reason == 1 {
+                assert_eq!(field, target);
+            }
+        }
+    }
+}
+
+#[test]
+fn vote_mode_combines_weak_matches_into_a_class() {
+    use crate::filter_vote;
+    let _host = lock();
+    let vote = |params: &[(&str, Param)], fields: &[(&str, &str)]| {
+        setup(params, fields);
+        let mut class = -5;
+        let result = unsafe { filter_vote(1, &mut class) };
+        (result, class)
+    };
+    let targets = text(r#"["iphone", "ipone", "galaxy", "iphon"]"#);
+    let weights = text(
+        r#"[{"class": 1, "weight": 0.3}, {"class": 1, "weight": 0.3},
+            {"class": 2, "weight": 0.5}, {"class": 1, "weight": 0.3}]"#,
+    );
+    let query = |extra: &[(&'static str, Param)]| {
+        let mut params = vec![
+            ("mode", text("vote")),
+            ("targets", targets.clone()),
+            ("target_weights", weights.clone()),
+            ("max_distance", int(1)),
+        ];
+        params.extend_from_slice(extra);
+        params
+    };
+    let threshold = |threshold: &str| ("vote_threshold", text(threshold));
+
+    // "iphne" is one edit from "iphone" and "ipone" (0.6), two from "iphon"
+    assert_eq!(
+        vote(&query(&[threshold("0.5")]), &[("name", "iphne")]),
+        (1, 1)
+    );
+    assert_eq!(
+        vote(&query(&[threshold("0.6")]), &[("name", "iphne")]),
+        (0, -1)
+    );
+    assert_eq!(
+        vote(&query(&[threshold("0.8")]), &[("name", "iphone")]),
+        (1, 1)
+    );
+    let exact = [threshold("0.5"), ("max_distance", int(0))];
+    assert_eq!(vote(&query(&exact), &[("name", "iphone")]), (0, -1));
+    let exact = [threshold("0.2"), ("max_distance", int(0))];
+    assert_eq!(vote(&query(&exact), &[("name", "iphone")]), (1, 1));
+    assert_eq!(
+        vote(&query(&[threshold("0.4")]), &[("name", "galaxi")]),
+        (1, 2)
+    );
+    assert_eq!(
+        vote(&query(&[threshold("0.5")]), &[("name", "galaxi")]),
+        (0, -1)
+    );
+
+    // The default threshold is 0, so any match elects its class
+    assert_eq!(vote(&query(&[]), &[("name", "zzz")]), (0, -1));
+    assert_eq!(vote(&query(&[]), &[("name", "galaxy")]), (1, 2));
+
+    let mut exact = 0;
+    setup(&query(&[("max_distance", int(0))]), &[("name", "galaxy")]);
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+
+    // negate flips the result, not the class
+    assert_eq!(
+        vote(&query(&[("negate", flag(true))]), &[("name", "galaxy")]),
+        (0, 2)
+    );
+
+    // A tie goes to the class that matched first
+    let tied = [
+        ("mode", text("vote")),
+        ("targets", text(r#"["ab", "ac"]"#)),
+        (
+            "target_weights",
+            text(r#"[{"class": 7, "weight": 1}, {"class": 3, "weight": 1}]"#),
+        ),
+        ("max_distance", int(1)),
+    ];
+    assert_eq!(vote(&tied, &[("name", "aa")]), (1, 7));
+
+    // No class outside "vote" mode
+    assert_eq!(vote(&[("target", text("a"))], &[("name", "a")]), (1, -1));
+
+    // Invalid: no list, missing or mismatched weights, a negative class
+    // or a NaN threshold
+    let one = text(r#"["a"]"#);
+    let weight = |weights: &str| ("target_weights", text(weights));
+    for (params, expected) in [
+        (vec![("mode", text("vote")), ("target", text("a"))], -1),
+        (
+            vec![("mode", text("vote")), ("targets", targets.clone())],
+            -1,
+        ),
+        (
+            vec![
+                ("mode", text("vote")),
+                ("targets", targets.clone()),
+                weight(r#"[{"class": 1, "weight": 1}]"#),
+            ],
+            -1,
+        ),
+        (
+            vec![
+                ("mode", text("vote")),
+                ("targets", one.clone()),
+                weight(r#"[{"class": -1, "weight": 1}]"#),
+            ],
+            -1,
+        ),
+        (
+            vec![
+                ("mode", text("vote")),
+                ("targets", one),
+                weight(r#"[{"class": 1, "weight": 1}]"#),
+                threshold("NaN"),
+            ],
+            -1,
+        ),
+    ] {
+        assert_eq!(
+            vote(&params, &[("name", "a")]),
+            (expected, -1),
+            "{:?}",
+            params
+        );
+    }
+}
+
+#[test]
+#[cfg(feature = "token")]
+fn oversized_lists_are_rejected_before_they_are_read() {
+    use crate::best_target;
+    let _host = lock();
+    let list = |len: usize| {
+        let elements: Vec<String> = (0..len).map(|i| format!("\"t{}\"", i)).collect();
+        format!("[{}]", elements.join(","))
+    };
+
+    let capped = |max_targets: i64| {
+        vec![
+            ("targets", text(&list(5))),
+            ("max_targets", int(max_targets)),
+            ("max_distance", int(0)),
+        ]
+    };
+    assert_eq!(run(&capped(4), &[("name", "t1")]), -2);
+    assert_eq!(run(&capped(5), &[("name", "t1")]), 1);
+    let mut negated = capped(4);
+    negated.push(("negate", flag(true)));
+    assert_eq!(run(&negated, &[("name", "t1")]), -2);
+    let logs = LOGS.lock().unwrap().clone();
+    assert!(
+        logs.iter()
+            .any(|log| log.contains("targets has 5 elements, over the cap of 4")),
+        "{:?}",
+        logs
+    );
+
+    // Alternatives count as targets
+    let alternatives = |max_targets: i64| {
+        [
+            ("target", text("a|b|c")),
+            ("alternation_delimiter", text("|")),
+            ("max_targets", int(max_targets)),
+        ]
+    };
+    assert_eq!(run(&alternatives(2), &[("name", "a")]), -2);
+    assert_eq!(run(&alternatives(3), &[("name", "a")]), 1);
+
+    setup(&capped(4), &[("name", "t1")]);
+    let (mut out, mut len, mut distance) = ([0u8; 16], 16, 0i64);
+    assert_eq!(
+        unsafe { best_target(1, out.as_mut_ptr(), &mut len, &mut distance) },
+        -2
+    );
+
+    // Array operands, on either side
+    let pairing = |max_elements: i64, field: &str| {
+        let params = [
+            ("mode", text("set_pairing")),
+            ("target", text(&list(3))),
+            ("max_array_elements", int(max_elements)),
+        ];
+        run(&params, &[("name", field)])
+    };
+    if cfg!(feature = "arrays") {
+        assert_eq!(pairing(2, &list(3)), -2);
+        assert_eq!(pairing(3, &list(3)), 1);
+        assert_eq!(pairing(2, r#"["t0"]"#), -2);
+    }
+
+    let tokens = |max_elements: i64| {
+        let params = [
+            ("algorithm", text("token_set_ratio")),
+            ("target", text("t0 t1 t2")),
+            ("tokens_field", text("tokens")),
+            ("max_array_elements", int(max_elements)),
+        ];
+        run(&params, &[("tokens", &list(3))])
+    };
+    if cfg!(feature = "arrays") {
+        assert_eq!(tokens(2), -2);
+        assert_eq!(tokens(3), 1);
+    }
+
+    // Lists over the 1 KiB parameter buffer are counted whole, not read as
+    // unset
+    let long = list(300);
+    assert!(long.len() > 1024);
+    let capped = |max_targets: i64| {
+        [
+            ("targets", text(&long)),
+            ("max_targets", int(max_targets)),
+            ("max_distance", int(0)),
+        ]
+    };
+    assert_eq!(run(&capped(299), &[("name", "t250")]), -2);
+    assert_eq!(run(&capped(300), &[("name", "t250")]), 1);
+    // A field whose tokens are a subset of the target's has ratio 1.0
+    let long_tokens = |max_elements: i64| {
+        let params = [
+            ("algorithm", text("token_set_ratio")),
+            ("target_tokens", text(&long)),
+            ("max_array_elements", int(max_elements)),
+        ];
+        run(&params, &[("name", "t0 t250")])
+    };
+    if cfg!(feature = "arrays") {
+        assert_eq!(long_tokens(299), -2);
+        assert_eq!(long_tokens(300), 1);
+    }
+
+    // A malformed list still just matches nothing
+    assert_eq!(run(&[("targets", text("[1]"))], &[("name", "a")]), 0);
+}
+
+#[test]
+#[cfg(feature = "ngram")]
+fn ngram_idf_weights_shared_ngrams_by_rarity() {
+    use crate::ngram::gram_idf;
+    let _host = lock();
+    let ngram = |target: &str, field: &str, min_similarity: &str, idf: Option<&str>| {
+        let mut params = vec![
+            ("algorithm", text("ngram")),
+            ("target", text(target)),
+            ("min_similarity", text(min_similarity)),
+        ];
+        params.extend(idf.map(|idf| ("ngram_idf", text(idf))));
+        run(&params, &[("name", field)])
+    };
+
+    // "nation" and "station" share ati, tio and ion: 2 * 3 / (4 + 5)
+    assert_eq!(ngram("nation", "station", "0.6", None), 1);
+    assert_eq!(
+        ngram(
+            "nation",
+            "station",
+            "0.6",
+            Some(r#"{"tio": 0.1, "ion": 0.1}"#)
+        ),
+        0
+    );
+    assert_eq!(
+        ngram("nation", "station", "0.6", Some(r#"{"tio": 1, "ion": 1}"#)),
+        1
+    );
+    assert_eq!(ngram("nation", "station", "0.75", None), 0);
+    assert_eq!(ngram("nation", "station", "0.75", Some(r#"{"ati": 5}"#)), 1);
+
+    // Keys of another length, negative weights and non-objects are invalid
+    assert_eq!(ngram("nation", "nation", "0.1", Some(r#"{"ab": 1}"#)), 0);
+    assert_eq!(ngram("nation", "nations", "0.1", Some(r#"{"ab": 1}"#)), 0);
+    assert_eq!(ngram("nation", "nations", "0.1", Some(r#"{"abc": -1}"#)), 0);
+    assert_eq!(ngram("nation", "nations", "0.1", Some("[1]")), 0);
+
+    // N-grams that all weigh nothing score as if there were none
+    assert_eq!(
+        ngram("abc", "abd", "0.0", Some(r#"{"abc": 0, "abd": 0}"#)),
+        1
+    );
+
+    // station weighs 4.5 with tio at 0.5, nation 3.5, and they share 2.5
+    let idf = gram_idf(vec![("tio".into(), 0.5)], 3).unwrap();
+    let similarity = crate::target_dice_similarity("station", "nation", 3, false, Some(&idf));
+    assert!((similarity - 5.0 / 8.0).abs() < 1e-12, "{}", similarity);
+}
+
+#[test]
+fn target_fields_join_parameters_into_the_target() {
+    let _host = lock();
+    let pieces = text(r#"["target_brand", "target_model"]"#);
+    let query = |extra: &[(&'static str, Param)]| {
+        let mut params = vec![
+            ("target_fields", pieces.clone()),
+            ("target_brand", text("Apple")),
+            ("target_model", text("iPhone 13")),
+            ("max_distance", int(0)),
+        ];
+        params.extend_from_slice(extra);
+        params
+    };
+    assert_eq!(run(&query(&[]), &[("name", "Apple iPhone 13")]), 1);
+    assert_eq!(run(&query(&[]), &[("name", "Apple iPhone")]), 0);
+    let dashed = [("target_separator", text("-"))];
+    assert_eq!(run(&query(&dashed), &[("name", "Apple-iPhone 13")]), 1);
+    assert_eq!(
+        run(
+            &query(&[("target", text("zzz"))]),
+            &[("name", "Apple iPhone 13")]
+        ),
+        1
+    );
+    let folded = [("case_insensitive", flag(true))];
+    assert_eq!(run(&query(&folded), &[("name", "apple iphone 13")]), 1);
+    let short = [("max_target_len", int(5))];
+    assert_eq!(run(&query(&short), &[("name", "Apple iPhone 13")]), -1);
+
+    // A missing piece is empty, and only missing pieces are no target
+    let missing = [
+        ("target_fields", pieces.clone()),
+        ("target_brand", text("Apple")),
+        ("max_distance", int(0)),
+    ];
+    assert_eq!(run(&missing, &[("name", "Apple ")]), 1);
+    assert_eq!(
+        run(&[("target_fields", text(r#"["x"]"#))], &[("name", "a")]),
+        0
+    );
+
+    // A malformed list is no target, even with `target` set
+    let malformed = [("target_fields", text("[1]")), ("target", text("a"))];
+    assert_eq!(run(&malformed, &[("name", "a")]), 0);
+
+    // strict_params knows the pieces the query names, and only those
+    let strict = r#"{"strict_params": true, "target_fields": ["target_brand", "target_model"],
+        "target_brand": "Apple", "target_model": "iPhone 13", "max_distance": 0}"#;
+    assert_eq!(
+        run(&[("config", text(strict))], &[("name", "Apple iPhone 13")]),
+        1
+    );
+    let strict = r#"{"strict_params": true, "target_fields": ["a"], "b": "x"}"#;
+    assert_eq!(run(&[("config", text(strict))], &[("name", "x")]), -1);
+}
+
+#[test]
+fn normalization_variants_match_when_any_variant_does() {
+    let _host = lock();
+    let three = r#"[[], ["case_insensitive"], ["case_insensitive", "ignore_accents"]]"#;
+    let query = |target: &str, max_distance: i64, variants: Option<&str>| {
+        let mut params = vec![
+            ("target", text(target)),
+            ("max_distance", int(max_distance)),
+        ];
+        params.extend(variants.map(|variants| ("normalization_variants", text(variants))));
+        params
+    };
+
+    // Only the accent-folded variant matches
+    let raw_and_case = r#"[[], ["case_insensitive"]]"#;
+    assert_eq!(
+        run(&query("Café Crème", 0, None), &[("name", "cafe creme")]),
+        0
+    );
+    assert_eq!(
+        run(
+            &query("Café Crème", 0, Some(raw_and_case)),
+            &[("name", "cafe creme")]
+        ),
+        0
+    );
+    assert_eq!(
+        run(
+            &query("Café Crème", 0, Some(three)),
+            &[("name", "cafe creme")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &query("Café Crème", 1, Some(three)),
+            &[("name", "cafe cremx")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &query("Café Crème", 1, Some(three)),
+            &[("name", "cafx cremx")]
+        ),
+        0
+    );
+    assert_eq!(run(&query("Café", 0, Some(three)), &[("name", "Café")]), 1);
+
+    let mut exact = 0;
+    setup(&query("Café", 1, Some(three)), &[("name", "cafe")]);
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 1);
+    setup(&query("Café", 1, Some(three)), &[("name", "cafx")]);
+    assert_eq!(unsafe { crate::filter_ex(1, &mut exact) }, 1);
+    assert_eq!(exact, 0);
+
+    // The variants replace the switches
+    let mut switched = query("Café", 0, Some("[[]]"));
+    switched.push(("ignore_accents", flag(true)));
+    assert_eq!(run(&switched, &[("name", "Cafe")]), 0);
+
+    let mut targets = query("x", 0, Some(three));
+    targets.push(("targets", text(r#"["zzz", "Café"]"#)));
+    assert_eq!(run(&targets, &[("name", "cafe")]), 1);
+
+    // Invalid variants match nothing, even negated
+    for variants in [
+        "[]",
+        r#"[["nope"]]"#,
+        r#"[["case_insensitive", "case_insensitive"]]"#,
+        r#"["x"]"#,
+        "{}",
+    ] {
+        let mut negated = query("a", 1, Some(variants));
+        negated.push(("negate", flag(true)));
+        assert_eq!(run(&negated, &[("name", "zzz")]), 0, "{}", variants);
+    }
+
+    // One variant is that normalization, and several the OR of each
+    let words = random_strings(11, 80, &['a', 'B', 'é', 'e', 'E'], 5);
+    for (field, target) in words.iter().zip(&words[1..]) {
+        if field.is_empty() || target.is_empty() {
+            continue;
+        }
+        for max_distance in 0..3 {
+            let mut switches = query(target, max_distance, None);
+            switches.push(("case_insensitive", flag(true)));
+            switches.push(("ignore_accents", flag(true)));
+            let one = query(
+                target,
+                max_distance,
+                Some(r#"[["case_insensitive", "ignore_accents"]]"#),
+            );
+            assert_eq!(
+                run(&switches, &[("name", field)]),
+                run(&one, &[("name", field)]),
+                "{} {} {}",
+                field,
+                target,
+                max_distance
+            );
+
+            let any =
+                (2..=switches.len()).any(|end| run(&switches[..end], &[("name", field)]) == 1);
+            let variants = run(
+                &query(target, max_distance, Some(three)),
+                &[("name", field)],
+            );
+            assert_eq!(variants == 1, any, "{} {} {}", field, target, max_distance);
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "jaro")]
+fn windowed_distance_matches_chars_within_the_window() {
+    use crate::jaro::{jaro_similarity, windowed_distance, windowed_similarity};
+    let _host = lock();
+
+    assert_eq!(windowed_distance("abcd", "abdc", 1), 1);
+    assert_eq!(windowed_distance("abc", "abd", 2), 1);
+    assert_eq!(windowed_distance("abc", "ab", 2), 1);
+    assert_eq!(windowed_distance("", "", 2), 0);
+    assert_eq!(windowed_distance("", "abc", 2), 3);
+    assert_eq!(windowed_distance("abc", "abc", 0), 0);
+
+    // "x" moves 3 places: unmatched with a window of 2, out of order with 3
+    assert_eq!(windowed_distance("xabc", "abcx", 2), 1);
+    assert_eq!(windowed_distance("xabc", "abcx", 3), 2);
+    assert_eq!(windowed_distance("ab", "ba", 0), 2);
+    assert_eq!(windowed_distance("ab", "ba", 1), 1);
+
+    // An adjacent swap is one edit, where Levenshtein needs two
+    assert_eq!(naive_levenshtein("martha", "marhta"), 2);
+    assert_eq!(windowed_distance("martha", "marhta", 1), 1);
+    assert_eq!(windowed_distance("martha", "marth", 2), 1);
+
+    // Jaro's window grows with the lengths, this one doesn't
+    let (rotated, target) = ("abcdefgh", "defghabc");
+    assert!((jaro_similarity(rotated, target) - 0.75).abs() < 1e-9);
+    assert_eq!(windowed_distance(rotated, target, 1), 8);
+    assert_eq!(windowed_distance(rotated, target, 2), 8);
+
+    // With Jaro's window, both are perfect only for equal strings
+    for (a, b) in [
+        ("martha", "marhta"),
+        ("dixon", "dicksonx"),
+        ("jellyfish", "smellyfish"),
+    ] {
+        let window = (a.chars().count().max(b.chars().count()) / 2).saturating_sub(1);
+        let distance = windowed_distance(a, b, window);
+        let similarity = windowed_similarity(a, b, window);
+        assert!((0.0..=1.0).contains(&similarity));
+        assert!(distance <= a.len().max(b.len()));
+        assert_eq!(jaro_similarity(a, b) == 1.0, distance == 0);
+    }
+
+    let words = random_strings(5, 200, &['a', 'b', 'c', 'd'], 7);
+    for (a, b) in words.iter().zip(&words[1..]) {
+        for window in 0..4 {
+            let distance = windowed_distance(a, b, window);
+            assert!(distance <= a.chars().count().max(b.chars().count()));
+            assert_eq!(distance == 0, a == b, "{} {} {}", a, b, window);
+            assert_eq!(
+                distance,
+                windowed_distance(b, a, window),
+                "{} {} {}",
+                a,
+                b,
+                window
+            );
+        }
+    }
+
+    let query = |target: &str, field: &str, max_distance: i64, window: Option<i64>| {
+        let mut params = vec![
+            ("algorithm", text("windowed")),
+            ("target", text(target)),
+            ("max_distance", int(max_distance)),
+        ];
+        params.extend(window.map(|window| ("match_window", int(window))));
+        run(&params, &[("name", field)])
+    };
+    assert_eq!(query("martha", "marhta", 1, None), 1);
+    assert_eq!(query("martha", "marhta", 0, None), 0);
+    assert_eq!(query("ab", "ba", 1, Some(0)), 0);
+    assert_eq!(query("ab", "ba", 1, Some(1)), 1);
+    assert_eq!(query("ab", "ba", 1, Some(-1)), 0);
+
+    // min_similarity alone is the wrong limit
+    let params = [
+        ("algorithm", text("windowed")),
+        ("target", text("ab")),
+        ("min_similarity", text("0.5")),
+    ];
+    assert_eq!(run(&params, &[("name", "ba")]), -1);
+
+    let params = [
+        ("algorithm", text("windowed")),
+        ("target", text("martha")),
+        ("max_distance", int(1)),
+    ];
+    setup(&params, &[("name", "marhta")]);
+    let mut score = 0f32;
+    assert_eq!(unsafe { crate::filter_score(1, &mut score) }, 1);
+    assert!((score - (1.0 - 1.0 / 6.0)).abs() < 1e-6);
+}
+
+#[test]
+#[cfg(all(
+    feature = "ngram",
+    feature = "jaro",
+    feature = "token",
+    feature = "phonetic",
+    feature = "substring"
+))]
+fn a_full_build_compiles_every_algorithm_in() {
+    use crate::missing_feature;
+    let _host = lock();
+    for algorithm in [
+        "jaro",
+        "ngram",
+        "token_set_ratio",
+        "phonetic_key",
+        "name_match",
+        "windowed",
+        "trigram_coverage",
+        "lcstr",
+    ] {
+        assert_eq!(missing_feature(algorithm), None);
+        let params = [
+            ("field", text("name")),
+            ("target", text("iphone")),
+            ("algorithm", text(algorithm)),
+        ];
+        assert_eq!(run(&params, &[("name", "iphone")]), 1, "{}", algorithm);
+    }
+    assert_eq!(missing_feature("bogus"), None);
+}
+
+#[test]
+#[cfg(not(any(
+    feature = "ngram",
+    feature = "jaro",
+    feature = "token",
+    feature = "phonetic",
+    feature = "substring"
+)))]
+fn a_minimal_build_rejects_the_algorithms_it_leaves_out() {
+    use crate::missing_feature;
+    let _host = lock();
+    for (algorithm, feature) in [
+        ("jaro", "jaro"),
+        ("jaro_winkler", "jaro"),
+        ("windowed", "jaro"),
+        ("ngram", "ngram"),
+        ("trigram_coverage", "ngram"),
+        ("token_set_ratio", "token"),
+        ("phonetic_key", "phonetic"),
+        ("name_match", "phonetic"),
+        ("lcstr", "substring"),
+    ] {
+        assert_eq!(missing_feature(algorithm), Some(feature));
+        let params = [("target", text("iphone")), ("algorithm", text(algorithm))];
+        assert_eq!(run(&params, &[("name", "iphone")]), -1, "{}", algorithm);
+        let logs = LOGS.lock().unwrap().clone();
+        assert!(logs
+            .iter()
+            .any(|message| message.contains("not compiled in")));
+
+        let negated = [
+            ("target", text("iphone")),
+            ("algorithm", text(algorithm)),
+            ("negate", flag(true)),
+        ];
+        assert_eq!(run(&negated, &[("name", "zzz")]), -1, "{}", algorithm);
+    }
+
+    let policy = [
+        ("target", text("iphone")),
+        ("max_distance", int(1)),
+        ("min_similarity", text("0.9")),
+        ("match_policy", text("distance_or_similarity")),
+    ];
+    assert_eq!(run(&policy, &[("name", "iphone")]), -1);
+
+    // Levenshtein and the modes need no feature
+    assert_eq!(missing_feature("levenshtein"), None);
+    assert_eq!(run(&[("target", text("iphone"))], &[("name", "iphnoe")]), 1);
+    let exact = [
+        ("target", text("iPhone")),
+        ("algorithm", text("exact_normalized")),
+    ];
+    assert_eq!(run(&exact, &[("name", "iPhone")]), 1);
+}
+
+#[test]
+fn regex_mode_matches_the_whole_field_within_max_distance() {
+    use crate::regex::Regex;
+    let _host = lock();
+    let check = |pattern: &str, value: &str, max_distance: Option<i64>| {
+        let mut params = vec![
+            ("field", text("code")),
+            ("mode", text("regex")),
+            ("pattern", text(pattern)),
+        ];
+        params.extend(max_distance.map(|max_distance| ("max_distance", int(max_distance))));
+        run(&params, &[("code", value)])
+    };
+
+    let code = r"[A-Z]{3}-\d{4}";
+    assert_eq!(check(code, "ABC-1234", None), 1);
+    assert!(unsafe { crate::EXACT_MATCH });
+    assert_eq!(check(code, "ABC-12345", None), 0);
+    assert_eq!(
+        check(code, "xABC-1234", None),
+        0,
+        "the whole field has to match"
+    );
+
+    assert_eq!(check(code, "ABC_1234", Some(1)), 1);
+    assert!(!unsafe { crate::EXACT_MATCH });
+    assert_eq!(check(code, "ABC-123", Some(1)), 1);
+    assert_eq!(check(code, "AB-123", Some(1)), 0);
+    assert_eq!(check(code, "AB-123", Some(2)), 1);
+    assert_eq!(check("colou?r", "colr", Some(1)), 1);
+    assert_eq!(check("colou?r", "clr", Some(1)), 0);
+    assert_eq!(check("(grey|gray) cat(s)?", "grey cats", None), 1);
+    assert_eq!(check("(grey|gray) cat(s)?", "grzy cat", Some(1)), 1);
+    assert_eq!(check(".*foo.*", "xxfooyy", None), 1);
+    assert_eq!(check(".*foo.*", "xxfoyy", Some(1)), 1);
+    assert_eq!(check("a*", "", None), 1);
+    assert_eq!(check("(a*)*b", "aaab", None), 1);
+    assert_eq!(check("(a*)*b", "aaac", Some(1)), 1);
+
+    // No target needed, and the field is still normalized
+    let folded = [
+        ("field", text("code")),
+        ("mode", text("regex")),
+        ("pattern", text("abc")),
+        ("case_insensitive", flag(true)),
+    ];
+    assert_eq!(run(&folded, &[("code", "ABC")]), 1);
+    let listed = [
+        ("field", text("code")),
+        ("mode", text("regex")),
+        ("pattern", text("ab+c")),
+        ("targets", text(r#"["x", "y"]"#)),
+    ];
+    assert_eq!(run(&listed, &[("code", "abbbc")]), 1);
+    let missing = [
+        ("field", text("nope")),
+        ("mode", text("regex")),
+        ("pattern", text("abc")),
+    ];
+    assert_eq!(run(&missing, &[("code", "ABC")]), 0);
+
+    let unset = [("field", text("code")), ("mode", text("regex"))];
+    assert_eq!(run(&unset, &[("code", "ABC")]), -1);
+    let logs = LOGS.lock().unwrap().join("\n");
+    assert!(logs.contains("needs a pattern"));
+    assert_eq!(check("(abc", "abc", None), -1);
+    assert_eq!(check(&"a".repeat(300), "a", None), -1);
+    let logs = LOGS.lock().unwrap().join("\n");
+    assert!(logs.contains("not a supported regex"));
+
+    // A literal pattern is Levenshtein distance
+    let words = random_strings(200, 300, &['a', 'b', 'c'], 6);
+    for pair in words.chunks(2) {
+        let (pattern, value) = (&pair[0], &pair[1]);
+        if pattern.is_empty() {
+            continue;
+        }
+        let distance = naive_levenshtein(pattern, value);
+        let regex = Regex::new(pattern).unwrap();
+        let chars: Vec<char> = value.chars().collect();
+        assert_eq!(
+            regex.full_match_distance(&chars, 10),
+            Some(distance),
+            "{} {}",
+            pattern,
+            value
+        );
+        if distance > 0 {
+            assert_eq!(regex.full_match_distance(&chars, distance - 1), None);
+        }
+    }
+}
+
+#[test]
+fn fields_are_read_only_until_one_matches() {
+    use host::FIELD_READS;
+    let _host = lock();
+    let document = [
+        ("a", "zzzz"),
+        ("b", "iphone"),
+        ("c", "qqqq"),
+        ("d", "rrrr"),
+        ("e", "ssss"),
+        ("f", "tttt"),
+    ];
+    let reads = || FIELD_READS.lock().unwrap().clone();
+
+    let single = [
+        ("fields", text(r#"["a", "b", "c", "d"]"#)),
+        ("target", text("iphone")),
+    ];
+    assert_eq!(run(&single, &document), 1);
+    assert_eq!(reads(), ["a", "b"]);
+
+    // Each field is compared with every target before the next is read
+    let listed = [
+        ("fields", text(r#"["a", "b", "c", "d", "e", "f"]"#)),
+        ("targets", text(r#"["galaxy", "iphone"]"#)),
+        ("case_insensitive", flag(true)),
+    ];
+    assert_eq!(run(&listed, &document), 1);
+    assert_eq!(reads(), ["a", "b"]);
+
+    // More fields than the memo holds are still read once each
+    let many = [
+        ("fields", text(r#"["a", "c", "d", "e", "f", "b"]"#)),
+        ("targets", text(r#"["galaxy", "pixel", "iphone"]"#)),
+        ("case_insensitive", flag(true)),
+    ];
+    assert_eq!(run(&many, &document), 1);
+    assert_eq!(reads(), ["a", "c", "d", "e", "f", "b"]);
+
+    let unmatched = [
+        ("fields", text(r#"["a", "c"]"#)),
+        ("targets", text(r#"["galaxy", "pixel"]"#)),
+    ];
+    assert_eq!(run(&unmatched, &document), 0);
+    assert_eq!(reads(), ["a", "c"]);
+
+    // A diagnostic code ends the search as well
+    let invalid = [
+        ("fields", text(r#"["a", "b"]"#)),
+        ("targets", text(r#"["galaxy", "pixel"]"#)),
+        ("min_similarity", text("0.5")),
+    ];
+    assert_eq!(run(&invalid, &document), -1);
+}
+
+#[test]
+fn fold_table_replaces_chars_in_both_operands() {
+    use host::set_params;
+    let _host = lock();
+    let check = |extra: &[(&'static str, Param)], value: &str, target: &str| {
+        let mut params = vec![
+            ("field", text("name")),
+            ("target", text(target)),
+            ("max_distance", int(0)),
+        ];
+        params.extend_from_slice(extra);
+        run(&params, &[("name", value)])
+    };
+    let table = text(r#"{"ß": "ss", "ı": "i", "ﬁ": "fi"}"#);
+    let folded = [("fold_table", table.clone())];
+
+    assert_eq!(check(&[], "straße", "strasse"), 0);
+    assert_eq!(check(&folded, "straße", "strasse"), 1);
+    assert!(unsafe { crate::EXACT_MATCH });
+    assert_eq!(check(&folded, "ﬁle", "file"), 1);
+    assert_eq!(check(&folded, "strasse", "straße"), 1);
+
+    // The table runs before case folding, so it sees the uppercase chars
+    let lowercase = [
+        ("fold_table", text(r#"{"é": "e"}"#)),
+        ("case_insensitive", flag(true)),
+    ];
+    assert_eq!(check(&lowercase, "CAFÉ", "cafe"), 0);
+    let both = [
+        ("fold_table", text(r#"{"é": "e", "É": "e"}"#)),
+        ("case_insensitive", flag(true)),
+    ];
+    assert_eq!(check(&both, "CAFÉ", "cafe"), 1);
+
+    // A malformed table is ignored
+    assert_eq!(
+        check(&[("fold_table", text(r#"{"ab": "x"}"#))], "ab", "x"),
+        0
+    );
+    let logs = LOGS.lock().unwrap().join("\n");
+    assert!(logs.contains("fold_table is not"));
+    assert_eq!(check(&[("fold_table", text("[1]"))], "a", "a"), 1);
+
+    // A new table replaces the last one
+    assert_eq!(check(&[("fold_table", text(r#"{"x": "a"}"#))], "x", "a"), 1);
+    assert_eq!(check(&[("fold_table", text(r#"{"x": "b"}"#))], "x", "a"), 0);
+    assert_eq!(check(&[("fold_table", text(r#"{"x": "b"}"#))], "x", "b"), 1);
+
+    // In a pipeline, the table is a step of its own
+    let without = [
+        ("fold_table", table.clone()),
+        ("normalization_pipeline", text(r#"["case_insensitive"]"#)),
+    ];
+    assert_eq!(check(&without, "straße", "strasse"), 0);
+    let after_case = [
+        ("fold_table", table.clone()),
+        (
+            "normalization_pipeline",
+            text(r#"["case_insensitive", "fold_table"]"#),
+        ),
+    ];
+    assert_eq!(check(&after_case, "STRAßE", "strasse"), 1);
+
+    // The forms kept for a document are told apart by table
+    let mut params = vec![
+        ("field", text("name")),
+        ("target", text("strasse")),
+        ("max_distance", int(0)),
+        ("fold_table", table),
+    ];
+    setup(&params, &[("name", "straße")]);
+    assert_eq!(crate::filter(1), 1);
+    params.pop();
+    set_params(&params);
+    assert_eq!(crate::filter(2), 0);
+}
+
+#[test]
+#[cfg(feature = "jaro")]
+fn consensus_needs_every_component_to_pass() {
+    use crate::jaro::jaro_winkler_similarity;
+    let _host = lock();
+    let both = text(
+        r#"[{"algorithm": "levenshtein", "max_distance": 2},
+            {"algorithm": "jaro_winkler", "min_similarity": 0.9}]"#,
+    );
+    let consensus = |value: &str, target: &str, components: Param| {
+        let params = [
+            ("field", text("name")),
+            ("target", text(target)),
+            ("match_policy", text("consensus")),
+            ("components", components),
+        ];
+        run(&params, &[("name", value)])
+    };
+    let single = |value: &str, target: &str, extra: &[(&'static str, Param)]| {
+        let mut params = vec![("field", text("name")), ("target", text(target))];
+        params.extend_from_slice(extra);
+        run(&params, &[("name", value)])
+    };
+    let levenshtein = [("max_distance", int(2))];
+    let jaro_winkler = [
+        ("algorithm", text("jaro_winkler")),
+        ("min_similarity", text("0.9")),
+    ];
+
+    // Levenshtein alone passes
+    assert_eq!(single("ab", "ba", &levenshtein), 1);
+    assert_eq!(single("ab", "ba", &jaro_winkler), 0);
+    assert_eq!(consensus("ab", "ba", both.clone()), 0);
+
+    // Jaro-Winkler alone passes
+    let (value, target) = ("johnsonxyz", "johnson");
+    assert!(jaro_winkler_similarity(value, target) >= 0.9);
+    assert_eq!(naive_levenshtein(value, target), 3);
+    assert_eq!(single(value, target, &jaro_winkler), 1);
+    assert_eq!(single(value, target, &levenshtein), 0);
+    assert_eq!(consensus(value, target, both.clone()), 0);
+
+    assert_eq!(consensus("jonson", "johnson", both.clone()), 1);
+    assert_eq!(consensus("johnson", "johnson", both.clone()), 1);
+
+    // A component without a limit takes the query's
+    let params = [
+        ("field", text("name")),
+        ("target", text("johnson")),
+        ("match_policy", text("consensus")),
+        ("max_distance", int(3)),
+        (
+            "components",
+            text(
+                r#"[{"algorithm": "levenshtein"}, {"algorithm": "jaro_winkler", "min_similarity": 0.9}]"#,
+            ),
+        ),
+    ];
+    assert_eq!(run(&params, &[("name", value)]), 1);
+
+    assert_eq!(consensus("a", "a", text("[]")), -1);
+    let logs = LOGS.lock().unwrap().join("\n");
+    assert!(logs.contains("non-empty components"));
+    assert_eq!(consensus("a", "b", text(r#"[{"algorithm": "bogus"}]"#)), -1);
+    assert_eq!(
+        consensus(
+            "a",
+            "b",
+            text(r#"[{"algorithm": "jaro", "max_distance": 1}]"#)
+        ),
+        -1
+    );
+    let logs = LOGS.lock().unwrap().join("\n");
+    assert!(logs.contains("max_distance doesn't apply to consensus algorithm \"jaro\""));
+    let negative = text(r#"[{"algorithm": "levenshtein", "max_distance": -1}]"#);
+    assert_eq!(consensus("a", "b", negative), -1);
+    assert_eq!(consensus("a", "b", text(r#"[{"weight": 1}]"#)), -1);
+    assert_eq!(
+        consensus("a", "a", text(r#"[{"algorithm": "exact_normalized"}]"#)),
+        1
+    );
+    let limited = text(r#"[{"algorithm": "exact_normalized", "min_similarity": 0.5}]"#);
+    assert_eq!(consensus("a", "b", limited), -1);
+
+    let params = [
+        ("field", text("name")),
+        ("target", text("abc")),
+        ("match_policy", text("consensus")),
+        ("algorithm", text("jaro")),
+        ("components", both.clone()),
+    ];
+    run(&params, &[("name", "abc")]);
+    let logs = LOGS.lock().unwrap().join("\n");
+    assert!(logs.contains("algorithm ignored with match_policy \"consensus\""));
+
+    let negated = [
+        ("field", text("name")),
+        ("target", text("ba")),
+        ("match_policy", text("consensus")),
+        ("components", both),
+        ("negate", flag(true)),
+    ];
+    assert_eq!(run(&negated, &[("name", "ab")]), 1);
+}
+
+#[test]
+fn filter_units_counts_chars_and_grapheme_clusters() {
+    use crate::graphemes::graphemes;
+    let _host = lock();
+    assert_eq!(graphemes("e\u{301}a"), ["e\u{301}", "a"]);
+    assert_eq!(graphemes("🇩🇪🇫🇷🇮"), ["🇩🇪", "🇫🇷", "🇮"]);
+    assert_eq!(graphemes("👨‍👩‍👧x"), ["👨‍👩‍👧", "x"]);
+    assert_eq!(graphemes("👍🏽👍"), ["👍🏽", "👍"]);
+    assert_eq!(graphemes("a\r\nb"), ["a", "\r\n", "b"]);
+    assert_eq!(graphemes("a\u{200D}b"), ["a\u{200D}", "b"]);
+    assert_eq!(
+        graphemes("\u{1100}\u{1161}\u{11A8}가"),
+        ["\u{1100}\u{1161}\u{11A8}", "가"]
+    );
+    assert!(graphemes("").is_empty());
+    let alphabet = ['a', '\u{301}', '\u{200D}', '👨', '🇩', '\r', '\n'];
+    for s in random_strings(3, 50, &alphabet, 9) {
+        assert_eq!(graphemes(&s).concat(), s);
+    }
+
+    let units = |target: &str, extra: &[(&'static str, Param)], value: &str| {
+        let mut params = vec![("field", text("name")), ("target", text(target))];
+        params.extend_from_slice(extra);
+        setup(&params, &[("name", value)]);
+        let (mut chars, mut clusters) = (9i64, 9i64);
+        let result = unsafe { crate::filter_units(1, &mut chars, &mut clusters) };
+        (result, chars, clusters)
+    };
+    assert_eq!(units("🇩🇪", &[], "🇫🇷"), (1, 2, 1));
+    assert_eq!(units("👨‍👩‍👧", &[("max_distance", int(1))], "👨"), (0, 4, 1));
+    let graphemes = [("max_distance", int(1)), ("unit", text("grapheme"))];
+    assert_eq!(units("👨‍👩‍👧", &graphemes, "👨"), (1, 4, 1));
+    let chars = [("max_distance", int(1)), ("unit", text("char"))];
+    assert_eq!(units("🇩🇪", &chars, "🇫🇷"), (0, 2, 1));
+    let exact = [("unit", text("grapheme")), ("max_distance", int(0))];
+    assert_eq!(units("cafe\u{301}", &exact, "cafe"), (0, 1, 1));
+    assert_eq!(
+        units("Abc", &[("case_insensitive", flag(true))], "abd"),
+        (1, 1, 1)
+    );
+
+    assert_eq!(units("abc", &[("unit", text("word"))], "abc"), (-1, -1, -1));
+    assert_eq!(
+        units("abc", &[("max_distance", int(-1))], "abc"),
+        (-1, -1, -1)
+    );
+    setup(&[("target", text("abc"))], &[("other", "x")]);
+    let (mut chars, mut clusters) = (9i64, 9i64);
+    assert_eq!(
+        unsafe { crate::filter_units(1, &mut chars, &mut clusters) },
+        0
+    );
+    assert_eq!((chars, clusters), (-1, -1));
+}
+
+#[test]
+#[cfg(feature = "token")]
+fn mixed_script_tokens_lower_the_token_set_ratio() {
+    use crate::script::mixes_scripts;
+    let _host = lock();
+    assert!(mixes_scripts("p\u{430}ypal"));
+    assert!(!mixes_scripts("paypal"));
+    assert!(!mixes_scripts("東京タワー"));
+    assert!(!mixes_scripts("abc123"));
+    assert!(!mixes_scripts("москва"));
+
+    let query = |penalty: Option<&str>| {
+        let mut params = vec![
+            ("field", text("name")),
+            ("target", text("paypal login")),
+            ("algorithm", text("token_set_ratio")),
+        ];
+        params.extend(penalty.map(|penalty| ("mixed_script_penalty", text(penalty))));
+        params
+    };
+    let spoof = "p\u{430}ypal login";
+    assert_eq!(run(&query(None), &[("name", spoof)]), 1);
+    assert_eq!(run(&query(Some("1.0")), &[("name", spoof)]), 0);
+    assert_eq!(run(&query(Some("1.0")), &[("name", "paypal logn")]), 1);
+    assert_eq!(run(&query(Some("0")), &[("name", spoof)]), 1);
+    assert_eq!(run(&query(Some("-1")), &[("name", spoof)]), 0);
+
+    setup(&query(Some("0")), &[("name", spoof)]);
+    assert_eq!(ratio(1), 92);
+    setup(&query(Some("1.0")), &[("name", spoof)]);
+    assert_eq!(ratio(1), 67);
+
+    // Tokens the host split are checked too
+    let mut tokens = vec![
+        ("tokens_field", text("tokens")),
+        ("target", text("paypal login")),
+        ("algorithm", text("token_set_ratio")),
+    ];
+    let document = [("tokens", r#"["pаypal", "login"]"#)];
+    if cfg!(feature = "arrays") {
+        assert_eq!(run(&tokens, &document), 1);
+        tokens.push(("mixed_script_penalty", text("1.0")));
+        assert_eq!(run(&tokens, &document), 0);
+    } else {
+        assert_eq!(run(&tokens, &document), -1);
+    }
+}
+
+#[test]
+fn strip_affixes_cut_boilerplate_from_the_field() {
+    let _host = lock();
+    let query = |extra: &[(&'static str, Param)]| {
+        let mut params = vec![
+            ("field", text("title")),
+            ("target", text("iPhone 13 Pro")),
+            ("max_distance", int(1)),
+        ];
+        params.extend_from_slice(extra);
+        params
+    };
+    let affixes = [
+        ("strip_prefixes", text(r#"["NEW!", "NEW! HOT!"]"#)),
+        ("strip_suffixes", text(r#"["(Refurbished)", "(Used)"]"#)),
+    ];
+    let title = "NEW! HOT! iPhone 13 Pro (Refurbished)";
+    assert_eq!(run(&query(&[]), &[("title", title)]), 0);
+    assert_eq!(run(&query(&affixes), &[("title", title)]), 1);
+    assert_eq!(run(&query(&affixes), &[("title", "NEW! iPhone 13 Pro")]), 1);
+
+    // A field with no affix is compared as it is
+    assert_eq!(run(&query(&affixes), &[("title", "iPhone 13 Pro")]), 1);
+    assert_eq!(run(&query(&affixes), &[("title", "iPhone 13 Pro Max")]), 0);
+    assert_eq!(run(&query(&affixes), &[("title", "OLD! iPhone 13 Pro")]), 0);
+
+    let lowercase = "new! hot! iPhone 13 Pro (refurbished)";
+    assert_eq!(run(&query(&affixes), &[("title", lowercase)]), 0);
+    let mut folded = query(&affixes);
+    folded.push(("case_insensitive", flag(true)));
+    assert_eq!(run(&folded, &[("title", lowercase)]), 1);
+
+    // The longest prefix is cut, not the first listed
+    let exact = [
+        ("field", text("title")),
+        ("target", text("x")),
+        ("max_distance", int(0)),
+        ("strip_prefixes", text(r#"["NEW!", "NEW! HOT!"]"#)),
+    ];
+    assert_eq!(run(&exact, &[("title", "NEW! HOT! x")]), 1);
+
+    setup(&query(&affixes), &[("title", title)]);
+    assert_eq!(ratio(1), 100);
+
+    let malformed = query(&[("strip_prefixes", text("[1]"))]);
+    assert_eq!(run(&malformed, &[("title", "iPhone 13 Pro")]), 0);
+    let logs = LOGS.lock().unwrap().join("\n");
+    assert!(logs.contains("strip_prefixes is not a JSON array of strings"));
+
+    // Nothing left of the field
+    let whole = [
+        ("field", text("title")),
+        ("target", text("abc")),
+        ("strip_suffixes", text(r#"["abc"]"#)),
+        ("empty_field_behavior", text("nomatch")),
+    ];
+    assert_eq!(run(&whole, &[("title", "abc")]), 0);
+
+    // Folded multibyte chars still cut on a char boundary
+    let accented = [
+        ("field", text("title")),
+        ("target", text("café")),
+        ("max_distance", int(0)),
+        ("case_insensitive", flag(true)),
+        ("strip_suffixes", text(r#"["ÉTÉ"]"#)),
+    ];
+    assert_eq!(run(&accented, &[("title", "Caféété")]), 1);
+}
+
+#[test]
+fn fuzzy_occurrences_counts_non_overlapping_windows() {
+    use crate::fuzzy_occurrences;
+    use crate::partial::occurrences;
+    let _host = lock();
+    let count = |params: &[(&'static str, Param)], value: &str| {
+        setup(params, &[("name", value)]);
+        fuzzy_occurrences(1)
+    };
+    let query = |target: &str, max_distance: i64| {
+        vec![
+            ("target", text(target)),
+            ("max_distance", int(max_distance)),
+        ]
+    };
+    assert_eq!(count(&query("iPhone", 1), "iPhne case for iPhone 13"), 2);
+    assert_eq!(count(&query("iPhone", 1), "iPhoneiPhone"), 2);
+    assert_eq!(count(&query("iPhone", 0), "iPhne case for iPhone 13"), 1);
+    assert_eq!(count(&query("iPhone", 1), "nothing here"), 0);
+    assert_eq!(count(&query("aa", 0), "aaaa"), 2);
+    assert_eq!(count(&query("aa", 0), "aaa"), 1);
+    assert_eq!(count(&query("ab", 2), "xyz"), 3);
+    assert_eq!(count(&query("", 0), "xyz"), 0);
+    assert_eq!(count(&query("ab", -1), "ab"), -1);
+    let folded = [
+        ("target", text("IPHONE")),
+        ("case_insensitive", flag(true)),
+        ("max_distance", int(0)),
+    ];
+    assert_eq!(count(&folded, "iphone IPhone"), 2);
+    setup(&[("target", text("abc"))], &[("other", "x")]);
+    assert_eq!(fuzzy_occurrences(1), 0);
+
+    // Greedily close each occurrence at the first end within the limit
+    let words = random_strings(41, 300, &['a', 'b', 'c'], 14);
+    for (k, field) in words.iter().enumerate() {
+        let target: String = words[(k * 7 + 3) % words.len()]
+            .chars()
+            .take(1 + k % 4)
+            .collect();
+        for max in 0..3 {
+            let chars: Vec<char> = field.chars().collect();
+            let (mut expected, mut from) = (0, 0);
+            for end in 1..=chars.len() {
+                let found = target.chars().count() <= max
+                    || (from..end).any(|start| {
+                        let window: String = chars[start..end].iter().collect();
+                        naive_levenshtein(&window, &target) <= max
+                    });
+                if found && !target.is_empty() {
+                    expected += 1;
+                    from = end;
+                }
+            }
+            assert_eq!(
+                occurrences(field, &target, max),
+                expected,
+                "{:?} {:?} {}",
+                field,
+                target,
+                max
+            );
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "substring")]
+fn lcstr_matches_on_the_longest_shared_run() {
+    use crate::substring::longest_common_substring;
+    let _host = lock();
+    assert_eq!(longest_common_substring("", ""), 0);
+    assert_eq!(longest_common_substring("abc", ""), 0);
+    assert_eq!(longest_common_substring("abcdef", "zcdez"), 3);
+    assert_eq!(longest_common_substring("zcdez", "abcdef"), 3);
+    assert_eq!(longest_common_substring("GeeksforGeeks", "GeeksQuiz"), 5);
+    assert_eq!(
+        longest_common_substring("OldSite:GeeksforGeeks.org", "NewSite:GeeksQuiz.com"),
+        10
+    );
+    assert_eq!(longest_common_substring("abab", "baba"), 3);
+    assert_eq!(longest_common_substring("xyz", "abc"), 0);
+    assert_eq!(
+        longest_common_substring("日本語テキスト", "テキスト処理"),
+        4
+    );
+
+    let words = random_strings(5, 200, &['a', 'b', 'c'], 10);
+    for pair in words.chunks(2) {
+        let a: Vec<char> = pair[0].chars().collect();
+        let longest = (0..=a.len())
+            .flat_map(|start| (start..=a.len()).map(move |end| (start, end)))
+            .filter(|&(start, end)| pair[1].contains(&a[start..end].iter().collect::<String>()))
+            .map(|(start, end)| end - start)
+            .max()
+            .unwrap_or(0);
+        assert_eq!(longest_common_substring(&pair[0], &pair[1]), longest);
+    }
+
+    let query = |target: &str, min_common_len: Option<i64>| {
+        let mut params = vec![
+            ("field", text("title")),
+            ("target", text(target)),
+            ("algorithm", text("lcstr")),
+        ];
+        params.extend(min_common_len.map(|len| ("min_common_len", int(len))));
+        params
+    };
+    assert_eq!(
+        run(
+            &query("XK-4471-B", Some(6)),
+            &[("title", "part no XK-4471 (black)")]
+        ),
+        1
+    );
+    assert_eq!(
+        run(&query("XK-4471-B", Some(6)), &[("title", "XK-4417-B")]),
+        0
+    );
+    assert_eq!(
+        run(&query("XK-4471-B", Some(5)), &[("title", "XK-4417-B")]),
+        1
+    );
+    assert_eq!(run(&query("XK-4471-B", None), &[("title", "zzXK-zz")]), 1);
+    assert_eq!(run(&query("XK-4471-B", None), &[("title", "zzXKzz")]), 0);
+
+    // The known lengths decide at the limit
+    assert_eq!(run(&query("zcdez", Some(3)), &[("title", "abcdef")]), 1);
+    assert_eq!(run(&query("zcdez", Some(4)), &[("title", "abcdef")]), 0);
+    assert_eq!(
+        run(&query("GeeksQuiz", Some(5)), &[("title", "GeeksforGeeks")]),
+        1
+    );
+    assert_eq!(
+        run(&query("GeeksQuiz", Some(6)), &[("title", "GeeksforGeeks")]),
+        0
+    );
+
+    // A shorter target can't share a long enough run, even with itself
+    assert_eq!(run(&query("ab", Some(5)), &[("title", "xxabxx")]), 0);
+    assert_eq!(run(&query("ab", Some(5)), &[("title", "ab")]), 0);
+    assert_eq!(run(&query("ab", Some(2)), &[("title", "ab")]), 1);
+    assert_eq!(run(&query("abc", Some(-1)), &[("title", "abc")]), 0);
+
+    // An exact-only target needs the run and equality both
+    let mut exact_only = query("XK-4471-B", Some(6));
+    exact_only.push(("min_target_len_for_fuzzy", int(10)));
+    assert_eq!(run(&exact_only, &[("title", "part no XK-4471 (black)")]), 0);
+    assert_eq!(run(&exact_only, &[("title", "XK-4471-B")]), 1);
+    let mut short = query("ab", Some(3));
+    short.push(("min_target_len_for_fuzzy", int(10)));
+    assert_eq!(run(&short, &[("title", "ab")]), 0);
+
+    setup(&query("abcdef", None), &[("title", "xxcdexx")]);
+    assert_eq!(ratio(1), 50);
+    let mut score = 0f32;
+    assert_eq!(unsafe { crate::filter_score(1, &mut score) }, 1);
+    assert_eq!(score, 0.5);
+
+    let consensus = [
+        ("field", text("title")),
+        ("target", text("XK-4471-B")),
+        ("match_policy", text("consensus")),
+        (
+            "components",
+            text(r#"[{"algorithm": "lcstr"}, {"algorithm": "levenshtein", "max_distance": 2}]"#),
+        ),
+    ];
+    assert_eq!(run(&consensus, &[("title", "XK-4471-8")]), 1);
+}
+
+#[test]
+fn non_finite_floats_are_rejected_end_to_end() {
+    let _host = lock();
+    let query = |extra: &[(&'static str, Param)]| {
+        let mut params = vec![("field", text("name")), ("target", text("abc"))];
+        params.extend_from_slice(extra);
+        params
+    };
+    for (name, value) in [
+        ("min_similarity", "NaN"),
+        ("threshold", "inf"),
+        ("case_mismatch_cost", "1e999"),
+        ("variant_cost", "-Infinity"),
+    ] {
+        for negate in [false, true] {
+            let params = query(&[(name, text(value)), ("negate", flag(negate))]);
+            assert_eq!(run(&params, &[("name", "abc")]), -1, "{} {}", name, value);
+        }
+        let logs = LOGS.lock().unwrap().join("\n");
+        assert!(logs.contains(&format!("{} is not a finite number", name)));
+    }
+    if cfg!(feature = "f64_params") {
+        for (name, value) in [("min_similarity", f64::NAN), ("threshold", f64::INFINITY)] {
+            assert_eq!(run(&query(&[(name, float(value))]), &[("name", "abc")]), -1);
+        }
+    }
+
+    // Each float is checked where it is read, by whichever export reads it
+    let pairing = |name, value| {
+        [
+            ("field", text("tags")),
+            ("target", text(r#"["a", "b"]"#)),
+            ("mode", text("set_pairing")),
+            (name, text(value)),
+        ]
+    };
+    assert_eq!(
+        run(&pairing("max_total", "NaN"), &[("tags", r#"["a", "b"]"#)]),
+        -1
+    );
+    let unmatched = pairing("unmatched_penalty", "-Infinity");
+    assert_eq!(run(&unmatched, &[("tags", r#"["a", "b"]"#)]), -1);
+    let penalty = query(&[("length_penalty", text("-inf"))]);
+    setup(&penalty, &[("name", "abc")]);
+    let mut score = 9.0f32;
+    assert_eq!(unsafe { crate::filter_score(1, &mut score) }, -1);
+    assert_eq!(score, -1.0);
+    // `filter` doesn't read `length_penalty`
+    assert_eq!(run(&penalty, &[("name", "abc")]), 1);
+    let reads = host::PARAM_READS.lock().unwrap().clone();
+    for unread in ["length_penalty", "unmatched_penalty", "vote_threshold"] {
+        assert!(!reads.contains(&unread.to_string()), "{:?}", reads);
+    }
+
+    let finite = query(&[("threshold", text("1")), ("length_penalty", text("0.5"))]);
+    assert_eq!(run(&finite, &[("name", "abd")]), 1);
+
+    // The digit-only modes would see no digits in "inf" or "NaN"
+    let numeric = |mode: &str, target: &str, value: &str, negate: bool| {
+        let params = [
+            ("field", text("n")),
+            ("target", text(target)),
+            ("mode", text(mode)),
+            ("negate", flag(negate)),
+        ];
+        run(&params, &[("n", value)])
+    };
+    for mode in ["numeric_typo", "phone"] {
+        assert_eq!(numeric(mode, "12345", "inf", false), -1);
+        assert_eq!(numeric(mode, "12345", "-Infinity", false), -1);
+        assert_eq!(numeric(mode, "NaN", "12345", false), -1);
+        assert_eq!(numeric(mode, "nan", "NaN", false), -1);
+        assert_eq!(numeric(mode, "NaN", "inf", true), -1);
+    }
+    assert_eq!(numeric("numeric_typo", "12345", "12354", false), 1);
+    assert_eq!(numeric("numeric_typo", "12345", "info 12345", false), 1);
+
+    // Other modes compare them as text
+    assert_eq!(run(&query(&[]), &[("name", "inf")]), 0);
+    let inf = [("field", text("name")), ("target", text("inf"))];
+    assert_eq!(run(&inf, &[("name", "inf")]), 1);
+}
+
+#[test]
+fn target_variants_match_the_dehyphenated_spelling() {
+    use crate::variants::spelling_variants;
+    let _host = lock();
+    assert_eq!(
+        spelling_variants("Wi-Fi Router"),
+        [
+            "Wi-Fi Router",
+            "wi-fi router",
+            "Wi-fi Router",
+            "WiFi Router",
+            "Wi Fi Router"
+        ]
+    );
+    assert_eq!(spelling_variants("abc"), ["abc", "Abc"]);
+    assert_eq!(spelling_variants(""), [""]);
+
+    let query = |extra: &[(&'static str, Param)]| {
+        let mut params = vec![
+            ("field", text("title")),
+            ("target", text("Wi-Fi Router")),
+            ("max_distance", int(0)),
+        ];
+        params.extend_from_slice(extra);
+        params
+    };
+    let on = [("target_variants", flag(true))];
+    // The field only matches the target with its hyphen removed
+    assert_eq!(run(&query(&[]), &[("title", "WiFi Router")]), 0);
+    assert_eq!(run(&query(&on), &[("title", "WiFi Router")]), 1);
+    for title in [
+        "Wi Fi Router",
+        "wi-fi router",
+        "Wi-fi Router",
+        "Wi-Fi Router",
+    ] {
+        assert_eq!(run(&query(&on), &[("title", title)]), 1, "{}", title);
+    }
+    // No variant combines two respellings
+    assert_eq!(run(&query(&on), &[("title", "wifi router")]), 0);
+    let negated = query(&[("target_variants", flag(true)), ("negate", flag(true))]);
+    assert_eq!(run(&negated, &[("title", "WiFi Router")]), 0);
+
+    // Each target of a list has its own variants
+    let listed = |all_targets: bool, title: &str| {
+        let params = [
+            ("field", text("title")),
+            ("targets", text(r#"["Wi-Fi", "wifi"]"#)),
+            ("max_distance", int(0)),
+            ("target_variants", flag(true)),
+            ("all_targets", flag(all_targets)),
+        ];
+        run(&params, &[("title", title)])
+    };
+    assert_eq!(listed(false, "WiFi"), 1);
+    assert_eq!(listed(true, "WiFi"), 0);
+    assert_eq!(listed(true, "wifi"), 0);
+
+    // A target read from the document, and several fields
+    let from_field = [
+        ("field", text("title")),
+        ("target_field", text("canon")),
+        ("max_distance", int(0)),
+        ("target_variants", flag(true)),
+    ];
+    assert_eq!(
+        run(&from_field, &[("title", "e mail"), ("canon", "E-Mail")]),
+        0
+    );
+    assert_eq!(
+        run(&from_field, &[("title", "E Mail"), ("canon", "E-Mail")]),
+        1
+    );
+    let fields = [
+        ("fields", text(r#"["a", "b"]"#)),
+        ("target", text("X-Ray")),
+        ("max_distance", int(0)),
+        ("target_variants", flag(true)),
+    ];
+    assert_eq!(run(&fields, &[("a", "zzz"), ("b", "XRay")]), 1);
+
+    // No target, no match
+    let untargeted = [("field", text("title")), ("target_variants", flag(true))];
+    assert_eq!(run(&untargeted, &[("title", "x")]), 0);
+}
+
+#[test]
+fn a_low_cell_budget_forces_an_early_flagged_decision() {
+    use crate::filter_budgeted;
+    let _host = lock();
+    let query = |budget: Option<i64>| {
+        let mut params = vec![
+            ("field", text("title")),
+            ("targets", text(r#"["galaxy s24", "pixel 9", "iphone 15"]"#)),
+            ("max_distance", int(1)),
+        ];
+        params.extend(budget.map(|cells| ("cell_budget", int(cells))));
+        params
+    };
+    let budgeted = |params: &[(&str, Param)], fields: &[(&str, &str)]| {
+        setup(params, fields);
+        let mut exhausted = -5;
+        let result = unsafe { filter_budgeted(1, &mut exhausted) };
+        (result, exhausted)
+    };
+    let doc = [("title", "iphone 15s")];
+
+    // 100 and 70 cells spent on the first two targets, and the 90 the
+    // matching third needs are over a budget of 250
+    assert_eq!(budgeted(&query(None), &doc), (1, 0));
+    assert_eq!(budgeted(&query(Some(250)), &doc), (0, 1));
+    assert_eq!(budgeted(&query(Some(260)), &doc), (1, 0));
+    assert_eq!(budgeted(&query(Some(0)), &doc), (0, 1));
+    assert_eq!(budgeted(&query(Some(-1)), &doc), (-1, 0));
+    assert!(LOGS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|log| log.contains("cell_budget is negative")));
+
+    // A match found before the budget runs out stands
+    assert_eq!(
+        budgeted(&query(Some(120)), &[("title", "galaxy s23")]),
+        (1, 0)
+    );
+    assert_eq!(
+        budgeted(&query(Some(99)), &[("title", "galaxy s23")]),
+        (0, 1)
+    );
+
+    // Each call starts over, and other exports compare without a budget
+    assert_eq!(budgeted(&query(Some(250)), &doc), (0, 1));
+    assert_eq!(budgeted(&query(None), &doc), (1, 0));
+    setup(
+        &[("field", text("title")), ("target", text("iphone 15"))],
+        &doc,
+    );
+    assert!(ratio(1) > 0);
+
+    // Fields past the budget aren't compared
+    let fields = |budget: i64| {
+        vec![
+            ("fields", text(r#"["a", "b"]"#)),
+            ("target", text("abcd")),
+            ("max_distance", int(0)),
+            ("cell_budget", int(budget)),
+        ]
+    };
+    let two = [("a", "zzzz"), ("b", "abcd")];
+    assert_eq!(budgeted(&fields(32), &two), (1, 0));
+    assert_eq!(budgeted(&fields(31), &two), (0, 1));
+
+    // A target never compared is no match for `all_targets`, `negate` flips
+    // the partial result, and `min_margin` is charged too
+    let mut all = query(Some(250));
+    all[1] = (
+        "targets",
+        text(r#"["iphone 15", "iphone 15t", "iphone 15u"]"#),
+    );
+    all.push(("all_targets", flag(true)));
+    assert_eq!(budgeted(&all, &doc), (0, 1));
+    let mut negated = query(Some(250));
+    negated.push(("negate", flag(true)));
+    assert_eq!(budgeted(&negated, &doc), (1, 1));
+    let mut margin = query(Some(150));
+    margin.push(("min_margin", text("0.1")));
+    assert_eq!(budgeted(&margin, &doc), (0, 1));
+}
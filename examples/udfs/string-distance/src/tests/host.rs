@@ -0,0 +1,484 @@
+//! A stand-in for the Quidditch host, which the tests link against instead
+//! of the real imports.
+//!
+//! Each import the module declares is defined here with `#[no_mangle]`,
+//! answering from the parameters and fields the test last passed to
+//! `setup`, with the return codes the module's helpers expect. The state is
+//! global, as the module's own is, so every test holds `lock` throughout.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+/// A query parameter as the host holds it
+#[derive(Clone, Debug)]
+pub enum Param {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+pub fn text(value: &str) -> Param {
+    Param::Str(value.to_string())
+}
+
+pub fn int(value: i64) -> Param {
+    Param::Int(value)
+}
+
+pub fn float(value: f64) -> Param {
+    Param::Float(value)
+}
+
+pub fn flag(value: bool) -> Param {
+    Param::Bool(value)
+}
+
+static LOCK: Mutex<()> = Mutex::new(());
+static PARAMS: Mutex<Option<HashMap<String, Param>>> = Mutex::new(None);
+static FIELDS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+/// The ID of the document, a new one at each `setup`
+static DOCUMENT_ID: Mutex<u64> = Mutex::new(0);
+/// Number of `get_field_string` calls since the last `setup`
+pub static FIELD_CALLS: Mutex<usize> = Mutex::new(0);
+/// The fields `get_field_string` read since the last `setup`, in order
+pub static FIELD_READS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Number of `get_field_chunk` calls since the last `setup`
+pub static CHUNK_CALLS: Mutex<usize> = Mutex::new(0);
+/// Number of `get_field_array` calls since the last `setup`
+pub static ARRAY_CALLS: Mutex<usize> = Mutex::new(0);
+/// Number of `get_param_*` calls since the last `setup`
+pub static PARAM_CALLS: Mutex<usize> = Mutex::new(0);
+/// The parameters `get_param_*` read since the last `setup`, in order
+pub static PARAM_READS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Number of `get_time_ns` calls since the last `setup`; each advances the
+/// clock by `CLOCK_STEP_NS`
+pub static CLOCK_READS: Mutex<i64> = Mutex::new(0);
+pub const CLOCK_STEP_NS: i64 = 1500;
+/// Every message logged since the last `setup`
+pub static LOGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// What `get_field_doc_freq` answers per field: the return code and the
+/// share it writes; other fields answer 1 (not known)
+pub static DOC_FREQ: Mutex<Option<HashMap<String, (i32, f64)>>> = Mutex::new(None);
+/// An import that breaks its contract: `(import, name, code, len)` makes
+/// `import` return `code` for `name` (any name if empty), writing `len` as
+/// the length or the scalar value and nothing into the buffer
+pub static MISBEHAVE: Mutex<Option<(&str, &str, i32, i32)>> = Mutex::new(None);
+/// Every counter `emit_stat` reported since the last `setup`, in order
+pub static STATS: Mutex<Vec<(String, i64)>> = Mutex::new(Vec::new());
+/// Whether `get_params_blob` passes the parameters, off (1) by default
+pub static BLOB_ON: Mutex<bool> = Mutex::new(false);
+/// Number of `get_params_blob` calls since the last `setup`
+pub static BLOB_CALLS: Mutex<usize> = Mutex::new(0);
+
+/// Serialize the tests, which share the host and the module's statics
+///
+/// A test that failed while holding the lock leaves nothing behind that
+/// the next `setup` doesn't replace, so a poisoned lock is taken anyway.
+pub fn lock() -> MutexGuard<'static, ()> {
+    LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Make `params` the query's parameters and `fields` the document's
+pub fn setup(params: &[(&str, Param)], fields: &[(&str, &str)]) {
+    set_params(params);
+    *DOC_FREQ.lock().unwrap() = None;
+    *MISBEHAVE.lock().unwrap() = None;
+    *BLOB_ON.lock().unwrap() = false;
+    next_document(fields);
+}
+
+/// Make `fields` the next document of the same query
+///
+/// The counters and logs start over, as at `setup`, but the parameters and
+/// the host's behavior are kept and the module isn't told of a change.
+pub fn next_document(fields: &[(&str, &str)]) {
+    let fields = fields
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+    *FIELDS.lock().unwrap() = Some(fields.collect());
+    *DOCUMENT_ID.lock().unwrap() += 1;
+    *FIELD_CALLS.lock().unwrap() = 0;
+    FIELD_READS.lock().unwrap().clear();
+    *CHUNK_CALLS.lock().unwrap() = 0;
+    *ARRAY_CALLS.lock().unwrap() = 0;
+    *PARAM_CALLS.lock().unwrap() = 0;
+    PARAM_READS.lock().unwrap().clear();
+    *CLOCK_READS.lock().unwrap() = 0;
+    LOGS.lock().unwrap().clear();
+    *BLOB_CALLS.lock().unwrap() = 0;
+    STATS.lock().unwrap().clear();
+}
+
+/// Make `params` the query's parameters, keeping the document
+///
+/// Like the host, this tells the module with `params_changed`.
+pub fn set_params(params: &[(&str, Param)]) {
+    let params = params
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.clone()));
+    *PARAMS.lock().unwrap() = Some(params.collect());
+    crate::params_changed();
+}
+
+/// `filter` on a document of `fields` for a query of `params`
+pub fn run(params: &[(&str, Param)], fields: &[(&str, &str)]) -> i32 {
+    setup(params, fields);
+    crate::filter(1)
+}
+
+/// The `len` bytes at `ptr`, as the host reads a name out of the module
+unsafe fn read(ptr: *const u8, len: i32) -> String {
+    let bytes = std::slice::from_raw_parts(ptr, len as usize);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn param(name: &str) -> Option<Param> {
+    *PARAM_CALLS.lock().unwrap() += 1;
+    PARAM_READS.lock().unwrap().push(name.to_string());
+    PARAMS.lock().unwrap().as_ref()?.get(name).cloned()
+}
+
+/// The code and length `import` answers for `name` if it misbehaves
+fn misbehaving(import: &str, name: &str) -> Option<(i32, i32)> {
+    match *MISBEHAVE.lock().unwrap() {
+        Some((bad, bad_name, code, len))
+            if bad == import && (bad_name.is_empty() || bad_name == name) =>
+        {
+            Some((code, len))
+        }
+        _ => None,
+    }
+}
+
+fn field(name: &str) -> Option<String> {
+    FIELDS.lock().unwrap().as_ref()?.get(name).cloned()
+}
+
+/// Copy `value` into the module's buffer of `*len_ptr` bytes: 0 and its
+/// length when it fits, 3 and the length needed when it doesn't
+unsafe fn write(value: &[u8], out: *mut u8, len_ptr: *mut i32) -> i32 {
+    if value.len() > *len_ptr as usize {
+        *len_ptr = value.len() as i32;
+        return 3;
+    }
+    std::ptr::copy_nonoverlapping(value.as_ptr(), out, value.len());
+    *len_ptr = value.len() as i32;
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn has_field(_ctx_id: i64, field_ptr: *const u8, field_len: i32) -> i32 {
+    let name = read(field_ptr, field_len);
+    if let Some((code, _)) = misbehaving("has_field", &name) {
+        return code;
+    }
+    field(&name).is_some() as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn get_field_string(
+    _ctx_id: i64,
+    field_ptr: *const u8,
+    field_len: i32,
+    value_ptr: *mut u8,
+    value_len_ptr: *mut i32,
+) -> i32 {
+    *FIELD_CALLS.lock().unwrap() += 1;
+    let name = read(field_ptr, field_len);
+    FIELD_READS.lock().unwrap().push(name.clone());
+    if let Some((code, len)) = misbehaving("get_field_string", &name) {
+        *value_len_ptr = len;
+        return code;
+    }
+    // Like the host, 1 when the value was written and 0 otherwise
+    match field(&name) {
+        Some(value) => (write(value.as_bytes(), value_ptr, value_len_ptr) == 0) as i32,
+        None => 0,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn get_document_id(_ctx_id: i64, id_ptr: *mut u8, id_len_ptr: *mut i32) -> i32 {
+    if let Some((code, len)) = misbehaving("get_document_id", "") {
+        *id_len_ptr = len;
+        return code;
+    }
+    let id = format!("doc-{}", DOCUMENT_ID.lock().unwrap());
+    (write(id.as_bytes(), id_ptr, id_len_ptr) == 0) as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn get_field_chunk(
+    _ctx_id: i64,
+    field_ptr: *const u8,
+    field_len: i32,
+    offset: i32,
+    buf_ptr: *mut u8,
+    buf_len: i32,
+    out_len_ptr: *mut i32,
+) -> i32 {
+    *CHUNK_CALLS.lock().unwrap() += 1;
+    let name = read(field_ptr, field_len);
+    if let Some((code, len)) = misbehaving("get_field_chunk", &name) {
+        *out_len_ptr = len;
+        return code;
+    }
+    let value = match field(&name) {
+        Some(value) => value,
+        None => return 1,
+    };
+    let rest = value.as_bytes().get(offset as usize..).unwrap_or_default();
+    let chunk = &rest[..rest.len().min(buf_len as usize)];
+    std::ptr::copy_nonoverlapping(chunk.as_ptr(), buf_ptr, chunk.len());
+    *out_len_ptr = chunk.len() as i32;
+    0
+}
+
+/// `text` as an encoded array if it is a JSON array of strings
+///
+/// Fields are held as text here, so a field that is a JSON array of strings
+/// stands for an array field; parameters are text, so the host parses one
+/// for `get_param_array` as well.
+#[cfg(feature = "arrays")]
+fn encode_array(text: &str) -> Option<Vec<u8>> {
+    let value = crate::json::parse(text)?;
+    let items: Option<Vec<&str>> = value.as_array()?.iter().map(|item| item.as_str()).collect();
+    Some(crate::array::encode(&items?))
+}
+
+#[cfg(feature = "arrays")]
+#[no_mangle]
+unsafe extern "C" fn get_field_array(
+    _ctx_id: i64,
+    field_ptr: *const u8,
+    field_len: i32,
+    value_ptr: *mut u8,
+    value_len_ptr: *mut i32,
+) -> i32 {
+    *ARRAY_CALLS.lock().unwrap() += 1;
+    let name = read(field_ptr, field_len);
+    if let Some((code, len)) = misbehaving("get_field_array", &name) {
+        *value_len_ptr = len;
+        return code;
+    }
+    match field(&name).map(|value| encode_array(&value)) {
+        Some(Some(array)) => write(&array, value_ptr, value_len_ptr),
+        Some(None) => 2,
+        None => 1,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn get_field_doc_freq(
+    _ctx_id: i64,
+    field_ptr: *const u8,
+    field_len: i32,
+    out_ptr: *mut f64,
+) -> i32 {
+    let name = read(field_ptr, field_len);
+    match DOC_FREQ
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|freqs| freqs.get(&name))
+    {
+        Some(&(code, freq)) => {
+            *out_ptr = freq;
+            code
+        }
+        None => 1,
+    }
+}
+
+/// Like the host, floats are truncated for an integer read
+#[no_mangle]
+unsafe extern "C" fn get_param_i64(name_ptr: *const u8, name_len: i32, out_ptr: *mut i64) -> i32 {
+    let name = read(name_ptr, name_len);
+    if let Some((code, len)) = misbehaving("get_param_i64", &name) {
+        *out_ptr = len as i64;
+        return code;
+    }
+    match param(&name) {
+        Some(Param::Int(value)) => {
+            *out_ptr = value;
+            0
+        }
+        Some(Param::Float(value)) => {
+            *out_ptr = value as i64;
+            0
+        }
+        Some(_) => 2,
+        None => 1,
+    }
+}
+
+/// Like the host, integers are widened for a float read
+#[no_mangle]
+unsafe extern "C" fn get_param_f64(name_ptr: *const u8, name_len: i32, out_ptr: *mut f64) -> i32 {
+    let name = read(name_ptr, name_len);
+    if let Some((code, len)) = misbehaving("get_param_f64", &name) {
+        *out_ptr = len as f64;
+        return code;
+    }
+    match param(&name) {
+        Some(Param::Float(value)) => {
+            *out_ptr = value;
+            0
+        }
+        Some(Param::Int(value)) => {
+            *out_ptr = value as f64;
+            0
+        }
+        Some(_) => 2,
+        None => 1,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn get_param_bool(name_ptr: *const u8, name_len: i32, out_ptr: *mut i32) -> i32 {
+    let name = read(name_ptr, name_len);
+    if let Some((code, len)) = misbehaving("get_param_bool", &name) {
+        *out_ptr = len;
+        return code;
+    }
+    match param(&name) {
+        Some(Param::Bool(value)) => {
+            *out_ptr = value as i32;
+            0
+        }
+        Some(_) => 2,
+        None => 1,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn get_param_string(
+    name_ptr: *const u8,
+    name_len: i32,
+    value_ptr: *mut u8,
+    value_len_ptr: *mut i32,
+) -> i32 {
+    let name = read(name_ptr, name_len);
+    if let Some((code, len)) = misbehaving("get_param_string", &name) {
+        *value_len_ptr = len;
+        return code;
+    }
+    match param(&name) {
+        Some(Param::Str(value)) => write(value.as_bytes(), value_ptr, value_len_ptr),
+        Some(_) => 2,
+        None => 1,
+    }
+}
+
+#[cfg(feature = "arrays")]
+#[no_mangle]
+unsafe extern "C" fn get_param_array(
+    name_ptr: *const u8,
+    name_len: i32,
+    value_ptr: *mut u8,
+    value_len_ptr: *mut i32,
+) -> i32 {
+    let name = read(name_ptr, name_len);
+    if let Some((code, len)) = misbehaving("get_param_array", &name) {
+        *value_len_ptr = len;
+        return code;
+    }
+    match param(&name) {
+        Some(Param::Str(value)) => match encode_array(&value) {
+            Some(array) => write(&array, value_ptr, value_len_ptr),
+            None => 2,
+        },
+        Some(_) => 2,
+        None => 1,
+    }
+}
+
+/// The parameter names as a JSON array, sorted
+#[no_mangle]
+unsafe extern "C" fn get_param_names(value_ptr: *mut u8, value_len_ptr: *mut i32) -> i32 {
+    if let Some((code, len)) = misbehaving("get_param_names", "") {
+        *value_len_ptr = len;
+        return code;
+    }
+    let mut names: Vec<String> = PARAMS
+        .lock()
+        .unwrap()
+        .iter()
+        .flatten()
+        .map(|(name, _)| format!("{:?}", name))
+        .collect();
+    names.sort();
+    let list = format!("[{}]", names.join(","));
+    write(list.as_bytes(), value_ptr, value_len_ptr)
+}
+
+/// `params` as `get_params_blob` writes them, in name order
+pub fn encode_blob(params: &[(&str, Param)]) -> Vec<u8> {
+    let mut params = params.to_vec();
+    params.sort_by(|a, b| a.0.cmp(b.0));
+    let text = |blob: &mut Vec<u8>, text: &str| {
+        blob.extend((text.len() as u32).to_le_bytes());
+        blob.extend(text.as_bytes());
+    };
+
+    let mut blob = (params.len() as u32).to_le_bytes().to_vec();
+    for (name, value) in &params {
+        text(&mut blob, name);
+        match value {
+            Param::Str(value) => {
+                blob.push(0);
+                text(&mut blob, value);
+            }
+            Param::Int(value) => {
+                blob.push(1);
+                blob.extend(value.to_le_bytes());
+            }
+            Param::Float(value) => {
+                blob.push(2);
+                blob.extend(value.to_le_bytes());
+            }
+            Param::Bool(value) => {
+                blob.push(3);
+                blob.push(*value as u8);
+            }
+        }
+    }
+    blob
+}
+
+/// Every parameter in one blob, when `BLOB_ON`
+#[no_mangle]
+unsafe extern "C" fn get_params_blob(out_ptr: *mut u8, out_len_ptr: *mut i32) -> i32 {
+    *BLOB_CALLS.lock().unwrap() += 1;
+    if let Some((code, len)) = misbehaving("get_params_blob", "") {
+        *out_len_ptr = len;
+        return code;
+    }
+    if !*BLOB_ON.lock().unwrap() {
+        return 1;
+    }
+    let params = PARAMS.lock().unwrap().clone().unwrap_or_default();
+    let params: Vec<(&str, Param)> = params
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.clone()))
+        .collect();
+    write(&encode_blob(&params), out_ptr, out_len_ptr)
+}
+
+#[no_mangle]
+unsafe extern "C" fn get_time_ns() -> i64 {
+    let mut reads = CLOCK_READS.lock().unwrap();
+    *reads += 1;
+    *reads * CLOCK_STEP_NS
+}
+
+#[no_mangle]
+unsafe extern "C" fn emit_stat(key_ptr: *const u8, key_len: i32, value: i64) {
+    STATS.lock().unwrap().push((read(key_ptr, key_len), value));
+}
+
+#[no_mangle]
+unsafe extern "C" fn log(_level: i32, msg_ptr: *const u8, msg_len: i32) {
+    LOGS.lock().unwrap().push(read(msg_ptr, msg_len));
+}
@@ -0,0 +1,161 @@
+//! Token-based similarity.
+//!
+//! Tokenizing serves the modes as well; the token set ratio is only compiled
+//! in with the `token` feature.
+
+#[cfg(feature = "token")]
+use std::collections::HashMap;
+
+#[cfg(feature = "token")]
+use crate::levenshtein_similarity;
+#[cfg(feature = "token")]
+use crate::script::mixes_scripts;
+use crate::regex::Regex;
+use crate::words::unicode_words;
+
+/// Per-token weights supplied with the `idf` parameter
+#[cfg(feature = "token")]
+pub type Idf = HashMap<String, f64>;
+
+/// How strings are split into tokens, selected with the `tokenizer` parameter
+#[derive(Clone, Debug)]
+pub enum Tokenizer {
+    /// Split on whitespace (default)
+    Whitespace,
+    /// Every non-whitespace char is a token, for scripts without spaces
+    Chars,
+    /// Words between Unicode word boundaries, see the `words` module
+    UnicodeWords,
+    /// Every non-empty match of `token_pattern` is a token
+    Regex(Regex),
+}
+
+impl Tokenizer {
+    /// Split `s` into tokens
+    pub fn tokenize<'a>(&self, s: &'a str) -> Vec<&'a str> {
+        match self {
+            Tokenizer::Whitespace => s.split_whitespace().collect(),
+            Tokenizer::Chars => s
+                .char_indices()
+                .filter(|(_, c)| !c.is_whitespace())
+                .map(|(i, c)| &s[i..i + c.len_utf8()])
+                .collect(),
+            Tokenizer::UnicodeWords => unicode_words(s),
+            Tokenizer::Regex(regex) => regex.find_all(s),
+        }
+    }
+}
+
+/// Join two token groups with a space, skipping an empty group
+#[cfg(feature = "token")]
+fn join_groups(a: &str, b: &str) -> String {
+    match (a.is_empty(), b.is_empty()) {
+        (true, _) => b.to_string(),
+        (_, true) => a.to_string(),
+        _ => format!("{} {}", a, b),
+    }
+}
+
+/// Token set ratio in [0, 1], after fuzzywuzzy's `token_set_ratio`
+///
+/// Both strings are reduced to sorted sets of unique tokens. The shared
+/// tokens (`t0`) are compared against `t0` plus each side's remaining
+/// tokens, and the best normalized Levenshtein similarity wins, so extra
+/// or reordered words don't count against a match.
+///
+/// With `idf` weights, see `weighted_token_set_ratio`. Each token whose
+/// letters mix scripts (a Latin word with one Cyrillic letter, a spoofing
+/// sign) takes `mixed_script_penalty` times its share of the tokens off the
+/// score, which stays at least 0.
+#[cfg(feature = "token")]
+pub fn token_set_ratio(
+    s1: &str,
+    s2: &str,
+    tokenizer: &Tokenizer,
+    idf: Option<&Idf>,
+    mixed_script_penalty: f64,
+) -> f64 {
+    let (a, b) = (tokenizer.tokenize(s1), tokenizer.tokenize(s2));
+    token_set_ratio_of(a, b, idf, mixed_script_penalty)
+}
+
+/// `token_set_ratio` of strings already split into tokens `a` and `b`
+#[cfg(feature = "token")]
+pub fn token_set_ratio_of(
+    mut a: Vec<&str>,
+    mut b: Vec<&str>,
+    idf: Option<&Idf>,
+    mixed_script_penalty: f64,
+) -> f64 {
+    a.sort_unstable();
+    a.dedup();
+    b.sort_unstable();
+    b.dedup();
+
+    let common: Vec<&str> = a.iter().copied().filter(|t| b.binary_search(t).is_ok()).collect();
+    let only_a: Vec<&str> = a.iter().copied().filter(|t| b.binary_search(t).is_err()).collect();
+    let only_b: Vec<&str> = b.iter().copied().filter(|t| a.binary_search(t).is_err()).collect();
+
+    let similarity = match idf {
+        Some(idf) => weighted_token_set_ratio(&common, &only_a, &only_b, idf),
+        None => plain_token_set_ratio(&common, &only_a, &only_b),
+    };
+    // Off by default, sparing the script lookups
+    if mixed_script_penalty == 0.0 {
+        return similarity;
+    }
+    let mixed = a.iter().chain(&b).filter(|t| mixes_scripts(t)).count();
+    let share = mixed as f64 / (a.len() + b.len()).max(1) as f64;
+    (similarity - mixed_script_penalty * share).max(0.0)
+}
+
+/// The unweighted token set ratio of the shared and leftover tokens
+#[cfg(feature = "token")]
+fn plain_token_set_ratio(common: &[&str], only_a: &[&str], only_b: &[&str]) -> f64 {
+    let t0 = common.join(" ");
+    let t1 = join_groups(&t0, &only_a.join(" "));
+    let t2 = join_groups(&t0, &only_b.join(" "));
+
+    let mut best = levenshtein_similarity(&t1, &t2);
+    if !t0.is_empty() {
+        best = best
+            .max(levenshtein_similarity(&t0, &t1))
+            .max(levenshtein_similarity(&t0, &t2));
+    }
+    best
+}
+
+/// IDF-weighted token set ratio
+///
+/// Mirrors the three comparisons of `token_set_ratio`, but each token
+/// contributes its IDF weight (1.0 if unlisted) instead of its length:
+/// the shared weight is divided by each side's total weight, and the two
+/// full sets score the shared weight plus the Levenshtein similarity of the
+/// leftover tokens scaled by their weight. A common shared token therefore
+/// counts for little, while a rare one dominates the score.
+#[cfg(feature = "token")]
+fn weighted_token_set_ratio(common: &[&str], only_a: &[&str], only_b: &[&str], idf: &Idf) -> f64 {
+    let weight = |tokens: &[&str]| -> f64 {
+        tokens.iter().map(|t| idf.get(*t).copied().unwrap_or(1.0)).sum()
+    };
+    let w_common = weight(common);
+    let w_a = w_common + weight(only_a);
+    let w_b = w_common + weight(only_b);
+
+    let w_max = w_a.max(w_b);
+    if w_max <= 0.0 {
+        // Nothing carries weight: only identical token sets are similar
+        return if only_a.is_empty() && only_b.is_empty() { 1.0 } else { 0.0 };
+    }
+
+    let leftover = levenshtein_similarity(&only_a.join(" "), &only_b.join(" "));
+    let mut best = (w_common + leftover * (w_max - w_common)) / w_max;
+    if !common.is_empty() {
+        for total in [w_a, w_b] {
+            if total > 0.0 {
+                best = best.max(w_common / total);
+            }
+        }
+    }
+    best
+}
@@ -0,0 +1,108 @@
+//! User-supplied transliteration applied by `Normalization` (`transliterate`,
+//! and `fold_table`, whose sources are single chars).
+
+/// The replacements of a `transliterate` or `fold_table` map, kept across
+/// calls
+///
+/// Parsing the map for every document would cost more than applying it,
+/// so the table holds on to the last map read, and is only replaced when a
+/// query passes another one. Each map loaded gets a new generation, which
+/// is what `Normalization` compares: forms cached under an earlier map
+/// don't match one loaded since.
+pub struct Table {
+    source: String,
+    generation: u64,
+    /// Sorted by source, so lookups can binary search
+    pairs: Vec<(String, String)>,
+    /// Chars in the longest source
+    longest: usize,
+}
+
+impl Table {
+    pub const fn new() -> Self {
+        Table {
+            source: String::new(),
+            generation: 0,
+            pairs: Vec::new(),
+            longest: 0,
+        }
+    }
+
+    /// Whether the table was loaded from the map text `source`
+    pub fn holds(&self, source: &str) -> bool {
+        self.generation > 0 && self.source == source
+    }
+
+    /// Replace the table by `pairs` of source → replacement, read from the
+    /// map text `source`
+    ///
+    /// Sources must not be empty; of a source listed twice, the first
+    /// replacement is kept.
+    pub fn load(&mut self, source: &str, mut pairs: Vec<(String, String)>) {
+        pairs.sort_by(|a, b| a.0.cmp(&b.0));
+        pairs.dedup_by(|later, earlier| later.0 == earlier.0);
+        self.longest = pairs.iter().map(|(from, _)| from.chars().count()).max().unwrap_or(0);
+        self.pairs = pairs;
+        self.source.clear();
+        self.source.push_str(source);
+        self.generation += 1;
+    }
+
+    /// The loaded map, as `Normalization` holds it
+    pub fn current(&'static self) -> Transliteration {
+        Transliteration {
+            generation: self.generation,
+            table: self,
+        }
+    }
+
+    fn get(&self, from: &str) -> Option<&str> {
+        let i = self.pairs.binary_search_by(|(source, _)| source.as_str().cmp(from)).ok()?;
+        Some(&self.pairs[i].1)
+    }
+}
+
+/// A loaded `Table`, compared by the generation it had when taken
+#[derive(Clone, Copy)]
+pub struct Transliteration {
+    generation: u64,
+    table: &'static Table,
+}
+
+impl PartialEq for Transliteration {
+    fn eq(&self, other: &Self) -> bool {
+        self.generation == other.generation
+    }
+}
+
+impl Transliteration {
+    /// Replace each source in `s` by its replacement
+    ///
+    /// Sources are found left to right, the longest first, so a map with
+    /// both "s" and "sch" reads "sch" as one; chars no source starts with
+    /// pass through unchanged. Replacements aren't transliterated again.
+    pub fn apply(&self, s: &str) -> String {
+        let bounds: Vec<usize> = s.char_indices().map(|(i, _)| i).chain([s.len()]).collect();
+        let chars = bounds.len() - 1;
+
+        let mut out = String::with_capacity(s.len());
+        let mut i = 0;
+        while i < chars {
+            let longest = self.table.longest.min(chars - i);
+            let found = (1..=longest)
+                .rev()
+                .find_map(|n| Some((n, self.table.get(&s[bounds[i]..bounds[i + n]])?)));
+            match found {
+                Some((n, replacement)) => {
+                    out.push_str(replacement);
+                    i += n;
+                }
+                None => {
+                    out.push_str(&s[bounds[i]..bounds[i + 1]]);
+                    i += 1;
+                }
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,44 @@
+//! Spelling variants of a target, for `target_variants`.
+
+/// Chars dropped or spaced out by the de-hyphenated variants
+const HYPHENS: [char; 3] = ['-', '\u{2010}', '\u{2011}'];
+
+/// `target` and the trivial respellings a document might carry instead
+///
+/// Besides `target` itself: lowercased, title-cased (each word's first
+/// letter upper case, the rest lower case), and de-hyphenated, with the
+/// hyphens removed ("Wi-Fi" → "WiFi") or replaced by spaces ("Wi Fi").
+/// Duplicates are dropped, `target` first, so there are at most five.
+pub fn spelling_variants(target: &str) -> Vec<String> {
+    let candidates = [
+        target.to_string(),
+        target.to_lowercase(),
+        title_case(target),
+        target.replace(HYPHENS, ""),
+        target.replace(HYPHENS, " "),
+    ];
+
+    let mut variants: Vec<String> = Vec::with_capacity(candidates.len());
+    for candidate in candidates {
+        if !variants.contains(&candidate) {
+            variants.push(candidate);
+        }
+    }
+    variants
+}
+
+/// `s` with the first letter of each whitespace-separated word upper case
+/// and every other letter lower case
+fn title_case(s: &str) -> String {
+    let mut title = String::with_capacity(s.len());
+    let mut word_start = true;
+    for c in s.chars() {
+        if word_start {
+            title.extend(c.to_uppercase());
+        } else {
+            title.extend(c.to_lowercase());
+        }
+        word_start = c.is_whitespace();
+    }
+    title
+}
@@ -0,0 +1,150 @@
+//! Levenshtein distance with per-pair, case-mismatch and variant
+//! substitution costs, and edits weighted by their position.
+
+use std::collections::HashMap;
+
+use crate::normalize::strip_accent;
+
+/// Substitution costs supplied with the `sub_costs` parameter, keyed by
+/// (target char, field char)
+pub type SubCosts = HashMap<(char, char), f64>;
+
+/// Edit costs for the weighted DP
+///
+/// An entry in `table` wins; otherwise chars that differ only by case cost
+/// `case_mismatch` when set, chars that differ by case and/or accent cost
+/// `variant` when set, and any other substitution costs 1.0. With
+/// `position_decay` every edit is then multiplied by `position_weight`.
+#[derive(Debug, Default)]
+pub struct Costs {
+    /// The `sub_costs` table
+    pub table: Option<SubCosts>,
+    /// The `case_mismatch_cost`
+    pub case_mismatch: Option<f64>,
+    /// The `variant_cost`
+    pub variant: Option<f64>,
+    /// The `position_decay` of `"position_weighting": "linear"`
+    pub position_decay: Option<f64>,
+}
+
+impl Costs {
+    /// Whether any edit costs other than 1.0
+    pub fn is_weighted(&self) -> bool {
+        self.table.is_some()
+            || self.case_mismatch.is_some()
+            || self.variant.is_some()
+            || self.position_decay.is_some()
+    }
+
+    /// Multiplier for an edit at target position `pos` (0-based)
+    ///
+    /// Linear decay: an edit at the first char costs double, and each later
+    /// position `position_decay` less, down to 1.0. With the default decay
+    /// of 0.25 that spans the first four chars, the prefix Jaro-Winkler
+    /// rewards. Never below 1.0, so row minima still never decrease.
+    pub fn position_weight(&self, pos: usize) -> f64 {
+        match self.position_decay {
+            Some(decay) => (2.0 - decay * pos as f64).max(1.0),
+            None => 1.0,
+        }
+    }
+
+    /// Cost of substituting target char `t` by a different field char `f`
+    pub fn substitution(&self, t: char, f: char) -> f64 {
+        if let Some(&cost) = self.table.as_ref().and_then(|table| table.get(&(t, f))) {
+            return cost;
+        }
+        if let Some(cost) = self.case_mismatch {
+            if t.to_lowercase().eq(f.to_lowercase()) {
+                return cost;
+            }
+        }
+        match self.variant {
+            Some(cost) if is_variant(t, f) => cost,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Whether `a` and `b` are equal once lowercased and stripped of accents
+/// ("É" and "e")
+fn is_variant(a: char, b: char) -> bool {
+    let fold = |c: char| c.to_lowercase().map(strip_accent);
+    fold(a).eq(fold(b))
+}
+
+/// Relative slack when comparing an accumulated cost against a bound
+///
+/// Fractional costs don't add up exactly in f64 (thirty 0.1 substitutions
+/// sum to 3.0000000000000004), so a distance equal to the bound on paper
+/// can land just above it.
+const COST_EPSILON: f64 = 1e-9;
+
+/// Whether an accumulated `cost` is within `bound`, allowing for rounding
+pub fn within_bound(cost: f64, bound: f64) -> bool {
+    cost <= bound + COST_EPSILON * bound.max(1.0)
+}
+
+/// Levenshtein distance where substituting `t` (target) by `f` (field)
+/// costs `costs.substitution(t, f)`
+///
+/// Insertions and deletions cost 1.0 and matching chars cost nothing, all
+/// times the `position_weight` of the target position the edit is at (an
+/// edit past the last target char counts as at the last one). As in
+/// `levenshtein_banded`, the DP stops once a whole row exceeds `bound`
+/// (costs are non-negative, so row minima never decrease) and the returned
+/// value is then only known to be greater than `bound` (see
+/// `within_bound`). Cells are f64, so huge costs on long strings saturate
+/// at +inf, which is over any bound, rather than wrapping around.
+pub fn weighted_levenshtein(field: &str, target: &str, costs: &Costs, bound: f64) -> f64 {
+    let target: Vec<char> = target.chars().collect();
+
+    let weights: Vec<f64> = (0..target.len().max(1)).map(|j| costs.position_weight(j)).collect();
+
+    let mut prev_row = vec![0.0; target.len() + 1];
+    for j in 0..target.len() {
+        prev_row[j + 1] = prev_row[j] + weights[j];
+    }
+    let mut curr_row = vec![0.0; target.len() + 1];
+
+    for f in field.chars() {
+        curr_row[0] = prev_row[0] + weights[0];
+
+        for (j, &t) in target.iter().enumerate() {
+            let cost = if f == t { 0.0 } else { costs.substitution(t, f) };
+            let weight = weights[j];
+            // Deleting after the last target char is at the last position
+            let deletion = weights[(j + 1).min(target.len() - 1)];
+
+            curr_row[j + 1] = (curr_row[j] + weight) // Insertion
+                .min(prev_row[j + 1] + deletion) // Deletion
+                .min(prev_row[j] + cost * weight); // Substitution
+        }
+
+        let row_min = curr_row.iter().copied().fold(f64::INFINITY, f64::min);
+        if !within_bound(row_min, bound) {
+            return row_min;
+        }
+
+        core::mem::swap(&mut prev_row, &mut curr_row);
+    }
+
+    prev_row[target.len()]
+}
+
+/// Substitution-only distance between two strings of equal char length
+///
+/// Sums the cost of each mismatched position from `costs`, weighted by
+/// position, so with the default costs this is the Hamming distance. Stops
+/// once the sum is over `bound`, returning a value only known to exceed it.
+pub fn substitution_distance(field: &str, target: &str, costs: &Costs, bound: f64) -> f64 {
+    let mut distance = 0.0;
+    let pairs = field.chars().zip(target.chars()).enumerate();
+    for (pos, (f, t)) in pairs.filter(|(_, (f, t))| f != t) {
+        distance += costs.substitution(t, f) * costs.position_weight(pos);
+        if !within_bound(distance, bound) {
+            break;
+        }
+    }
+    distance
+}
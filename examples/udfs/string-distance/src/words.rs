@@ -0,0 +1,164 @@
+//! Words by Unicode word boundaries, for `"tokenizer": "unicode_words"`.
+//!
+//! The default word-boundary rules of UAX #29, as `unicode-segmentation`'s
+//! `unicode_words` applies them, without the crate or its tables: chars
+//! are classified by their general properties and the blocks of the
+//! scripts concerned. Letters and digits run together into words, with
+//! the apostrophes, periods and commas that join them ("can't", "3.14");
+//! katakana runs form words; and every ideograph, hiragana char and Thai
+//! letter is a word of its own, since finding words in those scripts takes
+//! a dictionary the rules don't have. Combining marks stay with the char
+//! they follow.
+
+/// Word-break class of a char
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Letter,
+    Numeric,
+    Katakana,
+    /// Joins letters: ':' "·"
+    MidLetter,
+    /// Joins digits: ',' ';'
+    MidNum,
+    /// Joins either: '.' "'"
+    MidNumLet,
+    /// Underscore and other connector punctuation
+    ExtendNumLet,
+    /// Combining marks and joiners, part of the preceding char
+    Extend,
+    /// Ideographs, hiragana, Thai and the like: a word per char
+    Single,
+    Other,
+}
+
+fn class(c: char) -> Class {
+    match c {
+        '\'' | '.' | '\u{2018}' | '\u{2019}' | '\u{2024}' | '\u{FE52}' | '\u{FF07}'
+        | '\u{FF0E}' => Class::MidNumLet,
+        ':' | '\u{00B7}' | '\u{0387}' | '\u{05F4}' | '\u{2027}' | '\u{FE13}' | '\u{FE55}'
+        | '\u{FF1A}' => Class::MidLetter,
+        ',' | ';' | '\u{037E}' | '\u{0589}' | '\u{060C}' | '\u{060D}' | '\u{066C}'
+        | '\u{2044}' | '\u{FE10}' | '\u{FE14}' | '\u{FE50}' | '\u{FE54}' | '\u{FF0C}'
+        | '\u{FF1B}' => Class::MidNum,
+        '_' | '\u{203F}' | '\u{2040}' | '\u{2054}' | '\u{FE33}' | '\u{FE34}'
+        | '\u{FE4D}'..='\u{FE4F}' | '\u{FF3F}' => Class::ExtendNumLet,
+        c if is_extend(c) => Class::Extend,
+        c if is_katakana(c) => Class::Katakana,
+        c if is_single(c) => Class::Single,
+        c if c.is_numeric() => Class::Numeric,
+        c if c.is_alphabetic() => Class::Letter,
+        _ => Class::Other,
+    }
+}
+
+/// Combining marks of the common scripts, variation selectors and joiners
+pub fn is_extend(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'     // Combining diacritics
+        | '\u{0483}'..='\u{0489}'   // Cyrillic
+        | '\u{0591}'..='\u{05BD}'   // Hebrew points
+        | '\u{064B}'..='\u{065F}'   // Arabic harakat
+        | '\u{0900}'..='\u{0903}'   // Devanagari signs
+        | '\u{093A}'..='\u{094F}'
+        | '\u{0E31}'
+        | '\u{0E34}'..='\u{0E3A}'   // Thai vowels and tone marks
+        | '\u{0E47}'..='\u{0E4E}'
+        | '\u{0EB1}'
+        | '\u{0EB4}'..='\u{0EBC}'   // Lao
+        | '\u{0EC8}'..='\u{0ECD}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{200C}' | '\u{200D}'   // Zero-width (non-)joiner
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{3099}' | '\u{309A}'   // Kana voicing marks
+        | '\u{FE00}'..='\u{FE0F}'   // Variation selectors
+        | '\u{FE20}'..='\u{FE2F}'
+        | '\u{FF9E}' | '\u{FF9F}'   // Halfwidth voicing marks
+    )
+}
+
+fn is_katakana(c: char) -> bool {
+    matches!(c,
+        '\u{3031}'..='\u{3035}'     // Vertical kana repeat marks
+        | '\u{309B}' | '\u{309C}'
+        | '\u{30A0}'..='\u{30FA}'
+        | '\u{30FC}'..='\u{30FF}'   // Prolonged sound mark, iteration marks
+        | '\u{31F0}'..='\u{31FF}'
+        | '\u{32D0}'..='\u{32FE}'
+        | '\u{3300}'..='\u{3357}'
+        | '\u{FF66}'..='\u{FF9D}'   // Halfwidth katakana
+    )
+}
+
+/// Scripts written without spaces whose words the rules can't find
+fn is_single(c: char) -> bool {
+    matches!(c,
+        '\u{0E00}'..='\u{0EFF}'     // Thai, Lao
+        | '\u{1000}'..='\u{109F}'   // Myanmar
+        | '\u{1780}'..='\u{17FF}'   // Khmer
+        | '\u{3005}'..='\u{3007}'   // 々 〆 〇
+        | '\u{3040}'..='\u{309F}'   // Hiragana
+        | '\u{3400}'..='\u{4DBF}'   // CJK ideographs
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{3FFFF}'
+    )
+}
+
+/// A char with the combining marks after it
+struct Unit {
+    start: usize,
+    end: usize,
+    class: Class,
+}
+
+/// Words of `s` between Unicode word boundaries
+///
+/// Like `unicode_words`, only segments with a letter or digit are words,
+/// so spaces and punctuation between them are dropped.
+pub fn unicode_words(s: &str) -> Vec<&str> {
+    let mut units: Vec<Unit> = Vec::new();
+    for (i, c) in s.char_indices() {
+        let class = class(c);
+        let end = i + c.len_utf8();
+        match units.last_mut() {
+            // A mark belongs to its char, but not to a space before it
+            Some(last) if class == Class::Extend && last.class != Class::Other => last.end = end,
+            _ => units.push(Unit { start: i, end, class }),
+        }
+    }
+
+    let mut words = Vec::new();
+    let mut start = 0;
+    for i in 1..=units.len() {
+        if i < units.len() && joins(&units, i) {
+            continue;
+        }
+        let segment = &s[units[start].start..units[i - 1].end];
+        if segment.chars().any(char::is_alphanumeric) {
+            words.push(segment);
+        }
+        start = i;
+    }
+    words
+}
+
+/// Whether there is no word boundary between `units[i - 1]` and `units[i]`
+fn joins(units: &[Unit], i: usize) -> bool {
+    use Class::*;
+
+    let before = i.checked_sub(2).map(|j| units[j].class);
+    let after = units.get(i + 1).map(|unit| unit.class);
+    match (units[i - 1].class, units[i].class) {
+        (Letter | Numeric, Letter | Numeric) => true,
+        (Katakana, Katakana) => true,
+        (Letter | Numeric | Katakana | ExtendNumLet, ExtendNumLet) => true,
+        (ExtendNumLet, Letter | Numeric | Katakana) => true,
+        // "can't", "3.14": the mid char joins when the same kind follows it
+        (Letter, MidLetter | MidNumLet) => after == Some(Letter),
+        (MidLetter | MidNumLet, Letter) => before == Some(Letter),
+        (Numeric, MidNum | MidNumLet) => after == Some(Numeric),
+        (MidNum | MidNumLet, Numeric) => before == Some(Numeric),
+        _ => false,
+    }
+}
@@ -2,7 +2,10 @@ package udfs_test
 
 import (
 	"context"
+	"encoding/json"
+	"fmt"
 	"os"
+	"strings"
 	"testing"
 
 	"github.com/quidditch/quidditch/pkg/wasm"
@@ -109,6 +112,289 @@ func TestStringDistanceUDF(t *testing.T) {
 	}
 }
 
+// TestStringDistanceSelfTest checks the host imports against the module's
+// expectations through its self_test export, which returns a bitmask of the
+// imports that misbehaved. It is called on the module directly, since the
+// registry would pass the self_test_* values as function arguments. CI builds
+// the module first, so there a missing file fails the test instead of
+// skipping it.
+func TestStringDistanceSelfTest(t *testing.T) {
+	wasmPath := "string-distance/dist/string_distance.wasm"
+	if _, err := os.Stat(wasmPath); os.IsNotExist(err) {
+		if os.Getenv("CI") != "" {
+			t.Fatal("string_distance.wasm not built; the CI workflow should run string-distance/build.sh")
+		}
+		t.Skip("string_distance.wasm not built. Run: cd string-distance && ./build.sh")
+	}
+
+	wasmBytes, err := os.ReadFile(wasmPath)
+	require.NoError(t, err)
+
+	runtime, err := wasm.NewRuntime(&wasm.Config{
+		EnableJIT: true,
+		Logger:    zap.NewNop(),
+	})
+	require.NoError(t, err)
+	defer runtime.Close()
+
+	hostFuncs := wasm.NewHostFunctions(runtime)
+	require.NoError(t, hostFuncs.RegisterHostFunctions(runtime.GetContext(), runtime.GetWazeroRuntime()))
+	require.NoError(t, runtime.CompileModule("string_distance", wasmBytes, nil))
+
+	instance, err := runtime.NewModuleInstance("string_distance")
+	require.NoError(t, err)
+
+	// The known inputs self_test reads
+	docCtx := wasm.NewDocumentContextFromMap("doc1", 1.0, map[string]interface{}{
+		"self_test": "quidditch",
+	})
+	ctxID := hostFuncs.RegisterContext(docCtx)
+	defer hostFuncs.UnregisterContext(ctxID)
+
+	hostFuncs.RegisterParameters(map[string]interface{}{
+		"self_test_string": "quidditch",
+		"self_test_i64":    int64(42),
+		"self_test_f64":    0.5,
+		"self_test_bool":   true,
+	})
+	defer hostFuncs.UnregisterParameters()
+
+	results, err := instance.CallFunction(context.Background(), "self_test", ctxID)
+	require.NoError(t, err)
+	require.Len(t, results, 1)
+	assert.Equal(t, int32(0), int32(results[0]), "self_test bitmask of misbehaving host imports")
+}
+
+// loadStringDistance registers the string distance UDF's filter export as
+// string_distance 1.0.0 with the given parameters. It skips tb when the WASM
+// file hasn't been built, and closes the runtime when tb finishes.
+func loadStringDistance(tb testing.TB, params []wasm.UDFParameter) *wasm.UDFRegistry {
+	tb.Helper()
+
+	wasmPath := "string-distance/dist/string_distance.wasm"
+	if _, err := os.Stat(wasmPath); os.IsNotExist(err) {
+		tb.Skip("string_distance.wasm not built. Run: cd string-distance && ./build.sh")
+	}
+
+	wasmBytes, err := os.ReadFile(wasmPath)
+	require.NoError(tb, err)
+
+	logger := zap.NewNop()
+	runtime, err := wasm.NewRuntime(&wasm.Config{
+		EnableJIT: true,
+		Logger:    logger,
+	})
+	require.NoError(tb, err)
+	tb.Cleanup(func() { runtime.Close() })
+
+	registry, err := wasm.NewUDFRegistry(&wasm.UDFRegistryConfig{
+		Runtime: runtime,
+		Logger:  logger,
+	})
+	require.NoError(tb, err)
+
+	err = registry.Register(&wasm.UDFMetadata{
+		Name:         "string_distance",
+		Version:      "1.0.0",
+		FunctionName: "filter",
+		WASMBytes:    wasmBytes,
+		Parameters:   params,
+		Returns:      []wasm.UDFReturnType{{Type: wasm.ValueTypeI32}},
+	})
+	require.NoError(tb, err)
+
+	return registry
+}
+
+// BenchmarkStringDistanceShortTarget matches a 40-char field against a
+// 32-char target, the longest the UDF keeps in fixed stack rows, and against a
+// 33-char one, which goes through the general banded DP and its heap rows.
+// max_distance is high enough that neither computation stops early.
+func BenchmarkStringDistanceShortTarget(b *testing.B) {
+	registry := loadStringDistance(b, []wasm.UDFParameter{
+		{Name: "target", Type: wasm.ValueTypeString, Required: true},
+		{Name: "max_distance", Type: wasm.ValueTypeI64, Default: int64(2)},
+	})
+
+	docCtx := wasm.NewDocumentContextFromMap("doc1", 1.0, map[string]interface{}{
+		"name": "the quick brown fox jumps over a lazy do",
+	})
+	ctx := context.Background()
+
+	for _, n := range []int{32, 33} {
+		name := "stack"
+		if n > 32 {
+			name = "banded"
+		}
+		params := map[string]wasm.Value{
+			"target":       wasm.NewStringValue(strings.Repeat("pack my box with five dozen jugs!", 2)[:n]),
+			"max_distance": wasm.NewI64Value(40),
+		}
+
+		b.Run(name, func(b *testing.B) {
+			for i := 0; i < b.N; i++ {
+				registry.Call(ctx, "string_distance", "1.0.0", docCtx, params)
+			}
+		})
+	}
+}
+
+// BenchmarkStringDistanceDisjoint matches a 200-char field against a
+// 200-char target with no char in common. At max_distance 2 every row of the
+// banded DP is over the bound from the third row on, so the UDF gives up
+// there; at max_distance 199 the band covers nearly the whole table and every
+// row is computed before the distance of 200 rejects the document.
+func BenchmarkStringDistanceDisjoint(b *testing.B) {
+	registry := loadStringDistance(b, []wasm.UDFParameter{
+		{Name: "target", Type: wasm.ValueTypeString, Required: true},
+		{Name: "max_distance", Type: wasm.ValueTypeI64, Default: int64(2)},
+	})
+
+	docCtx := wasm.NewDocumentContextFromMap("doc1", 1.0, map[string]interface{}{
+		"name": strings.Repeat("abcdefghij", 20),
+	})
+	target := strings.Repeat("klmnopqrst", 20)
+	ctx := context.Background()
+
+	for _, maxDistance := range []int64{2, 199} {
+		name := "early_exit"
+		if maxDistance > 2 {
+			name = "full_dp"
+		}
+		params := map[string]wasm.Value{
+			"target":       wasm.NewStringValue(target),
+			"max_distance": wasm.NewI64Value(maxDistance),
+		}
+
+		b.Run(name, func(b *testing.B) {
+			for i := 0; i < b.N; i++ {
+				registry.Call(ctx, "string_distance", "1.0.0", docCtx, params)
+			}
+		})
+	}
+}
+
+// BenchmarkStringDistanceNormalizedTarget compares per-document cost with and
+// without target normalizations. The UDF normalizes the target once per query
+// and reuses it, so only the (short) field is normalized per document and the
+// two cases should cost about the same despite the long target.
+func BenchmarkStringDistanceNormalizedTarget(b *testing.B) {
+	registry := loadStringDistance(b, []wasm.UDFParameter{
+		{Name: "target", Type: wasm.ValueTypeString, Required: true},
+		{Name: "max_distance", Type: wasm.ValueTypeI64, Default: int64(2)},
+		{Name: "case_insensitive", Type: wasm.ValueTypeBool, Default: false},
+		{Name: "ignore_accents", Type: wasm.ValueTypeBool, Default: false},
+		{Name: "fold_width", Type: wasm.ValueTypeBool, Default: false},
+		{Name: "collapse_whitespace", Type: wasm.ValueTypeBool, Default: false},
+	})
+
+	docCtx := wasm.NewDocumentContextFromMap("doc1", 1.0, map[string]interface{}{
+		"name": "Crème Brûlée",
+	})
+	target := "Crème  Brûlée  à  la  Vanille  de  Madagascar,  ＦＲＡＩＣＨＥ  ｅｔ  ＭＡＩＳＯＮ"
+	ctx := context.Background()
+
+	for _, normalized := range []bool{false, true} {
+		name := "raw"
+		if normalized {
+			name = "normalized"
+		}
+		params := map[string]wasm.Value{
+			"target":              wasm.NewStringValue(target),
+			"case_insensitive":    wasm.NewBoolValue(normalized),
+			"ignore_accents":      wasm.NewBoolValue(normalized),
+			"fold_width":          wasm.NewBoolValue(normalized),
+			"collapse_whitespace": wasm.NewBoolValue(normalized),
+		}
+
+		b.Run(name, func(b *testing.B) {
+			for i := 0; i < b.N; i++ {
+				registry.Call(ctx, "string_distance", "1.0.0", docCtx, params)
+			}
+		})
+	}
+}
+
+// BenchmarkStringDistanceSharedTargets matches one long field against 100
+// short targets in the OR mode, none of which match. The UDF reads and
+// decodes the field once and runs every target's banded DP against it; the
+// per_target case turns that off with share_field_decoding so each target
+// reads and decodes the field again.
+func BenchmarkStringDistanceSharedTargets(b *testing.B) {
+	registry := loadStringDistance(b, []wasm.UDFParameter{
+		{Name: "targets", Type: wasm.ValueTypeString, Required: true},
+		{Name: "max_distance", Type: wasm.ValueTypeI64, Default: int64(2)},
+		{Name: "share_field_decoding", Type: wasm.ValueTypeBool, Default: true},
+	})
+
+	targets := make([]string, 100)
+	for i := range targets {
+		targets[i] = fmt.Sprintf("sku-%03d", i)
+	}
+	targetsJSON, err := json.Marshal(targets)
+	require.NoError(b, err)
+
+	docCtx := wasm.NewDocumentContextFromMap("doc1", 1.0, map[string]interface{}{
+		"name": strings.Repeat("lorem ipsum dolor sit amet ", 25),
+	})
+	ctx := context.Background()
+
+	for _, shared := range []bool{true, false} {
+		name := "shared"
+		params := map[string]wasm.Value{
+			"targets":      wasm.NewStringValue(string(targetsJSON)),
+			"max_distance": wasm.NewI64Value(3),
+		}
+		if !shared {
+			name = "per_target"
+			params["share_field_decoding"] = wasm.NewBoolValue(false)
+		}
+
+		b.Run(name, func(b *testing.B) {
+			for i := 0; i < b.N; i++ {
+				registry.Call(ctx, "string_distance", "1.0.0", docCtx, params)
+			}
+		})
+	}
+}
+
+// BenchmarkStringDistanceNgram scores a 200-char field against a long target
+// with the n-gram algorithm. Each n-gram's hash is rolled from the previous
+// one and the target's n-grams are hashed once per query, so the
+// per-document cost is one linear pass over the field plus the sort. The
+// trigram case packs n-grams exactly; 5-grams use the modular hash. The
+// module has no string-collecting path to compare against, so that
+// comparison is the crate's rolling_ngram_hashes_are_faster_than_collected_ngrams
+// test.
+func BenchmarkStringDistanceNgram(b *testing.B) {
+	registry := loadStringDistance(b, []wasm.UDFParameter{
+		{Name: "target", Type: wasm.ValueTypeString, Required: true},
+		{Name: "algorithm", Type: wasm.ValueTypeString, Default: "ngram"},
+		{Name: "ngram_size", Type: wasm.ValueTypeI64, Default: int64(3)},
+		{Name: "min_similarity", Type: wasm.ValueTypeF64, Default: float64(0.8)},
+	})
+
+	docCtx := wasm.NewDocumentContextFromMap("doc1", 1.0, map[string]interface{}{
+		"name": strings.Repeat("the quick brown fox jumps over the lazy dog ", 5)[:200],
+	})
+	target := strings.Repeat("pack my box with five dozen liquor jugs ", 4)
+	ctx := context.Background()
+
+	for _, n := range []int64{3, 5} {
+		params := map[string]wasm.Value{
+			"target":     wasm.NewStringValue(target),
+			"algorithm":  wasm.NewStringValue("ngram"),
+			"ngram_size": wasm.NewI64Value(n),
+		}
+
+		b.Run(fmt.Sprintf("n=%d", n), func(b *testing.B) {
+			for i := 0; i < b.N; i++ {
+				registry.Call(ctx, "string_distance", "1.0.0", docCtx, params)
+			}
+		})
+	}
+}
+
 // TestGeoFilterUDF demonstrates the C-based geo filter UDF
 func TestGeoFilterUDF(t *testing.T) {
 	wasmPath := "geo-filter/dist/geo_filter.wasm"
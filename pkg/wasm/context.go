@@ -20,6 +20,10 @@ type DocumentContext struct {
 	// Memory management
 	mu sync.RWMutex
 
+	// Share of documents holding this document's value, per field, for
+	// get_field_doc_freq; fields without one are unknown
+	docFreqs map[string]float64
+
 	// For debugging
 	fieldAccesses int
 }
@@ -73,6 +77,43 @@ func (dc *DocumentContext) GetFieldString(fieldPath string) (string, bool) {
 	}
 }
 
+// GetFieldStringArray retrieves an array-of-strings field value. exists
+// reports whether the field is set, and ok whether it holds an array of
+// strings; other values are not converted.
+func (dc *DocumentContext) GetFieldStringArray(fieldPath string) (values []string, exists bool, ok bool) {
+	dc.mu.RLock()
+	defer dc.mu.RUnlock()
+	dc.fieldAccesses++
+
+	value, exists := dc.getNestedField(fieldPath)
+	if !exists {
+		return nil, false, false
+	}
+	values, ok = stringArray(value)
+	return values, true, ok
+}
+
+// stringArray converts an array of strings, as []string or as the
+// []interface{} JSON decoding gives, to []string
+func stringArray(value interface{}) ([]string, bool) {
+	switch v := value.(type) {
+	case []string:
+		return v, true
+	case []interface{}:
+		values := make([]string, len(v))
+		for i, item := range v {
+			str, ok := item.(string)
+			if !ok {
+				return nil, false
+			}
+			values[i] = str
+		}
+		return values, true
+	default:
+		return nil, false
+	}
+}
+
 // GetFieldInt64 retrieves an int64 field value
 func (dc *DocumentContext) GetFieldInt64(fieldPath string) (int64, bool) {
 	dc.mu.RLock()
@@ -149,6 +190,26 @@ func (dc *DocumentContext) GetFieldBool(fieldPath string) (bool, bool) {
 	}
 }
 
+// SetFieldDocFreq records the share of documents in [0, 1] whose fieldPath
+// holds this document's value, as the search layer knows it from its index
+func (dc *DocumentContext) SetFieldDocFreq(fieldPath string, freq float64) {
+	dc.mu.Lock()
+	defer dc.mu.Unlock()
+	if dc.docFreqs == nil {
+		dc.docFreqs = make(map[string]float64)
+	}
+	dc.docFreqs[fieldPath] = freq
+}
+
+// GetFieldDocFreq retrieves the share recorded by SetFieldDocFreq; false
+// when none was recorded for fieldPath
+func (dc *DocumentContext) GetFieldDocFreq(fieldPath string) (float64, bool) {
+	dc.mu.RLock()
+	defer dc.mu.RUnlock()
+	freq, exists := dc.docFreqs[fieldPath]
+	return freq, exists
+}
+
 // HasField checks if a field exists
 func (dc *DocumentContext) HasField(fieldPath string) bool {
 	dc.mu.RLock()
@@ -291,6 +352,7 @@ func (cp *ContextPool) Get(documentID string, score float64, jsonData []byte) (*
 		ctx.data = data
 		ctx.documentID = documentID
 		ctx.score = score
+		ctx.docFreqs = nil
 		ctx.fieldAccesses = 0
 		return ctx, nil
 	default:
@@ -242,6 +242,78 @@ func TestArrayFieldAccess(t *testing.T) {
 	t.Log("✅ Array field access working")
 }
 
+func TestGetFieldStringArray(t *testing.T) {
+	jsonData := []byte(`{
+		"tags": ["new", "featured"],
+		"prices": [99.99, 89.99],
+		"name": "Widget",
+		"empty": []
+	}`)
+
+	ctx, err := NewDocumentContext("doc1", 1.0, jsonData)
+	if err != nil {
+		t.Fatalf("Failed to create context: %v", err)
+	}
+
+	tags, exists, ok := ctx.GetFieldStringArray("tags")
+	if !exists || !ok {
+		t.Fatal("Expected tags to be an array of strings")
+	}
+	if len(tags) != 2 || tags[0] != "new" || tags[1] != "featured" {
+		t.Errorf("Expected [new featured], got %v", tags)
+	}
+
+	empty, exists, ok := ctx.GetFieldStringArray("empty")
+	if !exists || !ok || len(empty) != 0 {
+		t.Errorf("Expected an empty array, got %v (exists %v, ok %v)", empty, exists, ok)
+	}
+
+	// Set, but not an array of strings
+	for _, field := range []string{"prices", "name"} {
+		_, exists, ok = ctx.GetFieldStringArray(field)
+		if !exists || ok {
+			t.Errorf("Expected %s to exist but not be an array of strings", field)
+		}
+	}
+
+	_, exists, _ = ctx.GetFieldStringArray("missing")
+	if exists {
+		t.Error("Expected missing field to not exist")
+	}
+}
+
+func TestFieldDocFreq(t *testing.T) {
+	ctx, err := NewDocumentContext("doc1", 1.0, []byte(`{"city": "Springfield"}`))
+	if err != nil {
+		t.Fatalf("Failed to create context: %v", err)
+	}
+
+	// Nothing recorded is not known, not a frequency of 0
+	if _, exists := ctx.GetFieldDocFreq("city"); exists {
+		t.Error("Expected no frequency before one is recorded")
+	}
+
+	ctx.SetFieldDocFreq("city", 0.6)
+	freq, exists := ctx.GetFieldDocFreq("city")
+	if !exists || freq != 0.6 {
+		t.Errorf("Expected frequency 0.6, got %f (exists %v)", freq, exists)
+	}
+	if _, exists := ctx.GetFieldDocFreq("name"); exists {
+		t.Error("Expected other fields to stay unknown")
+	}
+
+	// A pooled context forgets its frequencies
+	pool := NewContextPool(1)
+	pool.Put(ctx)
+	reused, err := pool.Get("doc2", 1.0, []byte(`{"city": "Shelbyville"}`))
+	if err != nil {
+		t.Fatalf("Failed to reuse context: %v", err)
+	}
+	if _, exists := reused.GetFieldDocFreq("city"); exists {
+		t.Error("Expected a reused context to have no frequencies")
+	}
+}
+
 func TestHasField(t *testing.T) {
 	jsonData := []byte(`{
 		"title": "Test",
@@ -3,9 +3,12 @@ package wasm
 import (
 	"context"
 	"encoding/binary"
+	"encoding/json"
 	"fmt"
 	"math"
+	"sort"
 	"sync"
+	"time"
 	"unsafe"
 
 	"github.com/tetratelabs/wazero"
@@ -104,6 +107,45 @@ func (hf *HostFunctions) RegisterHostFunctions(ctx context.Context, runtime waze
 		}, []api.ValueType{api.ValueTypeI32}).
 		Export("get_field_string")
 
+	// get_field_chunk(ctx_id: i64, field_ptr: i32, field_len: i32, offset: i32, buf_ptr: i32, buf_len: i32, out_len_ptr: i32) -> i32
+	// Returns: 0=success (out_len 0 marks the end of the value), 1=not found, 3=write error
+	hostBuilder.NewFunctionBuilder().
+		WithGoModuleFunction(api.GoModuleFunc(hf.getFieldChunk), []api.ValueType{
+			api.ValueTypeI64, // ctx_id
+			api.ValueTypeI32, // field_ptr
+			api.ValueTypeI32, // field_len
+			api.ValueTypeI32, // offset
+			api.ValueTypeI32, // buf_ptr
+			api.ValueTypeI32, // buf_len
+			api.ValueTypeI32, // out_len_ptr
+		}, []api.ValueType{api.ValueTypeI32}).
+		Export("get_field_chunk")
+
+	// get_field_array(ctx_id: i64, field_ptr: i32, field_len: i32, value_ptr: i32, value_len_ptr: i32) -> i32
+	// Writes an array-of-strings field (see encodeStringArray)
+	// Returns: 0=success, 1=not found, 2=not an array of strings, 3=buffer too small
+	hostBuilder.NewFunctionBuilder().
+		WithGoModuleFunction(api.GoModuleFunc(hf.getFieldArray), []api.ValueType{
+			api.ValueTypeI64, // ctx_id
+			api.ValueTypeI32, // field_ptr
+			api.ValueTypeI32, // field_len
+			api.ValueTypeI32, // value_ptr
+			api.ValueTypeI32, // value_len_ptr
+		}, []api.ValueType{api.ValueTypeI32}).
+		Export("get_field_array")
+
+	// get_field_doc_freq(ctx_id: i64, field_ptr: i32, field_len: i32, out_ptr: i32) -> i32
+	// Writes the share of documents holding this document's value (see SetFieldDocFreq)
+	// Returns: 0=success, 1=not known, 3=write error
+	hostBuilder.NewFunctionBuilder().
+		WithGoModuleFunction(api.GoModuleFunc(hf.getFieldDocFreq), []api.ValueType{
+			api.ValueTypeI64, // ctx_id
+			api.ValueTypeI32, // field_ptr
+			api.ValueTypeI32, // field_len
+			api.ValueTypeI32, // out_ptr
+		}, []api.ValueType{api.ValueTypeI32}).
+		Export("get_field_doc_freq")
+
 	hostBuilder.NewFunctionBuilder().
 		WithGoModuleFunction(api.GoModuleFunc(hf.getFieldInt64), []api.ValueType{
 			api.ValueTypeI64, // ctx_id
@@ -151,6 +193,21 @@ func (hf *HostFunctions) RegisterHostFunctions(ctx context.Context, runtime waze
 		}, []api.ValueType{api.ValueTypeF64}).
 		Export("get_score")
 
+	// get_time_ns() -> i64: monotonic clock for profiling
+	hostBuilder.NewFunctionBuilder().
+		WithGoModuleFunction(api.GoModuleFunc(hf.getTimeNs), []api.ValueType{},
+			[]api.ValueType{api.ValueTypeI64}).
+		Export("get_time_ns")
+
+	// emit_stat(key_ptr: i32, key_len: i32, value: i64): a UDF's work counter
+	hostBuilder.NewFunctionBuilder().
+		WithGoModuleFunction(api.GoModuleFunc(hf.emitStat), []api.ValueType{
+			api.ValueTypeI32, // key_ptr
+			api.ValueTypeI32, // key_len
+			api.ValueTypeI64, // value
+		}, []api.ValueType{}).
+		Export("emit_stat")
+
 	// Register logging function for debugging
 	hostBuilder.NewFunctionBuilder().
 		WithGoModuleFunction(api.GoModuleFunc(hf.log), []api.ValueType{
@@ -171,6 +228,38 @@ func (hf *HostFunctions) RegisterHostFunctions(ctx context.Context, runtime waze
 		}, []api.ValueType{api.ValueTypeI32}).
 		Export("get_param_string")
 
+	// get_param_array(name_ptr: i32, name_len: i32, value_ptr: i32, value_len_ptr: i32) -> i32
+	// Writes an array-of-strings parameter (see encodeStringArray)
+	// Returns: 0=success, 1=not found, 2=not an array of strings, 3=buffer too small
+	hostBuilder.NewFunctionBuilder().
+		WithGoModuleFunction(api.GoModuleFunc(hf.getParamArray), []api.ValueType{
+			api.ValueTypeI32, // name_ptr
+			api.ValueTypeI32, // name_len
+			api.ValueTypeI32, // value_ptr
+			api.ValueTypeI32, // value_len_ptr
+		}, []api.ValueType{api.ValueTypeI32}).
+		Export("get_param_array")
+
+	// get_param_names(value_ptr: i32, value_len_ptr: i32) -> i32
+	// Writes the parameter names as a JSON array of strings
+	// Returns: 0=success, 1=no parameters registered, 3=buffer too small
+	hostBuilder.NewFunctionBuilder().
+		WithGoModuleFunction(api.GoModuleFunc(hf.getParamNames), []api.ValueType{
+			api.ValueTypeI32, // value_ptr
+			api.ValueTypeI32, // value_len_ptr
+		}, []api.ValueType{api.ValueTypeI32}).
+		Export("get_param_names")
+
+	// get_params_blob(out_ptr: i32, out_len_ptr: i32) -> i32
+	// Writes every parameter in one binary blob (see encodeParamsBlob)
+	// Returns: 0=success, 1=no parameters registered, 3=buffer too small
+	hostBuilder.NewFunctionBuilder().
+		WithGoModuleFunction(api.GoModuleFunc(hf.getParamsBlob), []api.ValueType{
+			api.ValueTypeI32, // out_ptr
+			api.ValueTypeI32, // out_len_ptr
+		}, []api.ValueType{api.ValueTypeI32}).
+		Export("get_params_blob")
+
 	// get_param_i64(name_ptr: i32, name_len: i32, out_ptr: i32) -> i32
 	// Returns: 0=success, 1=not found, 2=not numeric, 3=write error
 	hostBuilder.NewFunctionBuilder().
@@ -261,6 +350,150 @@ func (hf *HostFunctions) getFieldString(ctx context.Context, mod api.Module, sta
 	stack[0] = 1 // Success
 }
 
+// getFieldChunk copies up to buf_len bytes of a string field value starting at
+// byte offset, so values larger than any WASM buffer can be streamed
+// Parameters: ctx_id, field_ptr, field_len, offset, buf_ptr, buf_len, out_len_ptr
+// Returns: 0=success (out_len 0 marks the end of the value), 1=not found, 3=write error
+func (hf *HostFunctions) getFieldChunk(ctx context.Context, mod api.Module, stack []uint64) {
+	ctxID := stack[0]
+	fieldPtr := uint32(stack[1])
+	fieldLen := uint32(stack[2])
+	offset := uint64(uint32(stack[3]))
+	bufPtr := uint32(stack[4])
+	bufLen := uint64(uint32(stack[5]))
+	outLenPtr := uint32(stack[6])
+
+	// Get document context
+	docCtx, exists := hf.GetContext(ctxID)
+	if !exists {
+		stack[0] = 1 // Field not found
+		return
+	}
+
+	// Read field path from WASM memory
+	fieldPath, ok := mod.Memory().Read(fieldPtr, fieldLen)
+	if !ok {
+		hf.logger.Warn("Failed to read field path from WASM memory")
+		stack[0] = 1
+		return
+	}
+
+	// Get field value
+	value, exists := docCtx.GetFieldString(string(fieldPath))
+	if !exists {
+		stack[0] = 1 // Field not found
+		return
+	}
+
+	// Slice out the requested chunk; past the end it is empty
+	var chunk []byte
+	if offset < uint64(len(value)) {
+		end := offset + bufLen
+		if end > uint64(len(value)) {
+			end = uint64(len(value))
+		}
+		chunk = []byte(value[offset:end])
+	}
+
+	// Write chunk to WASM memory
+	if len(chunk) > 0 && !mod.Memory().Write(bufPtr, chunk) {
+		hf.logger.Warn("Failed to write field chunk to WASM memory")
+		stack[0] = 3
+		return
+	}
+
+	// Write chunk length
+	if !mod.Memory().Write(outLenPtr, uint32ToBytes(uint32(len(chunk)))) {
+		hf.logger.Warn("Failed to write chunk length to WASM memory")
+		stack[0] = 3
+		return
+	}
+
+	stack[0] = 0 // Success
+}
+
+// getFieldArray retrieves an array-of-strings field value
+// Parameters: ctx_id, field_ptr, field_len, value_ptr, value_len_ptr
+// Returns: 0=success, 1=not found, 2=not an array of strings, 3=buffer too small
+func (hf *HostFunctions) getFieldArray(ctx context.Context, mod api.Module, stack []uint64) {
+	ctxID := stack[0]
+	fieldPtr := uint32(stack[1])
+	fieldLen := uint32(stack[2])
+	valuePtr := uint32(stack[3])
+	valueLenPtr := uint32(stack[4])
+
+	// Get document context
+	docCtx, exists := hf.GetContext(ctxID)
+	if !exists {
+		stack[0] = 1 // Field not found
+		return
+	}
+
+	// Read field path from WASM memory
+	fieldPath, ok := mod.Memory().Read(fieldPtr, fieldLen)
+	if !ok {
+		hf.logger.Warn("Failed to read field path from WASM memory")
+		stack[0] = 1
+		return
+	}
+
+	// Get field value
+	values, exists, ok := docCtx.GetFieldStringArray(string(fieldPath))
+	if !exists {
+		stack[0] = 1 // Field not found
+		return
+	}
+	if !ok {
+		stack[0] = 2 // Not an array of strings
+		return
+	}
+
+	stack[0] = writeSized(mod, valuePtr, valueLenPtr, encodeStringArray(values))
+}
+
+// getFieldDocFreq retrieves the share of documents in [0, 1] whose field holds
+// this document's value, as recorded with SetFieldDocFreq
+// Parameters: ctx_id, field_ptr, field_len, out_ptr
+// Returns: 0=success, 1=not known, 3=write error
+func (hf *HostFunctions) getFieldDocFreq(ctx context.Context, mod api.Module, stack []uint64) {
+	ctxID := stack[0]
+	fieldPtr := uint32(stack[1])
+	fieldLen := uint32(stack[2])
+	outPtr := uint32(stack[3])
+
+	// Get document context
+	docCtx, exists := hf.GetContext(ctxID)
+	if !exists {
+		stack[0] = 1 // Not known
+		return
+	}
+
+	// Read field path from WASM memory
+	fieldPath, ok := mod.Memory().Read(fieldPtr, fieldLen)
+	if !ok {
+		hf.logger.Warn("Failed to read field path from WASM memory")
+		stack[0] = 1
+		return
+	}
+
+	freq, exists := docCtx.GetFieldDocFreq(string(fieldPath))
+	if !exists {
+		stack[0] = 1 // Not known
+		return
+	}
+
+	// Write to WASM memory
+	buf := make([]byte, 8)
+	binary.LittleEndian.PutUint64(buf, math.Float64bits(freq))
+	if !mod.Memory().Write(outPtr, buf) {
+		stack[0] = 3
+		return
+	}
+
+	stack[0] = 0 // Success
+}
+
+// getFieldInt64 retrieves an int64 field value
 // getFieldInt64 retrieves an int64 field value
 // Parameters: ctx_id, field_ptr, field_len
 // Returns: value (or 0 if not found) in lower 32 bits, exists flag in upper 32 bits
@@ -445,6 +678,31 @@ func (hf *HostFunctions) getScore(ctx context.Context, mod api.Module, stack []u
 	stack[0] = api.EncodeF64(score)
 }
 
+// clockStart anchors get_time_ns so readings use Go's monotonic clock
+var clockStart = time.Now()
+
+// getTimeNs returns nanoseconds on a monotonic clock, for timing inside UDFs
+// Returns: nanoseconds since host start (only differences are meaningful)
+func (hf *HostFunctions) getTimeNs(ctx context.Context, mod api.Module, stack []uint64) {
+	stack[0] = uint64(time.Since(clockStart).Nanoseconds())
+}
+
+// emitStat records a counter a UDF reports about its last call
+// Parameters: key_ptr, key_len, value
+func (hf *HostFunctions) emitStat(ctx context.Context, mod api.Module, stack []uint64) {
+	keyPtr := uint32(stack[0])
+	keyLen := uint32(stack[1])
+	value := int64(stack[2])
+
+	keyBytes, ok := mod.Memory().Read(keyPtr, keyLen)
+	if !ok {
+		hf.logger.Warn("Failed to read stat key from WASM memory")
+		return
+	}
+
+	hf.logger.Debug("WASM stat", zap.String("key", string(keyBytes)), zap.Int64("value", value))
+}
+
 // log logs a message from WASM (for debugging)
 // Parameters: msg_ptr, msg_len
 func (hf *HostFunctions) log(ctx context.Context, mod api.Module, stack []uint64) {
@@ -528,6 +786,255 @@ func (hf *HostFunctions) getParamString(ctx context.Context, mod api.Module, sta
 	stack[0] = 0 // Success
 }
 
+// getParamArray retrieves an array-of-strings parameter from the current UDF
+// execution. Query parameters are scalars, so a string parameter holding a
+// JSON array of strings is passed as that array.
+// Parameters: name_ptr, name_len, value_ptr, value_len_ptr
+// Returns: 0=success, 1=not found, 2=not an array of strings, 3=buffer too small
+func (hf *HostFunctions) getParamArray(ctx context.Context, mod api.Module, stack []uint64) {
+	namePtr := uint32(stack[0])
+	nameLen := uint32(stack[1])
+	valuePtr := uint32(stack[2])
+	valueLenPtr := uint32(stack[3])
+
+	// Read parameter name from WASM memory
+	nameBytes, ok := mod.Memory().Read(namePtr, nameLen)
+	if !ok {
+		hf.logger.Error("Failed to read parameter name from memory")
+		stack[0] = 1 // Parameter not found
+		return
+	}
+	paramName := string(nameBytes)
+
+	// Look up parameter
+	paramValue, exists := hf.GetParameter(paramName)
+	if !exists {
+		hf.logger.Debug("Parameter not found", zap.String("name", paramName))
+		stack[0] = 1 // Parameter not found
+		return
+	}
+
+	values, ok := paramStringArray(paramValue)
+	if !ok {
+		hf.logger.Warn("Parameter is not an array of strings",
+			zap.String("name", paramName),
+			zap.String("type", fmt.Sprintf("%T", paramValue)))
+		stack[0] = 2 // Not an array of strings
+		return
+	}
+
+	stack[0] = writeSized(mod, valuePtr, valueLenPtr, encodeStringArray(values))
+}
+
+// paramStringArray converts a parameter to an array of strings: an array
+// itself, or a string holding one as JSON
+func paramStringArray(value interface{}) ([]string, bool) {
+	if text, ok := value.(string); ok {
+		var values []string
+		if err := json.Unmarshal([]byte(text), &values); err != nil || values == nil {
+			return nil, false
+		}
+		return values, true
+	}
+	return stringArray(value)
+}
+
+// encodeStringArray encodes an array of strings for get_field_array and
+// get_param_array, little-endian: a uint32 count, then per element a uint32
+// length and its bytes
+func encodeStringArray(values []string) []byte {
+	encoded := binary.LittleEndian.AppendUint32(make([]byte, 0, 64), uint32(len(values)))
+	for _, value := range values {
+		encoded = binary.LittleEndian.AppendUint32(encoded, uint32(len(value)))
+		encoded = append(encoded, value...)
+	}
+	return encoded
+}
+
+// writeSized copies value into a WASM buffer whose size is read from
+// valueLenPtr, writing back the length written, or the length needed when
+// it doesn't fit
+// Returns: 0=success, 3=buffer too small or write error
+func writeSized(mod api.Module, valuePtr, valueLenPtr uint32, value []byte) uint64 {
+	// Read buffer size from WASM memory
+	valueLenBytes, ok := mod.Memory().Read(valueLenPtr, 4)
+	if !ok {
+		return 3 // Buffer error
+	}
+	bufferSize := binary.LittleEndian.Uint32(valueLenBytes)
+
+	// Check if buffer is large enough
+	if uint32(len(value)) > bufferSize {
+		// Write required size
+		binary.LittleEndian.PutUint32(valueLenBytes, uint32(len(value)))
+		mod.Memory().Write(valueLenPtr, valueLenBytes)
+		return 3 // Buffer too small
+	}
+
+	// Write value to WASM memory
+	if !mod.Memory().Write(valuePtr, value) {
+		return 3 // Write error
+	}
+
+	// Write actual length
+	binary.LittleEndian.PutUint32(valueLenBytes, uint32(len(value)))
+	mod.Memory().Write(valueLenPtr, valueLenBytes)
+	return 0 // Success
+}
+
+// getParamNames lists the parameter names of the current UDF execution
+// getParamNames lists the parameter names of the current UDF execution
+// Parameters: value_ptr, value_len_ptr
+// Returns: 0=success, 1=no parameters registered, 3=buffer too small
+func (hf *HostFunctions) getParamNames(ctx context.Context, mod api.Module, stack []uint64) {
+	valuePtr := uint32(stack[0])
+	valueLenPtr := uint32(stack[1])
+
+	// Collect the names, sorted so the list is stable
+	hf.paramMutex.RLock()
+	registered := hf.currentParams != nil
+	names := make([]string, 0, len(hf.currentParams))
+	for name := range hf.currentParams {
+		names = append(names, name)
+	}
+	hf.paramMutex.RUnlock()
+	if !registered {
+		stack[0] = 1 // No parameters
+		return
+	}
+	sort.Strings(names)
+
+	valueBytes, err := json.Marshal(names)
+	if err != nil {
+		hf.logger.Error("Failed to encode parameter names", zap.Error(err))
+		stack[0] = 1
+		return
+	}
+
+	// Read buffer size from WASM memory
+	valueLenBytes, ok := mod.Memory().Read(valueLenPtr, 4)
+	if !ok {
+		stack[0] = 3 // Buffer error
+		return
+	}
+	bufferSize := binary.LittleEndian.Uint32(valueLenBytes)
+
+	// Check if buffer is large enough
+	if uint32(len(valueBytes)) > bufferSize {
+		// Write required size
+		binary.LittleEndian.PutUint32(valueLenBytes, uint32(len(valueBytes)))
+		mod.Memory().Write(valueLenPtr, valueLenBytes)
+		stack[0] = 3 // Buffer too small
+		return
+	}
+
+	// Write names to WASM memory
+	if !mod.Memory().Write(valuePtr, valueBytes) {
+		stack[0] = 3 // Write error
+		return
+	}
+
+	// Write actual length
+	binary.LittleEndian.PutUint32(valueLenBytes, uint32(len(valueBytes)))
+	mod.Memory().Write(valueLenPtr, valueLenBytes)
+
+	stack[0] = 0 // Success
+}
+
+// getParamsBlob writes all parameters of the current UDF execution in one call
+// Parameters: out_ptr, out_len_ptr
+// Returns: 0=success, 1=no parameters registered, 3=buffer too small
+func (hf *HostFunctions) getParamsBlob(ctx context.Context, mod api.Module, stack []uint64) {
+	outPtr := uint32(stack[0])
+	outLenPtr := uint32(stack[1])
+
+	hf.paramMutex.RLock()
+	registered := hf.currentParams != nil
+	blob := encodeParamsBlob(hf.currentParams)
+	hf.paramMutex.RUnlock()
+	if !registered {
+		stack[0] = 1 // No parameters
+		return
+	}
+
+	// Read buffer size from WASM memory
+	outLenBytes, ok := mod.Memory().Read(outLenPtr, 4)
+	if !ok {
+		stack[0] = 3 // Buffer error
+		return
+	}
+	bufferSize := binary.LittleEndian.Uint32(outLenBytes)
+
+	// Check if buffer is large enough
+	if uint32(len(blob)) > bufferSize {
+		// Write required size
+		binary.LittleEndian.PutUint32(outLenBytes, uint32(len(blob)))
+		mod.Memory().Write(outLenPtr, outLenBytes)
+		stack[0] = 3 // Buffer too small
+		return
+	}
+
+	// Write blob to WASM memory
+	if !mod.Memory().Write(outPtr, blob) {
+		stack[0] = 3 // Write error
+		return
+	}
+
+	// Write actual length
+	binary.LittleEndian.PutUint32(outLenBytes, uint32(len(blob)))
+	mod.Memory().Write(outLenPtr, outLenBytes)
+
+	stack[0] = 0 // Success
+}
+
+// encodeParamsBlob encodes parameters for get_params_blob, little-endian: a
+// uint32 count, then per parameter a uint32 name length, the name, a type tag
+// and the value (0: uint32 length and string bytes, 1: int64, 2: float64,
+// 3: one byte bool). Names are sorted so the same parameters give the same
+// blob; values of other types, which no get_param_* function reads, are left
+// out.
+func encodeParamsBlob(params map[string]interface{}) []byte {
+	names := make([]string, 0, len(params))
+	for name := range params {
+		names = append(names, name)
+	}
+	sort.Strings(names)
+
+	blob := make([]byte, 4, 64)
+	count := uint32(0)
+	for _, name := range names {
+		var value []byte
+		switch v := params[name].(type) {
+		case string:
+			value = binary.LittleEndian.AppendUint32([]byte{0}, uint32(len(v)))
+			value = append(value, v...)
+		case int64:
+			value = binary.LittleEndian.AppendUint64([]byte{1}, uint64(v))
+		case int32:
+			value = binary.LittleEndian.AppendUint64([]byte{1}, uint64(int64(v)))
+		case int:
+			value = binary.LittleEndian.AppendUint64([]byte{1}, uint64(int64(v)))
+		case float64:
+			value = binary.LittleEndian.AppendUint64([]byte{2}, math.Float64bits(v))
+		case float32:
+			value = binary.LittleEndian.AppendUint64([]byte{2}, math.Float64bits(float64(v)))
+		case bool:
+			value = []byte{3, 0}
+			if v {
+				value[1] = 1
+			}
+		default:
+			continue
+		}
+		blob = binary.LittleEndian.AppendUint32(blob, uint32(len(name)))
+		blob = append(blob, name...)
+		blob = append(blob, value...)
+		count++
+	}
+	binary.LittleEndian.PutUint32(blob[:4], count)
+	return blob
+}
+
 // getParamInt64 retrieves an int64 parameter from the current UDF execution
 // Parameters: name_ptr, name_len, out_ptr
 // Returns: 0=success, 1=not found, 2=not numeric, 3=write error
@@ -3,6 +3,8 @@ package wasm
 import (
 	"context"
 	"fmt"
+	"maps"
+	"reflect"
 	"sync"
 
 	"github.com/tetratelabs/wazero"
@@ -17,6 +19,10 @@ type ModuleInstance struct {
 	runtime  *Runtime
 	logger   *zap.Logger
 	mu       sync.RWMutex
+
+	// Parameters of the last call, for NoteParameters
+	lastParams map[string]Value
+	paramsSeen bool
 }
 
 // NewModuleInstance creates a new module instance
@@ -74,6 +80,29 @@ func (mi *ModuleInstance) CallFunction(ctx context.Context, functionName string,
 	return results, nil
 }
 
+// NoteParameters tells the module when params differ from those of the
+// instance's last call, through its optional params_changed export, so it
+// can keep what it read from them for the rest of a query. Modules without
+// the export are left alone.
+func (mi *ModuleInstance) NoteParameters(ctx context.Context, params map[string]Value) error {
+	mi.mu.Lock()
+	defer mi.mu.Unlock()
+
+	if mi.paramsSeen && reflect.DeepEqual(mi.lastParams, params) {
+		return nil
+	}
+
+	if fn := mi.module.ExportedFunction("params_changed"); fn != nil {
+		if _, err := fn.Call(ctx); err != nil {
+			return fmt.Errorf("error calling params_changed in module %s: %w", mi.name, err)
+		}
+	}
+
+	mi.lastParams = maps.Clone(params)
+	mi.paramsSeen = true
+	return nil
+}
+
 // GetFunction returns an exported function
 func (mi *ModuleInstance) GetFunction(functionName string) api.Function {
 	mi.mu.RLock()
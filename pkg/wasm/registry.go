@@ -351,6 +351,11 @@ func (r *UDFRegistry) Call(ctx context.Context, name, version string, docCtx *Do
 		defer instance.Close()
 	}
 
+	// Let the module keep what it read from unchanged parameters
+	if err := instance.NoteParameters(ctx, params); err != nil {
+		return nil, fmt.Errorf("UDF call failed: %w", err)
+	}
+
 	// Call function
 	results, err := instance.CallFunction(ctx, registered.Metadata.FunctionName, wasmParams...)
 
@@ -176,6 +176,55 @@ func TestCallFunction(t *testing.T) {
 	t.Logf("✅ WASM function call successful: 2 + 3 = %d", results[0])
 }
 
+func TestNoteParametersWithoutExport(t *testing.T) {
+	logger, _ := zap.NewDevelopment()
+	cfg := &Config{
+		EnableJIT:   true,
+		EnableDebug: true,
+		Logger:      logger,
+	}
+
+	runtime, err := NewRuntime(cfg)
+	if err != nil {
+		t.Fatalf("Failed to create runtime: %v", err)
+	}
+	defer runtime.Close()
+
+	metadata := &ModuleMetadata{
+		Name:        "add",
+		Version:     "1.0.0",
+		Description: "Simple addition module",
+	}
+
+	err = runtime.CompileModule("add", addWasmBytes, metadata)
+	if err != nil {
+		t.Fatalf("Failed to compile module: %v", err)
+	}
+
+	instance, err := runtime.NewModuleInstance("add")
+	if err != nil {
+		t.Fatalf("Failed to instantiate module: %v", err)
+	}
+	defer instance.Close()
+
+	// A module without params_changed is called as before
+	params := map[string]Value{"target": NewStringValue("abc")}
+	for i := 0; i < 2; i++ {
+		if err := instance.NoteParameters(context.Background(), params); err != nil {
+			t.Fatalf("Failed to note parameters: %v", err)
+		}
+	}
+	params["target"] = NewStringValue("abd")
+	if err := instance.NoteParameters(context.Background(), params); err != nil {
+		t.Fatalf("Failed to note changed parameters: %v", err)
+	}
+
+	results, err := instance.CallFunction(context.Background(), "add", 2, 3)
+	if err != nil || len(results) != 1 || results[0] != 5 {
+		t.Fatalf("Expected 5, got %v (%v)", results, err)
+	}
+}
+
 func TestModulePool(t *testing.T) {
 	logger, _ := zap.NewDevelopment()
 	cfg := &Config{